@@ -1,6 +1,6 @@
 #import bevy_sprite::{
     mesh2d_vertex_output::VertexOutput,
-    mesh2d_view_bindings::view,
+    mesh2d_view_bindings::{globals, view},
 }
 
 #ifdef TONEMAP_IN_SHADER
@@ -14,9 +14,14 @@ struct ChunkMaterial {
     flags: u32,
     alpha_cutoff: f32,
     chunk_size: f32,
+    // The color of the light from the sky, which changes through the day.
+    sky_tint: vec4<f32>,
 };
 
 const CHUNK_MATERIAL_FLAGS_TEXTURE_BIT: u32              = 1u;
+const CHUNK_MATERIAL_FLAGS_EDGE_DARKENING_BIT: u32       = 2u;
+const CHUNK_MATERIAL_FLAGS_SMOOTH_FLUIDS_BIT: u32        = 4u;
+const CHUNK_MATERIAL_FLAGS_LIGHTING_BIT: u32             = 8u;
 const CHUNK_MATERIAL_FLAGS_ALPHA_MODE_RESERVED_BITS: u32 = 3221225472u; // (0b11u32 << 30)
 const CHUNK_MATERIAL_FLAGS_ALPHA_MODE_OPAQUE: u32        = 0u;          // (0u32 << 30)
 const CHUNK_MATERIAL_FLAGS_ALPHA_MODE_MASK: u32          = 1073741824u; // (1u32 << 30)
@@ -25,8 +30,262 @@ const CHUNK_MATERIAL_FLAGS_ALPHA_MODE_BLEND: u32         = 2147483648u; // (2u32
 @group(2) @binding(0) var<uniform> material: ChunkMaterial;
 @group(2) @binding(1) var texture: texture_2d<f32>;
 @group(2) @binding(2) var texture_sampler: sampler;
-// Size is (CHUNK_SIZE * CHUNK_SIZE) / 4. e.g (32 * 32) / 4 = 256 since we're packing 4 indices into each vec4.
+#ifdef CHUNK_STORAGE_BUFFERS
+// The indices, walls and light levels back to back, laid out like the uniforms below. Storage
+// arrays are sized at runtime, so any CHUNK_SIZE fits.
+@group(2) @binding(10) var<storage, read> cells: array<vec4<u32>>;
+#else
+// Size is PACKED_INDICE_BUFFER_SIZE = (CHUNK_SIZE * CHUNK_SIZE) / 4. e.g (32 * 32) / 4 = 256 since we're packing 4 indices into each vec4.
 @group(2) @binding(3) var<uniform> indices: array<vec4<u32>, 256>; 
+#endif
+// Additional atlases, selected by the atlas id stored in the upper bits of each index. Must match MAX_ATLASES.
+@group(2) @binding(4) var atlas_1: texture_2d<f32>;
+@group(2) @binding(5) var atlas_2: texture_2d<f32>;
+@group(2) @binding(6) var atlas_3: texture_2d<f32>;
+
+// Override colors per sprite, SPRITES_PER_ATLAS entries per atlas. Size is PALETTE_SIZE.
+// Fully transparent entries mean the atlas color is used.
+@group(2) @binding(7) var<uniform> palette: array<vec4<f32>, 256>;
+const SPRITES_PER_ATLAS: u32 = 64u;
+
+// The walls behind the particles, packed like `indices`. Drawn wherever the particle layer is air.
+#ifndef CHUNK_STORAGE_BUFFERS
+@group(2) @binding(8) var<uniform> background_indices: array<vec4<u32>, 256>;
+#endif
+// How bright walls are drawn compared to the particles in front of them.
+const BACKGROUND_BRIGHTNESS: f32 = 0.45;
+
+// The light level of every cell, one byte each, packed sixteen to a vec4. Size is
+// PACKED_LIGHT_BUFFER_SIZE = (CHUNK_SIZE * CHUNK_SIZE) / 16.
+#ifndef CHUNK_STORAGE_BUFFERS
+@group(2) @binding(9) var<uniform> light: array<vec4<u32>, 64>;
+#endif
+// Must match MAX_LIGHT.
+const MAX_LIGHT: f32 = 15.0;
+// How bright unlit cells are drawn, so the darkest caves aren't solid black.
+const MIN_BRIGHTNESS: f32 = 0.03;
+
+// How far into a cell (as a fraction of its size) the edge darkening reaches, and how dark it gets.
+const EDGE_WIDTH: f32 = 0.15;
+const EDGE_DARKNESS: f32 = 0.7;
+
+// Must match ATLAS_ID_SHIFT.
+const ATLAS_ID_SHIFT: u32 = 24u;
+// Must match ANIMATION_SHIFT. The frame count minus one and the frame rate take four bits each.
+const ANIMATION_SHIFT: u32 = 16u;
+// Must match VARIANT_SHIFT. The variant takes the two bits below the animation.
+const VARIANT_SHIFT: u32 = 14u;
+const VARIANT_MASK: u32 = 49152u; // 3u32 << 14
+const SPRITE_INDEX_MASK: u32 = 16383u; // (1u32 << 14) - 1
+// How much darker each variant is drawn than the one before it.
+const VARIANT_SHADE: f32 = 0.06;
+// Must match LIQUID_CELL_BIT. The draw order sits between it and the atlas id.
+const LIQUID_CELL_BIT: u32 = 2147483648u; // 1u32 << 31
+// Must match DRAW_ORDER_SHIFT and MAX_DRAW_ORDER.
+const DRAW_ORDER_SHIFT: u32 = 26u;
+const DRAW_ORDER_MASK: u32 = 31u;
+const ATLAS_ID_MASK: u32 = 3u;
+
+// How far into a cell (as a fraction of its size) neighbors with a higher draw order are drawn.
+const OVERLAP_WIDTH: f32 = 0.25;
+
+fn atlas_width(atlas_id: u32) -> u32 {
+    switch atlas_id {
+        case 1u: { return textureDimensions(atlas_1).x; }
+        case 2u: { return textureDimensions(atlas_2).x; }
+        case 3u: { return textureDimensions(atlas_3).x; }
+        default: { return textureDimensions(texture).x; }
+    }
+}
+
+// How many sprites past its first frame an animated cell is drawn at the current time.
+fn animation_frame(value: u32) -> u32 {
+    let frames = ((value >> ANIMATION_SHIFT) & 15u) + 1u;
+    let frame_rate = (value >> (ANIMATION_SHIFT + 4u)) & 15u;
+    return u32(globals.time * f32(frame_rate)) % frames;
+}
+
+// The brightness a cell is drawn at for its variant, so neighbors of the same type differ a little.
+fn variant_brightness(value: u32) -> f32 {
+    return 1.0 - VARIANT_SHADE * f32((value & VARIANT_MASK) >> VARIANT_SHIFT);
+}
+
+// Whether two cell values hold the same type of particle, whatever their variants.
+fn same_type(a: u32, b: u32) -> bool {
+    return (a & ~VARIANT_MASK) == (b & ~VARIANT_MASK);
+}
+
+// Whether `upper` is drawn over the edge of `lower` where the two cells meet: both hold
+// particles and `upper` has the higher draw order.
+fn draws_over(upper: u32, lower: u32) -> bool {
+    let order = (upper >> DRAW_ORDER_SHIFT) & DRAW_ORDER_MASK;
+    let lower_order = (lower >> DRAW_ORDER_SHIFT) & DRAW_ORDER_MASK;
+    return (upper & SPRITE_INDEX_MASK) != 0u && (lower & SPRITE_INDEX_MASK) != 0u && order > lower_order;
+}
+
+// The packed cells, walls and light levels, read from wherever this build uploads them.
+#ifdef CHUNK_STORAGE_BUFFERS
+fn packed_cells(i: u32) -> vec4<u32> {
+    return cells[i];
+}
+
+fn packed_background(i: u32) -> vec4<u32> {
+    let cell_count = u32(material.chunk_size * material.chunk_size);
+    return cells[cell_count / 4u + i];
+}
+
+fn packed_light(i: u32) -> vec4<u32> {
+    let cell_count = u32(material.chunk_size * material.chunk_size);
+    return cells[cell_count / 2u + i];
+}
+#else
+fn packed_cells(i: u32) -> vec4<u32> {
+    return indices[i];
+}
+
+fn packed_background(i: u32) -> vec4<u32> {
+    return background_indices[i];
+}
+
+fn packed_light(i: u32) -> vec4<u32> {
+    return light[i];
+}
+#endif
+
+// The packed cell value at the given grid position. Cells are stored row-major, four to a vec4.
+fn cell_at(x: u32, y: u32) -> u32 {
+    let index = y * u32(material.chunk_size) + x;
+    return packed_cells(index / 4u)[index % 4u];
+}
+
+// The packed wall value at the given grid position, laid out like `cell_at`.
+fn background_at(x: u32, y: u32) -> u32 {
+    let index = y * u32(material.chunk_size) + x;
+    return packed_background(index / 4u)[index % 4u];
+}
+
+// The byte holding the light levels of the cell at the given grid position: its light in the
+// low four bits and its sky light in the high four. Positions outside the chunk are clamped to
+// its edge, since the neighboring chunks' levels aren't available.
+fn light_byte(x: i32, y: i32) -> u32 {
+    let last = i32(material.chunk_size) - 1;
+    let index = u32(clamp(y, 0, last) * (last + 1) + clamp(x, 0, last));
+    let word = packed_light(index / 16u)[(index / 4u) % 4u];
+    return (word >> ((index % 4u) * 8u)) & 255u;
+}
+
+// The light level of the cell at the given grid position, from 0 to 1.
+fn light_at(x: i32, y: i32) -> f32 {
+    return f32(light_byte(x, y) & 15u) / MAX_LIGHT;
+}
+
+// How much of the light of the cell at the given grid position comes from the sky, from 0 to 1.
+fn sky_light_at(x: i32, y: i32) -> f32 {
+    return f32(light_byte(x, y) >> 4u) / MAX_LIGHT;
+}
+
+// The brightness of a fragment at grid position `grid_pos` (in cells). The levels of the four
+// nearest cell centers are blended bilinearly, so light fades smoothly instead of in steps.
+fn brightness_at(grid_pos: vec2<f32>) -> f32 {
+    let p = grid_pos - 0.5;
+    let base = vec2<i32>(floor(p));
+    let f = p - floor(p);
+    let bottom = mix(light_at(base.x, base.y), light_at(base.x + 1, base.y), f.x);
+    let top = mix(light_at(base.x, base.y + 1), light_at(base.x + 1, base.y + 1), f.x);
+    return max(mix(bottom, top, f.y), MIN_BRIGHTNESS);
+}
+
+// How much a fragment at grid position `grid_pos` (in cells) is lit by the sky, from 0 to 1,
+// blended like `brightness_at`.
+fn sky_exposure_at(grid_pos: vec2<f32>) -> f32 {
+    let p = grid_pos - 0.5;
+    let base = vec2<i32>(floor(p));
+    let f = p - floor(p);
+    let bottom = mix(sky_light_at(base.x, base.y), sky_light_at(base.x + 1, base.y), f.x);
+    let top = mix(sky_light_at(base.x, base.y + 1), sky_light_at(base.x + 1, base.y + 1), f.x);
+    return mix(bottom, top, f.y);
+}
+
+// Whether the edge of `cell` towards `neighbor` is darkened: the two hold different types of
+// particle, and `cell` isn't drawn over its neighbor.
+fn shows_edge(cell: u32, neighbor: u32) -> bool {
+    return !same_type(neighbor, cell) && !draws_over(cell, neighbor);
+}
+
+// Whether the cell at (x, y) borders a different cell on the side of the cell the fragment is
+// closest to. `cell_uv` is the fragment's position within the cell, from 0 to 1.
+// Neighbors in other chunks aren't available, so chunk borders are never darkened.
+fn is_on_type_edge(x: u32, y: u32, cell: u32, cell_uv: vec2<f32>) -> bool {
+    let last = u32(material.chunk_size) - 1u;
+    if (cell_uv.x < EDGE_WIDTH && x > 0u && shows_edge(cell, cell_at(x - 1u, y))) {
+        return true;
+    }
+    if (cell_uv.x > 1.0 - EDGE_WIDTH && x < last && shows_edge(cell, cell_at(x + 1u, y))) {
+        return true;
+    }
+    if (cell_uv.y < EDGE_WIDTH && y > 0u && shows_edge(cell, cell_at(x, y - 1u))) {
+        return true;
+    }
+    if (cell_uv.y > 1.0 - EDGE_WIDTH && y < last && shows_edge(cell, cell_at(x, y + 1u))) {
+        return true;
+    }
+    return false;
+}
+
+// The cell value drawn at a fragment of the cell at (x, y): a neighbor drawn over the cell, if
+// the fragment is within OVERLAP_WIDTH of it, otherwise the cell itself. `cell_uv` is the
+// fragment's position within the cell, from 0 to 1. Neighbors in other chunks aren't available,
+// so they're never drawn over chunk borders.
+fn overlapping_cell(x: u32, y: u32, cell_uv: vec2<f32>) -> u32 {
+    let last = u32(material.chunk_size) - 1u;
+    var top = cell_at(x, y);
+    if (cell_uv.x < OVERLAP_WIDTH && x > 0u && draws_over(cell_at(x - 1u, y), top)) {
+        top = cell_at(x - 1u, y);
+    }
+    if (cell_uv.x > 1.0 - OVERLAP_WIDTH && x < last && draws_over(cell_at(x + 1u, y), top)) {
+        top = cell_at(x + 1u, y);
+    }
+    if (cell_uv.y < OVERLAP_WIDTH && y > 0u && draws_over(cell_at(x, y - 1u), top)) {
+        top = cell_at(x, y - 1u);
+    }
+    if (cell_uv.y > 1.0 - OVERLAP_WIDTH && y < last && draws_over(cell_at(x, y + 1u), top)) {
+        top = cell_at(x, y + 1u);
+    }
+    return top;
+}
+
+// How filled the cell at (x, y) is for fluid smoothing: air is empty, anything else is full.
+// Cells outside the chunk aren't available, so they count as `fallback`.
+fn fill_at(x: i32, y: i32, fallback: f32) -> f32 {
+    let size = i32(material.chunk_size);
+    if (x < 0 || y < 0 || x >= size || y >= size) {
+        return fallback;
+    }
+    return select(0.0, 1.0, (cell_at(u32(x), u32(y)) & SPRITE_INDEX_MASK) != 0u);
+}
+
+// Whether a liquid fragment at grid position `grid_pos` (in cells) is still inside the smoothed
+// surface. The fill of the four nearest cell centers is blended bilinearly and thresholded at
+// one half, which keeps flat surfaces in place but rounds off corners exposed to air.
+fn is_inside_fluid_surface(grid_pos: vec2<f32>) -> bool {
+    let p = grid_pos - 0.5;
+    let base = vec2<i32>(floor(p));
+    let f = p - floor(p);
+    // The fragment's own cell is liquid, so cells past the chunk edge continue it.
+    let bottom = mix(fill_at(base.x, base.y, 1.0), fill_at(base.x + 1, base.y, 1.0), f.x);
+    let top = mix(fill_at(base.x, base.y + 1, 1.0), fill_at(base.x + 1, base.y + 1, 1.0), f.x);
+    return mix(bottom, top, f.y) >= 0.5;
+}
+
+// Sampled at an explicit level because the atlas is chosen per cell, outside uniform control flow.
+fn sample_atlas(atlas_id: u32, uv: vec2<f32>) -> vec4<f32> {
+    switch atlas_id {
+        case 1u: { return textureSampleLevel(atlas_1, texture_sampler, uv, 0.0); }
+        case 2u: { return textureSampleLevel(atlas_2, texture_sampler, uv, 0.0); }
+        case 3u: { return textureSampleLevel(atlas_3, texture_sampler, uv, 0.0); }
+        default: { return textureSampleLevel(texture, texture_sampler, uv, 0.0); }
+    }
+}
 
 @fragment
 fn fragment(
@@ -38,7 +297,7 @@ fn fragment(
     output_color = output_color * mesh.color;
 #endif
 
-    // Calculate which cell in the 32x32 grid we're in based on UV coordinates
+    // Calculate which cell in the chunk's grid we're in based on UV coordinates
     // Use floor instead of direct casting to ensure consistent rounding behavior
     let grid_x = u32(floor(mesh.uv.x * material.chunk_size));
     // Flip Y coordinate since chunks are built from bottom-left (0,0)
@@ -48,20 +307,44 @@ fn fragment(
     // Clamp to valid range to prevent out-of-bounds access
     let safe_grid_x = min(grid_x, u32(material.chunk_size) - 1u);
     let safe_grid_y = min(grid_y, u32(material.chunk_size) - 1u);
-    let index = safe_grid_y * u32(material.chunk_size) + safe_grid_x;
     
-    // Get the index value from our indices array
-    let array_index = index / 4u;
-    let component_index = index % 4u;
-    let sprite_index = indices[array_index][component_index];
+    // Get the index value from our indices array, or that of a neighbor drawn over this cell
+    let cell_uv = fract(vec2<f32>(mesh.uv.x, 1.0 - mesh.uv.y) * material.chunk_size);
+    let cell = overlapping_cell(safe_grid_x, safe_grid_y, cell_uv);
+    var atlas_id = (cell >> ATLAS_ID_SHIFT) & ATLAS_ID_MASK;
+    var sprite_index = cell & SPRITE_INDEX_MASK;
+    var frame = animation_frame(cell);
+    // The value of whatever ends up drawn, the particle or the wall behind it
+    var drawn = cell;
+
+    // Liquid fragments outside the smoothed surface are drawn as air
+    if ((cell & LIQUID_CELL_BIT) != 0u && (material.flags & CHUNK_MATERIAL_FLAGS_SMOOTH_FLUIDS_BIT) != 0u) {
+        let grid_pos = vec2<f32>(mesh.uv.x, 1.0 - mesh.uv.y) * material.chunk_size;
+        if (!is_inside_fluid_surface(grid_pos)) {
+            atlas_id = 0u;
+            sprite_index = 0u;
+            frame = 0u;
+        }
+    }
+
+    // Air shows the wall behind it, if there is one
+    var is_background = false;
+    if (sprite_index == 0u) {
+        let wall = background_at(safe_grid_x, safe_grid_y);
+        atlas_id = (wall >> ATLAS_ID_SHIFT) & ATLAS_ID_MASK;
+        sprite_index = wall & SPRITE_INDEX_MASK;
+        frame = animation_frame(wall);
+        drawn = wall;
+        is_background = sprite_index > 0u;
+    }
 
     
     // Transform UVs to sample the correct part of the texture
     let uv = (material.uv_transform * vec3(mesh.uv, 1.0)).xy;
         
-    // The texture is 1 pixel tall with 7 pixels wide (indices 0-4)
+    // The texture is 1 pixel tall with one pixel per sprite index, so derive the width from the atlas
     // Calculate texture coordinates with a small inset to avoid edge artifacts
-    let sprite_width = 1.0 / 9.0;
+    let sprite_width = 1.0 / f32(atlas_width(atlas_id));
     let inset = 0.001; // Small inset to avoid sampling at exact texture boundaries
     
     // Calculate the texture coordinates with inset to avoid edge artifacts
@@ -72,12 +355,42 @@ fn fragment(
     
     // For sprites other than the empty sprite (index 0), adjust the sampling area
     if (sprite_index > 0u) {
+        // Animated sprites sample the current frame, which follows the first in the atlas.
         // Add a small offset to avoid sampling exactly at texture boundaries
-        tex_uv.x = (f32(sprite_index) * sprite_width) + (sprite_width * 0.5);
+        tex_uv.x = (f32(sprite_index + frame) * sprite_width) + (sprite_width * 0.5);
     }
     
-    if ((material.flags & CHUNK_MATERIAL_FLAGS_TEXTURE_BIT) != 0u) {
-        output_color = output_color * textureSample(texture, texture_sampler, tex_uv);
+    // Overrides are keyed by the first frame, so every frame is recolored alike
+    let palette_slot = min(atlas_id, 3u) * SPRITES_PER_ATLAS + min(sprite_index, SPRITES_PER_ATLAS - 1u);
+    let override_color = palette[palette_slot];
+    if (sprite_index > 0u && override_color.a > 0.0) {
+        output_color = output_color * override_color;
+    } else if ((material.flags & CHUNK_MATERIAL_FLAGS_TEXTURE_BIT) != 0u) {
+        output_color = output_color * sample_atlas(atlas_id, tex_uv);
+    }
+
+    if (sprite_index > 0u) {
+        output_color = vec4<f32>(output_color.rgb * variant_brightness(drawn), output_color.a);
+    }
+
+    if (is_background) {
+        output_color = vec4<f32>(output_color.rgb * BACKGROUND_BRIGHTNESS, output_color.a);
+    }
+
+    // Darken the borders between different particle types so terrain features stand out
+    if (!is_background && sprite_index > 0u && (material.flags & CHUNK_MATERIAL_FLAGS_EDGE_DARKENING_BIT) != 0u) {
+        if (is_on_type_edge(safe_grid_x, safe_grid_y, cell, cell_uv)) {
+            output_color = vec4<f32>(output_color.rgb * EDGE_DARKNESS, output_color.a);
+        }
+    }
+
+    // Tint cells with the sky's light as far as it reaches, then shade everything by how
+    // brightly it is lit
+    let grid_pos = vec2<f32>(mesh.uv.x, 1.0 - mesh.uv.y) * material.chunk_size;
+    let tint = mix(vec3<f32>(1.0), material.sky_tint.rgb, sky_exposure_at(grid_pos));
+    output_color = vec4<f32>(output_color.rgb * tint, output_color.a);
+    if ((material.flags & CHUNK_MATERIAL_FLAGS_LIGHTING_BIT) != 0u) {
+        output_color = vec4<f32>(output_color.rgb * brightness_at(grid_pos), output_color.a);
     }
     
 
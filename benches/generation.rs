@@ -0,0 +1,44 @@
+//! Benchmarks for world generation. Run with `cargo bench --bench generation`.
+
+use std::hint::black_box;
+
+use cavernborn::particle::definition::ParticleRegistry;
+use cavernborn::world::biome::Biome;
+use cavernborn::world::Map;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, SeedableRng};
+
+/// Every benchmark uses the same seed so results are comparable across commits.
+const SEED: u64 = 0x5EED;
+
+fn bench_generate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Map::generate");
+    // Generation is slow, so keep the sample count low.
+    group.sample_size(10);
+    for size in [8, 16, 32] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{size}x{size}")),
+            &size,
+            |b, &size| b.iter(|| Map::generate(size, size, black_box(SEED))),
+        );
+    }
+    group.finish();
+}
+
+fn bench_roll_special_particle(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Map::roll_special_particle");
+    // Shallow depths roll nothing, deeper ones pick between several candidates.
+    for depth in [0, 50, 200] {
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
+            let mut rng = StdRng::seed_from_u64(SEED);
+            let particles = ParticleRegistry::default();
+            b.iter(|| {
+                Map::roll_special_particle(black_box(depth), Biome::Plains, &particles, &mut rng)
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_generate, bench_roll_special_particle);
+criterion_main!(benches);
@@ -0,0 +1,68 @@
+//! Benchmarks for the particle simulation and chunk rendering data.
+//! Run with `cargo bench --bench simulation`.
+
+use std::hint::black_box;
+
+use bevy::math::{URect, UVec2};
+use cavernborn::particle::interaction::InteractionRules;
+use cavernborn::particle::{Direction, Liquid, Particle};
+use cavernborn::world::Map;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+/// Every benchmark uses the same seed so results are comparable across commits.
+const SEED: u64 = 0x5EED;
+
+/// A generated 8x8 chunk map with the top half flooded and every chunk active.
+/// Note: Fluids still pick random directions when spreading, so individual ticks vary slightly.
+fn water_heavy_map() -> Map {
+    let mut map = Map::generate(8, 8, SEED);
+    for x in 0..map.width {
+        for y in map.height / 2..map.height {
+            map.set_particle_at(
+                UVec2::new(x, y),
+                Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+            );
+        }
+    }
+    let region = map.compute_active_region(UVec2::new(4, 4), 8);
+    map.set_active_chunks(region);
+    map
+}
+
+fn bench_simulate_active_chunks(c: &mut Criterion) {
+    let rules = InteractionRules::default();
+    c.bench_function("Map::simulate_active_chunks/water_heavy", |b| {
+        b.iter_batched_ref(
+            water_heavy_map,
+            |map| {
+                map.simulate_active_chunks(&rules);
+                map.update_dirty_chunks();
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_to_spritesheet_indices(c: &mut Criterion) {
+    let map = Map::generate(2, 2, SEED);
+    let chunk = map.get_chunk_at(&UVec2::ZERO);
+    c.bench_function("Chunk::to_spritesheet_indices", |b| {
+        b.iter(|| black_box(chunk).to_spritesheet_indices(map.particles()))
+    });
+}
+
+fn bench_query_region(c: &mut Criterion) {
+    let map = water_heavy_map();
+    let rect = URect::new(0, 0, map.width, map.height);
+    c.bench_function("Map::query_region/whole_map", |b| {
+        b.iter(|| black_box(&map).query_region(rect).count())
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_simulate_active_chunks,
+    bench_to_spritesheet_indices,
+    bench_query_region
+);
+criterion_main!(benches);
@@ -0,0 +1,99 @@
+//! Settings read from [`CONFIG_PATH`] at startup.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::render::map_renderer::RENDER_DISTANCE;
+use crate::world::chunk::ACTIVE_CHUNK_RANGE;
+use crate::world::map::SIMULATION_RATE;
+
+/// The file settings are read from, relative to the working directory.
+pub const CONFIG_PATH: &str = "cavernborn.toml";
+
+/// Settings for the window, the world and the simulation. Read from [`CONFIG_PATH`] at startup,
+/// where every setting is optional and missing ones keep their defaults, e.g.
+///
+/// ```toml
+/// window_width = 1280.0
+/// map_width = 40
+/// simulation_rate = 60.0
+/// ```
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The width of the window in logical pixels.
+    pub window_width: f32,
+    /// The height of the window in logical pixels.
+    pub window_height: f32,
+    /// The width of the starting map, in chunks.
+    pub map_width: u32,
+    /// The height of the starting map, in chunks.
+    pub map_height: u32,
+    /// How many simulation ticks run per second.
+    pub simulation_rate: f64,
+    /// How far around the player particles are simulated, in chunks.
+    pub active_chunk_range: u32,
+    /// How far around the player chunks are drawn, in chunks.
+    pub render_distance: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            window_width: 1600.0,
+            window_height: 900.0,
+            map_width: 20,
+            map_height: 20,
+            simulation_rate: SIMULATION_RATE,
+            active_chunk_range: ACTIVE_CHUNK_RANGE,
+            render_distance: RENDER_DISTANCE,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the settings from `path`. A missing file gives the default settings, while a file
+    /// that can't be read or holds invalid settings is an error.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                Self::from_toml(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Couldn't read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Parses settings written as TOML, see [`Config`].
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let config: Config = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        if config.window_width <= 0.0 || config.window_height <= 0.0 {
+            return Err("the window size must be positive".to_string());
+        }
+        if config.map_width == 0 || config.map_height == 0 {
+            return Err("the map must be at least one chunk wide and tall".to_string());
+        }
+        if !(config.simulation_rate.is_finite() && config.simulation_rate > 0.0) {
+            return Err("the simulation rate must be positive".to_string());
+        }
+        Ok(config)
+    }
+
+    /// How far around the player chunks of streamed maps are kept loaded. One chunk beyond both
+    /// the render distance and the active range, so simulated particles never spill into
+    /// unloaded chunks.
+    pub fn stream_chunk_range(&self) -> u32 {
+        self.render_distance.max(self.active_chunk_range) + 1
+    }
+
+    /// The most chunks generated maps keep loaded at once. Twice the chunks in the streamed range,
+    /// so walking back and forth doesn't regenerate chunks that were just left behind.
+    pub fn max_loaded_chunks(&self) -> usize {
+        let side = 2 * self.stream_chunk_range() as usize + 1;
+        2 * side * side
+    }
+}
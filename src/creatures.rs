@@ -0,0 +1,436 @@
+//! Simple cave fauna that live in the map's particles.
+//!
+//! Creatures spawn in active chunks at the depths their kind lives at, see
+//! [`CreatureKind::depths`]. Bats flutter through open air and turn around at walls, while
+//! slimes hop along the ground and bounce off it. Both steer clear of lava, but acid and lava
+//! still hurt them on contact. Slimes float in liquids, and bats drown in them once their
+//! [`Breath`] runs out.
+
+use std::hash::Hasher;
+use std::ops::Range;
+
+use bevy::prelude::*;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::health::{find_hazard_contact, Health};
+use crate::particle::{Liquid, Particle, PARTICLE_SIZE};
+use crate::swimming::{measure_submersion, Breath, Submersion, DROWNING_DAMAGE};
+use crate::utils::coords::{get_chunk_from_world_pos, screen_to_world, world_to_screen};
+use crate::utils::hash::StableHasher;
+use crate::world::chunk::CHUNK_SIZE;
+use crate::world::Map;
+
+/// The most creatures alive at once.
+pub const MAX_CREATURES: usize = 12;
+/// How often a creature tries to spawn, in seconds.
+const SPAWN_INTERVAL: f32 = 2.0;
+/// How many cells are tried for each spawn before giving up until the next one.
+const SPAWN_ATTEMPTS: u32 = 16;
+/// How fast creatures fall, in cells per second squared.
+pub const CREATURE_GRAVITY: f32 = 60.0;
+/// How fast a fully submerged slime floats up, in cells per second squared.
+const SLIME_BUOYANCY: f32 = 90.0;
+/// How much of its speed a slime keeps when it bounces off the ground or a wall.
+const SLIME_BOUNCE: f32 = 0.4;
+/// The slowest fall that still bounces a slime back up, in cells per second.
+const SLIME_MIN_BOUNCE_SPEED: f32 = 10.0;
+/// How fast a slime hops up, in cells per second.
+const SLIME_HOP_SPEED: f32 = 25.0;
+/// How long a slime rests on the ground between hops, in seconds.
+const SLIME_HOP_SECS: f32 = 1.5;
+/// The chance per second that a bat flies off in a new direction.
+const BAT_TURN_CHANCE: f32 = 0.5;
+/// How many seconds a bat can stay under before drowning.
+const BAT_BREATH: f32 = 3.0;
+/// How many cells away creatures keep from lava.
+pub const LAVA_AVOID_DISTANCE: f32 = 3.0;
+
+/// Plugin that spawns creatures around the player and simulates them.
+pub struct CreaturesPlugin;
+
+impl Plugin for CreaturesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (spawn_creatures, move_creatures, hurt_creatures).chain(),
+        );
+    }
+}
+
+/// The kinds of creatures living in the caves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter)]
+pub enum CreatureKind {
+    /// Flies through open air, turning around at walls.
+    Bat,
+    /// Hops along the ground and floats in liquids.
+    Slime,
+}
+
+impl CreatureKind {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            CreatureKind::Bat => "Bat",
+            CreatureKind::Slime => "Slime",
+        }
+    }
+
+    /// The width and height of the creature, in cells.
+    pub fn size(&self) -> f32 {
+        match self {
+            CreatureKind::Bat => 3.0,
+            CreatureKind::Slime => 4.0,
+        }
+    }
+
+    /// How fast the creature gets around, in cells per second.
+    pub fn speed(&self) -> f32 {
+        match self {
+            CreatureKind::Bat => 15.0,
+            CreatureKind::Slime => 10.0,
+        }
+    }
+
+    pub fn max_health(&self) -> f32 {
+        match self {
+            CreatureKind::Bat => 10.0,
+            CreatureKind::Slime => 30.0,
+        }
+    }
+
+    /// How far below the surface the creature spawns, in particles.
+    pub fn depths(&self) -> Range<u32> {
+        match self {
+            CreatureKind::Bat => 10..300,
+            CreatureKind::Slime => 60..u32::MAX,
+        }
+    }
+
+    /// Whether the creature floats in liquids rather than drowning in them.
+    pub fn swims(&self) -> bool {
+        matches!(self, CreatureKind::Slime)
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            CreatureKind::Bat => Color::srgb(0.35, 0.25, 0.3),
+            CreatureKind::Slime => Color::srgb(0.3, 0.8, 0.3),
+        }
+    }
+}
+
+/// A creature and how it's moving.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Creature {
+    pub kind: CreatureKind,
+    /// In cells per second.
+    pub velocity: Vec2,
+    /// How many more seconds a slime rests before its next hop.
+    pub rest_secs: f32,
+}
+
+impl Creature {
+    pub fn new(kind: CreatureKind) -> Self {
+        Self {
+            kind,
+            velocity: Vec2::ZERO,
+            rest_secs: SLIME_HOP_SECS,
+        }
+    }
+
+    /// The corners of the square the creature covers around `center`, in world coordinates.
+    pub fn bounds(&self, center: Vec2) -> (Vec2, Vec2) {
+        let half_size = Vec2::splat(self.kind.size() / 2.0);
+        (center - half_size, center + half_size)
+    }
+
+    /// Move the creature by `secs` from `center`, in world coordinates, colliding with the map.
+    /// Returns where it ended up.
+    pub fn step(&mut self, map: &Map, center: Vec2, secs: f32, rng: &mut impl Rng) -> Vec2 {
+        let (min, max) = self.bounds(center);
+        let submersion = measure_submersion(map, min, max);
+        match self.kind {
+            CreatureKind::Bat => self.fly(map, center, submersion, secs, rng),
+            CreatureKind::Slime => self.hop(map, center, submersion, secs, rng),
+        }
+    }
+
+    fn fly(
+        &mut self,
+        map: &Map,
+        center: Vec2,
+        submersion: Submersion,
+        secs: f32,
+        rng: &mut impl Rng,
+    ) -> Vec2 {
+        if self.velocity == Vec2::ZERO || rng.random::<f32>() < BAT_TURN_CHANCE * secs {
+            self.velocity = random_direction(rng) * self.kind.speed();
+        }
+
+        let next = center + self.velocity * submersion.speed_factor() * secs;
+        let (min, max) = self.bounds(next);
+        if collides(map, min, max) || lava_near(map, min, max) {
+            // Fly back the way it came, at a slight angle so it doesn't get stuck bouncing.
+            let angle = rng.random_range(-0.5..0.5);
+            self.velocity = Vec2::from_angle(std::f32::consts::PI + angle).rotate(self.velocity);
+            return center;
+        }
+        next
+    }
+
+    fn hop(
+        &mut self,
+        map: &Map,
+        center: Vec2,
+        submersion: Submersion,
+        secs: f32,
+        rng: &mut impl Rng,
+    ) -> Vec2 {
+        self.velocity.y += (SLIME_BUOYANCY * submersion.fraction - CREATURE_GRAVITY) * secs;
+
+        let (min, max) = self.bounds(center);
+        let on_ground = collides(map, min - Vec2::Y * 0.5, max - Vec2::Y * 0.5);
+        if on_ground && self.velocity.y <= 0.0 {
+            self.velocity.x = 0.0;
+            self.rest_secs -= secs;
+            if self.rest_secs <= 0.0 {
+                self.rest_secs = SLIME_HOP_SECS;
+                let mut direction = if rng.random() { 1.0 } else { -1.0 };
+                // Don't hop into lava.
+                let landing = Vec2::X * direction * self.kind.size() * 2.0;
+                if lava_near(map, min + landing, max + landing) {
+                    direction = -direction;
+                }
+                self.velocity = Vec2::new(direction * self.kind.speed(), SLIME_HOP_SPEED);
+            }
+        }
+
+        let mut position = center;
+        let next = position + Vec2::X * self.velocity.x * submersion.speed_factor() * secs;
+        let (min, max) = self.bounds(next);
+        if collides(map, min, max) {
+            self.velocity.x *= -SLIME_BOUNCE;
+        } else {
+            position = next;
+        }
+
+        let next = position + Vec2::Y * self.velocity.y * secs;
+        let (min, max) = self.bounds(next);
+        if collides(map, min, max) {
+            self.velocity.y = if self.velocity.y < -SLIME_MIN_BOUNCE_SPEED {
+                -self.velocity.y * SLIME_BOUNCE
+            } else {
+                0.0
+            };
+        } else {
+            position = next;
+        }
+        position
+    }
+}
+
+fn random_direction(rng: &mut impl Rng) -> Vec2 {
+    Vec2::from_angle(rng.random_range(0.0..std::f32::consts::TAU))
+}
+
+/// Whether particles creatures can't move through fill any of the cells overlapping the
+/// rectangle from `min` to `max`, in world coordinates. Liquids, gases and fire can be moved
+/// through, and the edges of the map block like walls.
+pub fn collides(map: &Map, min: Vec2, max: Vec2) -> bool {
+    if min.cmplt(Vec2::ZERO).any() || max.x > map.width as f32 || max.y > map.height as f32 {
+        return true;
+    }
+    cells_overlapping(min, max).any(|position| {
+        map.get_particle_at(position).is_some_and(|particle| {
+            !matches!(
+                particle,
+                Particle::Liquid(_) | Particle::Gas(_) | Particle::Fire(_)
+            )
+        })
+    })
+}
+
+/// Whether there's lava within [`LAVA_AVOID_DISTANCE`] of the rectangle from `min` to `max`, in
+/// world coordinates.
+pub fn lava_near(map: &Map, min: Vec2, max: Vec2) -> bool {
+    let margin = Vec2::splat(LAVA_AVOID_DISTANCE);
+    cells_overlapping((min - margin).max(Vec2::ZERO), max + margin).any(|position| {
+        matches!(
+            map.get_particle_at(position),
+            Some(Particle::Liquid(Liquid::Lava(_)))
+        )
+    })
+}
+
+fn cells_overlapping(min: Vec2, max: Vec2) -> impl Iterator<Item = UVec2> {
+    let (x_start, y_start) = (min.x.floor().max(0.0) as u32, min.y.floor().max(0.0) as u32);
+    let (x_end, y_end) = (max.x.ceil().max(0.0) as u32, max.y.ceil().max(0.0) as u32);
+    (x_start..x_end).flat_map(move |x| (y_start..y_end).map(move |y| UVec2::new(x, y)))
+}
+
+/// Where a `kind` creature could spawn at `position`, in world coordinates: deep enough, in open
+/// air, and on the ground for creatures that don't fly. Returns the center of the creature.
+pub fn spawn_position(map: &Map, kind: CreatureKind, position: UVec2) -> Option<Vec2> {
+    let depth = map.depth_at(position)?;
+    if !kind.depths().contains(&depth) {
+        return None;
+    }
+
+    let half_size = kind.size() / 2.0;
+    let center = Vec2::new(position.x as f32 + half_size, position.y as f32 + half_size);
+    let (min, max) = Creature::new(kind).bounds(center);
+    let on_ground = collides(map, min - Vec2::Y, max - Vec2::Y);
+    let fits = !collides(map, min, max)
+        && measure_submersion(map, min, max).fraction == 0.0
+        && !lava_near(map, min, max);
+    (fits && (kind == CreatureKind::Bat || on_ground)).then_some(center)
+}
+
+fn creature_bundle(kind: CreatureKind, center: Vec2, map: &Map) -> impl Bundle {
+    let size = kind.size() * PARTICLE_SIZE as f32;
+    let screen = world_to_screen(center, map.width, map.height);
+    let breath = if kind.swims() {
+        Breath::default()
+    } else {
+        Breath {
+            current: BAT_BREATH,
+            max: BAT_BREATH,
+        }
+    };
+    (
+        Creature::new(kind),
+        Name::new(kind.display_name()),
+        Health::new(kind.max_health()),
+        breath,
+        Sprite {
+            color: kind.color(),
+            custom_size: Some(Vec2::splat(size)),
+            ..default()
+        },
+        Transform::from_xyz(screen.x, screen.y, 9.0),
+    )
+}
+
+/// The random number generator for creature decisions. Like [`Map::particle_rng`], it only
+/// depends on the simulation seed and tick, plus `stream` (whose decisions they are) and
+/// `round` (how often the system asked before). The round keeps creatures from repeating the
+/// same roll every frame while the simulation is paused.
+fn creature_rng(map: &Map, stream: u64, round: u64) -> SmallRng {
+    let mut hasher = StableHasher::default();
+    hasher.write(b"creatures");
+    hasher.write(&map.simulation_seed().to_le_bytes());
+    hasher.write(&map.simulation_tick().to_le_bytes());
+    hasher.write(&stream.to_le_bytes());
+    hasher.write(&round.to_le_bytes());
+    SmallRng::seed_from_u64(hasher.finish())
+}
+
+/// Picks a creature and where to spawn it in the active chunks, or `None` if no attempt found
+/// a spot. `round` counts the spawns tried before, and the same map and round always roll the
+/// same spawn.
+pub fn roll_spawn(map: &Map, round: u64) -> Option<(CreatureKind, Vec2)> {
+    let mut chunks: Vec<UVec2> = map.active_chunks.iter().copied().collect();
+    if chunks.is_empty() {
+        return None;
+    }
+    // Set order varies between runs.
+    chunks.sort_by_key(|chunk| (chunk.x, chunk.y));
+
+    let mut rng = creature_rng(map, u64::MAX, round);
+    let kinds: Vec<CreatureKind> = CreatureKind::iter().collect();
+    for _ in 0..SPAWN_ATTEMPTS {
+        let kind = kinds[rng.random_range(0..kinds.len())];
+        let chunk = chunks[rng.random_range(0..chunks.len())];
+        let position = chunk * CHUNK_SIZE
+            + UVec2::new(
+                rng.random_range(0..CHUNK_SIZE),
+                rng.random_range(0..CHUNK_SIZE),
+            );
+        if let Some(center) = spawn_position(map, kind, position) {
+            return Some((kind, center));
+        }
+    }
+    None
+}
+
+/// System that now and then spawns a creature somewhere in the active chunks.
+pub fn spawn_creatures(
+    mut commands: Commands,
+    time: Res<Time>,
+    map: Option<Res<Map>>,
+    creature_query: Query<(), With<Creature>>,
+    mut timer: Local<f32>,
+    mut round: Local<u64>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+    *timer += time.delta_secs();
+    if *timer < SPAWN_INTERVAL || creature_query.iter().count() >= MAX_CREATURES {
+        return;
+    }
+    *timer = 0.0;
+
+    let spawn = roll_spawn(&map, *round);
+    *round += 1;
+    if let Some((kind, center)) = spawn {
+        debug!("Spawning a {} at {}", kind.display_name(), center);
+        commands.spawn(creature_bundle(kind, center, &map));
+    }
+}
+
+/// System that moves creatures through the map.
+pub fn move_creatures(
+    time: Res<Time>,
+    map: Option<Res<Map>>,
+    mut creature_query: Query<(Entity, &mut Creature, &mut Transform)>,
+    mut round: Local<u64>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+
+    for (entity, mut creature, mut transform) in &mut creature_query {
+        let mut rng = creature_rng(&map, entity.to_bits(), *round);
+        let center = screen_to_world(transform.translation.truncate(), map.width, map.height);
+        let moved = creature.step(&map, center, time.delta_secs(), &mut rng);
+        let screen = world_to_screen(moved, map.width, map.height);
+        transform.translation.x = screen.x;
+        transform.translation.y = screen.y;
+    }
+    *round += 1;
+}
+
+/// System that hurts creatures touching hazards or out of breath, and despawns the ones that
+/// died or left the active chunks.
+pub fn hurt_creatures(
+    mut commands: Commands,
+    time: Res<Time>,
+    map: Option<Res<Map>>,
+    mut creature_query: Query<(Entity, &Creature, &Transform, &mut Health, &mut Breath)>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+
+    let secs = time.delta_secs();
+    for (entity, creature, transform, mut health, mut breath) in &mut creature_query {
+        let center = screen_to_world(transform.translation.truncate(), map.width, map.height);
+        let (min, max) = creature.bounds(center);
+        health.tick(secs);
+        if let Some(contact) = find_hazard_contact(&map, min, max) {
+            health.take_hit(contact.hazard.damage);
+        }
+        let head_under = measure_submersion(&map, min, max).head_under;
+        if !creature.kind.swims() && breath.tick(head_under, secs) {
+            health.take_hit(DROWNING_DAMAGE);
+        }
+
+        let chunk = get_chunk_from_world_pos(center.as_uvec2());
+        if health.is_dead() || !map.active_chunks.contains(&chunk) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
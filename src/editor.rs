@@ -0,0 +1,344 @@
+//! A world editor for authoring test scenarios by hand.
+//!
+//! F10 opens the editor, which pauses the simulation and swaps the mouse's digging and placing for
+//! area tools, see [`EditorTool`]. Tab cycles through the tools. Left click works with the
+//! [`SelectedParticle`], right click with air. Every operation is a single stroke in the
+//! [`EditHistory`], so Ctrl+Z undoes it whole. Closing the editor resumes the simulation, unless
+//! it was paused before the editor opened.
+
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::particle::{Particle, PARTICLE_SIZE};
+use crate::player::SelectedParticle;
+use crate::utils::coords::{cursor_cell, get_chunk_from_world_pos, world_to_screen};
+use crate::world::history::EditHistory;
+use crate::world::map::SimulationControl;
+use crate::world::Map;
+
+/// The most cells a single flood fill sets, so clicking into the open sky or a huge cave doesn't
+/// freeze the game. Larger regions are filled outwards from the clicked cell up to this size.
+pub const MAX_FLOOD_FILL: usize = 16_384;
+
+/// Plugin that adds the world editor, see the [module docs](self).
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorState>().add_systems(
+            Update,
+            (
+                toggle_editor,
+                editor_hotkeys,
+                use_editor_tool,
+                draw_editor_preview,
+            )
+                .chain()
+                .run_if(resource_exists::<Map>),
+        );
+    }
+}
+
+/// The area tools of the editor.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EditorTool {
+    /// Drag out a rectangle to fill it.
+    #[default]
+    Rectangle,
+    /// Click a cell to fill the region of the same particle it's part of.
+    FloodFill,
+    /// Drag out a rectangle to copy it.
+    Copy,
+    /// Click to paste the copied region with its bottom left corner at the cursor.
+    Paste,
+}
+
+impl EditorTool {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            EditorTool::Rectangle => "Rectangle",
+            EditorTool::FloodFill => "Flood fill",
+            EditorTool::Copy => "Copy",
+            EditorTool::Paste => "Paste",
+        }
+    }
+
+    /// The tool after this one when cycling through them.
+    pub fn next(&self) -> Self {
+        match self {
+            EditorTool::Rectangle => EditorTool::FloodFill,
+            EditorTool::FloodFill => EditorTool::Copy,
+            EditorTool::Copy => EditorTool::Paste,
+            EditorTool::Paste => EditorTool::Rectangle,
+        }
+    }
+}
+
+/// A copied region of the map, see [`copy_region`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clipboard {
+    pub size: UVec2,
+    /// The particle and wall of every cell, row by row from the bottom left.
+    pub cells: Vec<(Option<Particle>, Option<Particle>)>,
+}
+
+/// Whether the editor is open, and what it's doing.
+#[derive(Resource, Debug, Default)]
+pub struct EditorState {
+    pub enabled: bool,
+    pub tool: EditorTool,
+    /// Where the rectangle being dragged out started.
+    pub drag_start: Option<UVec2>,
+    pub clipboard: Option<Clipboard>,
+    /// Whether the simulation was paused before the editor opened, to leave it that way after.
+    was_paused: bool,
+}
+
+/// Run condition that holds while the editor is closed, or isn't part of the app at all.
+pub fn editor_closed(editor: Option<Res<EditorState>>) -> bool {
+    editor.is_none_or(|editor| !editor.enabled)
+}
+
+/// The rectangle between two opposite corner cells, both included.
+pub fn selection_rect(corner: UVec2, opposite: UVec2) -> URect {
+    URect::from_corners(corner.min(opposite), corner.max(opposite) + UVec2::ONE)
+}
+
+/// Sets every cell of `rect` to `particle`, clipped to the map. Returns how many cells were set.
+pub fn fill_rect(
+    map: &mut Map,
+    history: &mut EditHistory,
+    rect: URect,
+    particle: Option<Particle>,
+) -> usize {
+    let rect = rect.intersect(URect::new(0, 0, map.width, map.height));
+    for x in rect.min.x..rect.max.x {
+        for y in rect.min.y..rect.max.y {
+            history.set_particle(map, UVec2::new(x, y), particle);
+        }
+    }
+    (rect.width() * rect.height()) as usize
+}
+
+/// Sets the region of cells holding the same particle as `start`, or air, that's connected to it
+/// through their sides to `particle`. At most [`MAX_FLOOD_FILL`] cells are set, nearest to
+/// `start` first. Returns how many cells were set, which is none if the region already holds
+/// `particle`.
+pub fn flood_fill(
+    map: &mut Map,
+    history: &mut EditHistory,
+    start: UVec2,
+    particle: Option<Particle>,
+) -> usize {
+    if !map.within_bounds(start) {
+        return 0;
+    }
+    map.load_chunk(get_chunk_from_world_pos(start));
+    let target = map.get_particle_at(start);
+    if target == particle {
+        return 0;
+    }
+
+    let mut visited = HashSet::from([start]);
+    let mut pending = VecDeque::from([start]);
+    let mut filled = 0;
+    while let Some(position) = pending.pop_front() {
+        if filled == MAX_FLOOD_FILL {
+            info!("Flood fill stopped at {} cells", MAX_FLOOD_FILL);
+            break;
+        }
+        history.set_particle(map, position, particle);
+        filled += 1;
+
+        for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            let neighbor = position.as_ivec2() + offset;
+            if neighbor.cmplt(IVec2::ZERO).any() {
+                continue;
+            }
+            let neighbor = neighbor.as_uvec2();
+            if !map.within_bounds(neighbor) {
+                continue;
+            }
+            map.load_chunk(get_chunk_from_world_pos(neighbor));
+            if map.get_particle_at(neighbor) == target && visited.insert(neighbor) {
+                pending.push_back(neighbor);
+            }
+        }
+    }
+    filled
+}
+
+/// Copies the particles and walls of `rect`, clipped to the map, including the unloaded chunks of
+/// streamed maps.
+pub fn copy_region(map: &Map, rect: URect) -> Clipboard {
+    let rect = rect.intersect(URect::new(0, 0, map.width, map.height));
+    let mut cells = vec![(None, None); (rect.width() * rect.height()) as usize];
+    map.for_each_cell_in(rect, |position, particle, wall| {
+        let offset = position - rect.min;
+        cells[(offset.y * rect.width() + offset.x) as usize] = (particle, wall);
+    });
+    Clipboard {
+        size: rect.size(),
+        cells,
+    }
+}
+
+/// Pastes `clipboard` with its bottom left corner at `origin`, replacing the particles and walls
+/// there, empty cells included. Cells that fall off the map are dropped. Returns how many cells
+/// were pasted.
+pub fn paste_region(
+    map: &mut Map,
+    history: &mut EditHistory,
+    clipboard: &Clipboard,
+    origin: UVec2,
+) -> usize {
+    let mut pasted = 0;
+    for (index, &(particle, wall)) in clipboard.cells.iter().enumerate() {
+        let index = index as u32;
+        let position = origin + UVec2::new(index % clipboard.size.x, index / clipboard.size.x);
+        if !map.within_bounds(position) {
+            continue;
+        }
+        history.set_particle(map, position, particle);
+        history.set_wall(map, position, wall);
+        pasted += 1;
+    }
+    pasted
+}
+
+// Open and close the editor with F10, pausing the simulation while it's open
+fn toggle_editor(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut editor: ResMut<EditorState>,
+    control: Option<ResMut<SimulationControl>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F10) {
+        return;
+    }
+    editor.enabled = !editor.enabled;
+    editor.drag_start = None;
+
+    if let Some(mut control) = control {
+        if editor.enabled {
+            editor.was_paused = control.paused;
+            if !control.paused {
+                control.toggle_pause();
+            }
+        } else if control.paused && !editor.was_paused {
+            control.toggle_pause();
+        }
+    }
+    info!(
+        "Editor {}",
+        if editor.enabled { "opened" } else { "closed" }
+    );
+}
+
+// Cycle through the tools with Tab
+fn editor_hotkeys(keyboard: Res<ButtonInput<KeyCode>>, mut editor: ResMut<EditorState>) {
+    if editor.enabled && keyboard.just_pressed(KeyCode::Tab) {
+        editor.tool = editor.tool.next();
+        editor.drag_start = None;
+        info!("Editor tool: {}", editor.tool.display_name());
+    }
+}
+
+// Apply the current tool with the mouse
+fn use_editor_tool(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    selected: Option<Res<SelectedParticle>>,
+    mut editor: ResMut<EditorState>,
+    mut map: ResMut<Map>,
+    mut history: ResMut<EditHistory>,
+) {
+    if !editor.enabled {
+        return;
+    }
+    let Some(cell) = cursor_cell(&windows, &camera_q, map.width, map.height) else {
+        return;
+    };
+    let cell = cell.min(UVec2::new(map.width, map.height) - UVec2::ONE);
+    // Left click uses the selected particle, right click clears.
+    let button = if mouse_input.just_pressed(MouseButton::Left)
+        || mouse_input.just_released(MouseButton::Left)
+    {
+        MouseButton::Left
+    } else {
+        MouseButton::Right
+    };
+    let particle = match button {
+        MouseButton::Left => selected.map(|selected| selected.particle),
+        _ => None,
+    };
+    let pressed = mouse_input.just_pressed(button);
+    let released = mouse_input.just_released(button);
+
+    let changed = match editor.tool {
+        EditorTool::Rectangle | EditorTool::Copy => {
+            if pressed {
+                editor.drag_start = Some(cell);
+            }
+            let Some(start) = editor.drag_start.filter(|_| released) else {
+                return;
+            };
+            editor.drag_start = None;
+            let rect = selection_rect(start, cell);
+            if editor.tool == EditorTool::Copy {
+                editor.clipboard = Some(copy_region(&map, rect));
+                info!("Copied {}x{} cells", rect.width(), rect.height());
+                0
+            } else {
+                fill_rect(&mut map, &mut history, rect, particle)
+            }
+        }
+        EditorTool::FloodFill if pressed => flood_fill(&mut map, &mut history, cell, particle),
+        EditorTool::Paste if pressed => match &editor.clipboard {
+            Some(clipboard) => paste_region(&mut map, &mut history, clipboard, cell),
+            None => {
+                info!("Nothing to paste, copy a region first");
+                0
+            }
+        },
+        _ => 0,
+    };
+    if changed > 0 {
+        history.end_stroke();
+        debug!("{} changed {} cells", editor.tool.display_name(), changed);
+    }
+}
+
+// Outline the rectangle being dragged out, or where a paste would land
+fn draw_editor_preview(
+    mut gizmos: Gizmos,
+    editor: Res<EditorState>,
+    windows: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    map: Res<Map>,
+) {
+    if !editor.enabled {
+        return;
+    }
+    let Some(cell) = cursor_cell(&windows, &camera_q, map.width, map.height) else {
+        return;
+    };
+    let rect = match (editor.tool, editor.drag_start, &editor.clipboard) {
+        (EditorTool::Rectangle | EditorTool::Copy, Some(start), _) => selection_rect(start, cell),
+        (EditorTool::Paste, _, Some(clipboard)) => URect::from_corners(cell, cell + clipboard.size),
+        _ => selection_rect(cell, cell),
+    };
+
+    let min = world_to_screen(rect.min.as_vec2(), map.width, map.height);
+    let max = world_to_screen(rect.max.as_vec2(), map.width, map.height);
+    let color = match editor.tool {
+        EditorTool::Copy | EditorTool::Paste => Color::srgba(0.4, 0.8, 1.0, 0.8),
+        _ => Color::srgba(1.0, 0.9, 0.3, 0.8),
+    };
+    gizmos.rect_2d(
+        (min + max) / 2.0,
+        (max - min).max(Vec2::splat(PARTICLE_SIZE as f32)),
+        color,
+    );
+}
@@ -0,0 +1,320 @@
+//! Player health, and the damage hazards like lava and acid deal to it.
+//!
+//! Touching a hazard deals a hit, knocks the player away from it and makes them invulnerable for
+//! [`INVULNERABILITY_SECS`], so standing in a hazard hurts a hit at a time rather than every
+//! frame. Lava also sets the player burning, which keeps dealing smaller hits for a while after
+//! they get out. A player whose health runs out respawns at the surface, see [`spawn_point`].
+
+use bevy::prelude::*;
+
+use crate::particle::{Liquid, Particle, PARTICLE_SIZE};
+use crate::player::{player_world_bounds, Player, PLAYER_SIZE};
+use crate::utils::coords::{screen_to_world, world_to_screen};
+use crate::world::Map;
+
+/// The health a player starts and respawns with.
+pub const MAX_HEALTH: f32 = 100.0;
+/// How long a player can't be hit again after taking a hit, in seconds.
+pub const INVULNERABILITY_SECS: f32 = 0.5;
+/// The damage of each hit dealt while burning.
+pub const BURN_DAMAGE: f32 = 4.0;
+/// How fast a hit knocks the player away from the hazard, in pixels per second.
+pub const KNOCKBACK_SPEED: f32 = 300.0;
+/// How quickly knockback wears off, as the fraction of its speed lost per second.
+const KNOCKBACK_DAMPING: f32 = 8.0;
+/// How many cells above the surface the player respawns.
+const SPAWN_CLEARANCE: u32 = PLAYER_SIZE / PARTICLE_SIZE;
+
+const HEALTH_BAR_WIDTH: f32 = 200.0;
+const HEALTH_BAR_COLOR: Color = Color::srgb(0.8, 0.1, 0.1);
+const HEALTH_BAR_BACKGROUND: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+
+/// Plugin that damages players touching hazards and shows their health.
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_health_bar).add_systems(
+            Update,
+            (
+                apply_hazard_damage,
+                apply_knockback,
+                respawn_dead_players,
+                update_health_bar,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// How much a player has left before they die.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+    /// How many more seconds the player can't be hit.
+    pub invulnerable_secs: f32,
+    /// How many more seconds the player keeps burning, see [`BURN_DAMAGE`].
+    pub burning_secs: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            current: MAX_HEALTH,
+            max: MAX_HEALTH,
+            invulnerable_secs: 0.0,
+            burning_secs: 0.0,
+        }
+    }
+}
+
+impl Health {
+    /// Full health out of `max`.
+    pub fn new(max: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            ..default()
+        }
+    }
+
+    /// Deal `damage`, unless the player is still invulnerable from the last hit or already dead.
+    /// Returns whether the hit landed.
+    pub fn take_hit(&mut self, damage: f32) -> bool {
+        if self.invulnerable_secs > 0.0 || self.is_dead() {
+            return false;
+        }
+        self.current = (self.current - damage).max(0.0);
+        self.invulnerable_secs = INVULNERABILITY_SECS;
+        true
+    }
+
+    /// Count down invulnerability and burning by `secs`.
+    pub fn tick(&mut self, secs: f32) {
+        self.invulnerable_secs = (self.invulnerable_secs - secs).max(0.0);
+        self.burning_secs = (self.burning_secs - secs).max(0.0);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    /// How much health is left, from 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        (self.current / self.max).clamp(0.0, 1.0)
+    }
+
+    /// Back to full health, as after respawning.
+    pub fn restore(&mut self) {
+        *self = Self {
+            max: self.max,
+            current: self.max,
+            ..default()
+        };
+    }
+}
+
+/// Pushes the player around after a hit, wearing off over time.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Knockback {
+    /// In pixels per second.
+    pub velocity: Vec2,
+}
+
+/// The damage touching a particle deals.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hazard {
+    /// The damage of each hit.
+    pub damage: f32,
+    /// How long the player keeps burning after touching it, in seconds.
+    pub burn_secs: f32,
+}
+
+impl Hazard {
+    /// The hazard `particle` poses, if any. Lava burns, and acid corrodes.
+    pub fn of(particle: Particle) -> Option<Self> {
+        match particle {
+            Particle::Liquid(Liquid::Lava(_)) => Some(Self {
+                damage: 20.0,
+                burn_secs: 3.0,
+            }),
+            Particle::Liquid(Liquid::Acid(_)) => Some(Self {
+                damage: 10.0,
+                burn_secs: 0.0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The hazards a player touches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HazardContact {
+    /// The worst of the hazards touched: the most damage and the longest burn.
+    pub hazard: Hazard,
+    /// The middle of the hazardous cells touched, in world coordinates.
+    pub center: Vec2,
+}
+
+/// Finds the hazards in the cells overlapping the rectangle from `min` to `max`, in world
+/// coordinates.
+pub fn find_hazard_contact(map: &Map, min: Vec2, max: Vec2) -> Option<HazardContact> {
+    let (x_start, y_start) = (min.x.floor().max(0.0) as u32, min.y.floor().max(0.0) as u32);
+    let x_end = (max.x.ceil().max(0.0) as u32).min(map.width);
+    let y_end = (max.y.ceil().max(0.0) as u32).min(map.height);
+
+    let mut worst: Option<Hazard> = None;
+    let mut sum = Vec2::ZERO;
+    let mut count = 0;
+    for x in x_start..x_end {
+        for y in y_start..y_end {
+            let Some(hazard) = map.get_particle_at(UVec2::new(x, y)).and_then(Hazard::of) else {
+                continue;
+            };
+            worst = Some(match worst {
+                Some(worst) => Hazard {
+                    damage: worst.damage.max(hazard.damage),
+                    burn_secs: worst.burn_secs.max(hazard.burn_secs),
+                },
+                None => hazard,
+            });
+            sum += Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            count += 1;
+        }
+    }
+
+    worst.map(|hazard| HazardContact {
+        hazard,
+        center: sum / count as f32,
+    })
+}
+
+/// Where players respawn in screen coordinates: just above the surface in the middle of the map,
+/// or the middle of the map if it has no surface.
+pub fn spawn_point(map: &Map) -> Vec2 {
+    let x = map.width / 2;
+    let y = map
+        .surface_profile()
+        .get(x as usize)
+        .map_or(map.height / 2, |surface| surface + SPAWN_CLEARANCE)
+        .min(map.height.saturating_sub(1));
+    world_to_screen(
+        Vec2::new(x as f32 + 0.5, y as f32 + 0.5),
+        map.width,
+        map.height,
+    )
+}
+
+/// System that hits players touching hazards or still burning, knocking them away from hazards.
+pub fn apply_hazard_damage(
+    time: Res<Time>,
+    map: Option<Res<Map>>,
+    mut player_query: Query<(&Transform, &mut Health, &mut Knockback), With<Player>>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+
+    for (transform, mut health, mut knockback) in &mut player_query {
+        health.tick(time.delta_secs());
+
+        let center = transform.translation.truncate();
+        let (min, max) = player_world_bounds(center, &map);
+        match find_hazard_contact(&map, min, max) {
+            Some(contact) => {
+                health.burning_secs = health.burning_secs.max(contact.hazard.burn_secs);
+                if health.take_hit(contact.hazard.damage) {
+                    let player_pos = screen_to_world(center, map.width, map.height);
+                    let away = (player_pos - contact.center).normalize_or(Vec2::Y);
+                    knockback.velocity = away * KNOCKBACK_SPEED;
+                }
+            }
+            None if health.burning_secs > 0.0 => {
+                health.take_hit(BURN_DAMAGE);
+            }
+            None => {}
+        }
+    }
+}
+
+/// System that moves players by their knockback as it wears off.
+pub fn apply_knockback(time: Res<Time>, mut query: Query<(&mut Transform, &mut Knockback)>) {
+    let secs = time.delta_secs();
+    for (mut transform, mut knockback) in &mut query {
+        if knockback.velocity == Vec2::ZERO {
+            continue;
+        }
+        transform.translation += (knockback.velocity * secs).extend(0.0);
+        knockback.velocity *= (1.0 - KNOCKBACK_DAMPING * secs).max(0.0);
+        if knockback.velocity.length_squared() < 1.0 {
+            knockback.velocity = Vec2::ZERO;
+        }
+    }
+}
+
+/// System that brings dead players back at the [`spawn_point`] with full health.
+pub fn respawn_dead_players(
+    map: Option<Res<Map>>,
+    mut player_query: Query<(&mut Transform, &mut Health, &mut Knockback), With<Player>>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+
+    for (mut transform, mut health, mut knockback) in &mut player_query {
+        if !health.is_dead() {
+            continue;
+        }
+        let spawn = spawn_point(&map);
+        info!("Player died, respawning at {}", spawn);
+        transform.translation.x = spawn.x;
+        transform.translation.y = spawn.y;
+        health.restore();
+        *knockback = Knockback::default();
+    }
+}
+
+#[derive(Component)]
+struct HealthBarFill;
+
+// Show the player's health at the top of the screen
+fn setup_health_bar(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("HealthBar"),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                left: Val::Percent(50.0),
+                margin: UiRect::left(Val::Px(-HEALTH_BAR_WIDTH / 2.0)),
+                width: Val::Px(HEALTH_BAR_WIDTH),
+                height: Val::Px(16.0),
+                ..default()
+            },
+            BackgroundColor(HEALTH_BAR_BACKGROUND),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                HealthBarFill,
+                Node {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(HEALTH_BAR_COLOR),
+            ));
+        });
+}
+
+fn update_health_bar(
+    health_query: Query<&Health, (With<Player>, Changed<Health>)>,
+    mut fill_query: Query<&mut Node, With<HealthBarFill>>,
+) {
+    let Ok(health) = health_query.get_single() else {
+        return;
+    };
+    for mut node in &mut fill_query {
+        node.width = Val::Percent(health.fraction() * 100.0);
+    }
+}
@@ -0,0 +1,16 @@
+//! Core game systems for Cavernborn.
+//! The binary in `main.rs` wires these plugins into an app, while tests and tooling
+//! can use the map and simulation APIs directly.
+
+pub mod config;
+pub mod creatures;
+pub mod editor;
+pub mod health;
+pub mod particle;
+pub mod player;
+pub mod projectile;
+pub mod render;
+pub mod simulation;
+pub mod swimming;
+pub mod utils;
+pub mod world;
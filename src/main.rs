@@ -2,40 +2,70 @@ use bevy::app::AppExit;
 use bevy::input::keyboard::KeyCode;
 use bevy::input::ButtonInput;
 use bevy::prelude::*;
-use utils::debug;
-use world::camera;
+use cavernborn::config::{Config, CONFIG_PATH};
+use cavernborn::particle::definition::ParticleDefinitionsPlugin;
+use cavernborn::utils::debug;
+use cavernborn::world::camera;
+use cavernborn::world::generator::WorldSeed;
+use cavernborn::{creatures, editor, health, player, projectile, render, swimming};
+use std::path::Path;
 
-mod particle;
-mod player;
-mod render;
-mod simulation;
-mod utils;
-mod world;
-
-use crate::world::MapPlugin;
 use camera::{CameraPlugin, GameCamera};
+use cavernborn::world::MapPlugin;
+use creatures::CreaturesPlugin;
 use debug::DebugPlugin;
+use editor::EditorPlugin;
+use health::HealthPlugin;
 use player::PlayerPlugin;
+use projectile::ProjectilePlugin;
 use render::map_renderer::MapRendererPlugin;
+use swimming::SwimmingPlugin;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: "Cavernborn".into(),
-                resolution: (1600.0, 900.0).into(),
-                ..default()
-            }),
+    let seed = match WorldSeed::from_args(std::env::args().skip(1)) {
+        Ok(seed) => seed,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let config = match Config::load(Path::new(CONFIG_PATH)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let mut app = App::new();
+    app.insert_resource(config);
+    // Generate the starting map from the requested seed, see `setup_map`.
+    if let Some(seed) = seed {
+        app.insert_resource(seed);
+    }
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(Window {
+            title: "Cavernborn".into(),
+            resolution: (config.window_width, config.window_height).into(),
             ..default()
-        }))
-        .add_plugins(MapPlugin)
-        .add_plugins(CameraPlugin)
-        .add_plugins(PlayerPlugin)
-        .add_plugins(DebugPlugin)
-        .add_plugins(MapRendererPlugin)
-        .add_systems(Startup, show_controls)
-        .add_systems(Update, (check_escape, debug_camera_info))
-        .run();
+        }),
+        ..default()
+    }))
+    .add_plugins(ParticleDefinitionsPlugin)
+    .add_plugins(MapPlugin)
+    .add_plugins(CameraPlugin)
+    .add_plugins(PlayerPlugin)
+    .add_plugins(HealthPlugin)
+    .add_plugins(SwimmingPlugin)
+    .add_plugins(CreaturesPlugin)
+    .add_plugins(ProjectilePlugin)
+    .add_plugins(EditorPlugin)
+    .add_plugins(DebugPlugin)
+    .add_plugins(MapRendererPlugin)
+    .add_systems(Startup, show_controls)
+    .add_systems(Update, (check_escape, debug_camera_info))
+    .run();
 }
 
 fn check_escape(keyboard: Res<ButtonInput<KeyCode>>, mut exit: EventWriter<AppExit>) {
@@ -63,16 +93,14 @@ fn debug_camera_info(
 // Display control information when the game starts
 fn show_controls(mut commands: Commands) {
     commands
-        .spawn((
-            Node {
-                position_type: PositionType::Relative,
-                top: Val::Px(10.0),
-                left: Val::Px(10.0),
-                display: Display::Flex,
-                flex_direction: FlexDirection::Column,
-                ..default()
-            },
-        ))
+        .spawn((Node {
+            position_type: PositionType::Relative,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            display: Display::Flex,
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },))
         .with_children(|parent| {
             // Title
             parent.spawn(Text::from("Controls:\n"));
@@ -80,6 +108,29 @@ fn show_controls(mut commands: Commands) {
             parent.spawn(Text::from("Space: Toggle camera follow mode\n"));
             parent.spawn(Text::from("WASD: Move player/camera\n"));
             parent.spawn(Text::from("Shift: Speed up camera when disconnected\n"));
+            parent.spawn(Text::from("Z: Cycle zoom presets\n"));
+            parent.spawn(Text::from("M: Toggle the minimap\n"));
+            parent.spawn(Text::from("F6 / F7: Save / load the map\n"));
+            parent.spawn(Text::from(
+                "F12: Export the map to a PNG (Shift+F12 exports the visible part)\n",
+            ));
+            parent.spawn(Text::from(
+                "P: Pause the simulation (period steps a tick, + and - change the speed)\n",
+            ));
+            parent.spawn(Text::from("Left click: Dig, collecting ore and gems\n"));
+            parent.spawn(Text::from(
+                "Right click: Place the selected particle (number keys pick it)\n",
+            ));
+            parent.spawn(Text::from(
+                "[ and ]: Change the brush size (C cycles circle, square and line)\n",
+            ));
+            parent.spawn(Text::from("Ctrl+Z / Ctrl+Y: Undo / redo edits\n"));
+            parent.spawn(Text::from(
+                "F10: Toggle the editor (Tab cycles rectangle, flood fill, copy and paste)\n",
+            ));
+            parent.spawn(Text::from(
+                "G: Throw a water flask at the cursor (Shift+G throws a bomb)\n",
+            ));
 
             // Debug section title
             parent.spawn(Text::from("\nDebug Controls:\n"));
@@ -90,5 +141,10 @@ fn show_controls(mut commands: Commands) {
                 "F4: Toggle chunk visualization (highlights and coordinates)\n",
             ));
             parent.spawn(Text::from("F5: Toggle chunk outlines\n"));
+            parent.spawn(Text::from("F8: Toggle interaction rules panel\n"));
+            parent.spawn(Text::from("B: Explode at the cursor\n"));
+            parent.spawn(Text::from(
+                "R: Regenerate map with a new seed (Shift+R keeps the seed)\n",
+            ));
         });
 }
@@ -0,0 +1,356 @@
+//! Static data for each kind of particle, read from [`PARTICLE_DEFINITIONS_PATH`] instead of being
+//! hard-coded, so sprites, spawn depths and flow can be tuned without rebuilding.
+//!
+//! The [`ParticleRegistry`] resource holds the definitions in use. It starts out with the
+//! definitions the game was built with, see [`PARTICLE_DEFINITIONS`], and
+//! [`ParticleDefinitionsPlugin`] swaps in the asset file once it's loaded and again whenever it's
+//! edited. The map keeps its own copy for the simulation, see
+//! [`Map::particles`](crate::world::Map::particles).
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::ops::Range;
+use std::sync::{Arc, LazyLock};
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::Deserialize;
+use strum::IntoEnumIterator;
+
+use crate::render::chunk_material::{
+    MAX_ANIMATION_FRAMES, MAX_DRAW_ORDER, MAX_FRAME_RATE, MAX_VARIANTS,
+};
+
+use super::{Common, Particle, ParticleType};
+
+/// The asset the particle definitions are read from.
+pub const PARTICLE_DEFINITIONS_PATH: &str = "particles.toml";
+
+/// The particle definitions the game was built with. The runtime registry, [`ParticleRegistry`],
+/// starts out with these.
+pub static PARTICLE_DEFINITIONS: LazyLock<ParticleRegistry> = LazyLock::new(|| {
+    ParticleDefinitions::from_toml(include_str!("../../assets/particles.toml"))
+        .and_then(|definitions| ParticleRegistry::from_definitions(&definitions))
+        .expect("the built-in particle definitions are valid")
+});
+
+/// The static data of one kind of particle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParticleDefinition {
+    /// Where the particle's sprite is in its atlas.
+    pub spritesheet_index: u32,
+    /// The shallowest depth the particle is generated at.
+    #[serde(default)]
+    pub min_depth: u32,
+    /// The depth the particle stops being generated at.
+    #[serde(default = "unlimited_depth")]
+    pub max_depth: u32,
+    /// For specials, their weight in [`Map::roll_special_particle`]. For liquids, the chance out
+    /// of 100 that each attempt at a pocket makes one of them, see
+    /// [`crate::world::generator::plan_liquid_pockets`]. 0 for particles that aren't generated.
+    ///
+    /// [`Map::roll_special_particle`]: crate::world::Map::roll_special_particle
+    #[serde(default)]
+    pub spawn_chance: i32,
+    /// How easily a liquid flows and spreads. Higher values mean more spread, and 0 keeps it
+    /// in place.
+    #[serde(default)]
+    pub viscosity: i32,
+    /// Which way a liquid or gas flows on its own: -1 sinks and 1 rises.
+    #[serde(default)]
+    pub buoyancy: i32,
+    /// How many frames the particle's sprite animates through. The frames are consecutive sprites
+    /// in the atlas, starting at `spritesheet_index`.
+    #[serde(default = "single_frame")]
+    pub frames: u32,
+    /// How many frames a second the sprite animates at. 0 keeps it on the first frame.
+    #[serde(default)]
+    pub frame_rate: u32,
+    /// How many slightly different shades the particle's cells are drawn in, picked by position.
+    #[serde(default = "single_variant")]
+    pub variants: u32,
+    /// Where the particle's cells meet cells with a lower draw order, they're drawn a little over
+    /// them. Defaults to the draw order of the particle's
+    /// [`render_layer`](Particle::render_layer).
+    #[serde(default)]
+    pub draw_order: Option<u32>,
+    /// For steam, how many ticks it has to be stuck under a ceiling before it condenses back into
+    /// water. 0 never condenses.
+    #[serde(default)]
+    pub condense_ticks: u32,
+}
+
+fn unlimited_depth() -> u32 {
+    u32::MAX
+}
+
+fn single_frame() -> u32 {
+    1
+}
+
+fn single_variant() -> u32 {
+    1
+}
+
+/// A set of particle definitions keyed by [display name](ParticleType::display_name), as read
+/// from a file like [`PARTICLE_DEFINITIONS_PATH`].
+#[derive(Asset, TypePath, Clone, Debug, Default, PartialEq)]
+pub struct ParticleDefinitions {
+    pub particles: BTreeMap<String, ParticleDefinition>,
+}
+
+impl ParticleDefinitions {
+    /// Parses definitions written as TOML, one table per particle.
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let particles = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        Ok(Self { particles })
+    }
+}
+
+/// Runtime registry of the definition of every kind of particle. Cheap to clone, since clones
+/// share their definitions.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct ParticleRegistry {
+    definitions: Arc<HashMap<Particle, ParticleDefinition>>,
+}
+
+impl Default for ParticleRegistry {
+    fn default() -> Self {
+        PARTICLE_DEFINITIONS.clone()
+    }
+}
+
+impl ParticleRegistry {
+    /// Builds a registry out of `definitions`, which must define every kind of particle and
+    /// nothing else. The depths of the [`Common`] layers must cover every depth exactly once.
+    pub fn from_definitions(definitions: &ParticleDefinitions) -> Result<Self, String> {
+        let mut registry = HashMap::new();
+        for particle in Particle::all_variants() {
+            let name = particle.display_name();
+            let definition = definitions
+                .particles
+                .get(name)
+                .ok_or_else(|| format!("{} isn't defined", name))?;
+            if definition.min_depth >= definition.max_depth {
+                return Err(format!(
+                    "{} must have a max depth above its min depth",
+                    name
+                ));
+            }
+            if !(1..=MAX_ANIMATION_FRAMES).contains(&definition.frames) {
+                return Err(format!(
+                    "{} must have between 1 and {} frames",
+                    name, MAX_ANIMATION_FRAMES
+                ));
+            }
+            if definition.frame_rate > MAX_FRAME_RATE {
+                return Err(format!(
+                    "{} can't animate faster than {} frames a second",
+                    name, MAX_FRAME_RATE
+                ));
+            }
+            if !(1..=MAX_VARIANTS).contains(&definition.variants) {
+                return Err(format!(
+                    "{} must have between 1 and {} variants",
+                    name, MAX_VARIANTS
+                ));
+            }
+            if definition
+                .draw_order
+                .is_some_and(|order| order > MAX_DRAW_ORDER)
+            {
+                return Err(format!(
+                    "{} can't have a draw order above {}",
+                    name, MAX_DRAW_ORDER
+                ));
+            }
+            if definition.condense_ticks > u8::MAX as u32 {
+                return Err(format!(
+                    "{} can't take more than {} ticks to condense",
+                    name,
+                    u8::MAX
+                ));
+            }
+            registry.insert(particle, *definition);
+        }
+        if let Some(name) = definitions
+            .particles
+            .keys()
+            .find(|name| !registry.keys().any(|p| p.display_name() == name.as_str()))
+        {
+            return Err(format!("there's no particle called {}", name));
+        }
+
+        let mut layers: Vec<Range<u32>> = Common::iter()
+            .map(|common| registry[&common.into()])
+            .map(|definition| definition.min_depth..definition.max_depth)
+            .collect();
+        layers.sort_by_key(|layer| layer.start);
+        let covered = layers.first().is_some_and(|layer| layer.start == 0)
+            && layers.windows(2).all(|pair| pair[0].end == pair[1].start)
+            && layers.last().is_some_and(|layer| layer.end == u32::MAX);
+        if !covered {
+            return Err("the ground layers must cover every depth exactly once".to_string());
+        }
+
+        Ok(Self {
+            definitions: Arc::new(registry),
+        })
+    }
+
+    /// The definition of `particle`'s kind.
+    pub fn get(&self, particle: Particle) -> &ParticleDefinition {
+        &self.definitions[&particle]
+    }
+
+    /// The (atlas id, spritesheet index) pair used to draw `particle`.
+    pub fn sprite(&self, particle: Particle) -> (u32, u32) {
+        (
+            particle.get_atlas_id(),
+            self.get(particle).spritesheet_index,
+        )
+    }
+
+    /// The depths `particle` is generated at.
+    pub fn depths(&self, particle: Particle) -> Range<u32> {
+        let definition = self.get(particle);
+        definition.min_depth..definition.max_depth
+    }
+
+    /// See [`ParticleDefinition::spawn_chance`].
+    pub fn spawn_chance(&self, particle: Particle) -> i32 {
+        self.get(particle).spawn_chance
+    }
+
+    /// See [`ParticleDefinition::viscosity`].
+    pub fn viscosity(&self, particle: Particle) -> i32 {
+        self.get(particle).viscosity
+    }
+
+    /// See [`ParticleDefinition::buoyancy`].
+    pub fn buoyancy(&self, particle: Particle) -> i32 {
+        self.get(particle).buoyancy
+    }
+
+    /// The (frame count, frame rate) of `particle`'s sprite animation, see
+    /// [`ParticleDefinition::frames`].
+    pub fn animation(&self, particle: Particle) -> (u32, u32) {
+        let definition = self.get(particle);
+        (definition.frames, definition.frame_rate)
+    }
+
+    /// See [`ParticleDefinition::variants`].
+    pub fn variants(&self, particle: Particle) -> u32 {
+        self.get(particle).variants
+    }
+
+    /// See [`ParticleDefinition::draw_order`].
+    pub fn draw_order(&self, particle: Particle) -> u32 {
+        self.get(particle)
+            .draw_order
+            .unwrap_or_else(|| particle.render_layer().draw_order())
+    }
+
+    /// See [`ParticleDefinition::condense_ticks`].
+    pub fn condense_ticks(&self, particle: Particle) -> u32 {
+        self.get(particle).condense_ticks
+    }
+
+    /// The ground layer at `depth`, i.e. the [`Common`] particle whose depths contain it.
+    pub fn common_at_depth(&self, depth: u32) -> Common {
+        Common::iter()
+            .find(|common| self.depths((*common).into()).contains(&depth))
+            .expect("the ground layers cover every depth")
+    }
+}
+
+/// Loads [`ParticleDefinitions`] from TOML files, rejecting ones that don't make a valid
+/// [`ParticleRegistry`].
+#[derive(Default)]
+pub struct ParticleDefinitionsLoader;
+
+impl AssetLoader for ParticleDefinitionsLoader {
+    type Asset = ParticleDefinitions;
+    type Settings = ();
+    type Error = io::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let text = String::from_utf8(bytes).map_err(io::Error::other)?;
+        let definitions = ParticleDefinitions::from_toml(&text).map_err(io::Error::other)?;
+        ParticleRegistry::from_definitions(&definitions).map_err(io::Error::other)?;
+        Ok(definitions)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["toml"]
+    }
+}
+
+/// The loaded [`PARTICLE_DEFINITIONS_PATH`] asset.
+#[derive(Resource)]
+pub struct ParticleDefinitionsHandle(pub Handle<ParticleDefinitions>);
+
+/// Plugin that loads the particle definitions from [`PARTICLE_DEFINITIONS_PATH`]. The asset
+/// server's file watcher (Bevy's `file_watcher` feature) reloads them whenever the file changes.
+/// Needs the `AssetPlugin`, and the [`SimulationPlugin`](crate::world::SimulationPlugin) for the
+/// [`ParticleRegistry`] it updates.
+pub struct ParticleDefinitionsPlugin;
+
+impl Plugin for ParticleDefinitionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ParticleDefinitions>()
+            .init_asset_loader::<ParticleDefinitionsLoader>()
+            .add_systems(Startup, load_particle_definitions)
+            .add_systems(Update, apply_particle_definitions);
+    }
+}
+
+fn load_particle_definitions(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(ParticleDefinitionsHandle(
+        asset_server.load(PARTICLE_DEFINITIONS_PATH),
+    ));
+}
+
+/// System that swaps the [`ParticleRegistry`] for the loaded definitions whenever they're loaded
+/// or change. Invalid definitions are logged and the registry is left as it was.
+pub fn apply_particle_definitions(
+    mut events: EventReader<AssetEvent<ParticleDefinitions>>,
+    handle: Option<Res<ParticleDefinitionsHandle>>,
+    definitions: Res<Assets<ParticleDefinitions>>,
+    mut registry: ResMut<ParticleRegistry>,
+) {
+    let Some(handle) = handle else {
+        return;
+    };
+    let changed = events.read().any(|event| match event {
+        AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => {
+            *id == handle.0.id()
+        }
+        _ => false,
+    });
+    let Some(loaded) = definitions.get(&handle.0).filter(|_| changed) else {
+        return;
+    };
+
+    match ParticleRegistry::from_definitions(loaded) {
+        Ok(loaded) => {
+            if *registry != loaded {
+                info!(
+                    "Applied the particle definitions from {}",
+                    PARTICLE_DEFINITIONS_PATH
+                );
+                *registry = loaded;
+            }
+        }
+        Err(e) => error!("Invalid {}: {}", PARTICLE_DEFINITIONS_PATH, e),
+    }
+}
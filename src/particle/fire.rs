@@ -0,0 +1,92 @@
+use std::mem::discriminant;
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use super::{Particle, ParticleType, Powder, Solid};
+
+#[derive(Clone, Copy, Debug, EnumIter, Serialize, Deserialize)]
+pub enum Fire {
+    /// A burning flame, holding the number of ticks it has left before it burns out.
+    Flame(u8),
+    /// A smoldering solid like wood, holding the number of ticks it has left. Burns longer than
+    /// a flame and leaves ash behind.
+    Ember(u8),
+}
+
+// Like liquids, fire is compared by kind only so its remaining lifetime doesn't affect lookups.
+impl PartialEq for Fire {
+    fn eq(&self, other: &Self) -> bool {
+        discriminant(self) == discriminant(other)
+    }
+}
+
+impl Eq for Fire {}
+
+impl std::hash::Hash for Fire {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        discriminant(self).hash(state)
+    }
+}
+
+impl Default for Fire {
+    fn default() -> Self {
+        Self::Flame(Fire::LIFETIME)
+    }
+}
+
+impl Fire {
+    /// How many simulation ticks a freshly ignited flame burns for.
+    pub const LIFETIME: u8 = 60;
+
+    /// How many simulation ticks a freshly ignited ember smolders for.
+    pub const EMBER_LIFETIME: u8 = 180;
+
+    /// The fire that `fuel` turns into when it catches fire. Fuels that burn into ash smolder
+    /// as embers, everything else goes up in a flame.
+    pub fn igniting(fuel: Particle) -> Fire {
+        match fuel {
+            Particle::Solid(Solid::Wood) => Fire::Ember(Fire::EMBER_LIFETIME),
+            _ => Fire::default(),
+        }
+    }
+
+    /// Returns the number of ticks the fire has left before it burns out.
+    pub fn get_lifetime(&self) -> u8 {
+        match self {
+            Fire::Flame(lifetime) | Fire::Ember(lifetime) => *lifetime,
+        }
+    }
+
+    /// The same fire with `lifetime` ticks left.
+    pub fn with_lifetime(&self, lifetime: u8) -> Fire {
+        match self {
+            Fire::Flame(_) => Fire::Flame(lifetime),
+            Fire::Ember(_) => Fire::Ember(lifetime),
+        }
+    }
+
+    /// What the fire leaves behind once it burns out. `None` means it leaves air.
+    pub fn get_residue(&self) -> Option<Particle> {
+        match self {
+            Fire::Flame(_) => None,
+            Fire::Ember(_) => Some(Particle::Powder(Powder::Ash)),
+        }
+    }
+}
+
+impl ParticleType for Fire {
+    fn display_name(&self) -> &'static str {
+        match self {
+            Fire::Flame(_) => "Fire",
+            Fire::Ember(_) => "Ember",
+        }
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(match self {
+            Fire::Flame(_) => "Spreads to flammable neighbors and burns out over time.",
+            Fire::Ember(_) => "Smoldering wood. Spreads fire and crumbles into ash.",
+        })
+    }
+}
@@ -0,0 +1,107 @@
+use std::mem::discriminant;
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use super::ParticleType;
+
+/// Light particles that rise, drift and dissipate over time.
+#[derive(Clone, Copy, Debug, EnumIter, Serialize, Deserialize)]
+pub enum Gas {
+    Steam(GasState),
+    Smoke(GasState),
+    ToxicFumes(GasState),
+}
+
+/// Per-cell state carried along with a gas particle as it moves.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+pub struct GasState {
+    /// How many ticks the gas has left before it dissipates.
+    pub lifetime: u8,
+    /// How many ticks in a row the gas has been stuck against a ceiling.
+    pub ceiling_ticks: u8,
+}
+
+// Like liquids, gas is compared by kind only so its per-cell state doesn't affect lookups.
+impl PartialEq for Gas {
+    fn eq(&self, other: &Self) -> bool {
+        discriminant(self) == discriminant(other)
+    }
+}
+
+impl Eq for Gas {}
+
+impl std::hash::Hash for Gas {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        discriminant(self).hash(state)
+    }
+}
+
+impl Default for Gas {
+    fn default() -> Self {
+        Gas::Steam(GasState::default()).fresh()
+    }
+}
+
+impl Gas {
+    /// How many ticks a freshly released gas lasts before it dissipates.
+    pub fn get_max_lifetime(&self) -> u8 {
+        match self {
+            Gas::Steam(_) => 200,
+            Gas::Smoke(_) => 120,
+            Gas::ToxicFumes(_) => 240,
+        }
+    }
+
+    /// How heavy a gas is, on the same scale as [`super::Liquid::get_density`].
+    pub fn get_density(&self) -> u32 {
+        match self {
+            Gas::Steam(_) | Gas::Smoke(_) => 1,
+            Gas::ToxicFumes(_) => 2,
+        }
+    }
+
+    /// Returns the gas with a full lifetime, as if it was just released.
+    pub fn fresh(&self) -> Self {
+        self.with_state(GasState {
+            lifetime: self.get_max_lifetime(),
+            ceiling_ticks: 0,
+        })
+    }
+
+    /// Returns the per-cell state of the gas.
+    pub fn get_state(&self) -> &GasState {
+        match self {
+            Gas::Steam(state) | Gas::Smoke(state) | Gas::ToxicFumes(state) => state,
+        }
+    }
+
+    /// Returns the same kind of gas with the given state.
+    pub fn with_state(&self, state: GasState) -> Self {
+        match self {
+            Gas::Steam(_) => Gas::Steam(state),
+            Gas::Smoke(_) => Gas::Smoke(state),
+            Gas::ToxicFumes(_) => Gas::ToxicFumes(state),
+        }
+    }
+}
+
+impl ParticleType for Gas {
+    fn display_name(&self) -> &'static str {
+        match self {
+            Gas::Steam(_) => "Steam",
+            Gas::Smoke(_) => "Smoke",
+            Gas::ToxicFumes(_) => "Toxic Fumes",
+        }
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(match self {
+            Gas::Steam(_) => "Rises from boiling water and condenses back into it under ceilings.",
+            Gas::Smoke(_) => "Rises and fades away.",
+            Gas::ToxicFumes(_) => {
+                "A heavy, lingering cloud given off by acid as it dissolves rock."
+            }
+        })
+    }
+}
@@ -1,37 +1,33 @@
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
 use super::{ParticleType, WorldGenType};
+use crate::world::biome::Biome;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter, Default, Serialize, Deserialize)]
 pub enum Gem {
     #[default]
     Ruby,
 }
 
 impl WorldGenType for Gem {
-    fn min_depth(&self) -> u32 {
+    fn spawns_in(&self, biome: Biome) -> bool {
         match self {
-            Gem::Ruby => 80,
-        }
-    }
-
-    fn max_depth(&self) -> u32 {
-        match self {
-            Gem::Ruby => 150,
-        }
-    }
-
-    fn spawn_chance(&self) -> i32 {
-        match self {
-            Gem::Ruby => 3,
+            Gem::Ruby => matches!(biome, Biome::Desert | Biome::Volcanic),
         }
     }
 }
 
 impl ParticleType for Gem {
-    fn get_spritesheet_index(&self) -> u32 {
+    fn display_name(&self) -> &'static str {
         match self {
-            Gem::Ruby => 3,
+            Gem::Ruby => "Ruby",
         }
     }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(match self {
+            Gem::Ruby => "A rare gem found deep under deserts and volcanic ground.",
+        })
+    }
 }
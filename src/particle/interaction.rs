@@ -1,80 +1,215 @@
-use crate::particle::Solid;
-
-use super::{Direction, Liquid, Particle};
-use std::{collections::HashMap, hash::Hasher, sync::LazyLock};
+use super::{Common, Direction, Fire, Gas, GasState, Liquid, Particle, Solid};
+use bevy::prelude::Resource;
+use rand::Rng;
+use std::{collections::HashMap, sync::LazyLock};
+use strum::IntoEnumIterator;
 
+/// The built-in interaction rules. The runtime registry, [`InteractionRules`], starts out with these.
 pub static INTERACTION_RULES: LazyLock<HashMap<InteractionPair, InteractionRule>> =
     LazyLock::new(|| {
+        // Water and lava don't need a rule, since their temperatures boil the water into steam
+        // and harden the lava into obsidian. See `crate::world::heat`.
         let mut m = HashMap::new();
         m.insert(
             InteractionPair {
-                source: Particle::Liquid(Liquid::Water(Direction::Still)),
-                target: Particle::Liquid(Liquid::Lava(Direction::Still)),
+                source: Particle::Liquid(Liquid::Water(Direction::Still.into())),
+                target: Particle::Liquid(Liquid::Acid(Direction::Still.into())),
             },
             InteractionRule {
-                interaction_type: InteractionType::Replace,
-                result: Particle::Solid(Solid::Obsidian),
+                interaction_type: InteractionType::Preserve,
+                result: Particle::Liquid(Liquid::Water(Direction::Left.into())),
+                byproduct: None,
+                probability: 1.0,
+                cooldown: 0,
+                symmetric: true,
             },
         );
 
         m.insert(
             InteractionPair {
-                source: Particle::Liquid(Liquid::Water(Direction::Still)),
-                target: Particle::Liquid(Liquid::Acid(Direction::Still)),
+                source: Particle::Liquid(Liquid::Lava(Direction::Still.into())),
+                target: Particle::Liquid(Liquid::Oil(Direction::Still.into())),
             },
             InteractionRule {
                 interaction_type: InteractionType::Preserve,
-                result: Particle::Liquid(Liquid::Water(Direction::random())),
+                result: Particle::Fire(Fire::default()),
+                byproduct: None,
+                probability: 1.0,
+                cooldown: 0,
+                symmetric: true,
             },
         );
 
+        // Acid eats through terrain over time, see `Liquid::get_erosion_rate`, and is sometimes
+        // used up in the process.
+        let dissolvable = Common::iter()
+            .filter(|common| *common != Common::Bedrock)
+            .map(Particle::Common)
+            .chain([Particle::Solid(Solid::Wood)]);
+        for target in dissolvable {
+            m.insert(
+                InteractionPair {
+                    source: Particle::Liquid(Liquid::Acid(Direction::Still.into())),
+                    target,
+                },
+                InteractionRule {
+                    interaction_type: InteractionType::Dissolve,
+                    result: Particle::Gas(Gas::ToxicFumes(GasState::default()).fresh()),
+                    byproduct: None,
+                    probability: ACID_CONSUMPTION_CHANCE,
+                    cooldown: 0,
+                    symmetric: false,
+                },
+            );
+        }
+
         m
     });
 
-// Create a key type for interactions.
-#[derive(Clone, Copy)]
+/// The chance that acid is used up, turning into toxic fumes, when it dissolves a particle.
+pub const ACID_CONSUMPTION_CHANCE: f32 = 0.25;
+
+/// The particles a rule is registered for. Pairs are ordered: the source is the particle moving
+/// into the target, so (water, lava) and (lava, water) are different pairs. Rules marked
+/// [`InteractionRule::symmetric`] also apply to the reversed pair.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct InteractionPair {
     pub source: Particle,
     pub target: Particle,
 }
 
-impl std::hash::Hash for InteractionPair {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        // Hash both particles individually and sort to ensure commutativity
-        let mut hasher1 = std::collections::hash_map::DefaultHasher::new();
-        let mut hasher2 = std::collections::hash_map::DefaultHasher::new();
-
-        self.source.hash(&mut hasher1);
-        self.target.hash(&mut hasher2);
-
-        let (a, b) = (hasher1.finish(), hasher2.finish());
-        let (lo, hi): (u64, u64) = if a <= b { (a, b) } else { (b, a) };
-        lo.hash(state);
-        hi.hash(state);
-    }
-}
-
-impl PartialEq for InteractionPair {
-    fn eq(&self, other: &Self) -> bool {
-        (self.source == other.source && self.target == other.target)
-            || (self.source == other.target && self.target == other.source)
+impl InteractionPair {
+    /// The same pair with the source and target swapped.
+    pub fn reversed(&self) -> Self {
+        Self {
+            source: self.target,
+            target: self.source,
+        }
     }
 }
 
-impl Eq for InteractionPair {}
-
 /// Defines how an interaction resolves between two particles.
 #[derive(Clone, Copy, Debug)]
 pub enum InteractionType {
-    /// Both particles are consumed; the target becomes the result.
-    /// Example: water + lava → obsidian (water disappears, lava becomes obsidian)
+    /// Both particles are consumed; the target becomes the result and the source becomes the
+    /// rule's byproduct, or air if it has none.
+    /// Example: a water + lava rule with a steam byproduct turns the lava into obsidian and the
+    /// water into steam.
     Replace,
     /// The source particle survives; only the target becomes the result.
     /// Example: water + acid → water stays, acid becomes water
     Preserve,
+    /// The source wears the target away over time instead of reacting on contact, see
+    /// [`Liquid::get_erosion_rate`]. Once the target is worn through it's removed, and the rule's
+    /// probability is the chance the source is used up, becoming the result.
+    /// Example: acid + stone → the stone dissolves, and the acid sometimes turns into fumes
+    Dissolve,
 }
 
+#[derive(Clone, Copy, Debug)]
 pub struct InteractionRule {
     pub interaction_type: InteractionType,
     pub result: Particle,
+    /// What the source turns into when a [`InteractionType::Replace`] rule consumes it.
+    /// Ignored by [`InteractionType::Preserve`] rules, whose source survives.
+    pub byproduct: Option<Particle>,
+    /// The chance each tick that the particles react while they touch, from 0 to 1. Rules below
+    /// 1 take effect gradually, like acid slowly eating through stone.
+    pub probability: f32,
+    /// How many ticks the cell a reaction turned into the result waits before it can react again.
+    /// See [`crate::world::Map::interaction_ready`].
+    pub cooldown: u32,
+    /// Whether the rule also applies when its target moves into its source. Directional rules
+    /// only apply one way, so water falling onto lava can react differently than lava falling
+    /// onto water.
+    pub symmetric: bool,
+}
+
+impl InteractionRule {
+    /// Whether the rule takes effect as soon as its particles touch. [`InteractionType::Dissolve`]
+    /// rules only take effect once erosion wears the target through.
+    pub fn reacts_on_contact(&self) -> bool {
+        !matches!(self.interaction_type, InteractionType::Dissolve)
+    }
+
+    /// Rolls whether the rule takes effect this time, see [`InteractionRule::probability`].
+    pub fn roll(&self, rng: &mut impl Rng) -> bool {
+        self.probability >= 1.0 || rng.random::<f32>() < self.probability
+    }
+}
+
+/// Runtime registry of interaction rules used by the simulation.
+/// Seeded with the built-in [`INTERACTION_RULES`], and extended at runtime with [`InteractionRules::register`].
+#[derive(Resource)]
+pub struct InteractionRules {
+    rules: HashMap<InteractionPair, InteractionRule>,
+}
+
+impl Default for InteractionRules {
+    fn default() -> Self {
+        Self {
+            rules: INTERACTION_RULES
+                .iter()
+                .map(|(pair, rule)| (*pair, *rule))
+                .collect(),
+        }
+    }
+}
+
+impl InteractionRules {
+    /// Returns the rule for the pair's source moving into its target. This is the rule registered
+    /// for the pair itself, or else a symmetric rule registered for the reversed pair.
+    pub fn get(&self, pair: &InteractionPair) -> Option<&InteractionRule> {
+        self.rules.get(pair).or_else(|| {
+            self.rules
+                .get(&pair.reversed())
+                .filter(|rule| rule.symmetric)
+        })
+    }
+
+    /// Returns the rule for `a` moving into `b` along with whether `a` is the rule's source, which
+    /// it isn't when the rule is a symmetric one registered for `b` moving into `a`.
+    pub fn get_oriented(&self, a: Particle, b: Particle) -> Option<(&InteractionRule, bool)> {
+        let forward = InteractionPair {
+            source: a,
+            target: b,
+        };
+        if let Some(rule) = self.rules.get(&forward) {
+            return Some((rule, true));
+        }
+        self.rules
+            .get(&forward.reversed())
+            .filter(|rule| rule.symmetric)
+            .map(|rule| (rule, false))
+    }
+
+    /// Adds a rule, replacing and returning any existing rule for the same pair. A symmetric rule
+    /// also replaces the rule for the reversed pair, since it covers both.
+    pub fn register(
+        &mut self,
+        source: Particle,
+        target: Particle,
+        rule: InteractionRule,
+    ) -> Option<InteractionRule> {
+        let pair = InteractionPair { source, target };
+        let reversed = if rule.symmetric && source != target {
+            self.rules.remove(&pair.reversed())
+        } else {
+            None
+        };
+        self.rules.insert(pair, rule).or(reversed)
+    }
+
+    /// Iterates over all registered rules in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&InteractionPair, &InteractionRule)> {
+        self.rules.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
 }
@@ -1,14 +1,37 @@
 use std::mem::discriminant;
 
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
-use super::{Direction, ParticleType, WorldGenType};
+use crate::world::biome::Biome;
 
-#[derive(Clone, Copy, Debug, EnumIter)]
+use super::{
+    Common, Direction, Gas, Particle, ParticleType, Solid, WorldGenType, AMBIENT_TEMPERATURE,
+};
+
+#[derive(Clone, Copy, Debug, EnumIter, Serialize, Deserialize)]
 pub enum Liquid {
-    Water(Direction),
-    Lava(Direction),
-    Acid(Direction),
+    Water(LiquidState),
+    Lava(LiquidState),
+    Acid(LiquidState),
+    Oil(LiquidState),
+}
+
+/// Per-cell state carried along with a liquid particle as it moves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiquidState {
+    pub direction: Direction,
+    /// How many cells the liquid has fallen without landing.
+    pub fall_distance: u8,
+}
+
+impl From<Direction> for LiquidState {
+    fn from(direction: Direction) -> Self {
+        Self {
+            direction,
+            fall_distance: 0,
+        }
+    }
 }
 
 // Implement PartialEq, Eq, and Hash for Liquid to ensure that two liquids with different directions are considered equal.
@@ -28,75 +51,212 @@ impl std::hash::Hash for Liquid {
 
 impl Default for Liquid {
     fn default() -> Self {
-        Self::Water(Direction::default())
+        Self::Water(LiquidState::default())
     }
 }
 
+/// How the underground pockets of a liquid are shaped, see
+/// [`crate::world::generator::plan_liquid_pockets`]. Pockets are ellipses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PocketProfile {
+    /// The least a pocket reaches sideways from its middle, in cells.
+    pub min_half_width: u32,
+    /// The most a pocket reaches sideways from its middle, in cells.
+    pub max_half_width: u32,
+    /// The least a pocket reaches up and down from its middle, in cells.
+    pub min_half_height: u32,
+    /// The most a pocket reaches up and down from its middle, in cells.
+    pub max_half_height: u32,
+    /// The share of the pocket's height filled with the liquid from the bottom up, from 0 to 1.
+    /// The rest is left as an air gap.
+    pub fill: f32,
+}
+
 impl Liquid {
-    pub const BUOYANCY: i32 = -1;
+    /// How pockets of this liquid are shaped. Water forms wide lakes, lava smaller pools, and acid
+    /// small pockets filled to the brim.
+    pub fn pocket_profile(&self) -> PocketProfile {
+        match self {
+            Liquid::Water(_) => PocketProfile {
+                min_half_width: 6,
+                max_half_width: 14,
+                min_half_height: 3,
+                max_half_height: 6,
+                fill: 0.7,
+            },
+            Liquid::Oil(_) => PocketProfile {
+                min_half_width: 3,
+                max_half_width: 6,
+                min_half_height: 2,
+                max_half_height: 4,
+                fill: 0.8,
+            },
+            Liquid::Acid(_) => PocketProfile {
+                min_half_width: 2,
+                max_half_width: 4,
+                min_half_height: 2,
+                max_half_height: 3,
+                fill: 1.0,
+            },
+            Liquid::Lava(_) => PocketProfile {
+                min_half_width: 4,
+                max_half_width: 9,
+                min_half_height: 2,
+                max_half_height: 5,
+                fill: 0.6,
+            },
+        }
+    }
+
+    /// The temperature lava keeps itself at.
+    pub const LAVA_TEMPERATURE: f32 = 1200.0;
+
+    /// Water at or above this temperature boils into steam.
+    pub const BOILING_POINT: f32 = 100.0;
 
-    /// Describes how easily a fluid flows and spreads.
-    /// Higher values mean more spread.
-    pub fn get_viscosity(&self) -> i32 {
+    /// Lava cooled below this temperature hardens into obsidian.
+    pub const SOLIDIFYING_POINT: f32 = 1000.0;
+
+    /// How much hardness acid dissolves out of a neighboring particle per tick.
+    pub const ACID_EROSION_RATE: f32 = 0.05;
+
+    /// How much hardness water washes out of neighboring dirt per tick.
+    pub const WATER_EROSION_RATE: f32 = 0.002;
+
+    /// How readily heat flows through the fluid, from 0 (not at all) to 1.
+    pub fn get_conductivity(&self) -> f32 {
         match self {
-            Liquid::Water(_) => 5,
-            Liquid::Lava(_) => 3,
-            Liquid::Acid(_) => 4,
+            Liquid::Water(_) | Liquid::Lava(_) => 1.0,
+            Liquid::Acid(_) | Liquid::Oil(_) => 0.5,
         }
     }
 
-    /// Returns the direction of the fluid.
-    pub fn get_direction(&self) -> &Direction {
+    /// The temperature the fluid keeps its cell at. Water cools whatever heats it, and lava heats
+    /// whatever cools it. Other fluids take on the temperature around them.
+    pub fn get_resting_temperature(&self) -> Option<f32> {
         match self {
-            Liquid::Water(direction) | Liquid::Lava(direction) | Liquid::Acid(direction) => {
-                direction
+            Liquid::Water(_) => Some(AMBIENT_TEMPERATURE),
+            Liquid::Lava(_) => Some(Liquid::LAVA_TEMPERATURE),
+            Liquid::Acid(_) | Liquid::Oil(_) => None,
+        }
+    }
+
+    /// What the fluid turns into at `temperature`, or `None` if it stays liquid.
+    pub fn get_phase_change(&self, temperature: f32) -> Option<Particle> {
+        match self {
+            Liquid::Water(_) if temperature >= Liquid::BOILING_POINT => {
+                Some(Particle::Gas(Gas::default()))
+            }
+            Liquid::Lava(_) if temperature < Liquid::SOLIDIFYING_POINT => {
+                Some(Particle::Solid(Solid::Obsidian))
             }
+            _ => None,
         }
     }
 
-    /// Returns the direction of the fluid.
-    pub fn get_flipped_direction(&self) -> Self {
+    /// How much hardness the fluid wears out of a neighboring `target` per tick, see
+    /// [`Particle::get_hardness`]. Acid dissolves rock, dirt and wood, while water slowly washes
+    /// dirt away.
+    pub fn get_erosion_rate(&self, target: Particle) -> f32 {
+        match (self, target) {
+            (_, Particle::Common(Common::Bedrock)) => 0.0,
+            (Liquid::Acid(_), Particle::Common(_) | Particle::Solid(Solid::Wood)) => {
+                Liquid::ACID_EROSION_RATE
+            }
+            (Liquid::Water(_), Particle::Common(Common::Dirt | Common::Grass)) => {
+                Liquid::WATER_EROSION_RATE
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// How heavy a fluid is. Denser fluids sink through lighter ones, so lighter ones float.
+    pub fn get_density(&self) -> u32 {
         match self {
-            Liquid::Water(direction) => Liquid::Water(direction.get_opposite()),
-            Liquid::Lava(direction) => Liquid::Lava(direction.get_opposite()),
-            Liquid::Acid(direction) => Liquid::Acid(direction.get_opposite()),
+            Liquid::Water(_) => 10,
+            Liquid::Lava(_) => 30,
+            Liquid::Acid(_) => 12,
+            Liquid::Oil(_) => 8,
         }
     }
-}
 
-impl ParticleType for Liquid {
-    fn get_spritesheet_index(&self) -> u32 {
+    /// Returns the per-cell state of the fluid.
+    pub fn get_state(&self) -> &LiquidState {
         match self {
-            Liquid::Water(_) => 5,
-            Liquid::Lava(_) => 6,
-            Liquid::Acid(_) => 8,
+            Liquid::Water(state)
+            | Liquid::Lava(state)
+            | Liquid::Acid(state)
+            | Liquid::Oil(state) => state,
         }
     }
-}
 
-//TODO: Temp values.
-impl WorldGenType for Liquid {
-    fn min_depth(&self) -> u32 {
+    /// Returns the same fluid with the given state.
+    pub fn with_state(&self, state: LiquidState) -> Self {
         match self {
-            Liquid::Water(_) => 0,
-            Liquid::Lava(_) => 1,
-            Liquid::Acid(_) => 1,
+            Liquid::Water(_) => Liquid::Water(state),
+            Liquid::Lava(_) => Liquid::Lava(state),
+            Liquid::Acid(_) => Liquid::Acid(state),
+            Liquid::Oil(_) => Liquid::Oil(state),
         }
     }
 
-    fn max_depth(&self) -> u32 {
+    /// Returns the direction of the fluid.
+    pub fn get_direction(&self) -> &Direction {
+        &self.get_state().direction
+    }
+
+    /// Returns the fluid with its direction flipped.
+    pub fn get_flipped_direction(&self) -> Self {
+        let state = self.get_state();
+        self.with_state(LiquidState {
+            direction: state.direction.get_opposite(),
+            ..*state
+        })
+    }
+
+    /// Returns how many cells the fluid has fallen since it last landed.
+    pub fn get_fall_distance(&self) -> u8 {
+        self.get_state().fall_distance
+    }
+
+    /// Returns the fluid with the given fall distance.
+    pub fn with_fall_distance(&self, fall_distance: u8) -> Self {
+        self.with_state(LiquidState {
+            fall_distance,
+            ..*self.get_state()
+        })
+    }
+}
+
+impl ParticleType for Liquid {
+    fn display_name(&self) -> &'static str {
         match self {
-            Liquid::Water(_) => 100,
-            Liquid::Lava(_) => 100,
-            Liquid::Acid(_) => 100,
+            Liquid::Water(_) => "Water",
+            Liquid::Lava(_) => "Lava",
+            Liquid::Acid(_) => "Acid",
+            Liquid::Oil(_) => "Oil",
         }
     }
 
-    fn spawn_chance(&self) -> i32 {
+    fn description(&self) -> Option<&'static str> {
+        Some(match self {
+            Liquid::Water(_) => "Flows freely and cools lava into obsidian.",
+            Liquid::Lava(_) => "Molten rock that flows slowly.",
+            Liquid::Acid(_) => "A corrosive liquid.",
+            Liquid::Oil(_) => "Floats on water and catches fire from lava and flames.",
+        })
+    }
+
+    fn get_flammability(&self) -> f32 {
         match self {
-            Liquid::Water(_) => 100,
-            Liquid::Lava(_) => 100,
-            Liquid::Acid(_) => 100,
+            Liquid::Oil(_) => 0.8,
+            _ => 0.0,
         }
     }
 }
+
+impl WorldGenType for Liquid {
+    fn spawns_in(&self, biome: Biome) -> bool {
+        !matches!((self, biome), (Liquid::Lava(_), Biome::Frozen))
+    }
+}
@@ -1,42 +1,75 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
+use crate::world::biome::Biome;
+
+pub mod definition;
+mod fire;
+mod gas;
 mod gem;
 pub mod interaction;
 mod liquid;
 mod ore;
+mod powder;
 mod solid;
 
+pub use self::fire::Fire;
+pub use self::gas::{Gas, GasState};
 pub use self::gem::Gem;
-pub use self::liquid::Liquid;
-pub use self::ore::Ore;
+pub use self::liquid::{Liquid, LiquidState, PocketProfile};
+pub use self::ore::{Ore, VeinProfile};
+pub use self::powder::Powder;
 pub use self::solid::Solid;
 
 /// The square size of the particle in pixels.
 /// This is used in all logic that utilizes particles.
 pub(crate) const PARTICLE_SIZE: u32 = 3;
 
-/// Define a trait for types that can be used for world generation.
-pub trait WorldGenType: ParticleType {
-    /// The minimum depth at which this particle type can spawn.
-    fn min_depth(&self) -> u32;
+/// The temperature of air, and the one every cell settles back to unless its particle keeps its
+/// own, see [`Particle::get_resting_temperature`].
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
 
-    /// The maximum depth at which this particle type can spawn.
-    fn max_depth(&self) -> u32;
+/// The brightest a cell can be lit, by daylight or by a glowing particle.
+pub const MAX_LIGHT: u8 = 15;
 
-    /// The chance of a particle of this type to spawn at a given depth.
-    ///
-    /// Note: This does not always reflect in the total count of this particle type.
-    /// Something like gold will spawn in veins, disconnecting this value from the actual count.
-    fn spawn_chance(&self) -> i32;
+/// The atlas particles are drawn from unless they pick another one.
+pub const DEFAULT_ATLAS_ID: u32 = 0;
+
+/// Define a trait for types that can be used for world generation. Their depths and spawn chances
+/// are in their [definitions](definition::ParticleDefinition).
+pub trait WorldGenType: ParticleType {
+    /// Whether this particle type can spawn in `biome` at all. Most spawn in every biome.
+    fn spawns_in(&self, _biome: Biome) -> bool {
+        true
+    }
 }
 
 /// Trait for all particles.
 pub trait ParticleType: Copy + IntoEnumIterator {
-    fn get_spritesheet_index(&self) -> u32;
+    /// The atlas this particle's sprite is in. Where in the atlas is part of its
+    /// [definition](definition::ParticleDefinition).
+    fn get_atlas_id(&self) -> u32 {
+        DEFAULT_ATLAS_ID
+    }
+
+    /// A human-readable name for UIs, e.g. "Water" rather than the `Debug` output.
+    fn display_name(&self) -> &'static str;
+
+    /// A short human-readable description, if there is one worth showing.
+    fn description(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// The chance per tick that this particle catches fire from each adjacent flame or other
+    /// source of heat. Particles with a flammability of 0 never burn.
+    fn get_flammability(&self) -> f32 {
+        0.0
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter, Serialize, Deserialize)]
 pub enum Particle {
     /// The generic particle for a given depth.
     Common(Common),
@@ -46,6 +79,12 @@ pub enum Particle {
     Liquid(Liquid),
     /// Particles that are not mass-produced but also not specially spawned.
     Solid(Solid),
+    /// Burning particles that spread to flammable neighbors and burn out over time.
+    Fire(Fire),
+    /// Loose grains like sand that fall and pile up.
+    Powder(Powder),
+    /// Light particles like steam that rise and dissipate over time.
+    Gas(Gas),
 }
 
 impl Default for Particle {
@@ -55,78 +94,283 @@ impl Default for Particle {
 }
 
 impl ParticleType for Particle {
-    fn get_spritesheet_index(&self) -> u32 {
+    fn display_name(&self) -> &'static str {
         match self {
-            Particle::Common(common) => common.get_spritesheet_index(),
-            Particle::Special(special) => special.get_spritesheet_index(),
-            Particle::Liquid(fluid) => fluid.get_spritesheet_index(),
-            Particle::Solid(solid) => solid.get_spritesheet_index(),
+            Particle::Common(common) => common.display_name(),
+            Particle::Special(special) => special.display_name(),
+            Particle::Liquid(fluid) => fluid.display_name(),
+            Particle::Solid(solid) => solid.display_name(),
+            Particle::Fire(fire) => fire.display_name(),
+            Particle::Powder(powder) => powder.display_name(),
+            Particle::Gas(gas) => gas.display_name(),
+        }
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        match self {
+            Particle::Common(common) => common.description(),
+            Particle::Special(special) => special.description(),
+            Particle::Liquid(fluid) => fluid.description(),
+            Particle::Solid(solid) => solid.description(),
+            Particle::Fire(fire) => fire.description(),
+            Particle::Powder(powder) => powder.description(),
+            Particle::Gas(gas) => gas.description(),
+        }
+    }
+
+    fn get_atlas_id(&self) -> u32 {
+        match self {
+            Particle::Common(common) => common.get_atlas_id(),
+            Particle::Special(special) => special.get_atlas_id(),
+            Particle::Liquid(fluid) => fluid.get_atlas_id(),
+            Particle::Solid(solid) => solid.get_atlas_id(),
+            Particle::Fire(fire) => fire.get_atlas_id(),
+            Particle::Powder(powder) => powder.get_atlas_id(),
+            Particle::Gas(gas) => gas.get_atlas_id(),
+        }
+    }
+
+    fn get_flammability(&self) -> f32 {
+        match self {
+            Particle::Common(common) => common.get_flammability(),
+            Particle::Special(special) => special.get_flammability(),
+            Particle::Liquid(fluid) => fluid.get_flammability(),
+            Particle::Solid(solid) => solid.get_flammability(),
+            Particle::Fire(fire) => fire.get_flammability(),
+            Particle::Powder(powder) => powder.get_flammability(),
+            Particle::Gas(gas) => gas.get_flammability(),
         }
     }
 }
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter, Default)]
+
+impl Particle {
+    /// Every particle kind, including every nested variant. Stateful particles use their defaults.
+    pub fn all_variants() -> Vec<Particle> {
+        let specials = Ore::iter()
+            .map(Special::Ore)
+            .chain(Gem::iter().map(Special::Gem));
+        Common::iter()
+            .map(Particle::Common)
+            .chain(specials.map(Particle::Special))
+            .chain(Liquid::iter().map(Particle::Liquid))
+            .chain(Solid::iter().map(Particle::Solid))
+            .chain(Fire::iter().map(Particle::Fire))
+            .chain(Powder::iter().map(Particle::Powder))
+            .chain(Gas::iter().map(Particle::Gas))
+            .collect()
+    }
+
+    /// Whether this particle can catch fire.
+    pub fn is_flammable(&self) -> bool {
+        self.get_flammability() > 0.0
+    }
+
+    /// How hard this particle is to dig out. Loose particles like liquids are cleared instantly,
+    /// while `None` means it can't be dug at all.
+    pub fn get_hardness(&self) -> Option<u32> {
+        match self {
+            Particle::Common(Common::Grass | Common::Dirt) => Some(1),
+            Particle::Common(Common::Clay) => Some(2),
+            Particle::Common(Common::Stone) => Some(4),
+            Particle::Common(Common::Bedrock) => None,
+            Particle::Special(Special::Ore(_)) => Some(6),
+            Particle::Special(Special::Gem(_)) => Some(8),
+            Particle::Solid(Solid::Wood) => Some(3),
+            Particle::Solid(Solid::Torch) => Some(1),
+            Particle::Solid(Solid::Obsidian) => Some(10),
+            Particle::Powder(_) => Some(1),
+            Particle::Liquid(_) | Particle::Fire(_) | Particle::Gas(_) => Some(0),
+        }
+    }
+
+    /// Whether this particle keeps itself hotter than its surroundings, like lava. Heat sources
+    /// warm their neighbors and set flammable ones on fire without burning themselves.
+    pub fn is_heat_source(&self) -> bool {
+        self.get_resting_temperature()
+            .is_some_and(|temperature| temperature > AMBIENT_TEMPERATURE)
+    }
+
+    /// How readily heat flows into and out of this particle, from 0 (not at all) to 1.
+    pub fn get_conductivity(&self) -> f32 {
+        match self {
+            Particle::Liquid(liquid) => liquid.get_conductivity(),
+            Particle::Special(_) => 0.06,
+            Particle::Gas(_) => 0.02,
+            _ => 0.03,
+        }
+    }
+
+    /// The temperature this particle keeps its cell at, like lava staying hot and water staying
+    /// cool. `None` for particles that take on the temperature around them.
+    pub fn get_resting_temperature(&self) -> Option<f32> {
+        match self {
+            Particle::Liquid(liquid) => liquid.get_resting_temperature(),
+            _ => None,
+        }
+    }
+
+    /// What this particle turns into at `temperature`, like water boiling into steam.
+    /// `None` if it stays as it is.
+    pub fn get_phase_change(&self, temperature: f32) -> Option<Particle> {
+        match self {
+            Particle::Liquid(liquid) => liquid.get_phase_change(temperature),
+            _ => None,
+        }
+    }
+
+    /// How brightly this particle glows, from 0 (not at all) to [`MAX_LIGHT`].
+    pub fn get_light_emission(&self) -> u8 {
+        match self {
+            Particle::Solid(Solid::Torch) => 14,
+            Particle::Liquid(Liquid::Lava(_)) => 12,
+            Particle::Fire(Fire::Flame(_)) => 10,
+            Particle::Fire(Fire::Ember(_)) => 5,
+            _ => 0,
+        }
+    }
+
+    /// How many light levels are lost when light spreads into a cell holding this particle.
+    /// Light reaches a little way into solid ground, so cave walls aren't pitch black.
+    pub fn get_light_falloff(&self) -> u8 {
+        match self {
+            Particle::Fire(_) | Particle::Gas(_) => 1,
+            Particle::Liquid(_) => 2,
+            _ => 4,
+        }
+    }
+
+    /// Whether daylight shines straight through this particle, like it does through air.
+    pub fn is_transparent(&self) -> bool {
+        self.get_light_falloff() <= 1
+    }
+
+    /// Whether this is the same particle as `other` with the same per-cell state. `==` only
+    /// compares kinds, ignoring e.g. a liquid's direction or how long a fire has left.
+    pub fn is_identical(&self, other: &Particle) -> bool {
+        match (self, other) {
+            (Particle::Liquid(a), Particle::Liquid(b)) => a == b && a.get_state() == b.get_state(),
+            (Particle::Gas(a), Particle::Gas(b)) => a == b && a.get_state() == b.get_state(),
+            (Particle::Fire(Fire::Flame(a)), Particle::Fire(Fire::Flame(b)))
+            | (Particle::Fire(Fire::Ember(a)), Particle::Fire(Fire::Ember(b))) => a == b,
+            _ => self == other,
+        }
+    }
+
+    /// Whether this particle changes on its own and needs its chunk simulated.
+    pub fn is_dynamic(&self) -> bool {
+        matches!(
+            self,
+            Particle::Liquid(_) | Particle::Fire(_) | Particle::Powder(_) | Particle::Gas(_)
+        )
+    }
+
+    /// How heavy a loose particle is. Heavier particles sink through lighter liquids and gases.
+    /// `None` for particles that hold their place.
+    pub fn get_density(&self) -> Option<u32> {
+        match self {
+            Particle::Liquid(liquid) => Some(liquid.get_density()),
+            Particle::Gas(gas) => Some(gas.get_density()),
+            Particle::Powder(powder) => Some(powder.get_density()),
+            _ => None,
+        }
+    }
+
+    /// The layer this particle is drawn on. Particle definitions can override its draw order,
+    /// see [`ParticleDefinition::draw_order`](definition::ParticleDefinition::draw_order).
+    pub fn render_layer(&self) -> RenderLayer {
+        match self {
+            Particle::Common(_)
+            | Particle::Special(_)
+            | Particle::Solid(_)
+            | Particle::Powder(_) => RenderLayer::Solid,
+            Particle::Liquid(_) => RenderLayer::Liquid,
+            Particle::Fire(_) | Particle::Gas(_) => RenderLayer::Effect,
+        }
+    }
+}
+
+/// The draw layers particles are sorted into, from back to front. Liquid cells are also tagged
+/// with [`LIQUID_CELL_BIT`](crate::render::chunk_material::LIQUID_CELL_BIT) for the shader to
+/// smooth.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, EnumIter)]
+pub enum RenderLayer {
+    Solid = 0,
+    Liquid = 1,
+    /// Effects like fire and gases, drawn over everything else.
+    Effect = 2,
+}
+
+impl RenderLayer {
+    /// The draw order of particles on this layer, unless their definition sets one, see
+    /// [`encode_draw_order`](crate::render::chunk_material::encode_draw_order). Higher values
+    /// draw over lower ones.
+    pub fn draw_order(&self) -> u32 {
+        *self as u32
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter, Default, Serialize, Deserialize)]
 pub enum Common {
+    Grass,
     #[default]
     Dirt,
+    Clay,
     Stone,
+    Bedrock,
 }
 
 impl ParticleType for Common {
-    fn get_spritesheet_index(&self) -> u32 {
+    fn display_name(&self) -> &'static str {
         match self {
-            Common::Dirt => 1,
-            Common::Stone => 2,
+            Common::Grass => "Grass",
+            Common::Dirt => "Dirt",
+            Common::Clay => "Clay",
+            Common::Stone => "Stone",
+            Common::Bedrock => "Bedrock",
         }
     }
-}
 
-impl ParticleType for Special {
-    fn get_spritesheet_index(&self) -> u32 {
+    fn description(&self) -> Option<&'static str> {
+        Some(match self {
+            Common::Grass => "Topsoil covering the surface. Burns.",
+            Common::Dirt => "Loose soil just below the surface.",
+            Common::Clay => "Dense soil found between dirt and stone.",
+            Common::Stone => "The bulk of the underground.",
+            Common::Bedrock => "The indestructible floor of the world.",
+        })
+    }
+
+    fn get_flammability(&self) -> f32 {
         match self {
-            Special::Ore(ore) => ore.get_spritesheet_index(),
-            Special::Gem(gem) => gem.get_spritesheet_index(),
+            Common::Grass => 0.3,
+            _ => 0.0,
         }
     }
 }
 
-impl Common {
-    pub fn min_depth(&self) -> u32 {
+impl ParticleType for Special {
+    fn display_name(&self) -> &'static str {
         match self {
-            Common::Dirt => 0,
-            Common::Stone => 12,
+            Special::Ore(ore) => ore.display_name(),
+            Special::Gem(gem) => gem.display_name(),
         }
     }
 
-    pub fn max_depth(&self) -> u32 {
+    fn description(&self) -> Option<&'static str> {
         match self {
-            Common::Dirt => 12,
-            Common::Stone => u32::MAX,
+            Special::Ore(ore) => ore.description(),
+            Special::Gem(gem) => gem.description(),
         }
     }
 
-    /// Returns the appropriate common particle for a given depth, if the depth falls within an exclusive range.
-    /// Uses half-open intervals [min, max) where min is inclusive and max is exclusive.
-    /// Panics if no variant's range contains the depth or if multiple variants' ranges contain the depth.
-    pub fn get_exclusive_at_depth(depth: u32) -> Common {
-        let mut result: Option<Common> = None;
-
-        for variant in Common::iter() {
-            if depth >= variant.min_depth() && depth < variant.max_depth() {
-                if result.is_some() {
-                    panic!(
-                        "Multiple common particles valid at depth {}. This indicates overlapping ranges.",
-                        depth
-                    );
-                }
-                result = Some(variant);
-            }
+    fn get_atlas_id(&self) -> u32 {
+        match self {
+            Special::Ore(ore) => ore.get_atlas_id(),
+            Special::Gem(gem) => gem.get_atlas_id(),
         }
-
-        result.unwrap_or_else(|| panic!("Cannot get common particle at depth {}.", depth))
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter, Serialize, Deserialize)]
 pub enum Special {
     Ore(Ore),
     Gem(Gem),
@@ -139,30 +383,25 @@ impl Default for Special {
 }
 
 impl Special {
-    pub fn min_depth(&self) -> u32 {
+    pub fn spawns_in(&self, biome: Biome) -> bool {
         match self {
-            Special::Ore(ore) => ore.min_depth(),
-            Special::Gem(gem) => gem.min_depth(),
+            Special::Ore(ore) => ore.spawns_in(biome),
+            Special::Gem(gem) => gem.spawns_in(biome),
         }
     }
 
-    pub fn max_depth(&self) -> u32 {
-        match self {
-            Special::Ore(ore) => ore.max_depth(),
-            Special::Gem(gem) => gem.max_depth(),
-        }
+    pub fn all_variants() -> Vec<Special> {
+        Special::iter().collect()
     }
 
-    pub fn spawn_chance(&self) -> i32 {
+    /// A unique, stable id for each variant, numbering ores first and then gems.
+    /// Used to order particles deterministically when nothing else tells them apart.
+    pub fn variant_id(&self) -> u32 {
         match self {
-            Special::Ore(ore) => ore.spawn_chance(),
-            Special::Gem(gem) => gem.spawn_chance(),
+            Special::Ore(ore) => *ore as u32,
+            Special::Gem(gem) => Ore::iter().count() as u32 + *gem as u32,
         }
     }
-
-    pub fn all_variants() -> Vec<Special> {
-        Special::iter().collect()
-    }
 }
 
 impl From<Common> for Particle {
@@ -171,13 +410,31 @@ impl From<Common> for Particle {
     }
 }
 
+impl From<Fire> for Particle {
+    fn from(fire: Fire) -> Self {
+        Particle::Fire(fire)
+    }
+}
+
+impl From<Gas> for Particle {
+    fn from(gas: Gas) -> Self {
+        Particle::Gas(gas)
+    }
+}
+
+impl From<Powder> for Particle {
+    fn from(powder: Powder) -> Self {
+        Particle::Powder(powder)
+    }
+}
+
 impl From<Liquid> for Particle {
     fn from(liquid: Liquid) -> Self {
         Particle::Liquid(liquid)
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
 pub enum Direction {
     /// The particle is not moving.
     Still = 0,
@@ -202,8 +459,8 @@ impl Direction {
     }
 
     /// Returns a random direction.
-    pub fn random() -> Direction {
-        if rand::random() {
+    pub fn random(rng: &mut impl Rng) -> Direction {
+        if rng.random() {
             Direction::Left
         } else {
             Direction::Right
@@ -1,37 +1,100 @@
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
 use super::{ParticleType, WorldGenType};
+use crate::world::biome::Biome;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, EnumIter, Default, Serialize, Deserialize)]
 pub enum Ore {
     #[default]
     Gold,
+    Iron,
+    Copper,
+    Coal,
+    Diamond,
 }
 
-impl WorldGenType for Ore {
-    fn min_depth(&self) -> u32 {
-        match self {
-            Ore::Gold => 23,
-        }
-    }
+/// How the veins of an ore are shaped, see [`crate::world::generator::spawn_vein`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VeinProfile {
+    /// The fewest cells a vein grows around the cell it starts from.
+    pub min_size: u32,
+    /// The most cells a vein grows around the cell it starts from.
+    pub max_size: u32,
+    /// The chance each new cell grows off a random cell of the vein instead of the last one
+    /// placed, from 0 to 1. Blobby veins form round clusters, the rest wind out in strands.
+    pub blobbiness: f32,
+    /// The chance each new cell grows straight sideways, from 0 to 1. Veins with a strong bias
+    /// form flat seams.
+    pub horizontal_bias: f32,
+}
 
-    fn max_depth(&self) -> u32 {
+impl Ore {
+    /// How veins of this ore are shaped. Deeper ores form larger clusters, but spawn more rarely.
+    pub fn vein_profile(&self) -> VeinProfile {
         match self {
-            Ore::Gold => u32::MAX,
+            Ore::Coal => VeinProfile {
+                min_size: 4,
+                max_size: 8,
+                blobbiness: 0.2,
+                horizontal_bias: 0.7,
+            },
+            Ore::Copper => VeinProfile {
+                min_size: 3,
+                max_size: 6,
+                blobbiness: 0.1,
+                horizontal_bias: 0.0,
+            },
+            Ore::Iron => VeinProfile {
+                min_size: 3,
+                max_size: 7,
+                blobbiness: 0.5,
+                horizontal_bias: 0.2,
+            },
+            Ore::Gold => VeinProfile {
+                min_size: 5,
+                max_size: 9,
+                blobbiness: 0.6,
+                horizontal_bias: 0.0,
+            },
+            Ore::Diamond => VeinProfile {
+                min_size: 8,
+                max_size: 14,
+                blobbiness: 0.9,
+                horizontal_bias: 0.0,
+            },
         }
     }
+}
 
-    fn spawn_chance(&self) -> i32 {
+impl WorldGenType for Ore {
+    fn spawns_in(&self, biome: Biome) -> bool {
         match self {
-            Ore::Gold => 20,
+            // Coal forms from buried plants, which deserts and volcanic ground lack.
+            Ore::Coal => !matches!(biome, Biome::Desert | Biome::Volcanic),
+            _ => true,
         }
     }
 }
 
 impl ParticleType for Ore {
-    fn get_spritesheet_index(&self) -> u32 {
+    fn display_name(&self) -> &'static str {
         match self {
-            Ore::Gold => 4,
+            Ore::Gold => "Gold Ore",
+            Ore::Iron => "Iron Ore",
+            Ore::Copper => "Copper Ore",
+            Ore::Coal => "Coal",
+            Ore::Diamond => "Diamond",
         }
     }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(match self {
+            Ore::Gold => "Spawns in clusters deep underground.",
+            Ore::Iron => "Spawns in small clumps throughout the stone layer.",
+            Ore::Copper => "Spawns in thin, winding veins near the surface.",
+            Ore::Coal => "Spawns in flat seams just below the dirt layer.",
+            Ore::Diamond => "Spawns in rare, large clusters deep in the stone layer.",
+        })
+    }
 }
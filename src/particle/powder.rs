@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use super::ParticleType;
+
+/// Loose grains that fall and pile up, sinking through liquids lighter than them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, EnumIter, Serialize, Deserialize)]
+pub enum Powder {
+    #[default]
+    Sand,
+    Gravel,
+    /// What burnt wood leaves behind.
+    Ash,
+}
+
+impl Powder {
+    /// How deep the drop next to a grain has to be before it slides down the side of a pile.
+    /// Larger values make steeper piles, i.e. a higher angle of repose.
+    pub fn get_slide_drop(&self) -> u32 {
+        match self {
+            Powder::Sand | Powder::Ash => 1,
+            Powder::Gravel => 2,
+        }
+    }
+
+    /// How heavy a grain is, on the same scale as [`super::Liquid::get_density`].
+    /// Powders sink through liquids that are lighter than them.
+    pub fn get_density(&self) -> u32 {
+        match self {
+            Powder::Sand => 20,
+            Powder::Gravel => 25,
+            Powder::Ash => 14,
+        }
+    }
+}
+
+impl ParticleType for Powder {
+    fn display_name(&self) -> &'static str {
+        match self {
+            Powder::Sand => "Sand",
+            Powder::Gravel => "Gravel",
+            Powder::Ash => "Ash",
+        }
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(match self {
+            Powder::Sand => "Fine grains that pile into gentle slopes and sink in water.",
+            Powder::Gravel => "Coarse stones that pile into steep slopes and sink in water.",
+            Powder::Ash => "Light flakes left behind by burnt wood.",
+        })
+    }
+}
@@ -1,17 +1,38 @@
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
 use super::ParticleType;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, EnumIter)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, EnumIter, Serialize, Deserialize)]
 pub enum Solid {
     #[default]
     Obsidian,
+    Wood,
+    /// Glows brightly, lighting up the caves around it.
+    Torch,
 }
 
 impl ParticleType for Solid {
-    fn get_spritesheet_index(&self) -> u32 {
+    fn display_name(&self) -> &'static str {
         match self {
-            Solid::Obsidian => 7,
+            Solid::Obsidian => "Obsidian",
+            Solid::Wood => "Wood",
+            Solid::Torch => "Torch",
+        }
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some(match self {
+            Solid::Obsidian => "Hardened lava, left behind when water meets lava.",
+            Solid::Wood => "Smolders for a long time when lit, leaving ash behind.",
+            Solid::Torch => "Lights up the dark around it.",
+        })
+    }
+
+    fn get_flammability(&self) -> f32 {
+        match self {
+            Solid::Wood => 0.05,
+            Solid::Obsidian | Solid::Torch => 0.0,
         }
     }
 }
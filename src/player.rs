@@ -1,14 +1,38 @@
+use std::collections::{HashMap, HashSet};
+use std::mem::discriminant;
+
 use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
 use bevy::prelude::*;
 
-use crate::particle::Direction;
-use crate::particle::Liquid::{Lava, Water};
-use crate::particle::Particle::Liquid;
-use crate::utils::coords::bresenham_line;
+use crate::editor::editor_closed;
+use crate::health::{Health, Knockback};
+use crate::particle::{Liquid, Particle, ParticleType, Special, PARTICLE_SIZE};
+use crate::swimming::{Breath, Submersion};
+use crate::utils::coords::{bresenham_line, cursor_cell, screen_to_world, world_to_screen};
+use crate::world::generator::WorldSeed;
+use crate::world::history::{CellEdit, EditHistory, EditLayer};
+use crate::world::map::Map;
 
 // Constants for player
-const PLAYER_SIZE: u32 = 20;
+/// The width and height of the player in pixels.
+pub(crate) const PLAYER_SIZE: u32 = 20;
 const PLAYER_SPEED: f32 = 150.0;
+/// The keys that pick a particle category to place, in the order of the [`Particle`] variants.
+const PALETTE_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+/// How much hardness is dug out of a cell per second, see [`Particle::get_hardness`].
+const DIG_RATE: f32 = 10.0;
+/// The largest size the brush can be set to, see [`Brush`].
+pub const MAX_BRUSH_SIZE: u32 = 10;
 
 // Player plugin
 pub struct PlayerPlugin;
@@ -18,16 +42,29 @@ impl Plugin for PlayerPlugin {
         app.init_resource::<DebugMode>()
             .init_resource::<CameraConnection>()
             .init_resource::<LastMousePosition>()
-            .init_resource::<DeletionSize>()
+            .init_resource::<LineStart>()
+            .init_resource::<Brush>()
+            .init_resource::<Inventory>()
+            .init_resource::<SelectedParticle>()
             .add_plugins(FrameTimeDiagnosticsPlugin)
             .add_systems(Startup, spawn_player)
             .add_systems(Startup, setup_fps_counter)
+            .add_systems(Startup, setup_inventory_text)
+            .add_systems(Startup, setup_selected_particle_text)
             .add_systems(Update, player_movement)
             .add_systems(Update, toggle_debug_mode)
             .add_systems(Update, toggle_camera_connection)
             .add_systems(Update, update_fps_counter)
-            .add_systems(Update, handle_mouse_interactions)
-            .add_systems(Update, handle_deletion_size_change);
+            .add_systems(Update, update_seed_text)
+            .add_systems(Update, update_queue_text)
+            .add_systems(Update, update_coverage_text)
+            .add_systems(Update, update_inventory_text)
+            .add_systems(Update, select_particle)
+            .add_systems(Update, update_selected_particle_text)
+            .add_systems(Update, handle_mouse_interactions.run_if(editor_closed))
+            .add_systems(Update, undo_redo_hotkeys)
+            .add_systems(Update, handle_brush_change)
+            .add_systems(Update, draw_brush_preview.run_if(editor_closed));
     }
 }
 
@@ -38,6 +75,21 @@ pub struct Player;
 #[derive(Component)]
 pub struct FpsText;
 
+#[derive(Component)]
+pub struct SeedText;
+
+#[derive(Component)]
+pub struct QueueText;
+
+#[derive(Component)]
+pub struct CoverageText;
+
+#[derive(Component)]
+pub struct InventoryText;
+
+#[derive(Component)]
+pub struct SelectedParticleText;
+
 #[derive(Component)]
 struct FpsContainer;
 
@@ -60,15 +112,122 @@ impl Default for CameraConnection {
     }
 }
 
-// Resource to track the deletion size
-#[derive(Resource)]
-pub struct DeletionSize {
+/// The shapes the [`Brush`] can take.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BrushShape {
+    /// Every cell within half the brush's size of the cursor.
+    Circle,
+    /// A square the size of the brush around the cursor.
+    #[default]
+    Square,
+    /// A line from where the button is pressed to where it's released, as thick as the brush,
+    /// for floors and tunnels.
+    Line,
+}
+
+impl BrushShape {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            BrushShape::Circle => "Circle",
+            BrushShape::Square => "Square",
+            BrushShape::Line => "Line",
+        }
+    }
+
+    /// The shape after this one when cycling through them.
+    pub fn next(&self) -> Self {
+        match self {
+            BrushShape::Circle => BrushShape::Square,
+            BrushShape::Square => BrushShape::Line,
+            BrushShape::Line => BrushShape::Circle,
+        }
+    }
+}
+
+/// The brush the mouse digs and places with. Digging and placing share its shape and size.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Brush {
+    pub shape: BrushShape,
+    /// How many cells across the brush is. Even sizes reach one cell further left and down.
     pub size: u32,
 }
 
-impl Default for DeletionSize {
+impl Default for Brush {
     fn default() -> Self {
-        Self { size: 2 } // Default size is 2x2
+        Self {
+            shape: BrushShape::default(),
+            size: 2, // Default size is 2x2
+        }
+    }
+}
+
+impl Brush {
+    /// The offsets from the cursor the brush spans on each axis, inclusive.
+    fn span(&self) -> (i32, i32) {
+        let half = self.size as i32 / 2;
+        (-half, self.size as i32 - 1 - half)
+    }
+
+    /// Whether the cell `offset` away from the cursor is covered by the brush. Lines are drawn
+    /// by stamping a square along them, see [`Brush::for_each_cell_on_line`].
+    pub fn covers(&self, offset: IVec2) -> bool {
+        let (low, high) = self.span();
+        if offset.min_element() < low || offset.max_element() > high {
+            return false;
+        }
+        match self.shape {
+            BrushShape::Square | BrushShape::Line => true,
+            BrushShape::Circle => {
+                // In half cells, so even sizes can be measured from the corner they're centered on.
+                let from_center = offset * 2 - IVec2::splat(low + high);
+                let diameter = self.size as i32 - 1;
+                from_center.length_squared() <= diameter * diameter + 1
+            }
+        }
+    }
+
+    /// Calls `f` with every cell the brush covers around `center` that's within the map bounds.
+    pub fn for_each_cell(
+        &self,
+        center: UVec2,
+        map_width: u32,
+        map_height: u32,
+        mut f: impl FnMut(UVec2),
+    ) {
+        let (low, high) = self.span();
+        for x_offset in low..=high {
+            for y_offset in low..=high {
+                if !self.covers(IVec2::new(x_offset, y_offset)) {
+                    continue;
+                }
+                let x = center.x as i32 + x_offset;
+                let y = center.y as i32 + y_offset;
+                if x < 0 || y < 0 || x >= map_width as i32 || y >= map_height as i32 {
+                    continue;
+                }
+                f(UVec2::new(x as u32, y as u32));
+            }
+        }
+    }
+
+    /// Calls `f` once with every cell the brush covers along the line from `start` to `end`
+    /// that's within the map bounds.
+    pub fn for_each_cell_on_line(
+        &self,
+        start: UVec2,
+        end: UVec2,
+        map_width: u32,
+        map_height: u32,
+        mut f: impl FnMut(UVec2),
+    ) {
+        let mut cells = HashSet::new();
+        for point in bresenham_line(start, end) {
+            self.for_each_cell(point, map_width, map_height, |cell| {
+                if cells.insert(cell) {
+                    f(cell);
+                }
+            });
+        }
     }
 }
 
@@ -76,6 +235,99 @@ impl Default for DeletionSize {
 #[derive(Resource, Default)]
 struct LastMousePosition(Option<UVec2>);
 
+/// Where and when a mouse button was pressed with the [`BrushShape::Line`] brush, until it's
+/// released.
+#[derive(Resource, Default)]
+struct LineStart(Option<(UVec2, f32)>);
+
+/// The particle placed with right click.
+/// Number keys pick a particle category, and pressing the same key again cycles through it.
+#[derive(Resource)]
+pub struct SelectedParticle {
+    pub particle: Particle,
+}
+
+impl Default for SelectedParticle {
+    fn default() -> Self {
+        Self {
+            particle: Particle::Liquid(Liquid::Water(Default::default())),
+        }
+    }
+}
+
+impl SelectedParticle {
+    /// The particle categories in palette order, each with every particle in it.
+    pub fn categories() -> Vec<Vec<Particle>> {
+        let mut categories: Vec<Vec<Particle>> = Vec::new();
+        for particle in Particle::all_variants() {
+            match categories
+                .iter_mut()
+                .find(|category| discriminant(&category[0]) == discriminant(&particle))
+            {
+                Some(category) => category.push(particle),
+                None => categories.push(vec![particle]),
+            }
+        }
+        categories
+    }
+
+    /// Select the first particle of the `index`th category, or the next one in it if the
+    /// selection is already in that category. Returns false if there's no such category.
+    pub fn select_category(&mut self, index: usize) -> bool {
+        let Some(category) = Self::categories().into_iter().nth(index) else {
+            return false;
+        };
+        self.particle = match category.iter().position(|&p| p == self.particle) {
+            Some(current) => category[(current + 1) % category.len()],
+            None => category[0],
+        };
+        true
+    }
+}
+
+/// The ore and gems the player has mined.
+#[derive(Resource, Default, Debug)]
+pub struct Inventory {
+    counts: HashMap<Special, u32>,
+}
+
+impl Inventory {
+    pub fn add(&mut self, special: Special) {
+        *self.counts.entry(special).or_default() += 1;
+    }
+
+    /// Takes one `special` back out, e.g. when the dig that collected it is undone.
+    pub fn remove(&mut self, special: Special) {
+        if let Some(count) = self.counts.get_mut(&special) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&special);
+            }
+        }
+    }
+
+    /// How many of `special` have been collected.
+    pub fn count(&self, special: Special) -> u32 {
+        self.counts.get(&special).copied().unwrap_or(0)
+    }
+
+    /// How many particles have been collected in total.
+    pub fn total(&self) -> u32 {
+        self.counts.values().sum()
+    }
+
+    /// Every collected kind with its count, sorted by name so the HUD keeps a stable order.
+    pub fn entries(&self) -> Vec<(Special, u32)> {
+        let mut entries: Vec<(Special, u32)> = self
+            .counts
+            .iter()
+            .map(|(&special, &count)| (special, count))
+            .collect();
+        entries.sort_by_key(|(special, _)| special.display_name());
+        entries
+    }
+}
+
 // Spawn the player
 fn spawn_player(mut commands: Commands) {
     info!("Spawning player");
@@ -83,6 +335,10 @@ fn spawn_player(mut commands: Commands) {
     commands.spawn((
         Player,
         Name::new("Player"),
+        Health::default(),
+        Knockback::default(),
+        Submersion::default(),
+        Breath::default(),
         Sprite {
             color: Color::srgb(0.2, 0.2, 0.8), // Blue color
             custom_size: Some(Vec2::new(PLAYER_SIZE as f32, PLAYER_SIZE as f32)),
@@ -92,18 +348,27 @@ fn spawn_player(mut commands: Commands) {
     ));
 }
 
+/// The corners of the square the player covers around `center`, in world coordinates.
+pub fn player_world_bounds(center: Vec2, map: &Map) -> (Vec2, Vec2) {
+    let half_size = Vec2::splat(PLAYER_SIZE as f32 / 2.0);
+    (
+        screen_to_world(center - half_size, map.width, map.height),
+        screen_to_world(center + half_size, map.width, map.height),
+    )
+}
+
 // Player movement system
 fn player_movement(
     time: Res<Time>,
     keyboard: Res<ButtonInput<KeyCode>>,
     camera_connection: Res<CameraConnection>,
-    mut player_query: Query<&mut Transform, With<Player>>,
+    mut player_query: Query<(&mut Transform, Option<&Submersion>), With<Player>>,
 ) {
     if !camera_connection.connected_to_player {
         return;
     }
 
-    if let Ok(mut transform) = player_query.get_single_mut() {
+    if let Ok((mut transform, submersion)) = player_query.get_single_mut() {
         let mut direction = Vec2::ZERO;
 
         // AD movement (horizontal)
@@ -125,7 +390,11 @@ fn player_movement(
         // Move player
         if direction != Vec2::ZERO {
             let normalized_direction = direction.normalize_or_zero();
-            let delta = normalized_direction * PLAYER_SPEED * time.delta_secs();
+            let mut delta = normalized_direction * PLAYER_SPEED * time.delta_secs();
+            // Liquid drags on the player as they swim through it
+            if let Some(submersion) = submersion {
+                delta.x *= submersion.speed_factor();
+            }
             transform.translation.x += delta.x;
             transform.translation.y += delta.y;
 
@@ -150,21 +419,26 @@ fn toggle_debug_mode(keyboard: Res<ButtonInput<KeyCode>>, mut debug_mode: ResMut
     }
 }
 
-// Setup FPS counter
+// Setup FPS counter in the bottom left corner, above the selected particle, since the minimap
+// takes the bottom right
 fn setup_fps_counter(mut commands: Commands) {
     commands
         .spawn((
             FpsContainer,
             Node {
                 position_type: PositionType::Absolute,
-                bottom: Val::Px(10.0),
-                right: Val::Px(10.0),
+                bottom: Val::Px(40.0),
+                left: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
                 ..default()
             },
             Visibility::Hidden, // Start hidden
         ))
         .with_children(|parent| {
             parent.spawn((FpsText, Text::from("FPS: 0")));
+            parent.spawn((SeedText, Text::from("Seed: -")));
+            parent.spawn((QueueText, Text::from("Interchunk queue: 0")));
+            parent.spawn((CoverageText, Text::from("Chunks: -")));
         });
 }
 
@@ -196,6 +470,66 @@ fn update_fps_counter(
     }
 }
 
+// Show the current world seed in the debug HUD
+fn update_seed_text(
+    seed: Option<Res<WorldSeed>>,
+    mut seed_query: Query<&mut Text, With<SeedText>>,
+) {
+    let Some(seed) = seed else {
+        return;
+    };
+
+    if seed.is_changed() {
+        for mut text in &mut seed_query {
+            *text = Text::from(format!("Seed: {}", seed.0));
+        }
+    }
+}
+
+// Show how many cross-chunk moves the simulation queued, so runaway flow is visible
+fn update_queue_text(
+    debug_mode: Res<DebugMode>,
+    map: Option<Res<Map>>,
+    mut queue_query: Query<&mut Text, With<QueueText>>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+
+    if debug_mode.enabled {
+        for mut text in &mut queue_query {
+            *text = Text::from(format!(
+                "Interchunk queue: {} (peak {})",
+                map.interchunk_queue_len(),
+                map.peak_interchunk_queue_len()
+            ));
+        }
+    }
+}
+
+// Show how many chunks are active and how many of those are actually simulated
+fn update_coverage_text(
+    debug_mode: Res<DebugMode>,
+    map: Option<Res<Map>>,
+    mut coverage_query: Query<&mut Text, With<CoverageText>>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+
+    if debug_mode.enabled {
+        for mut text in &mut coverage_query {
+            *text = Text::from(format!(
+                "Chunks: {} active / {} total, {} simulating ({} settled)",
+                map.active_chunk_count(),
+                map.chunk_count(),
+                map.simulating_chunk_count(),
+                map.settled_chunk_count()
+            ));
+        }
+    }
+}
+
 // New system to toggle camera connection with spacebar
 fn toggle_camera_connection(
     keyboard: Res<ButtonInput<KeyCode>>,
@@ -214,49 +548,144 @@ fn toggle_camera_connection(
     }
 }
 
-/// Iterates over a `size x size` area centered at `center_pos`, calling `f` for each
-/// position that is within the given bounds.
-fn for_each_in_area(
-    center_pos: UVec2,
-    map_width: u32,
-    map_height: u32,
-    size: u32,
-    mut f: impl FnMut(UVec2),
+fn place_particles_at(
+    cells: impl IntoIterator<Item = UVec2>,
+    map: &mut Map,
+    history: &mut EditHistory,
+    particle: Particle,
 ) {
-    let half_size = size / 2;
-    for x_offset in 0..size {
-        for y_offset in 0..size {
-            let x = center_pos.x as i32 + x_offset as i32 - half_size as i32;
-            let y = center_pos.y as i32 + y_offset as i32 - half_size as i32;
-            if x < 0 || y < 0 || x >= map_width as i32 || y >= map_height as i32 {
-                continue;
+    for pos in cells {
+        history.set_particle(map, pos, Some(particle));
+    }
+}
+
+/// Places walls of `particle` behind `cells`. Loose particles like liquids can't be walls, so
+/// nothing is placed for them.
+fn place_walls_at(
+    cells: impl IntoIterator<Item = UVec2>,
+    map: &mut Map,
+    history: &mut EditHistory,
+    particle: Particle,
+) {
+    if particle.is_dynamic() {
+        return;
+    }
+    for pos in cells {
+        history.set_wall(map, pos, Some(particle));
+    }
+}
+
+/// Digs `cells` for another `seconds`, or the walls behind them, recording what was dug in
+/// `history`.
+fn dig_cells(
+    cells: impl IntoIterator<Item = UVec2>,
+    walls: bool,
+    seconds: f32,
+    map: &mut Map,
+    history: &mut EditHistory,
+    inventory: &mut Inventory,
+) {
+    for cell in cells {
+        let (layer, before) = if walls {
+            (EditLayer::Wall, map.get_background_at(cell))
+        } else {
+            (EditLayer::Particle, map.get_particle_at(cell))
+        };
+        let dug = if walls {
+            dig_wall(map, cell)
+        } else {
+            dig_particle(map, cell, seconds, inventory)
+        };
+        if dug {
+            history.record(CellEdit {
+                position: cell,
+                layer,
+                before,
+                after: None,
+            });
+            // Undoing the dig takes back what it collected.
+            if let Some(Particle::Special(special)) = before.filter(|_| !walls) {
+                history.record_collected(special);
             }
-            f(UVec2::new(x as u32, y as u32));
         }
     }
 }
 
-fn place_fluid_at(
-    center_pos: UVec2,
-    map: &mut crate::world::Map,
-    size: u32,
-    fluid_type: crate::particle::Liquid,
+/// Edits along the line from where a mouse button was pressed to where it's released, for the
+/// [`BrushShape::Line`] brush. The line is dug for as long as the button was held.
+#[allow(clippy::too_many_arguments)]
+fn draw_line(
+    mouse_input: &ButtonInput<MouseButton>,
+    cell: UVec2,
+    walls: bool,
+    now: f32,
+    particle: Particle,
+    brush: &Brush,
+    line_start: &mut LineStart,
+    map: &mut Map,
+    history: &mut EditHistory,
+    inventory: &mut Inventory,
 ) {
-    for_each_in_area(center_pos, map.width, map.height, size, |pos| {
-        map.set_particle_at(pos, Some(Liquid(fluid_type)));
-    });
+    if mouse_input.any_just_pressed([MouseButton::Left, MouseButton::Right]) {
+        line_start.0 = Some((cell, now));
+    }
+    let dig = mouse_input.just_released(MouseButton::Left);
+    if !dig && !mouse_input.just_released(MouseButton::Right) {
+        return;
+    }
+    let Some((start, pressed_at)) = line_start.0.take() else {
+        return;
+    };
+
+    let mut cells = Vec::new();
+    brush.for_each_cell_on_line(start, cell, map.width, map.height, |pos| cells.push(pos));
+    if dig {
+        dig_cells(cells, walls, now - pressed_at, map, history, inventory);
+    } else if walls {
+        place_walls_at(cells, map, history, particle);
+    } else {
+        place_particles_at(cells, map, history, particle);
+    }
+    history.end_stroke();
 }
 
 // Helper function to handle mouse interactions
+#[allow(clippy::too_many_arguments)]
 fn handle_mouse_interactions(
     mouse_input: Res<ButtonInput<MouseButton>>,
     keyboard: Res<ButtonInput<KeyCode>>,
+    selected: Res<SelectedParticle>,
     windows: Query<&Window>,
     camera_q: Query<(&Camera, &GlobalTransform)>,
+    time: Res<Time>,
     mut map: ResMut<crate::world::Map>,
     mut last_pos: ResMut<LastMousePosition>,
-    deletion_size: Res<DeletionSize>,
+    mut line_start: ResMut<LineStart>,
+    brush: Res<Brush>,
+    mut inventory: ResMut<Inventory>,
+    mut history: ResMut<EditHistory>,
 ) {
+    // Holding shift works on the walls behind the particles instead
+    let walls = keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+
+    if brush.shape == BrushShape::Line {
+        if let Some(cell) = cursor_cell(&windows, &camera_q, map.width, map.height) {
+            draw_line(
+                &mouse_input,
+                cell,
+                walls,
+                time.elapsed_secs(),
+                selected.particle,
+                &brush,
+                &mut line_start,
+                &mut map,
+                &mut history,
+                &mut inventory,
+            );
+        }
+        return;
+    }
+
     // Handle case when left mouse button is released - reset last position
     if mouse_input.just_released(MouseButton::Left) {
         last_pos.0 = None;
@@ -266,77 +695,243 @@ fn handle_mouse_interactions(
     // Check which mouse button is being pressed
     let left_pressed = mouse_input.pressed(MouseButton::Left);
     let right_pressed = mouse_input.pressed(MouseButton::Right);
-    // Check if shift is pressed
-    let shift_pressed =
-        keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
 
     if !left_pressed && !right_pressed {
+        // Everything edited while a button was held is undone together.
+        history.end_stroke();
         return; // Exit early if no relevant mouse button is pressed
     }
 
-    // Get the primary window
-    let window = windows.single();
-
-    // Get cursor position in window if available
-    if let Some(cursor_position) = window.cursor_position() {
-        // Get camera for screen to world conversion
-        let (camera, camera_transform) = camera_q.single();
-
-        // Convert screen position to world coordinates using the 2D-specific method
-        if let Ok(world_position) = camera.viewport_to_world_2d(camera_transform, cursor_position) {
-            // Convert to our map's coordinates
-            let current_pos =
-                crate::utils::coords::cursor_to_map_coords(world_position, map.width, map.height);
-
-            // Handle left click (remove particles)
-            if left_pressed {
-                if let Some(last_mouse_pos) = last_pos.0 {
-                    // Draw a line using Bresenham's line algorithm to get all points between last and current
-                    let line_points = bresenham_line(last_mouse_pos, current_pos);
-
-                    // Remove particles at all points along the line
-                    for point in line_points {
-                        remove_particles_at(point, &mut map, deletion_size.size);
-                    }
-                } else {
-                    // First click, just remove at current position
-                    remove_particles_at(current_pos, &mut map, deletion_size.size);
-                }
+    let Some(current_pos) = cursor_cell(&windows, &camera_q, map.width, map.height) else {
+        return;
+    };
+
+    // Handle left click (dig particles)
+    if left_pressed {
+        // Draw a line using Bresenham's line algorithm to get all points between last and current
+        let line_points = match last_pos.0 {
+            Some(last_mouse_pos) => bresenham_line(last_mouse_pos, current_pos),
+            // First click, just dig at current position
+            None => vec![current_pos],
+        };
 
-                // Update last position to current
-                last_pos.0 = Some(current_pos);
-            }
+        // Every cell along the line is dug once per frame, however many points cover it.
+        let mut cells = HashSet::new();
+        for point in line_points {
+            brush.for_each_cell(point, map.width, map.height, |pos| {
+                cells.insert(pos);
+            });
+        }
+        dig_cells(
+            cells,
+            walls,
+            time.delta_secs(),
+            &mut map,
+            &mut history,
+            &mut inventory,
+        );
 
-            if right_pressed {
-                let fluid = if shift_pressed {
-                    Lava(Direction::default())
-                } else {
-                    Water(Direction::default())
-                };
-                place_fluid_at(current_pos, &mut map, 3, fluid);
-            }
+        // Update last position to current
+        last_pos.0 = Some(current_pos);
+    }
+
+    if right_pressed {
+        let mut cells = Vec::new();
+        brush.for_each_cell(current_pos, map.width, map.height, |pos| cells.push(pos));
+        if walls {
+            place_walls_at(cells, &mut map, &mut history, selected.particle);
+        } else {
+            place_particles_at(cells, &mut map, &mut history, selected.particle);
+        }
+    }
+}
+
+/// Undoes the last edit stroke with Ctrl+Z, and redoes it with Ctrl+Y or Ctrl+Shift+Z.
+fn undo_redo_hotkeys(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut map: ResMut<Map>,
+    mut history: ResMut<EditHistory>,
+    mut inventory: ResMut<Inventory>,
+) {
+    if !keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let undo = keyboard.just_pressed(KeyCode::KeyZ) && !shift;
+    let redo =
+        keyboard.just_pressed(KeyCode::KeyY) || (keyboard.just_pressed(KeyCode::KeyZ) && shift);
+
+    if undo {
+        if !history.undo(&mut map, &mut inventory) {
+            info!("Nothing to undo");
         }
+    } else if redo && !history.redo(&mut map, &mut inventory) {
+        info!("Nothing to redo");
     }
 }
 
-fn remove_particles_at(center_pos: UVec2, map: &mut crate::world::Map, size: u32) {
-    for_each_in_area(center_pos, map.width, map.height, size, |pos| {
-        map.set_particle_at(pos, None);
-    });
+/// Removes the wall behind `position`. Walls can only be reached through empty cells, so a wall
+/// behind a particle stays. Returns whether a wall was removed.
+pub fn dig_wall(map: &mut Map, position: UVec2) -> bool {
+    if map.get_particle_at(position).is_some() || map.get_background_at(position).is_none() {
+        return false;
+    }
+    map.set_background_at(position, None);
+    true
 }
 
-// Handle keyboard input to change deletion size
-fn handle_deletion_size_change(
-    keyboard: Res<ButtonInput<KeyCode>>,
-    mut deletion_size: ResMut<DeletionSize>,
+/// Dig at the particle at `position` for another `seconds`, wearing its hardness down, see
+/// [`Map::wear_particle_at`]. Once it's worn through it's removed, and ore and gems are added to
+/// `inventory`. Half-dug particles stay worn, so digging can pick up where it left off.
+/// Returns whether the particle was removed.
+pub fn dig_particle(
+    map: &mut Map,
+    position: UVec2,
+    seconds: f32,
+    inventory: &mut Inventory,
+) -> bool {
+    let Some(particle) = map.wear_particle_at(position, seconds * DIG_RATE) else {
+        return false;
+    };
+    if let Particle::Special(special) = particle {
+        inventory.add(special);
+    }
+    true
+}
+
+// Show the collected resources in the top right corner
+fn setup_inventory_text(mut commands: Commands) {
+    commands.spawn((
+        InventoryText,
+        Text::from("Inventory: empty"),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            right: Val::Px(10.0),
+            ..default()
+        },
+    ));
+}
+
+// Update the inventory readout whenever something is collected
+fn update_inventory_text(
+    inventory: Res<Inventory>,
+    mut inventory_query: Query<&mut Text, With<InventoryText>>,
 ) {
+    if !inventory.is_changed() {
+        return;
+    }
+
+    let mut readout = String::from("Inventory:");
+    if inventory.total() == 0 {
+        readout.push_str(" empty");
+    }
+    for (special, count) in inventory.entries() {
+        readout.push_str(&format!("\n{}: {}", special.display_name(), count));
+    }
+    for mut text in &mut inventory_query {
+        *text = Text::from(readout.clone());
+    }
+}
+
+// Handle keyboard input to change the brush shape and size
+fn handle_brush_change(keyboard: Res<ButtonInput<KeyCode>>, mut brush: ResMut<Brush>) {
     // Increase size with ] key
     if keyboard.just_pressed(KeyCode::BracketRight) {
-        deletion_size.size = (deletion_size.size + 1).min(10); // Cap at 10
+        brush.size = (brush.size + 1).min(MAX_BRUSH_SIZE);
     }
 
     // Decrease size with [ key
     if keyboard.just_pressed(KeyCode::BracketLeft) {
-        deletion_size.size = (deletion_size.size - 1).max(1); // Minimum of 1
+        brush.size = (brush.size - 1).max(1); // Minimum of 1
+    }
+
+    // Cycle through the shapes with C key
+    if keyboard.just_pressed(KeyCode::KeyC) {
+        brush.shape = brush.shape.next();
+        info!("Brush shape: {}", brush.shape.display_name());
+    }
+}
+
+// Outline the cells the brush covers around the cursor. The outline is drawn in world space, so
+// it zooms along with the map.
+fn draw_brush_preview(
+    mut gizmos: Gizmos,
+    brush: Res<Brush>,
+    line_start: Res<LineStart>,
+    windows: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    map: Option<Res<Map>>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+    let Some(cell) = cursor_cell(&windows, &camera_q, map.width, map.height) else {
+        return;
+    };
+    if !map.within_bounds(cell) {
+        return;
+    }
+
+    // Even sizes are centered on the corner below and left of the cell.
+    let (low, high) = brush.span();
+    let shift = (low + high) as f32 / 2.0 + 0.5;
+    let center_of = |cell: UVec2| world_to_screen(cell.as_vec2() + shift, map.width, map.height);
+    let reach = brush.size as f32 * PARTICLE_SIZE as f32;
+    let color = Color::srgba(1.0, 1.0, 1.0, 0.6);
+    match brush.shape {
+        BrushShape::Circle => {
+            gizmos.circle_2d(center_of(cell), reach / 2.0, color);
+        }
+        BrushShape::Square => {
+            gizmos.rect_2d(center_of(cell), Vec2::splat(reach), color);
+        }
+        BrushShape::Line => {
+            gizmos.rect_2d(center_of(cell), Vec2::splat(reach), color);
+            if let Some((start, _pressed_at)) = line_start.0 {
+                gizmos.rect_2d(center_of(start), Vec2::splat(reach), color);
+                gizmos.line_2d(center_of(start), center_of(cell), color);
+            }
+        }
+    }
+}
+
+// Pick the particle to place with the number keys
+fn select_particle(keyboard: Res<ButtonInput<KeyCode>>, mut selected: ResMut<SelectedParticle>) {
+    for (index, key) in PALETTE_KEYS.iter().enumerate() {
+        if keyboard.just_pressed(*key) && selected.select_category(index) {
+            info!("Selected {}", selected.particle.display_name());
+        }
+    }
+}
+
+// Show which particle right click places in the bottom left corner
+fn setup_selected_particle_text(mut commands: Commands) {
+    commands.spawn((
+        SelectedParticleText,
+        Text::from("Placing: -"),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        },
+    ));
+}
+
+fn update_selected_particle_text(
+    selected: Res<SelectedParticle>,
+    mut text_query: Query<&mut Text, With<SelectedParticleText>>,
+) {
+    if !selected.is_changed() {
+        return;
+    }
+
+    for mut text in &mut text_query {
+        *text = Text::from(format!(
+            "Placing: {} (right click, 1-{} to change)",
+            selected.particle.display_name(),
+            SelectedParticle::categories().len()
+        ));
     }
 }
@@ -0,0 +1,285 @@
+//! Things the player throws, like water flasks and bombs.
+//!
+//! A thrown [`Projectile`] flies in an arc under gravity. Each frame it marches through every
+//! cell between where it was and where it's going, see [`march_cells`], so even a fast throw
+//! can't skip through a thin wall. Where it hits, it takes effect: a water flask splashes water
+//! around the impact, and a bomb sends an [`Explosion`].
+
+use bevy::prelude::*;
+
+use crate::particle::{Direction, Liquid, Particle, PARTICLE_SIZE};
+use crate::player::Player;
+use crate::utils::coords::{cursor_world_position, screen_to_world, world_to_screen};
+use crate::world::explosion::Explosion;
+use crate::world::Map;
+
+/// How fast projectiles leave the player's hand, in cells per second.
+pub const THROW_SPEED: f32 = 80.0;
+/// How fast projectiles fall, in cells per second squared.
+pub const PROJECTILE_GRAVITY: f32 = 60.0;
+/// How far from the impact a water flask splashes water, in cells.
+pub const SPLASH_RADIUS: u32 = 4;
+/// The radius of a bomb's explosion, in cells.
+pub const BOMB_RADIUS: u32 = 8;
+
+/// Plugin that lets the player throw projectiles and flies them until they hit something.
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Explosion>()
+            .add_systems(Update, (throw_projectiles, move_projectiles).chain());
+    }
+}
+
+/// The things that can be thrown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProjectileKind {
+    /// Splashes water around where it lands.
+    WaterFlask,
+    /// Blows a crater where it lands.
+    Bomb,
+}
+
+impl ProjectileKind {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ProjectileKind::WaterFlask => "Water flask",
+            ProjectileKind::Bomb => "Bomb",
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            ProjectileKind::WaterFlask => Color::srgb(0.3, 0.5, 1.0),
+            ProjectileKind::Bomb => Color::srgb(0.15, 0.15, 0.15),
+        }
+    }
+
+    /// Take effect on hitting the particle at `cell`, having flown through `before` last.
+    /// Returns the explosion to set off, if any.
+    pub fn impact(&self, map: &mut Map, cell: UVec2, before: UVec2) -> Option<Explosion> {
+        match self {
+            ProjectileKind::WaterFlask => {
+                let water = Particle::Liquid(Liquid::Water(Direction::Still.into()));
+                map.splash(before, SPLASH_RADIUS, water);
+                None
+            }
+            ProjectileKind::Bomb => Some(Explosion {
+                center: cell,
+                radius: BOMB_RADIUS,
+            }),
+        }
+    }
+}
+
+/// A projectile in flight.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Projectile {
+    pub kind: ProjectileKind,
+    /// In cells per second.
+    pub velocity: Vec2,
+}
+
+/// Where a projectile ended up after a [`Projectile::step`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flight {
+    /// Still in the air at this position, in world coordinates.
+    Flying(Vec2),
+    /// Hit the particle at `cell`, having flown through `before` last.
+    Hit { cell: UVec2, before: UVec2 },
+    /// Left the map through its sides or bottom.
+    Lost,
+}
+
+impl Projectile {
+    /// A projectile thrown from `from` towards `target`, in world coordinates.
+    pub fn thrown(kind: ProjectileKind, from: Vec2, target: Vec2) -> Self {
+        Self {
+            kind,
+            velocity: (target - from).normalize_or(Vec2::X) * THROW_SPEED,
+        }
+    }
+
+    /// Fly the projectile for `secs` from `position`, in world coordinates.
+    pub fn step(&mut self, map: &Map, position: Vec2, secs: f32) -> Flight {
+        self.velocity.y -= PROJECTILE_GRAVITY * secs;
+        let next = position + self.velocity * secs;
+
+        let blocks = |cell: IVec2| {
+            cell.cmpge(IVec2::ZERO).all()
+                && map
+                    .get_particle_at(cell.as_uvec2())
+                    .is_some_and(|particle| {
+                        !matches!(particle, Particle::Gas(_) | Particle::Fire(_))
+                    })
+        };
+        if let Some((cell, before)) = march_cells(position, next, blocks) {
+            return Flight::Hit {
+                cell: cell.as_uvec2(),
+                before: before.max(IVec2::ZERO).as_uvec2(),
+            };
+        }
+
+        // Above the map there's only sky, so a high throw can come back down.
+        if next.x < 0.0 || next.x >= map.width as f32 || next.y < 0.0 {
+            Flight::Lost
+        } else {
+            Flight::Flying(next)
+        }
+    }
+}
+
+/// Walks through every cell the segment from `from` to `to` crosses, in world coordinates, in
+/// order. Returns the first cell that `blocks`, along with the cell visited before it, which is
+/// the cell itself if the segment starts inside it.
+pub fn march_cells(
+    from: Vec2,
+    to: Vec2,
+    mut blocks: impl FnMut(IVec2) -> bool,
+) -> Option<(IVec2, IVec2)> {
+    let delta = to - from;
+    let mut cell = from.floor().as_ivec2();
+    let end = to.floor().as_ivec2();
+
+    // How far along the segment, from 0 to 1, the next cell border on each axis is, and how far
+    // it is between borders.
+    let axis = |from: f32, delta: f32, cell: i32| -> (i32, f32, f32) {
+        if delta > 0.0 {
+            (1, (cell as f32 + 1.0 - from) / delta, 1.0 / delta)
+        } else if delta < 0.0 {
+            (-1, (from - cell as f32) / -delta, 1.0 / -delta)
+        } else {
+            (0, f32::INFINITY, f32::INFINITY)
+        }
+    };
+    let (step_x, mut next_x, delta_x) = axis(from.x, delta.x, cell.x);
+    let (step_y, mut next_y, delta_y) = axis(from.y, delta.y, cell.y);
+
+    let mut before = cell;
+    loop {
+        if blocks(cell) {
+            return Some((cell, before));
+        }
+        if cell == end {
+            return None;
+        }
+
+        before = cell;
+        if next_x < next_y {
+            if next_x > 1.0 {
+                return None;
+            }
+            cell.x += step_x;
+            next_x += delta_x;
+        } else {
+            if next_y > 1.0 {
+                return None;
+            }
+            cell.y += step_y;
+            next_y += delta_y;
+        }
+    }
+}
+
+impl Map {
+    /// Fills the empty cells within `radius` cells of `center` with `particle`, like a flask of
+    /// water bursting. Returns how many cells were filled.
+    pub fn splash(&mut self, center: UVec2, radius: u32, particle: Particle) -> usize {
+        let squared_radius = radius * radius;
+        let mut filled = 0;
+        for x in center.x.saturating_sub(radius)..=center.x.saturating_add(radius) {
+            for y in center.y.saturating_sub(radius)..=center.y.saturating_add(radius) {
+                let position = UVec2::new(x, y);
+                let (dx, dy) = (center.x.abs_diff(x), center.y.abs_diff(y));
+                if dx * dx + dy * dy > squared_radius
+                    || !self.within_bounds(position)
+                    || self.get_particle_at(position).is_some()
+                {
+                    continue;
+                }
+                self.set_particle_at(position, Some(particle));
+                filled += 1;
+            }
+        }
+        filled
+    }
+}
+
+/// Throws a water flask towards the cursor with G, or a bomb with Shift+G.
+pub fn throw_projectiles(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    player_query: Query<&Transform, With<Player>>,
+    map: Option<Res<Map>>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+    if !keyboard.just_pressed(KeyCode::KeyG) {
+        return;
+    }
+    let (Ok(player), Some(cursor)) = (
+        player_query.get_single(),
+        cursor_world_position(&windows, &camera_q),
+    ) else {
+        return;
+    };
+
+    let kind = if keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        ProjectileKind::Bomb
+    } else {
+        ProjectileKind::WaterFlask
+    };
+    let from = player.translation.truncate();
+    let projectile = Projectile::thrown(
+        kind,
+        screen_to_world(from, map.width, map.height),
+        screen_to_world(cursor, map.width, map.height),
+    );
+    info!("Throwing a {}", kind.display_name().to_lowercase());
+    commands.spawn((
+        projectile,
+        Name::new(kind.display_name()),
+        Sprite {
+            color: kind.color(),
+            custom_size: Some(Vec2::splat(2.0 * PARTICLE_SIZE as f32)),
+            ..default()
+        },
+        Transform::from_xyz(from.x, from.y, 11.0),
+    ));
+}
+
+/// System that flies projectiles and sets them off where they hit.
+pub fn move_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    map: Option<ResMut<Map>>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &mut Transform)>,
+    mut explosions: EventWriter<Explosion>,
+) {
+    let Some(mut map) = map else {
+        return;
+    };
+
+    for (entity, mut projectile, mut transform) in &mut projectile_query {
+        let position = screen_to_world(transform.translation.truncate(), map.width, map.height);
+        match projectile.step(&map, position, time.delta_secs()) {
+            Flight::Flying(next) => {
+                let screen = world_to_screen(next, map.width, map.height);
+                transform.translation.x = screen.x;
+                transform.translation.y = screen.y;
+            }
+            Flight::Hit { cell, before } => {
+                debug!("{} hit {}", projectile.kind.display_name(), cell);
+                if let Some(explosion) = projectile.kind.impact(&mut map, cell, before) {
+                    explosions.send(explosion);
+                }
+                commands.entity(entity).despawn_recursive();
+            }
+            Flight::Lost => commands.entity(entity).despawn_recursive(),
+        }
+    }
+}
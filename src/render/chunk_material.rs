@@ -1,11 +1,18 @@
+use std::hash::Hasher;
+
 use bevy::asset::AssetId;
 use bevy::asset::{load_internal_asset, Asset, AssetApp, Assets, Handle};
 use bevy::color::{Color, LinearRgba};
 use bevy::math::Affine2;
 use bevy::prelude::*;
+use bevy::render::mesh::MeshVertexBufferLayoutRef;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::storage::ShaderStorageBuffer;
 use bevy::render::{render_asset::RenderAssets, render_resource::*, texture::GpuImage};
-use bevy::sprite::{AlphaMode2d, Material2d, Material2dPlugin};
+use bevy::sprite::{AlphaMode2d, Material2d, Material2dKey, Material2dPlugin};
 
+use crate::render::palette::{palette_slot, PaletteColors};
+use crate::utils::hash::StableHasher;
 use crate::world::chunk::CHUNK_SIZE;
 
 pub const CHUNK_MATERIAL_SHADER_HANDLE: Handle<Shader> = Handle::Weak(AssetId::Uuid {
@@ -14,6 +21,244 @@ pub const CHUNK_MATERIAL_SHADER_HANDLE: Handle<Shader> = Handle::Weak(AssetId::U
 
 pub const INDICE_BUFFER_SIZE: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
 
+/// The number of `UVec4`s in the packed indices uniform.
+/// Note: This must match the length of the `indices` array in the shader.
+pub const PACKED_INDICE_BUFFER_SIZE: usize = INDICE_BUFFER_SIZE / 4;
+
+const _: () = assert!(
+    INDICE_BUFFER_SIZE % 4 == 0,
+    "Chunk cells must pack evenly into UVec4s"
+);
+const _: () = assert!(
+    INDICE_BUFFER_SIZE % 16 == 0,
+    "Chunk light levels must pack evenly into UVec4s"
+);
+// The storage buffer path reads arrays of any length, so only the uniform path pins CHUNK_SIZE.
+#[cfg(not(feature = "storage_buffers"))]
+const _: () = assert!(
+    PACKED_INDICE_BUFFER_SIZE == 256,
+    "The shader's indices array length must be updated along with CHUNK_SIZE"
+);
+#[cfg(not(feature = "storage_buffers"))]
+const _: () = assert!(
+    INDICE_BUFFER_SIZE / 16 == 64,
+    "The shader's light array length must be updated along with CHUNK_SIZE"
+);
+
+/// Whether chunk cells reach the shader through a storage buffer instead of uniform arrays.
+///
+/// Uniform arrays need a fixed length in the shader and are capped at 64KiB, which limits
+/// [`CHUNK_SIZE`] to 32. Building with the `storage_buffers` feature uploads the indices, walls
+/// and light levels into one storage buffer per chunk instead, see [`ChunkMaterial::cell_buffer`],
+/// so [`CHUNK_SIZE`] can be raised without touching the shader. Storage buffers aren't available
+/// on WebGL2, which is why uniforms stay the default.
+pub const STORAGE_BUFFERS: bool = cfg!(feature = "storage_buffers");
+
+/// The shader def that switches the shader to reading cells from the storage buffer.
+const STORAGE_BUFFERS_SHADER_DEF: &str = "CHUNK_STORAGE_BUFFERS";
+
+/// The number of atlas textures a chunk material can bind.
+/// Note: This must match the atlas bindings in the shader.
+pub const MAX_ATLASES: usize = 4;
+
+/// The number of palette entries reserved for each atlas. Sprites past this share the last entry.
+pub const SPRITES_PER_ATLAS: usize = 64;
+
+/// The number of entries in the palette uniform.
+/// Note: This must match the length of the `palette` array in the shader.
+pub const PALETTE_SIZE: usize = MAX_ATLASES * SPRITES_PER_ATLAS;
+
+/// Cell values store the atlas id in the bits above this shift, and the sprite index and its
+/// animation below it.
+pub const ATLAS_ID_SHIFT: u32 = 24;
+
+/// Cell values store the sprite's animation in the bits from this shift up to the atlas id, see
+/// [`encode_animation`].
+pub const ANIMATION_SHIFT: u32 = 16;
+
+/// Cell values store the cell's variant in the two bits from this shift up to the animation, see
+/// [`encode_variant`]. The sprite index sits below it.
+pub const VARIANT_SHIFT: u32 = 14;
+
+const SPRITE_INDEX_MASK: u32 = (1 << VARIANT_SHIFT) - 1;
+
+/// The most variants a particle's cells can be drawn in.
+pub const MAX_VARIANTS: u32 = 4;
+
+/// The most frames a sprite animation can have.
+pub const MAX_ANIMATION_FRAMES: u32 = 16;
+
+/// The most frames a second a sprite can animate at.
+pub const MAX_FRAME_RATE: u32 = 15;
+
+const _: () = assert!(
+    MAX_ANIMATION_FRAMES <= 16 && MAX_FRAME_RATE < 16 && ANIMATION_SHIFT + 8 == ATLAS_ID_SHIFT,
+    "Sprite animations must fit in the four bit frame count and rate below the atlas id"
+);
+const _: () = assert!(
+    MAX_VARIANTS == 1 << (ANIMATION_SHIFT - VARIANT_SHIFT),
+    "Cell variants must fit in the bits below the animation"
+);
+
+/// Cell values store the draw order of their particle in the bits from this shift up to
+/// [`LIQUID_CELL_BIT`], see [`encode_draw_order`]. The atlas id sits below it.
+pub const DRAW_ORDER_SHIFT: u32 = 26;
+
+/// The highest draw order a particle can have.
+pub const MAX_DRAW_ORDER: u32 = 31;
+
+/// Set on cell values holding a liquid, so the shader can smooth liquid surfaces.
+/// It sits above the draw order bits and is ignored by [`decode_sprite`].
+pub const LIQUID_CELL_BIT: u32 = 1 << 31;
+
+const ATLAS_ID_MASK: u32 = (1 << (DRAW_ORDER_SHIFT - ATLAS_ID_SHIFT)) - 1;
+
+const _: () = assert!(
+    MAX_ATLASES <= (ATLAS_ID_MASK + 1) as usize,
+    "Atlas ids must fit in the bits below the draw order"
+);
+const _: () = assert!(
+    MAX_DRAW_ORDER == (LIQUID_CELL_BIT >> DRAW_ORDER_SHIFT) - 1,
+    "Draw orders must fit in the bits below the liquid bit"
+);
+
+/// Encodes an (atlas id, sprite index) pair into a single cell value for the shader.
+/// Sprites from the default atlas encode to their plain index.
+pub fn encode_sprite(atlas_id: u32, index: u32) -> u32 {
+    debug_assert!(
+        (atlas_id as usize) < MAX_ATLASES,
+        "Atlas id {} is out of range",
+        atlas_id
+    );
+    debug_assert!(
+        index <= SPRITE_INDEX_MASK,
+        "Sprite index {} is out of range",
+        index
+    );
+    (atlas_id << ATLAS_ID_SHIFT) | (index & SPRITE_INDEX_MASK)
+}
+
+/// Decodes a cell value written by [`encode_sprite`] back into (atlas id, sprite index).
+pub fn decode_sprite(value: u32) -> (u32, u32) {
+    (
+        (value >> ATLAS_ID_SHIFT) & ATLAS_ID_MASK,
+        value & SPRITE_INDEX_MASK,
+    )
+}
+
+/// Tags a cell value with an animation through `frames` consecutive sprites, starting at its
+/// sprite index, at `frame_rate` frames a second. The shader picks the frame from the time, so
+/// animated cells don't need to be uploaded again. [`decode_sprite`] ignores the animation.
+pub fn encode_animation(value: u32, frames: u32, frame_rate: u32) -> u32 {
+    debug_assert!(
+        (1..=MAX_ANIMATION_FRAMES).contains(&frames),
+        "{} animation frames is out of range",
+        frames
+    );
+    debug_assert!(
+        frame_rate <= MAX_FRAME_RATE,
+        "Frame rate {} is out of range",
+        frame_rate
+    );
+    let frames = frames.clamp(1, MAX_ANIMATION_FRAMES) - 1;
+    let frame_rate = frame_rate.min(MAX_FRAME_RATE);
+    value | (frames << ANIMATION_SHIFT) | (frame_rate << (ANIMATION_SHIFT + 4))
+}
+
+/// Decodes the (frame count, frame rate) written by [`encode_animation`]. Cells without one
+/// have a single frame.
+pub fn decode_animation(value: u32) -> (u32, u32) {
+    (
+        ((value >> ANIMATION_SHIFT) & 0xF) + 1,
+        (value >> (ANIMATION_SHIFT + 4)) & 0xF,
+    )
+}
+
+/// Tags a cell value with the variant it's drawn in. The shader shades each variant a little
+/// darker than the last, so large areas of one particle don't look flat. [`decode_sprite`]
+/// ignores the variant.
+pub fn encode_variant(value: u32, variant: u32) -> u32 {
+    debug_assert!(
+        variant < MAX_VARIANTS,
+        "Variant {} is out of range",
+        variant
+    );
+    value | ((variant % MAX_VARIANTS) << VARIANT_SHIFT)
+}
+
+/// Decodes the variant written by [`encode_variant`].
+pub fn decode_variant(value: u32) -> u32 {
+    (value >> VARIANT_SHIFT) % MAX_VARIANTS
+}
+
+/// Tags a cell value with the draw order of its particle. Where two cells with particles meet,
+/// the shader draws the one with the higher draw order a little over the other's edge, e.g. so
+/// liquids lap over the ground they rest on. [`decode_sprite`] ignores the draw order.
+pub fn encode_draw_order(value: u32, draw_order: u32) -> u32 {
+    debug_assert!(
+        draw_order <= MAX_DRAW_ORDER,
+        "Draw order {} is out of range",
+        draw_order
+    );
+    value | (draw_order.min(MAX_DRAW_ORDER) << DRAW_ORDER_SHIFT)
+}
+
+/// Decodes the draw order written by [`encode_draw_order`].
+pub fn decode_draw_order(value: u32) -> u32 {
+    (value >> DRAW_ORDER_SHIFT) & MAX_DRAW_ORDER
+}
+
+/// Which of `variants` variants the cell at `position` in the world is drawn in. It only
+/// depends on the position, so a cell keeps its variant across runs and re-uploads.
+pub fn cell_variant(position: UVec2, variants: u32) -> u32 {
+    if variants <= 1 {
+        return 0;
+    }
+    let mut hasher = StableHasher::default();
+    hasher.write(&position.x.to_le_bytes());
+    hasher.write(&position.y.to_le_bytes());
+    (hasher.finish() % variants as u64) as u32
+}
+
+/// Whether a cell value is tagged with [`LIQUID_CELL_BIT`].
+pub fn is_liquid_cell(value: u32) -> bool {
+    value & LIQUID_CELL_BIT != 0
+}
+
+/// Per-cell spritesheet indices packed four to a `UVec4`, in the layout the shader reads.
+pub type PackedIndices = [UVec4; PACKED_INDICE_BUFFER_SIZE];
+
+/// Packs row-major cell indices (`y * CHUNK_SIZE + x`) into the shader's layout.
+/// Cell `i` is stored in component `i % 4` of element `i / 4`.
+pub fn pack_indices(indices: &[u32; INDICE_BUFFER_SIZE]) -> PackedIndices {
+    let mut packed = [UVec4::ZERO; PACKED_INDICE_BUFFER_SIZE];
+    for (element, cells) in packed.iter_mut().zip(indices.chunks_exact(4)) {
+        *element = UVec4::from_slice(cells);
+    }
+    packed
+}
+
+/// The number of `UVec4`s in the packed light uniform. Light levels are bytes, so each `UVec4`
+/// holds sixteen cells.
+/// Note: This must match the length of the `light` array in the shader.
+pub const PACKED_LIGHT_BUFFER_SIZE: usize = INDICE_BUFFER_SIZE / 16;
+
+/// Per-cell light levels packed sixteen to a `UVec4`, in the layout the shader reads.
+pub type PackedLight = [UVec4; PACKED_LIGHT_BUFFER_SIZE];
+
+/// Packs row-major light levels (`y * CHUNK_SIZE + x`) into the shader's layout.
+/// Cell `i` is stored in byte `i % 4`, counting from the lowest, of component `(i / 4) % 4` of
+/// element `i / 16`.
+pub fn pack_light(levels: &[u8; INDICE_BUFFER_SIZE]) -> PackedLight {
+    let mut packed = [UVec4::ZERO; PACKED_LIGHT_BUFFER_SIZE];
+    for (element, cells) in packed.iter_mut().zip(levels.chunks_exact(16)) {
+        for (component, bytes) in cells.chunks_exact(4).enumerate() {
+            element[component] = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+    packed
+}
+
 #[derive(Default)]
 pub struct ChunkMaterialPlugin;
 
@@ -50,23 +295,110 @@ pub struct ChunkMaterial {
     pub color: Color,
     pub alpha_mode: AlphaMode2d,
     pub uv_transform: Affine2,
+    /// The default atlas (id 0).
     #[texture(1)]
     #[sampler(2)]
     pub texture: Option<Handle<Image>>,
-    #[uniform(3)]
-    pub indices: [UVec4; INDICE_BUFFER_SIZE / 4],
+    #[cfg_attr(not(feature = "storage_buffers"), uniform(3))]
+    pub indices: PackedIndices,
+    /// Additional atlases (ids 1 and up). Unused slots fall back to a blank texture.
+    #[texture(4)]
+    pub atlas_1: Option<Handle<Image>>,
+    #[texture(5)]
+    pub atlas_2: Option<Handle<Image>>,
+    #[texture(6)]
+    pub atlas_3: Option<Handle<Image>>,
+    /// Override colors per sprite, see [`ParticlePalette`](crate::render::palette::ParticlePalette).
+    #[uniform(7)]
+    pub palette: PaletteColors,
+    /// Darken the edges of cells that border a different particle type.
+    pub edge_darkening: bool,
+    /// Round off the corners of liquid surfaces instead of drawing them as blocky cells.
+    pub smooth_fluids: bool,
+    /// The walls behind the particles, packed like `indices`. Drawn dimmed wherever the particle
+    /// layer is air.
+    #[cfg_attr(not(feature = "storage_buffers"), uniform(8))]
+    pub background_indices: PackedIndices,
+    /// How brightly each cell is lit, see [`crate::world::lighting`].
+    #[cfg_attr(not(feature = "storage_buffers"), uniform(9))]
+    pub light: PackedLight,
+    /// Shade cells by their light level. Otherwise every cell is drawn fully lit.
+    pub lighting: bool,
+    /// The color of the light from the sky, see
+    /// [`TimeOfDay::surface_tint`](crate::world::time_of_day::TimeOfDay::surface_tint). Cells are
+    /// tinted by it as much as the sky lights them, so the surface follows the day while caves
+    /// don't.
+    pub sky_tint: Color,
+    /// `indices`, `background_indices` and `light` laid out back to back, which the shader reads
+    /// instead of the uniforms when [`STORAGE_BUFFERS`] is on. Rebuilt by
+    /// [`ChunkMaterial::upload_cell_buffer`] whenever the cells change, and unused otherwise.
+    #[cfg_attr(feature = "storage_buffers", storage(10, read_only))]
+    pub cell_buffer: Handle<ShaderStorageBuffer>,
 }
 
 impl ChunkMaterial {
-    pub fn from_indices(texture: Handle<Image>, indices: [UVec4; INDICE_BUFFER_SIZE / 4]) -> Self {
+    pub fn from_indices(texture: Handle<Image>, indices: PackedIndices) -> Self {
+        Self::from_atlases(&[texture], indices)
+    }
+
+    /// Create a material drawing from `atlases`, where each atlas' id is its position in the slice.
+    /// Panics if more than [`MAX_ATLASES`] atlases are given.
+    pub fn from_atlases(atlases: &[Handle<Image>], indices: PackedIndices) -> Self {
+        assert!(
+            atlases.len() <= MAX_ATLASES,
+            "A chunk material supports at most {} atlases",
+            MAX_ATLASES
+        );
+        let atlas = |id: usize| atlases.get(id).cloned();
         Self {
             color: Color::WHITE,
             alpha_mode: AlphaMode2d::Opaque,
             uv_transform: Affine2::default(),
-            texture: Some(texture),
+            texture: atlas(0),
             indices,
+            atlas_1: atlas(1),
+            atlas_2: atlas(2),
+            atlas_3: atlas(3),
+            palette: [Vec4::ZERO; PALETTE_SIZE],
+            edge_darkening: false,
+            smooth_fluids: false,
+            background_indices: [UVec4::ZERO; PACKED_INDICE_BUFFER_SIZE],
+            light: [UVec4::ZERO; PACKED_LIGHT_BUFFER_SIZE],
+            lighting: false,
+            sky_tint: Color::WHITE,
+            cell_buffer: Handle::default(),
         }
     }
+
+    /// The contents of [`ChunkMaterial::cell_buffer`]: the packed indices, then the packed wall
+    /// indices, then the packed light levels.
+    pub fn cell_buffer_data(&self) -> Vec<UVec4> {
+        [
+            &self.indices[..],
+            &self.background_indices[..],
+            &self.light[..],
+        ]
+        .concat()
+    }
+
+    /// Uploads the cells into a new storage buffer for the shader, if [`STORAGE_BUFFERS`] is on.
+    /// A new buffer is made each time rather than writing into the old one, so the material's bind
+    /// group is rebuilt along with it and never points at stale cells.
+    pub fn upload_cell_buffer(&mut self, buffers: &mut Assets<ShaderStorageBuffer>) {
+        if STORAGE_BUFFERS {
+            let mut buffer = ShaderStorageBuffer::from(self.cell_buffer_data());
+            buffer.asset_usage = RenderAssetUsages::RENDER_WORLD;
+            self.cell_buffer = buffers.add(buffer);
+        }
+    }
+
+    /// The override color the shader draws cell `cell` (row-major) with, or `None` if the cell
+    /// is air or uses its atlas color.
+    pub fn cell_color(&self, cell: usize) -> Option<Vec4> {
+        let (atlas_id, index) = decode_sprite(self.indices[cell / 4][cell % 4]);
+        let color = self.palette[palette_slot(atlas_id, index)];
+        (index > 0 && color.w > 0.0).then_some(color)
+    }
 }
 
 impl Default for ChunkMaterial {
@@ -77,7 +409,18 @@ impl Default for ChunkMaterial {
             alpha_mode: AlphaMode2d::Blend,
             uv_transform: Affine2::default(),
             texture: None,
-            indices: [UVec4::ZERO; INDICE_BUFFER_SIZE / 4],
+            indices: [UVec4::ZERO; PACKED_INDICE_BUFFER_SIZE],
+            atlas_1: None,
+            atlas_2: None,
+            atlas_3: None,
+            palette: [Vec4::ZERO; PALETTE_SIZE],
+            edge_darkening: false,
+            smooth_fluids: false,
+            background_indices: [UVec4::ZERO; PACKED_INDICE_BUFFER_SIZE],
+            light: [UVec4::ZERO; PACKED_LIGHT_BUFFER_SIZE],
+            lighting: false,
+            sky_tint: Color::WHITE,
+            cell_buffer: Handle::default(),
         }
     }
 }
@@ -87,6 +430,9 @@ bitflags::bitflags! {
     #[repr(transparent)]
     pub struct ChunkMaterialFlags: u32 {
         const TEXTURE                    = 1 << 0;
+        const EDGE_DARKENING             = 1 << 1;
+        const SMOOTH_FLUIDS              = 1 << 2;
+        const LIGHTING                   = 1 << 3;
         /// Bitmask reserving bits for the [`AlphaMode2d`]
         /// Values are just sequential values bitshifted into
         /// the bitmask, and can range from 0 to 3.
@@ -104,15 +450,26 @@ impl ChunkMaterialFlags {
     const ALPHA_MODE_SHIFT_BITS: u32 = 32 - Self::ALPHA_MODE_MASK_BITS.count_ones();
 }
 
-/// The GPU representation of the uniform data of a [`ColorMaterial`].
-#[derive(Clone, Default, ShaderType)]
-#[allow(dead_code)] // Fields used by the GPU shader via ShaderType derive
-pub struct ChunkMaterialUniform {
-    pub color: Vec4,
-    pub uv_transform: Mat3,
-    pub flags: u32,
-    pub alpha_cutoff: f32,
-    pub chunk_size: f32,
+pub use uniform::ChunkMaterialUniform;
+
+// The `ShaderType` derive checks each field's type in a function it never calls, which the
+// compiler reports as dead code. The checks sit next to the struct rather than inside it, so only
+// an allow on the enclosing module reaches them.
+#[allow(dead_code)]
+mod uniform {
+    use bevy::prelude::*;
+    use bevy::render::render_resource::ShaderType;
+
+    /// The GPU representation of the uniform data of a [`ChunkMaterial`](super::ChunkMaterial).
+    #[derive(Clone, Default, ShaderType)]
+    pub struct ChunkMaterialUniform {
+        pub color: Vec4,
+        pub uv_transform: Mat3,
+        pub flags: u32,
+        pub alpha_cutoff: f32,
+        pub chunk_size: f32,
+        pub sky_tint: Vec4,
+    }
 }
 
 impl AsBindGroupShaderType<ChunkMaterialUniform> for ChunkMaterial {
@@ -121,6 +478,15 @@ impl AsBindGroupShaderType<ChunkMaterialUniform> for ChunkMaterial {
         if self.texture.is_some() {
             flags |= ChunkMaterialFlags::TEXTURE;
         }
+        if self.edge_darkening {
+            flags |= ChunkMaterialFlags::EDGE_DARKENING;
+        }
+        if self.smooth_fluids {
+            flags |= ChunkMaterialFlags::SMOOTH_FLUIDS;
+        }
+        if self.lighting {
+            flags |= ChunkMaterialFlags::LIGHTING;
+        }
 
         // Defaults to 0.5 like in 3d
         let mut alpha_cutoff = 0.5;
@@ -138,6 +504,7 @@ impl AsBindGroupShaderType<ChunkMaterialUniform> for ChunkMaterial {
             flags: flags.bits(),
             alpha_cutoff,
             chunk_size: CHUNK_SIZE as f32,
+            sky_tint: LinearRgba::from(self.sky_tint).to_f32_array().into(),
         }
     }
 }
@@ -150,4 +517,17 @@ impl Material2d for ChunkMaterial {
     fn alpha_mode(&self) -> AlphaMode2d {
         self.alpha_mode
     }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if STORAGE_BUFFERS {
+            if let Some(fragment) = descriptor.fragment.as_mut() {
+                fragment.shader_defs.push(STORAGE_BUFFERS_SHADER_DEF.into());
+            }
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,144 @@
+//! Exporting the map to PNG images, e.g. to share what a seed generated.
+//!
+//! F12 writes the whole map and Shift+F12 only the part the camera sees, into a file named after
+//! the time it was taken. Every cell becomes one pixel, colored like it is on the minimap.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::particle::Particle;
+use crate::utils::coords::screen_to_world;
+use crate::world::camera::GameCamera;
+use crate::world::Map;
+
+use super::map_renderer::MapRenderResources;
+use super::minimap::{cell_color, particle_color, AIR_COLOR};
+use super::palette::ParticlePalette;
+
+/// Where exported images are written.
+#[derive(Resource)]
+pub struct ExportSettings {
+    pub directory: PathBuf,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("exports"),
+        }
+    }
+}
+
+/// Plugin that exports the map to PNG images with F12.
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ExportSettings>()
+            .add_systems(Update, export_hotkey);
+    }
+}
+
+/// Draws the cells of `map` within `region`, in world coordinates, into an opaque RGBA image with
+/// one pixel per cell, coloring particles with `color`. The top row of the region comes first,
+/// like images are stored. Unloaded chunks of streamed maps are drawn as they would be loaded.
+pub fn rasterize_map(map: &Map, region: URect, color: impl Fn(Particle) -> [u8; 4]) -> Image {
+    let region = region.intersect(URect::new(0, 0, map.width, map.height));
+    let mut data = vec![0; (region.width() * region.height() * 4) as usize];
+    map.for_each_cell_in(region, |position, particle, wall| {
+        let [r, g, b, _] = cell_color(particle, wall, &color);
+        let row = region.max.y - 1 - position.y;
+        let pixel = (row * region.width() + position.x - region.min.x) as usize * 4;
+        data[pixel..pixel + 4].copy_from_slice(&[r, g, b, u8::MAX]);
+    });
+
+    Image::new(
+        Extent3d {
+            width: region.width(),
+            height: region.height(),
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+/// Writes `image` to `path` as a PNG, creating its directory if needed.
+pub fn save_png(image: Image, path: &Path) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    image
+        .try_into_dynamic()
+        .map_err(io::Error::other)?
+        .save(path)
+        .map_err(io::Error::other)
+}
+
+/// The file in `directory` an export taken at `time` is written to.
+pub fn export_path(directory: &Path, time: SystemTime) -> PathBuf {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    directory.join(format!("map-{}.png", millis))
+}
+
+/// The cells the camera sees, given its position and projection.
+fn visible_region(
+    camera_transform: &GlobalTransform,
+    projection: &OrthographicProjection,
+    map: &Map,
+) -> URect {
+    let center = camera_transform.translation().truncate();
+    let min = screen_to_world(center + projection.area.min, map.width, map.height);
+    let max = screen_to_world(center + projection.area.max, map.width, map.height);
+    URect::from_corners(
+        min.floor().max(Vec2::ZERO).as_uvec2(),
+        max.ceil().max(Vec2::ZERO).as_uvec2(),
+    )
+}
+
+// Export the whole map with F12, or only what the camera sees with Shift+F12
+fn export_hotkey(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    map: Option<Res<Map>>,
+    settings: Res<ExportSettings>,
+    palette: Res<ParticlePalette>,
+    render_resources: Res<MapRenderResources>,
+    images: Res<Assets<Image>>,
+    camera_query: Query<(&GlobalTransform, &OrthographicProjection), With<GameCamera>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F12) {
+        return;
+    }
+    let Some(map) = map else {
+        return;
+    };
+
+    let region = if keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        let Ok((camera_transform, projection)) = camera_query.get_single() else {
+            return;
+        };
+        visible_region(camera_transform, projection, &map)
+    } else {
+        URect::new(0, 0, map.width, map.height)
+    };
+
+    let atlases = render_resources.sprite_atlases();
+    let image = rasterize_map(&map, region, |particle| {
+        particle_color(particle, map.particles(), &palette, atlases, &images).unwrap_or(AIR_COLOR)
+    });
+    let path = export_path(&settings.directory, SystemTime::now());
+    match save_png(image, &path) {
+        Ok(()) => info!("Exported the map to {}", path.display()),
+        Err(e) => error!("Failed to export the map to {}: {}", path.display(), e),
+    }
+}
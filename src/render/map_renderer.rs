@@ -1,36 +1,105 @@
 use std::collections::HashMap;
 
+use crate::config::Config;
+use crate::particle::definition::ParticleRegistry;
 use crate::player::Player;
 use crate::utils::{self, coords};
+use crate::world::camera::GameCamera;
 use crate::world::chunk::{Chunk, CHUNK_SIZE};
+use crate::world::generator::MapRegenerated;
 use crate::world::map::Map;
+use crate::world::time_of_day::TimeOfDay;
 use bevy::prelude::*;
+use bevy::render::storage::ShaderStorageBuffer;
 
-use crate::render::chunk_material::ChunkMaterial;
+use crate::render::chunk_material::{ChunkMaterial, MAX_ATLASES};
+use crate::render::palette::{PaletteColors, ParticlePalette};
 
 use super::chunk_material::ChunkMaterialPlugin;
+use super::export::ExportPlugin;
+use super::minimap::MinimapPlugin;
 
-/// The range (in chunks) at which chunks are rendered around the player.
+/// The default range (in chunks) at which chunks are rendered around the player, see [`Config`].
 /// It is used to spawn the chunk renderers, so it is not quite culling.
 /// The actual frustum culling is done in the `render_map` system.
-const RENDER_DISTANCE: u32 = 16;
+pub(crate) const RENDER_DISTANCE: u32 = 16;
+
+/// The brightness chunks outside the active range are drawn at while
+/// `MapRenderSettings::dim_inactive_chunks` is on.
+const INACTIVE_CHUNK_BRIGHTNESS: f32 = 0.6;
+
+/// Settings for the map renderer.
+#[derive(Resource)]
+pub struct MapRenderSettings {
+    /// The maximum number of chunk renderers spawned in a single frame. Remaining chunks are
+    /// spawned on later frames, closest to the camera first. Reusing a spare renderer doesn't
+    /// count towards the budget.
+    pub spawn_budget: usize,
+    /// Darken cell edges where different particle types meet, so terrain features stand out.
+    pub edge_darkening: bool,
+    /// Round off liquid surfaces instead of drawing them as blocky cells.
+    pub smooth_fluids: bool,
+    /// Draw chunks outside the active range dimmed, so the active boundary is visible.
+    pub dim_inactive_chunks: bool,
+    /// Shade cells by how brightly they are lit, so caves are dark and lava glows.
+    pub lighting: bool,
+}
+
+impl Default for MapRenderSettings {
+    fn default() -> Self {
+        Self {
+            spawn_budget: 32,
+            edge_darkening: false,
+            smooth_fluids: false,
+            dim_inactive_chunks: false,
+            lighting: true,
+        }
+    }
+}
 
 /// Plugin that handles rendering the map
 pub struct MapRendererPlugin;
 
 impl Plugin for MapRendererPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(ChunkMaterialPlugin)
+        app.add_plugins((ChunkMaterialPlugin, MinimapPlugin, ExportPlugin))
+            .init_resource::<MapRenderSettings>()
+            .init_resource::<ParticlePalette>()
             .add_systems(Startup, setup_map_renderer)
-            .add_systems(Update, render_map);
+            .add_systems(Update, (prune_despawned_renderers, render_map).chain());
     }
 }
 
 /// Component that marks an entity as the map renderer and tracks chunk renderer entities.
-#[derive(Component)]
+#[derive(Component, Default)]
 pub struct MapRenderer {
-    /// Maps chunk positions to (entity, material handle, last-rendered version).
+    /// Maps chunk positions to (entity, material handle, last-rendered [`Chunk::render_version`]).
     pub chunk_renderers: HashMap<UVec2, (Entity, Handle<ChunkMaterial>, u64)>,
+    /// Hidden renderers of chunks that left the render range, kept to draw the next chunks that
+    /// come into range instead of despawning them and spawning new ones.
+    pub spare_renderers: Vec<(Entity, Handle<ChunkMaterial>)>,
+}
+
+impl MapRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every material handle, including those of spare renderers.
+    fn materials(&self) -> impl Iterator<Item = &Handle<ChunkMaterial>> {
+        self.chunk_renderers
+            .values()
+            .map(|(_entity, handle, _version)| handle)
+            .chain(self.spare_renderers.iter().map(|(_entity, handle)| handle))
+    }
+
+    /// Hide the renderer of `chunk_pos`, if it has one, and keep it for reuse.
+    fn retire(&mut self, commands: &mut Commands, chunk_pos: UVec2) {
+        if let Some((entity, handle, _version)) = self.chunk_renderers.remove(&chunk_pos) {
+            commands.entity(entity).insert(Visibility::Hidden);
+            self.spare_renderers.push((entity, handle));
+        }
+    }
 }
 
 /// Component that marks an individual chunk's renderer and stores handles to resources.
@@ -40,10 +109,36 @@ pub struct ChunkRenderer;
 /// Resource to store shared rendering resources
 #[derive(Resource)]
 pub struct MapRenderResources {
-    sprite_atlas: Handle<Image>,
+    /// Particle atlases, indexed by atlas id. The first one is the default atlas.
+    sprite_atlases: Vec<Handle<Image>>,
     chunk_mesh: Handle<Mesh>,
 }
 
+impl MapRenderResources {
+    pub fn new(sprite_atlas: Handle<Image>, chunk_mesh: Handle<Mesh>) -> Self {
+        Self {
+            sprite_atlases: vec![sprite_atlas],
+            chunk_mesh,
+        }
+    }
+
+    /// Register another atlas and return its id. Chunk renderers spawned afterwards can draw from it.
+    /// Panics if all [`MAX_ATLASES`] slots are taken.
+    pub fn add_atlas(&mut self, atlas: Handle<Image>) -> u32 {
+        assert!(
+            self.sprite_atlases.len() < MAX_ATLASES,
+            "Cannot register more than {} atlases",
+            MAX_ATLASES
+        );
+        self.sprite_atlases.push(atlas);
+        (self.sprite_atlases.len() - 1) as u32
+    }
+
+    pub fn sprite_atlases(&self) -> &[Handle<Image>] {
+        &self.sprite_atlases
+    }
+}
+
 /// System that sets up the map renderer
 fn setup_map_renderer(
     mut commands: Commands,
@@ -58,27 +153,23 @@ fn setup_map_renderer(
     let chunk_mesh = meshes.add(Rectangle::new(chunk_size_pixels, chunk_size_pixels));
 
     // Insert resources
-    commands.insert_resource(MapRenderResources {
-        sprite_atlas: sprite_atlas.clone(),
-        chunk_mesh: chunk_mesh.clone(),
-    });
+    commands.insert_resource(MapRenderResources::new(sprite_atlas, chunk_mesh));
 
     // Create the map renderer entity
     commands.spawn((
-        MapRenderer {
-            chunk_renderers: HashMap::new(),
-        },
+        MapRenderer::new(),
         Name::new("MapRenderer"),
         Transform::default(),
         InheritedVisibility::default(),
     ));
 }
 
-/// Get chunks to render based on player position and `RENDER_DISTANCE`.
-fn get_chunks_to_render<'a>(map: &'a Map, player_transform: &Transform) -> Vec<(UVec2, &'a Chunk)> {
-    // Convert RENDER_DISTANCE from chunks to world units
-    const RENDER_RANGE: u32 = RENDER_DISTANCE * CHUNK_SIZE;
-
+/// Get chunks to render based on player position and the render distance (in chunks).
+fn get_chunks_to_render<'a>(
+    map: &'a Map,
+    player_transform: &Transform,
+    render_distance: u32,
+) -> Vec<(UVec2, &'a Chunk)> {
     // Convert player position to world coordinates
     let player_pos = utils::coords::screen_to_world(
         player_transform.translation.truncate(),
@@ -87,21 +178,50 @@ fn get_chunks_to_render<'a>(map: &'a Map, player_transform: &Transform) -> Vec<(
     );
 
     // Get chunk positions within range and pair them with chunk references
-    map.get_chunks_near(player_pos, RENDER_RANGE)
+    map.get_chunks_near(player_pos, render_distance * CHUNK_SIZE)
         .into_iter()
         .map(|pos| (pos, map.get_chunk_at(&pos)))
         .collect()
 }
 
-/// System that renders chunks near the player based on RENDER_DISTANCE.
-/// Uses cached chunk renderers to avoid despawning/respawning entities every frame.
-fn render_map(
+/// Writes the particles, walls and light levels of `chunk` into `material`, drawn as `particles`
+/// define them. The palette is rewritten too, since it's laid out by sprite. With the
+/// `storage_buffers` feature the cells also go into a new storage buffer from `buffers`.
+fn upload_cells(
+    material: &mut ChunkMaterial,
+    chunk: &Chunk,
+    particles: &ParticleRegistry,
+    palette: PaletteColors,
+    buffers: Option<&mut Assets<ShaderStorageBuffer>>,
+) {
+    material.indices = chunk.to_spritesheet_indices(particles);
+    material.background_indices = chunk.to_background_spritesheet_indices(particles);
+    material.light = chunk.to_packed_light();
+    material.palette = palette;
+    if let Some(buffers) = buffers {
+        material.upload_cell_buffer(buffers);
+    }
+}
+
+/// System that renders chunks near the player within [`Config::render_distance`].
+/// Chunk renderers persist across frames, and their materials are only rewritten for chunks
+/// whose version changed. Renderers of chunks leaving the range are reused for chunks entering
+/// it, and at most `MapRenderSettings::spawn_budget` new renderers are spawned per frame.
+#[allow(clippy::too_many_arguments)]
+pub fn render_map(
     mut commands: Commands,
     map: Res<Map>,
+    settings: Res<MapRenderSettings>,
+    config: Option<Res<Config>>,
     player_query: Query<&Transform, With<Player>>,
+    camera_query: Query<&Transform, (With<GameCamera>, Without<Player>)>,
     mut map_renderer_query: Query<(Entity, &mut MapRenderer)>,
     render_resources: Res<MapRenderResources>,
+    palette: Res<ParticlePalette>,
+    time_of_day: Option<Res<TimeOfDay>>,
     mut materials: ResMut<Assets<ChunkMaterial>>,
+    mut cell_buffers: Option<ResMut<Assets<ShaderStorageBuffer>>>,
+    mut regenerated: EventReader<MapRegenerated>,
 ) {
     // Get player transform and chunks to render first
     let player_transform = match player_query.get_single() {
@@ -109,7 +229,16 @@ fn render_map(
         Err(_) => return, // Early return if player not found
     };
 
-    let chunks_to_render = get_chunks_to_render(&map, player_transform);
+    let render_distance = config.map_or(RENDER_DISTANCE, |config| config.render_distance);
+    let chunks_to_render = get_chunks_to_render(&map, player_transform, render_distance);
+
+    // Spawning is prioritized around the camera, falling back to the player if there is none
+    let focus_transform = camera_query.get_single().unwrap_or(player_transform);
+    let focus_pos = coords::screen_to_world(
+        focus_transform.translation.truncate(),
+        map.width,
+        map.height,
+    );
 
     // Now access the renderer after gathering all required data
     let (map_renderer_entity, mut map_renderer) = match map_renderer_query.get_single_mut() {
@@ -123,60 +252,211 @@ fn render_map(
     let visible_positions: std::collections::HashSet<UVec2> =
         chunks_to_render.iter().map(|(pos, _)| *pos).collect();
 
-    // Remove renderers for chunks that are no longer visible
-    map_renderer
+    // Retire renderers for chunks that are no longer visible. A regenerated map has unrelated
+    // chunk versions and may have a different size, so every renderer is retired and placed again.
+    let regenerated = regenerated.read().count() > 0;
+    let retired: Vec<UVec2> = map_renderer
         .chunk_renderers
-        .retain(|pos, (entity, _handle, _version)| {
-            if visible_positions.contains(pos) {
-                true
-            } else {
-                commands.entity(*entity).despawn_recursive();
-                false
+        .keys()
+        .filter(|pos| regenerated || !visible_positions.contains(pos))
+        .copied()
+        .collect();
+    for chunk_pos in retired {
+        map_renderer.retire(&mut commands, chunk_pos);
+    }
+
+    // Recolor every existing renderer when the palette changes
+    let palette_colors = palette.to_colors(map.particles());
+    if palette.is_changed() {
+        for handle in map_renderer.materials() {
+            if let Some(material) = materials.get_mut(handle.id()) {
+                material.palette = palette_colors;
             }
-        });
+        }
+    }
+
+    // Follow the sky's light, which only changes every few seconds
+    let sky_tint = time_of_day.map_or(Color::WHITE, |time_of_day| time_of_day.surface_tint());
+    for handle in map_renderer.materials() {
+        if materials
+            .get(handle.id())
+            .is_some_and(|m| m.sky_tint != sky_tint)
+        {
+            if let Some(material) = materials.get_mut(handle.id()) {
+                material.sky_tint = sky_tint;
+            }
+        }
+    }
 
-    // Update existing renderers or spawn new ones
+    // Apply toggled shader options to every existing renderer
+    if settings.is_changed() {
+        for handle in map_renderer.materials() {
+            if let Some(material) = materials.get_mut(handle.id()) {
+                material.edge_darkening = settings.edge_darkening;
+                material.smooth_fluids = settings.smooth_fluids;
+                material.lighting = settings.lighting;
+            }
+        }
+    }
+
+    // Update existing renderers, and collect chunks that still need one
+    let mut missing = Vec::new();
     for (chunk_pos, chunk) in chunks_to_render {
         if let Some((_entity, handle, last_version)) =
             map_renderer.chunk_renderers.get_mut(&chunk_pos)
         {
+            // Only touch the material when the tint changes, so unchanged chunks aren't re-uploaded
+            let tint = chunk_tint(&settings, &map, chunk_pos);
+            if materials.get(handle.id()).is_some_and(|m| m.color != tint) {
+                if let Some(material) = materials.get_mut(handle.id()) {
+                    material.color = tint;
+                }
+            }
+
             // Only update material if the chunk has changed since last render
-            if chunk.version != *last_version {
+            if chunk.render_version() != *last_version {
                 if let Some(material) = materials.get_mut(handle.id()) {
-                    material.indices = chunk.to_spritesheet_indices();
+                    upload_cells(
+                        material,
+                        chunk,
+                        map.particles(),
+                        palette_colors,
+                        cell_buffers.as_deref_mut(),
+                    );
                 }
-                *last_version = chunk.version;
+                *last_version = chunk.render_version();
             }
         } else {
-            // Spawn a new renderer entity for this chunk
-            let (_chunk_size, center_pos) =
-                coords::chunk_screen_rect(chunk_pos, map.width, map.height);
-
-            let material_handle = materials.add(ChunkMaterial::from_indices(
-                render_resources.sprite_atlas.clone(),
-                chunk.to_spritesheet_indices(),
-            ));
-
-            let chunk_renderer = commands
-                .spawn((
-                    ChunkRenderer,
-                    Mesh2d(render_resources.chunk_mesh.clone()),
-                    MeshMaterial2d(material_handle.clone()),
-                    Transform::from_xyz(center_pos.x, center_pos.y, 1.0),
-                    Visibility::Inherited,
-                    InheritedVisibility::default(),
-                    ViewVisibility::default(),
-                ))
-                .id();
-
-            // Add the chunk renderer as a child of the map renderer
-            commands
-                .entity(map_renderer_entity)
-                .add_child(chunk_renderer);
-
-            map_renderer
-                .chunk_renderers
-                .insert(chunk_pos, (chunk_renderer, material_handle, chunk.version));
+            missing.push((chunk_pos, chunk));
         }
     }
+
+    // Spawn the closest chunks first, and leave the rest for later frames
+    missing.sort_by(|(a, _), (b, _)| {
+        let distance_a = chunk_center(*a).distance_squared(focus_pos);
+        let distance_b = chunk_center(*b).distance_squared(focus_pos);
+        distance_a.total_cmp(&distance_b)
+    });
+    let reused = map_renderer.spare_renderers.len().min(missing.len());
+    let budget = (reused + settings.spawn_budget).min(missing.len());
+
+    let mut missing = missing.into_iter().take(budget);
+    for (chunk_pos, chunk) in missing.by_ref().take(reused) {
+        // Move a spare renderer over to this chunk
+        let (entity, handle) = map_renderer.spare_renderers.pop().unwrap();
+        let (_chunk_size, center_pos) = coords::chunk_screen_rect(chunk_pos, map.width, map.height);
+        if let Some(material) = materials.get_mut(handle.id()) {
+            material.color = chunk_tint(&settings, &map, chunk_pos);
+            upload_cells(
+                material,
+                chunk,
+                map.particles(),
+                palette_colors,
+                cell_buffers.as_deref_mut(),
+            );
+        }
+        commands.entity(entity).insert((
+            Transform::from_xyz(center_pos.x, center_pos.y, 1.0),
+            Visibility::Inherited,
+        ));
+        map_renderer
+            .chunk_renderers
+            .insert(chunk_pos, (entity, handle, chunk.render_version()));
+    }
+
+    for (chunk_pos, chunk) in missing {
+        // Spawn a new renderer entity for this chunk
+        let (_chunk_size, center_pos) = coords::chunk_screen_rect(chunk_pos, map.width, map.height);
+
+        let mut material = ChunkMaterial {
+            color: chunk_tint(&settings, &map, chunk_pos),
+            palette: palette_colors,
+            edge_darkening: settings.edge_darkening,
+            smooth_fluids: settings.smooth_fluids,
+            lighting: settings.lighting,
+            sky_tint,
+            background_indices: chunk.to_background_spritesheet_indices(map.particles()),
+            light: chunk.to_packed_light(),
+            ..ChunkMaterial::from_atlases(
+                &render_resources.sprite_atlases,
+                chunk.to_spritesheet_indices(map.particles()),
+            )
+        };
+        if let Some(buffers) = cell_buffers.as_deref_mut() {
+            material.upload_cell_buffer(buffers);
+        }
+        let material_handle = materials.add(material);
+
+        let chunk_renderer = commands
+            .spawn((
+                ChunkRenderer,
+                Mesh2d(render_resources.chunk_mesh.clone()),
+                MeshMaterial2d(material_handle.clone()),
+                Transform::from_xyz(center_pos.x, center_pos.y, 1.0),
+                Visibility::Inherited,
+                InheritedVisibility::default(),
+                ViewVisibility::default(),
+            ))
+            .id();
+
+        // Add the chunk renderer as a child of the map renderer
+        commands
+            .entity(map_renderer_entity)
+            .add_child(chunk_renderer);
+
+        map_renderer.chunk_renderers.insert(
+            chunk_pos,
+            (chunk_renderer, material_handle, chunk.render_version()),
+        );
+    }
+}
+
+/// System that forgets chunk renderers whose entities were despawned by something else, like a
+/// recursive despawn of the map renderer's children. Dropping their material handles frees the
+/// materials, and chunks still in range get a new renderer from [`render_map`].
+pub fn prune_despawned_renderers(
+    mut map_renderer_query: Query<&mut MapRenderer>,
+    renderer_query: Query<(), With<ChunkRenderer>>,
+) {
+    for mut map_renderer in &mut map_renderer_query {
+        let despawned = map_renderer
+            .chunk_renderers
+            .values()
+            .map(|(entity, _handle, _version)| entity)
+            .chain(
+                map_renderer
+                    .spare_renderers
+                    .iter()
+                    .map(|(entity, _)| entity),
+            )
+            .any(|entity| !renderer_query.contains(*entity));
+        // Only touch the component when something was despawned, to keep change detection quiet.
+        if !despawned {
+            continue;
+        }
+        map_renderer
+            .chunk_renderers
+            .retain(|_pos, (entity, _handle, _version)| renderer_query.contains(*entity));
+        map_renderer
+            .spare_renderers
+            .retain(|(entity, _handle)| renderer_query.contains(*entity));
+    }
+}
+
+/// The color a chunk's material is tinted with, dimming inactive chunks if enabled.
+fn chunk_tint(settings: &MapRenderSettings, map: &Map, chunk_pos: UVec2) -> Color {
+    if settings.dim_inactive_chunks && !map.active_chunks.contains(&chunk_pos) {
+        Color::srgb(
+            INACTIVE_CHUNK_BRIGHTNESS,
+            INACTIVE_CHUNK_BRIGHTNESS,
+            INACTIVE_CHUNK_BRIGHTNESS,
+        )
+    } else {
+        Color::WHITE
+    }
+}
+
+/// The center of a chunk in world coordinates.
+fn chunk_center(chunk_pos: UVec2) -> Vec2 {
+    (chunk_pos.as_vec2() + 0.5) * CHUNK_SIZE as f32
 }
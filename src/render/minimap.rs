@@ -0,0 +1,448 @@
+//! A small overview of the whole map in the corner of the screen.
+//!
+//! Every pixel of the minimap stands for a square block of cells and shows the particle most of
+//! them hold, in the color that particle is drawn with. Only chunks whose version changed since
+//! they were last drawn are redrawn. The player's position and the active chunks are marked on
+//! top of it.
+
+use std::collections::HashMap;
+
+use bevy::image::ImageSampler;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::particle::definition::ParticleRegistry;
+use crate::particle::Particle;
+use crate::player::Player;
+use crate::utils::coords;
+use crate::world::chunk::{Chunk, CHUNK_SIZE};
+use crate::world::generator::MapRegenerated;
+use crate::world::Map;
+
+use super::map_renderer::MapRenderResources;
+use super::palette::ParticlePalette;
+
+/// The color of empty cells without a wall behind them.
+pub(crate) const AIR_COLOR: [u8; 4] = [12, 12, 18, 200];
+
+/// How much darker walls are drawn than particles of the same type, from 0 to 1.
+const WALL_SHADE: f32 = 0.45;
+
+/// Settings for the minimap. Toggled with M.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct MinimapSettings {
+    /// Whether the minimap is shown.
+    pub visible: bool,
+    /// How many cells wide and tall the block behind each pixel is. Must divide the chunk size.
+    pub scale: u32,
+    /// How large the longest side of the minimap is drawn, in logical pixels.
+    pub max_size: f32,
+}
+
+impl Default for MinimapSettings {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            scale: 4,
+            max_size: 240.0,
+        }
+    }
+}
+
+/// The minimap's pixels in RGBA, one per `scale` by `scale` block of cells. Rows are stored from
+/// the top of the map down, like images are.
+pub struct MinimapImage {
+    scale: u32,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    /// The version of every chunk when its pixels were last drawn.
+    drawn: HashMap<UVec2, u64>,
+}
+
+impl MinimapImage {
+    /// An empty minimap for a map of `map_width` by `map_height` cells. Panics if `scale`
+    /// doesn't divide the chunk size, since every pixel has to belong to a single chunk.
+    pub fn new(map_width: u32, map_height: u32, scale: u32) -> Self {
+        assert!(
+            scale > 0 && CHUNK_SIZE % scale == 0,
+            "The minimap scale must divide the chunk size of {}",
+            CHUNK_SIZE
+        );
+        let width = map_width / scale;
+        let height = map_height / scale;
+        Self {
+            scale,
+            width,
+            height,
+            data: vec![0; (width * height * 4) as usize],
+            drawn: HashMap::new(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// The pixels in RGBA, ready to upload.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The color of the pixel at `(x, y)`, counted from the bottom left like map positions.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let start = self.pixel_offset(x, y);
+        self.data[start..start + 4].try_into().unwrap()
+    }
+
+    /// Redraw every chunk on the next [`MinimapImage::update`], e.g. after colors changed.
+    pub fn invalidate(&mut self) {
+        self.drawn.clear();
+    }
+
+    /// Redraws the chunks of `map` that changed since they were last drawn, coloring particles
+    /// with `color`. Returns how many chunks were redrawn.
+    pub fn update(&mut self, map: &Map, color: impl Fn(Particle) -> [u8; 4]) -> usize {
+        let pixels_per_chunk = CHUNK_SIZE / self.scale;
+        let mut redrawn = 0;
+        for chunk in map.chunks.iter().flatten() {
+            if self.drawn.get(&chunk.position) == Some(&chunk.version) {
+                continue;
+            }
+            self.drawn.insert(chunk.position, chunk.version);
+            redrawn += 1;
+
+            // Unloaded chunks of streamed maps are drawn as they would be loaded.
+            let chunk = map.full_chunk(chunk.position);
+            for block_x in 0..pixels_per_chunk {
+                for block_y in 0..pixels_per_chunk {
+                    let block_color = self.block_color(&chunk, block_x, block_y, &color);
+                    let start = self.pixel_offset(
+                        chunk.position.x * pixels_per_chunk + block_x,
+                        chunk.position.y * pixels_per_chunk + block_y,
+                    );
+                    self.data[start..start + 4].copy_from_slice(&block_color);
+                }
+            }
+        }
+        redrawn
+    }
+
+    /// The color most cells of a block in `chunk` are drawn with.
+    fn block_color(
+        &self,
+        chunk: &Chunk,
+        block_x: u32,
+        block_y: u32,
+        color: &impl Fn(Particle) -> [u8; 4],
+    ) -> [u8; 4] {
+        let mut counts: Vec<([u8; 4], u32)> = Vec::new();
+        for x in block_x * self.scale..(block_x + 1) * self.scale {
+            for y in block_y * self.scale..(block_y + 1) * self.scale {
+                let local_pos = UVec2::new(x, y);
+                let cell_color = cell_color(
+                    chunk.get_particle(local_pos),
+                    chunk.get_background(local_pos),
+                    color,
+                );
+                match counts.iter_mut().find(|(color, _)| *color == cell_color) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((cell_color, 1)),
+                }
+            }
+        }
+        // Ties go to the color seen first, so blocks come out the same every time.
+        counts
+            .iter()
+            .fold(None, |best: Option<&([u8; 4], u32)>, entry| match best {
+                Some(best) if best.1 >= entry.1 => Some(best),
+                _ => Some(entry),
+            })
+            .map_or(AIR_COLOR, |(color, _)| *color)
+    }
+
+    fn pixel_offset(&self, x: u32, y: u32) -> usize {
+        (((self.height - 1 - y) * self.width + x) * 4) as usize
+    }
+}
+
+/// The color a cell holding `particle` in front of `wall` is drawn with, coloring particles with
+/// `color`. Walls are drawn darker, and cells with neither are drawn as air.
+pub(crate) fn cell_color(
+    particle: Option<Particle>,
+    wall: Option<Particle>,
+    color: &impl Fn(Particle) -> [u8; 4],
+) -> [u8; 4] {
+    match (particle, wall) {
+        (Some(particle), _) => color(particle),
+        (None, Some(wall)) => shade(color(wall), WALL_SHADE),
+        (None, None) => AIR_COLOR,
+    }
+}
+
+fn shade(color: [u8; 4], amount: f32) -> [u8; 4] {
+    let [r, g, b, a] = color;
+    let darken = |channel: u8| (channel as f32 * (1.0 - amount)) as u8;
+    [darken(r), darken(g), darken(b), a]
+}
+
+/// The color `particle` is drawn with: its palette override if it has one, or else the pixel of
+/// the sprite `particles` give it. `None` while the atlas is still loading.
+pub(crate) fn particle_color(
+    particle: Particle,
+    particles: &ParticleRegistry,
+    palette: &ParticlePalette,
+    atlases: &[Handle<Image>],
+    images: &Assets<Image>,
+) -> Option<[u8; 4]> {
+    if let Some(color) = palette.get(particle) {
+        return Some(color.to_srgba().to_u8_array());
+    }
+    let (atlas_id, index) = particles.sprite(particle);
+    let atlas = images.get(atlases.get(atlas_id as usize)?)?;
+    let start = index as usize * 4;
+    atlas.data.get(start..start + 4)?.try_into().ok()
+}
+
+/// The minimap image and the texture it's uploaded to.
+#[derive(Resource)]
+pub struct Minimap {
+    pub image: MinimapImage,
+    pub texture: Handle<Image>,
+}
+
+/// Marks the node the minimap is drawn in.
+#[derive(Component)]
+pub struct MinimapNode;
+
+/// Marks the dot showing where the player is on the minimap.
+#[derive(Component)]
+pub struct MinimapPlayerMarker;
+
+/// Marks the rectangle outlining the active chunks on the minimap.
+#[derive(Component)]
+pub struct MinimapActiveArea;
+
+/// Plugin that draws the minimap.
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MinimapSettings>()
+            .add_systems(Startup, setup_minimap)
+            .add_systems(
+                Update,
+                (
+                    toggle_minimap,
+                    update_minimap,
+                    update_minimap_markers.after(update_minimap),
+                ),
+            );
+    }
+}
+
+// Spawn the minimap node in the bottom right corner. Its texture is created once the map exists.
+fn setup_minimap(mut commands: Commands) {
+    commands
+        .spawn((
+            MinimapNode,
+            ImageNode::default(),
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.0),
+                right: Val::Px(10.0),
+                border: UiRect::all(Val::Px(1.0)),
+                ..default()
+            },
+            BorderColor(Color::srgba(1.0, 1.0, 1.0, 0.6)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                MinimapActiveArea,
+                Node {
+                    position_type: PositionType::Absolute,
+                    border: UiRect::all(Val::Px(1.0)),
+                    ..default()
+                },
+                BorderColor(Color::srgb(0.2, 1.0, 0.3)),
+            ));
+            parent.spawn((
+                MinimapPlayerMarker,
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: Val::Px(4.0),
+                    height: Val::Px(4.0),
+                    margin: UiRect::all(Val::Px(-2.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgb(1.0, 0.2, 0.2)),
+            ));
+        });
+}
+
+// Show or hide the minimap with M
+fn toggle_minimap(keyboard: Res<ButtonInput<KeyCode>>, mut settings: ResMut<MinimapSettings>) {
+    if keyboard.just_pressed(KeyCode::KeyM) {
+        settings.visible = !settings.visible;
+        info!(
+            "Minimap {}",
+            if settings.visible { "shown" } else { "hidden" }
+        );
+    }
+}
+
+/// System that redraws the chunks that changed since the last frame into the minimap texture.
+/// The texture is recreated whenever the map's size or the minimap scale changes.
+#[allow(clippy::too_many_arguments)]
+pub fn update_minimap(
+    mut commands: Commands,
+    map: Res<Map>,
+    settings: Res<MinimapSettings>,
+    palette: Res<ParticlePalette>,
+    render_resources: Res<MapRenderResources>,
+    mut minimap: Option<ResMut<Minimap>>,
+    mut images: ResMut<Assets<Image>>,
+    mut regenerated: EventReader<MapRegenerated>,
+    mut node_query: Query<(&mut ImageNode, &mut Node), With<MinimapNode>>,
+) {
+    let regenerated = regenerated.read().count() > 0;
+    if !settings.visible {
+        return;
+    }
+
+    let outdated = minimap.as_ref().is_none_or(|minimap| {
+        minimap.image.width() != map.width / settings.scale
+            || minimap.image.height() != map.height / settings.scale
+            || minimap.image.scale() != settings.scale
+    });
+    if outdated {
+        let image = MinimapImage::new(map.width, map.height, settings.scale);
+        let mut texture = Image::new_fill(
+            Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0; 4],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        texture.sampler = ImageSampler::nearest();
+        let texture = images.add(texture);
+
+        // Fit the longest side to the configured size.
+        let pixel_size = settings.max_size / image.width().max(image.height()) as f32;
+        for (mut image_node, mut node) in &mut node_query {
+            image_node.image = texture.clone();
+            node.width = Val::Px(image.width() as f32 * pixel_size);
+            node.height = Val::Px(image.height() as f32 * pixel_size);
+        }
+        commands.insert_resource(Minimap { image, texture });
+        // The resource is available from the next frame on.
+        return;
+    }
+    let Some(minimap) = minimap.as_mut() else {
+        return;
+    };
+
+    if regenerated || palette.is_changed() {
+        minimap.image.invalidate();
+    }
+
+    // Particle colors come from the atlases, so wait until they're loaded.
+    let atlases = render_resources.sprite_atlases();
+    if atlases.iter().any(|atlas| images.get(atlas).is_none()) {
+        return;
+    }
+    let redrawn = minimap.image.update(&map, |particle| {
+        particle_color(particle, map.particles(), &palette, atlases, &images).unwrap_or(AIR_COLOR)
+    });
+    if redrawn > 0 {
+        if let Some(texture) = images.get_mut(&minimap.texture) {
+            texture.data.copy_from_slice(minimap.image.data());
+        }
+    }
+}
+
+type PlayerMarkerFilter = (
+    With<MinimapPlayerMarker>,
+    Without<MinimapNode>,
+    Without<MinimapActiveArea>,
+);
+type ActiveAreaFilter = (
+    With<MinimapActiveArea>,
+    Without<MinimapNode>,
+    Without<MinimapPlayerMarker>,
+);
+
+/// System that shows or hides the minimap and moves its markers to the player's position and
+/// the active chunks.
+pub fn update_minimap_markers(
+    map: Res<Map>,
+    settings: Res<MinimapSettings>,
+    player_query: Query<&Transform, With<Player>>,
+    mut node_query: Query<&mut Node, With<MinimapNode>>,
+    mut player_marker_query: Query<&mut Node, PlayerMarkerFilter>,
+    mut active_area_query: Query<(&mut Node, &mut Visibility), ActiveAreaFilter>,
+) {
+    for mut node in &mut node_query {
+        let display = if settings.visible {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        if node.display != display {
+            node.display = display;
+        }
+    }
+    if !settings.visible {
+        return;
+    }
+
+    // Positions in percent of the minimap, measured from its top left corner.
+    let percent = |position: Vec2| {
+        (
+            Val::Percent(position.x / map.width as f32 * 100.0),
+            Val::Percent(100.0 - position.y / map.height as f32 * 100.0),
+        )
+    };
+
+    if let Ok(transform) = player_query.get_single() {
+        let player_pos =
+            coords::screen_to_world(transform.translation.truncate(), map.width, map.height);
+        let (left, top) = percent(player_pos);
+        for mut marker in &mut player_marker_query {
+            marker.left = left;
+            marker.top = top;
+        }
+    }
+
+    let min = map.active_chunks.iter().copied().reduce(UVec2::min);
+    let max = map.active_chunks.iter().copied().reduce(UVec2::max);
+    for (mut area, mut visibility) in &mut active_area_query {
+        let (Some(min), Some(max)) = (min, max) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        let top_left = Vec2::new(min.x as f32, (max.y + 1) as f32) * CHUNK_SIZE as f32;
+        let (left, top) = percent(top_left);
+        area.left = left;
+        area.top = top;
+        area.width =
+            Val::Percent((max.x + 1 - min.x) as f32 * CHUNK_SIZE as f32 / map.width as f32 * 100.0);
+        area.height = Val::Percent(
+            (max.y + 1 - min.y) as f32 * CHUNK_SIZE as f32 / map.height as f32 * 100.0,
+        );
+    }
+}
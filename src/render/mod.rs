@@ -1,2 +1,5 @@
 pub mod chunk_material;
+pub mod export;
 pub mod map_renderer;
+pub mod minimap;
+pub mod palette;
@@ -0,0 +1,60 @@
+//! Runtime color overrides for particle types, e.g. for high-contrast or colorblind-friendly schemes.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::particle::definition::ParticleRegistry;
+use crate::particle::Particle;
+
+use super::chunk_material::{MAX_ATLASES, PALETTE_SIZE, SPRITES_PER_ATLAS};
+
+/// Per-sprite colors in the layout the shader reads, see [`palette_slot`].
+/// A fully transparent entry means "use the atlas color".
+pub type PaletteColors = [Vec4; PALETTE_SIZE];
+
+/// The palette entry used by a sprite. Each atlas gets its own block of `SPRITES_PER_ATLAS` slots.
+pub fn palette_slot(atlas_id: u32, index: u32) -> usize {
+    let atlas_id = (atlas_id as usize).min(MAX_ATLASES - 1);
+    let index = (index as usize).min(SPRITES_PER_ATLAS - 1);
+    atlas_id * SPRITES_PER_ATLAS + index
+}
+
+/// Colors that replace the atlas colors of particle types when rendering.
+/// Particles without an entry keep their atlas color, so the default palette changes nothing.
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct ParticlePalette {
+    /// Override colors keyed by particle type.
+    overrides: HashMap<Particle, Color>,
+}
+
+impl ParticlePalette {
+    /// Draw every cell of `particle`'s type in `color`.
+    pub fn set(&mut self, particle: Particle, color: Color) {
+        self.overrides.insert(particle, color);
+    }
+
+    /// Go back to drawing `particle` with its atlas color.
+    pub fn reset(&mut self, particle: Particle) {
+        self.overrides.remove(&particle);
+    }
+
+    /// The override color for `particle`, if it has one.
+    pub fn get(&self, particle: Particle) -> Option<Color> {
+        self.overrides.get(&particle).copied()
+    }
+
+    /// The palette in the layout the chunk material uploads to the shader, with sprites as
+    /// `particles` define them. Particles sharing a sprite share a color.
+    pub fn to_colors(&self, particles: &ParticleRegistry) -> PaletteColors {
+        let mut colors = [Vec4::ZERO; PALETTE_SIZE];
+        for (&particle, color) in &self.overrides {
+            let (atlas_id, index) = particles.sprite(particle);
+            let mut color = LinearRgba::from(*color);
+            // Zero alpha is reserved for "no override", so keep overrides visible.
+            color.alpha = color.alpha.max(f32::EPSILON);
+            colors[palette_slot(atlas_id, index)] = color.to_f32_array().into();
+        }
+        colors
+    }
+}
@@ -0,0 +1,134 @@
+use bevy::math::{IVec2, UVec2};
+use rand::Rng;
+
+use crate::{
+    particle::{Fire, Gas, GasState, Particle, ParticleType},
+    utils::coords::{chunk_local_to_world, world_to_chunk_local},
+    world::chunk::{cell_index, ParticleMove},
+};
+
+use super::{SimulationContext, Simulator};
+
+/// The orthogonal neighbors fire can spread to.
+pub(crate) const SPREAD_OFFSETS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+/// Tunes how fire behaves on a map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CombustionSettings {
+    /// Scales every particle's flammability, i.e. the chance per tick that fire spreads to it.
+    pub spread_chance: f32,
+    /// The chance per tick that a fire gives off smoke into the empty cell above it.
+    pub smoke_chance: f32,
+}
+
+impl Default for CombustionSettings {
+    fn default() -> Self {
+        Self {
+            spread_chance: 1.0,
+            smoke_chance: 0.1,
+        }
+    }
+}
+
+pub struct FireSimulator;
+
+impl Simulator<Fire> for FireSimulator {
+    /// Burns a fire particle down, spreads it to flammable neighbors and gives off smoke.
+    /// Burnt out fires leave their residue, like ash, behind.
+    /// Ignitions in other chunks are queued directly, so this never returns a move.
+    fn simulate(
+        &mut self,
+        mut context: SimulationContext,
+        fire: Fire,
+        x: u32,
+        y: u32,
+    ) -> Option<ParticleMove> {
+        let particle_world_pos =
+            chunk_local_to_world(context.original_chunk.position, UVec2::new(x, y));
+        let mut rng = context.map.particle_rng(particle_world_pos);
+
+        // A fire with no lifetime left burns out, leaving its residue or air behind.
+        let lifetime = fire.get_lifetime();
+        let (xi, yi) = cell_index(UVec2::new(x, y), "FireSimulator::simulate");
+        context.new_cells[xi][yi] = match lifetime {
+            0 => fire.get_residue(),
+            _ => Some(fire.with_lifetime(lifetime - 1).into()),
+        };
+
+        let settings = context.map.combustion;
+        if rng.random::<f32>() < settings.smoke_chance {
+            self.emit_smoke(&mut context, particle_world_pos);
+        }
+
+        for offset in SPREAD_OFFSETS {
+            let neighbor = particle_world_pos.as_ivec2() + offset;
+            if neighbor.cmplt(IVec2::ZERO).any() {
+                continue;
+            }
+            let neighbor = neighbor.as_uvec2();
+
+            // Only flammable particles catch fire, anything else stops the spread.
+            let Some(target) = context.map.get_particle_at(neighbor) else {
+                continue;
+            };
+            if rng.random::<f32>() >= target.get_flammability() * settings.spread_chance {
+                continue;
+            }
+
+            self.ignite(&mut context, particle_world_pos, neighbor, target);
+        }
+
+        None
+    }
+}
+
+impl FireSimulator {
+    /// Sets `fuel` at `target` on fire, either in this chunk or through the inter-chunk queue.
+    fn ignite(
+        &self,
+        context: &mut SimulationContext,
+        source: UVec2,
+        target: UVec2,
+        fuel: Particle,
+    ) {
+        let flame = Particle::Fire(Fire::igniting(fuel));
+
+        if context.original_chunk.is_within_chunk(target) {
+            let (x, y) = cell_index(world_to_chunk_local(target), "FireSimulator::ignite");
+            context.new_cells[x][y] = Some(flame);
+        } else {
+            context.chunk_queue.entry(target).or_insert(ParticleMove {
+                source_pos: source,
+                target_pos: target,
+                particle: flame,
+                source_particle: None,
+                source_result: None,
+                replace_target: true,
+            });
+        }
+    }
+
+    /// Puts fresh smoke in the cell above `source` if it's empty, either in this chunk or
+    /// through the inter-chunk queue. Smoke never displaces anything.
+    fn emit_smoke(&self, context: &mut SimulationContext, source: UVec2) {
+        let target = source + UVec2::Y;
+        if !context.map.is_valid_position(target) {
+            return;
+        }
+        let smoke = Particle::Gas(Gas::Smoke(GasState::default()).fresh());
+
+        if context.original_chunk.is_within_chunk(target) {
+            let (x, y) = cell_index(world_to_chunk_local(target), "FireSimulator::emit_smoke");
+            context.new_cells[x][y].get_or_insert(smoke);
+        } else {
+            context.chunk_queue.entry(target).or_insert(ParticleMove {
+                source_pos: source,
+                target_pos: target,
+                particle: smoke,
+                source_particle: None,
+                source_result: None,
+                replace_target: false,
+            });
+        }
+    }
+}
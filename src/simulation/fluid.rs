@@ -1,12 +1,61 @@
-use bevy::math::UVec2;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::math::{IVec2, UVec2};
+use rand::Rng;
 
 use crate::{
-    particle::Liquid,
-    utils::coords::chunk_local_to_world,
-    world::chunk::ParticleMove,
+    particle::{Liquid, Particle},
+    utils::coords::{chunk_local_to_world, get_chunk_from_world_pos},
+    world::{chunk::ParticleMove, Map},
+};
+
+use super::{
+    handle_particle_movement, try_move, MoveResult, ReadOnlySimulationContext, SimulationContext,
+    Simulator,
 };
 
-use super::{handle_particle_movement, try_move, MoveResult, SimulationContext, Simulator};
+/// The neighbors a body of liquid is connected through.
+const BODY_OFFSETS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+/// Chunks whose liquid was found level, so [`FluidSimulator::find_pressure_moves`] can skip
+/// them until something in or around them changes.
+#[derive(Default)]
+pub(crate) struct LevelChunks {
+    /// The versions of each level chunk and the chunks around it when it was found level, see
+    /// [`neighborhood_versions`].
+    chunks: HashMap<UVec2, [Option<u64>; 9]>,
+}
+
+/// The versions of the chunk at `chunk_pos` and its eight neighbors, `None` for the ones that
+/// are off the map or inactive, since liquid only levels through active chunks.
+fn neighborhood_versions(map: &Map, chunk_pos: UVec2) -> [Option<u64>; 9] {
+    let mut versions = [None; 9];
+    for (i, version) in versions.iter_mut().enumerate() {
+        let neighbor = chunk_pos.as_ivec2() + IVec2::new(i as i32 % 3 - 1, i as i32 / 3 - 1);
+        if neighbor.cmplt(IVec2::ZERO).any() {
+            continue;
+        }
+        let neighbor = neighbor.as_uvec2();
+        if map.active_chunks.contains(&neighbor) {
+            *version = Some(map.get_chunk_at(&neighbor).version);
+        }
+    }
+    versions
+}
+
+/// Tunes how liquids level out on a map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PressureSettings {
+    /// The most cells each connected body of liquid moves per tick to level its surface.
+    /// 0 turns leveling off.
+    pub flow_rate: usize,
+}
+
+impl Default for PressureSettings {
+    fn default() -> Self {
+        Self { flow_rate: 4 }
+    }
+}
 
 pub struct FluidSimulator;
 
@@ -14,62 +63,86 @@ impl Simulator<Liquid> for FluidSimulator {
     /// Calculates the new position for a fluid particle, reading old positions from the map and writing to new_cells.
     fn simulate(
         &mut self,
-        context: SimulationContext,
+        mut context: SimulationContext,
         fluid: Liquid,
         x: u32,
         y: u32,
     ) -> Option<ParticleMove> {
         let particle_world_pos =
             chunk_local_to_world(context.original_chunk.position, UVec2::new(x, y));
-        let step =
-            self.calculate_step(&context, fluid, particle_world_pos.x, particle_world_pos.y);
+        let step = self.calculate_step(
+            &context.as_read_only(),
+            fluid,
+            particle_world_pos.x,
+            particle_world_pos.y,
+        );
+        context.start_interaction_cooldown(particle_world_pos, step.target_pos(), fluid.into());
 
-        match step {
-            MoveResult::Move(new_pos, new_particle) => {
-                // Source moves to new position (empty cell or Replace interaction)
-                handle_particle_movement(
-                    context.original_chunk,
-                    context.new_cells,
-                    particle_world_pos,
-                    new_pos,
-                    new_particle,
-                    false,
-                )
-            }
-            MoveResult::Preserve {
-                source_particle,
-                target_pos,
-                result,
-            } => {
-                // Source stays at its original local position
-                context.new_cells[x as usize][y as usize] = Some(source_particle);
-                // Place the interaction result at the target position
-                handle_particle_movement(
-                    context.original_chunk,
-                    context.new_cells,
-                    particle_world_pos,
-                    target_pos,
-                    result,
-                    true,
-                )
-            }
-        }
+        handle_particle_movement(&mut context, particle_world_pos, fluid.into(), step)
     }
 }
 
 impl FluidSimulator {
     /// Calculates the new position of a fluid particle in world coordinates.
     /// It will either move to a new position, or interact with a neighboring particle if possible.
+    /// Nothing is written, so this can also be used to preview moves.
     pub fn calculate_step(
         &self,
-        context: &SimulationContext,
+        context: &ReadOnlySimulationContext,
+        fluid: Liquid,
+        x: u32,
+        y: u32,
+    ) -> MoveResult {
+        let viscosity = context.map.particles().viscosity(fluid.into());
+        self.calculate_step_with_viscosity(context, fluid, viscosity, x, y)
+    }
+
+    /// Same as [`FluidSimulator::calculate_step`], but with an explicit viscosity instead of the fluid's own.
+    /// A viscosity of 0 means the fluid is stationary. Negative values are clamped to 1.
+    pub fn calculate_step_with_viscosity(
+        &self,
+        context: &ReadOnlySimulationContext,
+        fluid: Liquid,
+        viscosity: i32,
+        x: u32,
+        y: u32,
+    ) -> MoveResult {
+        let step = self.find_move(context, fluid, viscosity, x, y);
+
+        // Track how far the fluid has fallen: a move downward adds to the distance, anything else
+        // means the fluid has landed.
+        match step {
+            MoveResult::Move(new_pos, Particle::Liquid(moved)) if moved == fluid => {
+                let fall_distance = if new_pos.y < y {
+                    let fallen = (y - new_pos.y).min(u8::MAX as u32) as u8;
+                    fluid.get_fall_distance().saturating_add(fallen)
+                } else {
+                    0
+                };
+                MoveResult::Move(new_pos, moved.with_fall_distance(fall_distance).into())
+            }
+            step => step,
+        }
+    }
+
+    /// Finds where a fluid particle moves to, without tracking its fall distance.
+    fn find_move(
+        &self,
+        context: &ReadOnlySimulationContext,
         fluid: Liquid,
+        viscosity: i32,
         x: u32,
         y: u32,
     ) -> MoveResult {
         let particle = fluid.into();
-        let buoyancy = Liquid::BUOYANCY;
-        let viscosity = fluid.get_viscosity();
+        let buoyancy = context.map.particles().buoyancy(particle);
+
+        // Stationary fluids never move, so keep them where they are.
+        if viscosity == 0 {
+            return MoveResult::Move(UVec2::new(x, y), particle);
+        }
+        // Anything that moves must try at least one offset, otherwise the loops below are empty.
+        let viscosity = viscosity.max(1);
 
         // Try vertical movement first
         for offset in (0..viscosity).rev() {
@@ -90,7 +163,10 @@ impl FluidSimulator {
 
             match (move_right, move_left) {
                 // If both are possible, choose one randomly.
-                (Some(right), Some(left)) => return if rand::random() { right } else { left },
+                (Some(right), Some(left)) => {
+                    let rng = &mut context.map.particle_rng(UVec2::new(x, y));
+                    return if rng.random() { right } else { left };
+                }
                 // If one is possible, return that.
                 (Some(result), None) | (None, Some(result)) => return result,
                 // If neither are possible, do nothing.
@@ -107,4 +183,158 @@ impl FluidSimulator {
         // If no movement is possible, flip direction
         MoveResult::Move(UVec2::new(x, y), fluid.get_flipped_direction().into())
     }
+
+    /// Finds the moves that level connected bodies of the same liquid in active chunks, as
+    /// (source, target) pairs. Pressure pushes a body's liquid out of its highest surface cells
+    /// into the lowest empty cells next to it, so basins fill evenly and both arms of a U-bend
+    /// end up at a common height. Up to `flow_rate` cells move per body, and only while the
+    /// source sits more than a cell above the target, so level surfaces stay put. Each empty
+    /// cell is the target of at most one move, even when it borders several bodies.
+    ///
+    /// Chunks whose bodies all turned out level are remembered in `level`, and aren't searched
+    /// for bodies again until they or a chunk next to them changes.
+    pub(crate) fn find_pressure_moves(
+        map: &Map,
+        flow_rate: usize,
+        level: &mut LevelChunks,
+    ) -> Vec<(UVec2, UVec2)> {
+        let mut moves = Vec::new();
+        if flow_rate == 0 {
+            return moves;
+        }
+
+        let mut chunk_positions: Vec<UVec2> = map
+            .active_chunks
+            .iter()
+            .filter(|pos| map.is_chunk_moving(**pos))
+            .copied()
+            .collect();
+        // Sort for deterministic results, matching the order chunks are stored in.
+        chunk_positions.sort_by_key(|pos| (pos.x, pos.y));
+
+        let mut visited = HashSet::new();
+        // Targets already taken by an earlier body.
+        let mut claimed = HashSet::new();
+        // Chunks holding part of a body that still has liquid to move.
+        let mut unlevel = HashSet::new();
+        let mut searched = Vec::new();
+        for chunk_pos in chunk_positions {
+            let versions = neighborhood_versions(map, chunk_pos);
+            if level.chunks.get(&chunk_pos) == Some(&versions) {
+                continue;
+            }
+            searched.push((chunk_pos, versions));
+
+            let chunk = map.get_chunk_at(&chunk_pos);
+            for (x, column) in chunk.cells().iter().enumerate() {
+                for (y, &particle) in column.iter().enumerate() {
+                    let Some(Particle::Liquid(liquid)) = particle else {
+                        continue;
+                    };
+                    let position = chunk_local_to_world(chunk_pos, UVec2::new(x as u32, y as u32));
+                    if map.particles().viscosity(liquid.into()) == 0 || !visited.insert(position) {
+                        continue;
+                    }
+
+                    let body = find_body(map, position, liquid, &mut visited);
+                    let body_moves = level_body(map, &body, flow_rate, &mut claimed);
+                    if !body_moves.is_empty() {
+                        unlevel.extend(body.iter().map(|&cell| get_chunk_from_world_pos(cell)));
+                        moves.extend(body_moves);
+                    }
+                }
+            }
+        }
+
+        level
+            .chunks
+            .retain(|chunk_pos, _| !unlevel.contains(chunk_pos));
+        for (chunk_pos, versions) in searched {
+            if !unlevel.contains(&chunk_pos) {
+                level.chunks.insert(chunk_pos, versions);
+            }
+        }
+        moves
+    }
+}
+
+/// The neighbor of `position` at `offset`, if it is on the map in an active chunk.
+fn active_neighbor(map: &Map, position: UVec2, offset: IVec2) -> Option<UVec2> {
+    let neighbor = position.as_ivec2() + offset;
+    if neighbor.cmplt(IVec2::ZERO).any() || !map.within_bounds(neighbor.as_uvec2()) {
+        return None;
+    }
+    let neighbor = neighbor.as_uvec2();
+    map.active_chunks
+        .contains(&get_chunk_from_world_pos(neighbor))
+        .then_some(neighbor)
+}
+
+/// Every cell connected to `start` through cells of the same liquid in active chunks. Found cells
+/// are added to `visited`.
+fn find_body(map: &Map, start: UVec2, liquid: Liquid, visited: &mut HashSet<UVec2>) -> Vec<UVec2> {
+    let mut body = Vec::new();
+    let mut frontier = VecDeque::from([start]);
+    while let Some(position) = frontier.pop_front() {
+        body.push(position);
+        for offset in BODY_OFFSETS {
+            let Some(neighbor) = active_neighbor(map, position, offset) else {
+                continue;
+            };
+            if map.get_particle_at(neighbor) == Some(Particle::Liquid(liquid))
+                && visited.insert(neighbor)
+            {
+                frontier.push_back(neighbor);
+            }
+        }
+    }
+    body
+}
+
+/// Pairs the highest surface cells of `body` with the lowest empty cells next to it that would
+/// hold liquid, see [`FluidSimulator::find_pressure_moves`]. Targets in `claimed` are skipped,
+/// and the ones picked are added to it.
+fn level_body(
+    map: &Map,
+    body: &[UVec2],
+    flow_rate: usize,
+    claimed: &mut HashSet<UVec2>,
+) -> Vec<(UVec2, UVec2)> {
+    let is_empty = |position: UVec2| map.get_particle_at(position).is_none();
+
+    // Surface cells have open air right above them.
+    let mut surfaces: Vec<UVec2> = body
+        .iter()
+        .copied()
+        .filter(|&position| active_neighbor(map, position, IVec2::Y).is_some_and(is_empty))
+        .collect();
+
+    // Liquid moved into a target rests there instead of falling further.
+    let mut targets: Vec<UVec2> = body
+        .iter()
+        .flat_map(|&position| {
+            [IVec2::X, IVec2::NEG_X, IVec2::Y]
+                .into_iter()
+                .filter_map(move |offset| active_neighbor(map, position, offset))
+        })
+        .filter(|&target| {
+            !claimed.contains(&target)
+                && is_empty(target)
+                && (target.y == 0 || !is_empty(UVec2::new(target.x, target.y - 1)))
+        })
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    surfaces.sort_by_key(|pos| (std::cmp::Reverse(pos.y), pos.x));
+    targets.sort_by_key(|pos| (pos.y, pos.x));
+
+    let moves: Vec<(UVec2, UVec2)> = surfaces
+        .into_iter()
+        .zip(targets)
+        .take(flow_rate)
+        .take_while(|(source, target)| source.y > target.y + 1)
+        .collect();
+    claimed.extend(moves.iter().map(|&(_, target)| target));
+    moves
 }
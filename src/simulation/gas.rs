@@ -0,0 +1,112 @@
+use bevy::math::UVec2;
+use rand::Rng;
+
+use crate::{
+    particle::{Direction, Gas, GasState, Liquid, Particle},
+    utils::coords::chunk_local_to_world,
+    world::chunk::ParticleMove,
+};
+
+use super::{
+    handle_particle_movement, try_move, MoveResult, ReadOnlySimulationContext, SimulationContext,
+    Simulator,
+};
+
+pub struct GasSimulator;
+
+impl Simulator<Gas> for GasSimulator {
+    /// Ages a gas particle and calculates its new position, reading old positions from the map and
+    /// writing to new_cells. A gas with no lifetime left dissipates, leaving air behind.
+    fn simulate(
+        &mut self,
+        mut context: SimulationContext,
+        gas: Gas,
+        x: u32,
+        y: u32,
+    ) -> Option<ParticleMove> {
+        let state = *gas.get_state();
+        if state.lifetime == 0 {
+            return None;
+        }
+        let gas = gas.with_state(GasState {
+            lifetime: state.lifetime - 1,
+            ..state
+        });
+
+        let particle_world_pos =
+            chunk_local_to_world(context.original_chunk.position, UVec2::new(x, y));
+        let step = self.calculate_step(
+            &context.as_read_only(),
+            gas,
+            particle_world_pos.x,
+            particle_world_pos.y,
+        );
+        context.start_interaction_cooldown(particle_world_pos, step.target_pos(), gas.into());
+
+        handle_particle_movement(&mut context, particle_world_pos, gas.into(), step)
+    }
+}
+
+impl GasSimulator {
+    /// Calculates the new position of a gas particle in world coordinates. Gas rises while drifting
+    /// randomly from side to side, and spreads sideways under ceilings. Steam that stays stuck under
+    /// a ceiling long enough condenses back into water, see
+    /// [`crate::particle::definition::ParticleDefinition::condense_ticks`].
+    /// Nothing is written, so this can also be used to preview moves.
+    pub fn calculate_step(
+        &self,
+        context: &ReadOnlySimulationContext,
+        gas: Gas,
+        x: u32,
+        y: u32,
+    ) -> MoveResult {
+        let state = *gas.get_state();
+        let mut rng = context.map.particle_rng(UVec2::new(x, y));
+        let up = y.saturating_add_signed(context.map.particles().buoyancy(gas.into()));
+
+        // Rising always resets the ceiling count, since the gas is no longer stuck.
+        let risen: Particle = gas
+            .with_state(GasState {
+                ceiling_ticks: 0,
+                ..state
+            })
+            .into();
+        let drifted = x
+            .checked_add_signed(rng.random_range(-1..=1))
+            .filter(|&drifted| drifted != x);
+        for target_x in drifted.into_iter().chain([x]) {
+            if let Some(result) = try_move(context, UVec2::new(target_x, up), risen) {
+                return result;
+            }
+        }
+
+        // Stuck under a ceiling.
+        let ceiling_ticks = state.ceiling_ticks.saturating_add(1);
+        let condense_ticks = context.map.particles().condense_ticks(gas.into());
+        if matches!(gas, Gas::Steam(_))
+            && condense_ticks > 0
+            && ceiling_ticks as u32 >= condense_ticks
+        {
+            let water = Liquid::Water(Direction::random(&mut rng).into());
+            return MoveResult::Move(UVec2::new(x, y), water.into());
+        }
+
+        let stuck: Particle = gas
+            .with_state(GasState {
+                ceiling_ticks,
+                ..state
+            })
+            .into();
+        let sides = if rng.random() { [-1, 1] } else { [1, -1] };
+        for side in sides {
+            let Some(side_x) = x.checked_add_signed(side) else {
+                continue;
+            };
+            if let Some(result) = try_move(context, UVec2::new(side_x, y), stuck) {
+                return result;
+            }
+        }
+
+        MoveResult::Move(UVec2::new(x, y), stuck)
+    }
+}
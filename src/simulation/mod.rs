@@ -3,17 +3,20 @@ use dashmap::DashMap;
 
 use crate::{
     particle::{
-        interaction::{InteractionPair, InteractionType, INTERACTION_RULES},
+        interaction::{InteractionPair, InteractionRule, InteractionRules, InteractionType},
         Particle, ParticleType,
     },
     utils::coords::world_to_chunk_local,
     world::{
-        chunk::{Chunk, ParticleMove, CHUNK_SIZE},
+        chunk::{cell_index, Cells, Chunk, ParticleMove, CHUNK_SIZE},
         Map,
     },
 };
 
+pub mod fire;
 pub mod fluid;
+pub mod gas;
+pub mod powder;
 
 /// A trait for types that can simulate particles.
 pub trait Simulator<P: ParticleType> {
@@ -31,8 +34,9 @@ pub enum MoveResult {
     /// Source particle moves to the new position, becoming the given particle.
     /// Used for empty-cell moves and Replace interactions.
     Move(UVec2, Particle),
-    /// Source particle stays at its current position. The result particle
-    /// should be placed at the target position (Preserve interaction).
+    /// The source position keeps a particle: the source itself for Preserve interactions, or
+    /// the rule's byproduct for Replace interactions that have one. The result particle should
+    /// be placed at the target position.
     Preserve {
         source_particle: Particle,
         target_pos: UVec2,
@@ -40,24 +44,105 @@ pub enum MoveResult {
     },
 }
 
+impl MoveResult {
+    /// Where the particle moves to, or where the interaction result is placed.
+    pub fn target_pos(&self) -> UVec2 {
+        match self {
+            MoveResult::Move(target_pos, _) => *target_pos,
+            MoveResult::Preserve { target_pos, .. } => *target_pos,
+        }
+    }
+}
+
 /// A context for particle simulation.
-/// Contains references to the map, original chunk, chunk queue, and new cells.
+/// Contains references to the map, interaction rules, original chunk, chunk queue, new cells,
+/// and the interaction cooldowns started this tick.
 pub struct SimulationContext<'a> {
     pub map: &'a Map,
+    pub rules: &'a InteractionRules,
     pub original_chunk: &'a Chunk,
     pub chunk_queue: &'a DashMap<UVec2, ParticleMove>,
     pub new_cells: &'a mut [[Option<Particle>; CHUNK_SIZE as usize]; CHUNK_SIZE as usize],
+    /// The tick each cell that reacted this tick can react again, see
+    /// [`Map::interaction_ready`]. Kept apart from the map until the tick ends, so every chunk
+    /// sees the same cooldowns no matter which thread simulates it first.
+    pub cooldowns: &'a DashMap<UVec2, u64>,
 }
 
 impl<'a> SimulationContext<'a> {
     pub fn new(
         map: &'a Map,
+        rules: &'a InteractionRules,
         original_chunk: &'a Chunk,
         chunk_queue: &'a DashMap<UVec2, ParticleMove>,
         new_cells: &'a mut [[Option<Particle>; CHUNK_SIZE as usize]; CHUNK_SIZE as usize],
+        cooldowns: &'a DashMap<UVec2, u64>,
+    ) -> Self {
+        Self {
+            map,
+            rules,
+            original_chunk,
+            chunk_queue,
+            new_cells,
+            cooldowns,
+        }
+    }
+
+    /// Borrows a read-only view of this context, for computing moves without writing anything.
+    pub fn as_read_only(&self) -> ReadOnlySimulationContext<'_> {
+        ReadOnlySimulationContext {
+            map: self.map,
+            rules: self.rules,
+            original_chunk: self.original_chunk,
+            chunk_queue: self.chunk_queue,
+            new_cells: self.new_cells,
+        }
+    }
+
+    /// Starts the cooldown of the interaction a particle at `source_pos` takes part in by moving
+    /// to `target_pos`, if any. Moves are also computed just to preview them, so this is only
+    /// called once a move is actually made.
+    pub fn start_interaction_cooldown(
+        &self,
+        source_pos: UVec2,
+        target_pos: UVec2,
+        particle: Particle,
+    ) {
+        if source_pos == target_pos {
+            return;
+        }
+        let context = self.as_read_only();
+        if let Some(rule) = find_interaction(&context, target_pos, particle) {
+            if rule.cooldown > 0 {
+                let ready = self.map.simulation_tick() + 1 + rule.cooldown as u64;
+                self.cooldowns.insert(target_pos, ready);
+            }
+        }
+    }
+}
+
+/// A read-only view of a [`SimulationContext`].
+/// Movement logic only needs to read state, so this lets callers preview where particles would
+/// move without mutating anything.
+pub struct ReadOnlySimulationContext<'a> {
+    pub map: &'a Map,
+    pub rules: &'a InteractionRules,
+    pub original_chunk: &'a Chunk,
+    pub chunk_queue: &'a DashMap<UVec2, ParticleMove>,
+    pub new_cells: &'a Cells,
+}
+
+impl<'a> ReadOnlySimulationContext<'a> {
+    pub fn new(
+        map: &'a Map,
+        rules: &'a InteractionRules,
+        original_chunk: &'a Chunk,
+        chunk_queue: &'a DashMap<UVec2, ParticleMove>,
+        new_cells: &'a Cells,
     ) -> Self {
         Self {
             map,
+            rules,
             original_chunk,
             chunk_queue,
             new_cells,
@@ -67,42 +152,62 @@ impl<'a> SimulationContext<'a> {
 
 /// Tries to move a particle to a new position, handling interactions and validation.
 pub fn try_move(
-    context: &SimulationContext,
+    context: &ReadOnlySimulationContext,
     new_pos: UVec2,
     particle: Particle,
 ) -> Option<MoveResult> {
     // First try to move to an empty spot.
     if validate_move_empty(context, new_pos) {
         Some(MoveResult::Move(new_pos, particle))
-    } else if let Some((result, interaction_type)) = resolve_interaction(context, new_pos, particle)
-    {
-        match interaction_type {
-            InteractionType::Replace => Some(MoveResult::Move(new_pos, result)),
-            InteractionType::Preserve => Some(MoveResult::Preserve {
+    } else if let Some(rule) = resolve_interaction(context, new_pos, particle) {
+        match (rule.interaction_type, rule.byproduct) {
+            (InteractionType::Replace, None) => Some(MoveResult::Move(new_pos, rule.result)),
+            // The source is consumed but leaves its byproduct behind instead of air.
+            (InteractionType::Replace, Some(byproduct)) => Some(MoveResult::Preserve {
+                source_particle: byproduct,
+                target_pos: new_pos,
+                result: rule.result,
+            }),
+            (InteractionType::Preserve, _) => Some(MoveResult::Preserve {
                 source_particle: particle,
                 target_pos: new_pos,
-                result,
+                result: rule.result,
             }),
+            // Never resolved on contact, see `resolve_interaction`.
+            (InteractionType::Dissolve, _) => None,
         }
     } else {
         None
     }
 }
 
+/// The interaction a particle takes part in by moving to `new_pos`, if it can't simply move there.
+fn find_interaction(
+    context: &ReadOnlySimulationContext,
+    new_pos: UVec2,
+    particle: Particle,
+) -> Option<InteractionRule> {
+    if validate_move_empty(context, new_pos) {
+        None
+    } else {
+        resolve_interaction(context, new_pos, particle)
+    }
+}
+
 /// Checks if a particle can move to a new position.
 ///
 /// This function first verifies that the new position is valid within the map's boundaries.
 /// If the new position is within the same chunk, it also ensures that the spot is empty
 /// in the chunk's updated state. If the position is outside the original chunk, movement
 /// is checked against what is currently in the queue.
-fn validate_move_empty(context: &SimulationContext, new_pos: UVec2) -> bool {
+fn validate_move_empty(context: &ReadOnlySimulationContext, new_pos: UVec2) -> bool {
     // Was it valid on the older not-yet-updated map?
     context.map.is_valid_position(new_pos)
         && match context.original_chunk.is_within_chunk(new_pos) {
             // We're within the same new chunk... Let's make sure it's empty in the new chunk too.
             true => {
-                let local = world_to_chunk_local(new_pos);
-                context.new_cells[local.x as usize][local.y as usize].is_none()
+                let (x, y) = cell_index(world_to_chunk_local(new_pos), "validate_move_empty");
+                context.new_cells[x][y].is_none()
             }
             // Not within the same chunk, so have we already queued a move to this location?
             false => !context.chunk_queue.contains_key(&new_pos),
@@ -110,13 +215,14 @@ fn validate_move_empty(context: &SimulationContext, new_pos: UVec2) -> bool {
 }
 
 /// Attempts to resolve an interaction between a moving particle and the particle at `new_pos`.
-/// Returns the resulting particle and interaction type if an interaction is possible.
+/// Returns the rule to apply if an interaction is possible, the cell at `new_pos` isn't cooling
+/// down and the rule's probability roll succeeds this tick.
 fn resolve_interaction(
-    context: &SimulationContext,
+    context: &ReadOnlySimulationContext,
     new_pos: UVec2,
     particle: Particle,
-) -> Option<(Particle, InteractionType)> {
-    if !context.map.within_bounds(new_pos) {
+) -> Option<InteractionRule> {
+    if !context.map.within_bounds(new_pos) || !context.map.interaction_ready(new_pos) {
         return None;
     }
 
@@ -129,51 +235,79 @@ fn resolve_interaction(
     };
 
     // Ensure these two particles can interact...
-    let rule = INTERACTION_RULES.get(&interaction_pair)?;
+    let rule = context
+        .rules
+        .get(&interaction_pair)
+        .filter(|rule| rule.reacts_on_contact())?;
 
     // Now handle whether it's within the same chunk or not.
-    if context.original_chunk.is_within_chunk(new_pos) {
+    let rule = if context.original_chunk.is_within_chunk(new_pos) {
         // Check if the new chunk has a valid interaction rule
-        let local_pos = world_to_chunk_local(new_pos);
-        let new_target = context.new_cells[local_pos.x as usize][local_pos.y as usize]?;
-        INTERACTION_RULES
+        let (x, y) = cell_index(world_to_chunk_local(new_pos), "resolve_interaction");
+        let new_target = context.new_cells[x][y]?;
+        context
+            .rules
             .get(&InteractionPair {
                 source: particle,
                 target: new_target,
             })
-            .map(|r| (r.result, r.interaction_type))
+            .filter(|rule| rule.reacts_on_contact())?
     } else {
         // If it's outside the chunk, check if it's already queued for movement
         if context.chunk_queue.contains_key(&new_pos) {
-            None
-        } else {
-            Some((rule.result, rule.interaction_type))
+            return None;
         }
-    }
+        rule
+    };
+
+    // The roll only depends on the target cell and the tick, so previews agree with the move.
+    rule.roll(&mut context.map.particle_rng(new_pos))
+        .then_some(*rule)
 }
 
 /// Handles the result of a particle movement calculation, either updating the local chunk
 /// or queueing for inter-chunk movement.
+///
+/// A particle leaving the chunk stays at its source until the map commits the move, so it's never
+/// lost if another move wins its target or the target fills up in the meantime. Interactions
+/// with a particle in another chunk work the same way: the result and what the source becomes
+/// only appear once the move commits.
 pub fn handle_particle_movement(
-    original_chunk: &Chunk,
-    new_cells: &mut [[Option<Particle>; CHUNK_SIZE as usize]; CHUNK_SIZE as usize],
+    context: &mut SimulationContext,
     source_pos: UVec2,
-    new_pos: UVec2,
     particle: Particle,
-    preserve_source: bool,
+    step: MoveResult,
 ) -> Option<ParticleMove> {
+    let (new_pos, new_particle, source_result) = match step {
+        MoveResult::Move(new_pos, new_particle) => (new_pos, new_particle, None),
+        MoveResult::Preserve {
+            source_particle,
+            target_pos,
+            result,
+        } => (target_pos, result, Some(source_particle)),
+    };
+
     // If the new position is not within the chunk, queue it for inter-chunk movement.
-    if !original_chunk.is_within_chunk(new_pos) {
+    if !context.original_chunk.is_within_chunk(new_pos) {
+        let (x, y) = cell_index(world_to_chunk_local(source_pos), "handle_particle_movement");
+        context.new_cells[x][y].get_or_insert(particle);
         Some(ParticleMove {
             source_pos,
             target_pos: new_pos,
-            particle,
-            preserve_source,
+            particle: new_particle,
+            source_particle: Some(particle),
+            source_result,
+            // Moves only go to cells that were empty, so an occupied target means an interaction.
+            replace_target: context.map.get_particle_at(new_pos).is_some(),
         })
     } else {
         // Otherwise, update the local chunk's new_cells directly
-        let particle_local_pos = world_to_chunk_local(new_pos);
-        new_cells[particle_local_pos.x as usize][particle_local_pos.y as usize] = Some(particle);
+        if let Some(source_result) = source_result {
+            let (x, y) = cell_index(world_to_chunk_local(source_pos), "handle_particle_movement");
+            context.new_cells[x][y] = Some(source_result);
+        }
+        let (x, y) = cell_index(world_to_chunk_local(new_pos), "handle_particle_movement");
+        context.new_cells[x][y] = Some(new_particle);
         None
     }
 }
@@ -0,0 +1,85 @@
+use bevy::math::UVec2;
+use rand::Rng;
+
+use crate::{
+    particle::{Particle, Powder},
+    utils::coords::chunk_local_to_world,
+    world::chunk::ParticleMove,
+};
+
+use super::{
+    handle_particle_movement, try_move, MoveResult, ReadOnlySimulationContext, SimulationContext,
+    Simulator,
+};
+
+pub struct PowderSimulator;
+
+impl Simulator<Powder> for PowderSimulator {
+    /// Calculates the new position for a powder grain, reading old positions from the map and
+    /// writing to new_cells. Sinking through liquids happens after movement, see
+    /// `Map::settle_by_density`.
+    fn simulate(
+        &mut self,
+        mut context: SimulationContext,
+        powder: Powder,
+        x: u32,
+        y: u32,
+    ) -> Option<ParticleMove> {
+        let particle_world_pos =
+            chunk_local_to_world(context.original_chunk.position, UVec2::new(x, y));
+        let step = self.calculate_step(
+            &context.as_read_only(),
+            powder,
+            particle_world_pos.x,
+            particle_world_pos.y,
+        );
+        context.start_interaction_cooldown(particle_world_pos, step.target_pos(), powder.into());
+
+        handle_particle_movement(&mut context, particle_world_pos, powder.into(), step)
+    }
+}
+
+impl PowderSimulator {
+    /// Calculates the new position of a powder grain in world coordinates. Grains fall straight
+    /// down, and otherwise slide diagonally off piles steeper than their angle of repose.
+    /// Nothing is written, so this can also be used to preview moves.
+    pub fn calculate_step(
+        &self,
+        context: &ReadOnlySimulationContext,
+        powder: Powder,
+        x: u32,
+        y: u32,
+    ) -> MoveResult {
+        let particle: Particle = powder.into();
+        let stay = MoveResult::Move(UVec2::new(x, y), particle);
+        if y == 0 {
+            return stay;
+        }
+
+        if let Some(result) = try_move(context, UVec2::new(x, y - 1), particle) {
+            return result;
+        }
+
+        // Try both sides in a random order so piles don't lean one way.
+        let rng = &mut context.map.particle_rng(UVec2::new(x, y));
+        let sides = if rng.random() { [-1, 1] } else { [1, -1] };
+        for side in sides {
+            let Some(side_x) = x.checked_add_signed(side) else {
+                continue;
+            };
+            // A grain can't slide through a wall, and only slides where the drop beside it is
+            // at least as deep as the powder's slide drop.
+            let drop = powder.get_slide_drop();
+            let steep = drop <= y
+                && (0..=drop).all(|d| context.map.is_valid_position(UVec2::new(side_x, y - d)));
+            if !steep {
+                continue;
+            }
+            if let Some(result) = try_move(context, UVec2::new(side_x, y - 1), particle) {
+                return result;
+            }
+        }
+
+        stay
+    }
+}
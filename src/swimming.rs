@@ -0,0 +1,238 @@
+//! Swimming: how liquid around the player buoys them up, slows them down and runs out their
+//! breath.
+//!
+//! Each frame the cells under the player are sampled into a [`Submersion`]. The more of the
+//! player is in liquid, the faster they float up and the slower they walk. While their head is
+//! under water, their [`Breath`] drains, and once it's empty they start drowning. Lava and acid
+//! hurt on their own instead, see [`crate::health`].
+
+use bevy::prelude::*;
+
+use crate::health::Health;
+use crate::particle::{Liquid, Particle};
+use crate::player::{player_world_bounds, Player};
+use crate::world::Map;
+
+/// How long the player can hold their breath, in seconds.
+pub const MAX_BREATH: f32 = 10.0;
+/// How many seconds of breath come back per second with the player's head out of liquid.
+pub const BREATH_RECOVERY_RATE: f32 = 4.0;
+/// The damage of each hit dealt while drowning.
+pub const DROWNING_DAMAGE: f32 = 10.0;
+/// How fast a fully submerged player floats up, in pixels per second.
+pub const BUOYANCY_SPEED: f32 = 60.0;
+/// How much slower a fully submerged player moves sideways, from 0 to 1.
+pub const SWIM_SLOWDOWN: f32 = 0.5;
+
+const BREATH_BAR_WIDTH: f32 = 200.0;
+const BREATH_BAR_COLOR: Color = Color::srgb(0.2, 0.5, 0.9);
+const BREATH_BAR_BACKGROUND: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+
+/// Plugin that lets players swim in the map's liquids and shows their breath.
+pub struct SwimmingPlugin;
+
+impl Plugin for SwimmingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_breath_bar).add_systems(
+            Update,
+            (
+                update_submersion,
+                (apply_buoyancy, update_breath),
+                update_breath_bar,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// How much of the player is in liquid, updated every frame.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Submersion {
+    /// The fraction of the cells the player covers that hold liquid, from 0 to 1.
+    pub fraction: f32,
+    /// Whether the top row of cells the player covers holds water, the liquid they can drown in.
+    pub head_under: bool,
+}
+
+impl Submersion {
+    /// How fast the player moves sideways, as a fraction of their speed on land.
+    pub fn speed_factor(&self) -> f32 {
+        1.0 - SWIM_SLOWDOWN * self.fraction
+    }
+}
+
+/// How long the player can stay under before drowning.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Breath {
+    /// In seconds.
+    pub current: f32,
+    /// In seconds.
+    pub max: f32,
+}
+
+impl Default for Breath {
+    fn default() -> Self {
+        Self {
+            current: MAX_BREATH,
+            max: MAX_BREATH,
+        }
+    }
+}
+
+impl Breath {
+    pub fn is_empty(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    /// How much breath is left, from 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        (self.current / self.max).clamp(0.0, 1.0)
+    }
+
+    /// Drain the breath by `secs` while the head is under, or refill it otherwise. Returns
+    /// whether the breath has run out, so its holder is drowning.
+    pub fn tick(&mut self, head_under: bool, secs: f32) -> bool {
+        if head_under {
+            self.current = (self.current - secs).max(0.0);
+            return self.is_empty();
+        }
+        if self.current < self.max {
+            self.current = (self.current + BREATH_RECOVERY_RATE * secs).min(self.max);
+        }
+        false
+    }
+}
+
+/// Measures how much of the rectangle from `min` to `max`, in world coordinates, is in liquid,
+/// and whether its top row is under water. Cells outside the map count as dry.
+pub fn measure_submersion(map: &Map, min: Vec2, max: Vec2) -> Submersion {
+    let (x_start, y_start) = (min.x.floor().max(0.0) as u32, min.y.floor().max(0.0) as u32);
+    let x_end = (max.x.ceil().max(0.0) as u32).min(map.width);
+    let y_end = (max.y.ceil().max(0.0) as u32).min(map.height);
+    if x_start >= x_end || y_start >= y_end {
+        return Submersion::default();
+    }
+
+    let is_liquid = |x, y| {
+        matches!(
+            map.get_particle_at(UVec2::new(x, y)),
+            Some(Particle::Liquid(_))
+        )
+    };
+    let is_water = |x, y| {
+        matches!(
+            map.get_particle_at(UVec2::new(x, y)),
+            Some(Particle::Liquid(Liquid::Water(_)))
+        )
+    };
+    let mut liquid = 0;
+    for x in x_start..x_end {
+        for y in y_start..y_end {
+            if is_liquid(x, y) {
+                liquid += 1;
+            }
+        }
+    }
+
+    let cells = (x_end - x_start) * (y_end - y_start);
+    Submersion {
+        fraction: liquid as f32 / cells as f32,
+        head_under: (x_start..x_end).any(|x| is_water(x, y_end - 1)),
+    }
+}
+
+/// System that samples the map under each player into their [`Submersion`].
+pub fn update_submersion(
+    map: Option<Res<Map>>,
+    mut player_query: Query<(&Transform, &mut Submersion), With<Player>>,
+) {
+    let Some(map) = map else {
+        return;
+    };
+
+    for (transform, mut submersion) in &mut player_query {
+        let (min, max) = player_world_bounds(transform.translation.truncate(), &map);
+        submersion.set_if_neq(measure_submersion(&map, min, max));
+    }
+}
+
+/// System that floats players up by how much of them is in liquid.
+pub fn apply_buoyancy(
+    time: Res<Time>,
+    mut player_query: Query<(&mut Transform, &Submersion), With<Player>>,
+) {
+    for (mut transform, submersion) in &mut player_query {
+        transform.translation.y += BUOYANCY_SPEED * submersion.fraction * time.delta_secs();
+    }
+}
+
+/// System that drains the breath of players with their head under and refills it otherwise,
+/// hurting players who ran out.
+pub fn update_breath(
+    time: Res<Time>,
+    mut player_query: Query<(&Submersion, &mut Breath, &mut Health), With<Player>>,
+) {
+    let secs = time.delta_secs();
+    for (submersion, mut breath, mut health) in &mut player_query {
+        if breath.tick(submersion.head_under, secs) {
+            health.take_hit(DROWNING_DAMAGE);
+        }
+    }
+}
+
+#[derive(Component)]
+struct BreathBar;
+
+#[derive(Component)]
+struct BreathBarFill;
+
+// Show the player's breath under the health bar, only while they're short of it
+fn setup_breath_bar(mut commands: Commands) {
+    commands
+        .spawn((
+            BreathBar,
+            Name::new("BreathBar"),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(30.0),
+                left: Val::Percent(50.0),
+                margin: UiRect::left(Val::Px(-BREATH_BAR_WIDTH / 2.0)),
+                width: Val::Px(BREATH_BAR_WIDTH),
+                height: Val::Px(8.0),
+                ..default()
+            },
+            BackgroundColor(BREATH_BAR_BACKGROUND),
+            Visibility::Hidden,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                BreathBarFill,
+                Node {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(BREATH_BAR_COLOR),
+            ));
+        });
+}
+
+fn update_breath_bar(
+    breath_query: Query<&Breath, (With<Player>, Changed<Breath>)>,
+    mut bar_query: Query<&mut Visibility, With<BreathBar>>,
+    mut fill_query: Query<&mut Node, With<BreathBarFill>>,
+) {
+    let Ok(breath) = breath_query.get_single() else {
+        return;
+    };
+    for mut visibility in &mut bar_query {
+        *visibility = if breath.current < breath.max {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    for mut node in &mut fill_query {
+        node.width = Val::Percent(breath.fraction() * 100.0);
+    }
+}
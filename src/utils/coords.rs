@@ -3,6 +3,7 @@
 use crate::particle::PARTICLE_SIZE;
 use crate::world::chunk::CHUNK_SIZE;
 use bevy::math::{UVec2, Vec2};
+use bevy::prelude::{Camera, GlobalTransform, Query, Window};
 
 /// Convert screen-space coordinates to world-space coordinates (in particle units)
 pub fn screen_to_world(screen_pos: Vec2, map_width: u32, map_height: u32) -> Vec2 {
@@ -12,6 +13,15 @@ pub fn screen_to_world(screen_pos: Vec2, map_width: u32, map_height: u32) -> Vec
     )
 }
 
+/// Convert world-space coordinates (in particle units) to screen-space coordinates, the inverse
+/// of [`screen_to_world`]
+pub fn world_to_screen(world_pos: Vec2, map_width: u32, map_height: u32) -> Vec2 {
+    Vec2::new(
+        world_pos.x * PARTICLE_SIZE as f32 - ((map_width * PARTICLE_SIZE) / 2) as f32,
+        world_pos.y * PARTICLE_SIZE as f32 - ((map_height * PARTICLE_SIZE) / 2) as f32,
+    )
+}
+
 /// Convert world-space coordinates (in particle units) to chunk coordinates
 pub fn get_chunk_from_world_pos(world_pos: UVec2) -> UVec2 {
     UVec2::new(world_pos.x / CHUNK_SIZE, world_pos.y / CHUNK_SIZE)
@@ -54,6 +64,31 @@ pub fn cursor_to_map_coords(cursor_world_pos: Vec2, map_width: u32, map_height:
     UVec2::new(world_pos.x.max(0.0) as u32, world_pos.y.max(0.0) as u32)
 }
 
+/// The cursor's world position in Bevy's camera system, if the cursor is over the window
+pub fn cursor_world_position(
+    windows: &Query<&Window>,
+    camera_q: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Vec2> {
+    let (Ok(window), Ok((camera, camera_transform))) =
+        (windows.get_single(), camera_q.get_single())
+    else {
+        return None;
+    };
+    let cursor = window.cursor_position()?;
+    camera.viewport_to_world_2d(camera_transform, cursor).ok()
+}
+
+/// The map cell under the cursor, if the cursor is over the window
+pub fn cursor_cell(
+    windows: &Query<&Window>,
+    camera_q: &Query<(&Camera, &GlobalTransform)>,
+    map_width: u32,
+    map_height: u32,
+) -> Option<UVec2> {
+    cursor_world_position(windows, camera_q)
+        .map(|position| cursor_to_map_coords(position, map_width, map_height))
+}
+
 // Implements Bresenham's line algorithm to get all points between start and end
 pub fn bresenham_line(start: UVec2, end: UVec2) -> Vec<UVec2> {
     let mut points = Vec::new();
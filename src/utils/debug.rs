@@ -1,11 +1,17 @@
-use crate::{player::DebugMode, utils::coords, world::chunk::CHUNK_SIZE, world::map::Map};
+use crate::{
+    particle::{interaction::InteractionRules, Particle, ParticleType},
+    player::DebugMode,
+    utils::coords,
+    world::chunk::CHUNK_SIZE,
+    world::map::Map,
+};
 use bevy::{
     math::{Affine3A, Vec3A},
     prelude::*,
     render::primitives::{Aabb, Frustum},
     utils::HashSet,
 };
-use bevy_inspector_egui::quick::WorldInspectorPlugin;
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui, quick::WorldInspectorPlugin};
 
 // Debug overlay colors
 const ACTIVE_VISUAL_COLOR: Color = Color::srgba(0.0, 1.0, 0.0, 0.2);
@@ -32,7 +38,8 @@ impl Plugin for DebugPlugin {
                     (sync_visual_colors, sync_outline_colors),
                 )
                     .chain(),
-            );
+            )
+            .add_systems(Update, (show_interaction_rules, show_cell_inspector));
     }
 }
 
@@ -40,6 +47,7 @@ impl Plugin for DebugPlugin {
 pub struct DebugState {
     pub show_chunks: bool,
     pub show_chunk_outlines: bool,
+    pub show_interaction_rules: bool,
     pub chunk_visuals_parent: Option<Entity>,
     pub chunk_outlines_parent: Option<Entity>,
 }
@@ -236,6 +244,153 @@ fn toggle_debug_features(
             }
         );
     }
+
+    if keyboard.just_pressed(KeyCode::F8) {
+        debug_state.show_interaction_rules = !debug_state.show_interaction_rules;
+        info!(
+            "Interaction rules panel: {}",
+            if debug_state.show_interaction_rules {
+                "ON"
+            } else {
+                "OFF"
+            }
+        );
+    }
+}
+
+/// A single row of the interaction rules panel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InteractionRuleRow {
+    pub source: String,
+    pub target: String,
+    pub interaction_type: String,
+    pub result: String,
+}
+
+/// Builds the rows shown in the interaction rules panel, sorted by source and target.
+pub fn interaction_rule_rows(rules: &InteractionRules) -> Vec<InteractionRuleRow> {
+    let mut rows: Vec<InteractionRuleRow> = rules
+        .iter()
+        .map(|(pair, rule)| InteractionRuleRow {
+            source: particle_label(&pair.source),
+            target: particle_label(&pair.target),
+            interaction_type: format!("{:?}", rule.interaction_type),
+            result: particle_label(&rule.result),
+        })
+        .collect();
+
+    rows.sort_by(|a, b| (&a.source, &a.target).cmp(&(&b.source, &b.target)));
+    rows
+}
+
+/// A short label for a particle. Per-cell state is omitted, since rules ignore it.
+fn particle_label(particle: &Particle) -> String {
+    particle.display_name().to_string()
+}
+
+fn show_interaction_rules(
+    mut contexts: EguiContexts,
+    debug_mode: Res<DebugMode>,
+    debug_state: Res<DebugState>,
+    rules: Res<InteractionRules>,
+) {
+    if !debug_mode.enabled || !debug_state.show_interaction_rules {
+        return;
+    }
+
+    let rows = interaction_rule_rows(&rules);
+    egui::Window::new("Interaction Rules").show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("interaction_rules")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Source");
+                ui.strong("Target");
+                ui.strong("Type");
+                ui.strong("Result");
+                ui.end_row();
+
+                for row in &rows {
+                    ui.label(&row.source);
+                    ui.label(&row.target);
+                    ui.label(&row.interaction_type);
+                    ui.label(&row.result);
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+/// What the cell inspector shows about the cell under the cursor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellInspection {
+    /// The particle in the cell, or `None` for air.
+    pub particle: Option<Particle>,
+    /// The cell's position in world coordinates.
+    pub position: UVec2,
+    /// The chunk holding the cell.
+    pub chunk_pos: UVec2,
+    /// The cell's position within its chunk.
+    pub local_pos: UVec2,
+    /// Whether the chunk has been modified since its last update.
+    pub dirty: bool,
+    /// Whether the chunk needs active simulation.
+    pub should_simulate: bool,
+}
+
+/// Inspects the cell at `position`, or returns `None` if it's outside the map.
+pub fn inspect_cell(map: &Map, position: UVec2) -> Option<CellInspection> {
+    if !map.within_bounds(position) {
+        return None;
+    }
+
+    let chunk_pos = coords::get_chunk_from_world_pos(position);
+    let chunk = map.get_chunk_at(&chunk_pos);
+    Some(CellInspection {
+        particle: map.get_particle_at(position),
+        position,
+        chunk_pos,
+        local_pos: coords::world_to_chunk_local(position),
+        dirty: chunk.dirty,
+        should_simulate: chunk.should_simulate,
+    })
+}
+
+fn show_cell_inspector(
+    mut contexts: EguiContexts,
+    debug_mode: Res<DebugMode>,
+    windows: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    map: Res<Map>,
+) {
+    if !debug_mode.enabled {
+        return;
+    }
+    let Some(inspection) = coords::cursor_cell(&windows, &camera_q, map.width, map.height)
+        .and_then(|position| inspect_cell(&map, position))
+    else {
+        return;
+    };
+
+    egui::Window::new("Cell Inspector").show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("cell_inspector").show(ui, |ui| {
+            let particle = inspection
+                .particle
+                .map_or("Air".to_string(), |particle| particle_label(&particle));
+            let rows = [
+                ("Particle", particle),
+                ("Position", inspection.position.to_string()),
+                ("Chunk", inspection.chunk_pos.to_string()),
+                ("Local", inspection.local_pos.to_string()),
+                ("Dirty", inspection.dirty.to_string()),
+                ("Simulating", inspection.should_simulate.to_string()),
+            ];
+            for (label, value) in rows {
+                ui.strong(label);
+                ui.label(value);
+                ui.end_row();
+            }
+        });
+    });
 }
 
 fn create_line_segment(
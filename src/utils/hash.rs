@@ -0,0 +1,32 @@
+//! Hashing that is stable across runs, platforms and Rust versions.
+
+use std::hash::Hasher;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A 64-bit FNV-1a hasher. Unlike `DefaultHasher`, its output never changes between runs,
+/// so it can be used for identifiers that are stored or shared.
+///
+/// Note: The default `Hasher::write_u32` etc. use native endianness, so write integers as
+/// little-endian bytes to stay platform independent.
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -1,2 +1,3 @@
 pub mod coords;
 pub mod debug;
+pub mod hash;
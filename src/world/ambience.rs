@@ -0,0 +1,108 @@
+//! Tracks how much liquid surrounds the player and which biome they are in, so audio and
+//! visual systems can drive ambience.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::particle::Liquid;
+use crate::player::Player;
+use crate::utils::coords::screen_to_world;
+
+use super::biome::Biome;
+use super::Map;
+
+/// Settings for the nearby fluid scan.
+#[derive(Resource)]
+pub struct AmbienceSettings {
+    /// Radius (in particle units) around the player that is scanned for liquids.
+    pub radius: u32,
+    /// Minimum time between scans, since counting cells every frame is wasteful.
+    pub update_interval: Duration,
+}
+
+impl Default for AmbienceSettings {
+    fn default() -> Self {
+        Self {
+            radius: 48,
+            update_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Number of liquid cells of each type near the player.
+/// Audio systems can map these to ambient water/lava loop volumes.
+#[derive(Resource, Default, Debug)]
+pub struct NearbyFluid {
+    pub counts: HashMap<Liquid, u32>,
+}
+
+impl NearbyFluid {
+    /// Number of nearby cells of the given liquid type. The liquid's direction is ignored.
+    pub fn count(&self, liquid: Liquid) -> u32 {
+        self.counts.get(&liquid).copied().unwrap_or(0)
+    }
+
+    /// Number of nearby liquid cells of any type.
+    pub fn total(&self) -> u32 {
+        self.counts.values().sum()
+    }
+}
+
+/// The biome of the column the player is standing in.
+/// Only written when the biome changes, so systems can react with `Res::is_changed`.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurrentBiome(pub Biome);
+
+/// System that tracks the biome of the player's column.
+pub fn update_current_biome(
+    map: Res<Map>,
+    player_query: Query<&Transform, With<Player>>,
+    mut current_biome: ResMut<CurrentBiome>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+
+    let player_pos = screen_to_world(
+        player_transform.translation.truncate(),
+        map.width,
+        map.height,
+    );
+    let column = (player_pos.x.max(0.0) as u32).min(map.width.saturating_sub(1));
+
+    current_biome.set_if_neq(CurrentBiome(map.biome_at(column)));
+}
+
+/// System that periodically recounts the liquids around the player.
+pub fn update_nearby_fluid(
+    time: Res<Time>,
+    settings: Res<AmbienceSettings>,
+    map: Res<Map>,
+    player_query: Query<&Transform, With<Player>>,
+    mut nearby_fluid: ResMut<NearbyFluid>,
+    mut since_last_scan: Local<Option<Duration>>,
+) {
+    // Always scan on the first run, then throttle to the configured interval.
+    if let Some(elapsed) = since_last_scan.as_mut() {
+        *elapsed += time.delta();
+        if *elapsed < settings.update_interval {
+            return;
+        }
+    }
+    *since_last_scan = Some(Duration::ZERO);
+
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+
+    let player_pos = screen_to_world(
+        player_transform.translation.truncate(),
+        map.width,
+        map.height,
+    );
+    let center = UVec2::new(player_pos.x.max(0.0) as u32, player_pos.y.max(0.0) as u32);
+
+    nearby_fluid.counts = map.count_liquids_in_radius(center, settings.radius);
+}
@@ -0,0 +1,179 @@
+//! Horizontal biome bands chosen during generation.
+//!
+//! Each biome changes what the generator puts in its columns: the ground it's made of, which
+//! special particles spawn there and how often, and which liquids pool in it.
+
+use bevy::math::Vec2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::particle::definition::ParticleRegistry;
+use crate::particle::{Common, Liquid, Ore, Particle, Powder, Solid, Special, WorldGenType};
+use crate::world::noise::NoiseSource;
+
+/// The narrowest a biome band can be, in columns.
+const MIN_BIOME_WIDTH: u32 = 64;
+/// The widest a biome band can be, in columns.
+const MAX_BIOME_WIDTH: u32 = 256;
+/// Mixed into the world seed so biome bands don't correlate with terrain randomness.
+const BIOME_SEED_SALT: u64 = 0xB105_E5EE_D000_0001;
+/// How quickly the noise picking each band's biome changes along the map, see
+/// [`BiomeLayout::generate`].
+const BIOME_FREQUENCY: f32 = 1.0 / 128.0;
+/// Where in the generator's noise biomes are picked from, clear of the other features.
+const BIOME_NOISE_OFFSET: Vec2 = Vec2::new(-9_419.3, -3_187.7);
+/// Stone at least this deep in volcanic bands is obsidian instead.
+const VOLCANIC_DEPTH: u32 = 200;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
+pub enum Biome {
+    #[default]
+    Plains,
+    Desert,
+    Frozen,
+    Volcanic,
+}
+
+impl Biome {
+    /// The particle that fills the ground `depth` cells below the surface of this biome, in place
+    /// of the usual [`Common`] layer `particles` put at that depth. Deserts are sandy down to the
+    /// clay, frozen ground is bare gravel, and volcanic ground is covered in ash over obsidian
+    /// depths.
+    pub fn ground_at_depth(&self, depth: u32, particles: &ParticleRegistry) -> Particle {
+        let common = particles.common_at_depth(depth);
+        match (self, common) {
+            (Biome::Desert, Common::Grass | Common::Dirt) => Powder::Sand.into(),
+            (Biome::Frozen, Common::Grass) => Powder::Gravel.into(),
+            (Biome::Volcanic, Common::Grass) => Powder::Ash.into(),
+            (Biome::Volcanic, Common::Stone) if depth >= VOLCANIC_DEPTH => {
+                Particle::Solid(Solid::Obsidian)
+            }
+            _ => common.into(),
+        }
+    }
+
+    /// The spawn chance `particles` give `special` in this biome, or 0 if it can't spawn here at
+    /// all, see [`WorldGenType::spawns_in`].
+    pub fn special_spawn_chance(&self, special: Special, particles: &ParticleRegistry) -> i32 {
+        if !special.spawns_in(*self) {
+            return 0;
+        }
+        let chance = particles.spawn_chance(Particle::Special(special));
+        match (self, special) {
+            (Biome::Volcanic, Special::Ore(Ore::Diamond) | Special::Gem(_)) => chance * 2,
+            (Biome::Frozen, Special::Ore(Ore::Iron)) => chance * 2,
+            _ => chance,
+        }
+    }
+
+    /// The chance `particles` give a pocket of `liquid` forming in this biome, or 0 if it can't
+    /// form here at all, see [`WorldGenType::spawns_in`].
+    pub fn liquid_pocket_chance(&self, liquid: Liquid, particles: &ParticleRegistry) -> i32 {
+        if !liquid.spawns_in(*self) {
+            return 0;
+        }
+        let chance = particles.spawn_chance(liquid.into());
+        match (self, liquid) {
+            (Biome::Desert, Liquid::Water(_)) => chance / 4,
+            (Biome::Desert, Liquid::Oil(_)) => chance * 2,
+            (Biome::Volcanic, Liquid::Lava(_)) => chance * 3,
+            (Biome::Volcanic, Liquid::Water(_)) => chance / 2,
+            _ => chance,
+        }
+    }
+}
+
+/// A biome band starting at column `start` and running until the next region starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BiomeRegion {
+    pub start: u32,
+    pub biome: Biome,
+}
+
+/// The biome boundaries across the map's columns.
+/// Regions are sorted by `start`, and the first region always starts at column 0.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BiomeLayout {
+    regions: Vec<BiomeRegion>,
+}
+
+impl Default for BiomeLayout {
+    fn default() -> Self {
+        Self::uniform(Biome::default())
+    }
+}
+
+impl BiomeLayout {
+    /// A layout where every column has the same biome.
+    pub fn uniform(biome: Biome) -> Self {
+        Self {
+            regions: vec![BiomeRegion { start: 0, biome }],
+        }
+    }
+
+    /// Splits `width` columns into randomly sized bands, never placing the same biome twice in a row.
+    /// The generator's `noise` at the start of each band picks its biome. The same seed and noise
+    /// always produce the same layout.
+    pub fn generate(width: u32, seed: u64, noise: &dyn NoiseSource) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed ^ BIOME_SEED_SALT);
+        let mut regions: Vec<BiomeRegion> = Vec::new();
+        let mut start = 0;
+
+        while start < width || regions.is_empty() {
+            let previous = regions.last().map(|region| region.biome);
+            let choices: Vec<Biome> = Biome::iter()
+                .filter(|biome| Some(*biome) != previous)
+                .collect();
+            let value = noise.sample(
+                start as f32 * BIOME_FREQUENCY + BIOME_NOISE_OFFSET.x,
+                BIOME_NOISE_OFFSET.y,
+            );
+            let index = ((value + 1.0) / 2.0 * choices.len() as f32) as usize;
+            let biome = choices[index.min(choices.len() - 1)];
+
+            regions.push(BiomeRegion { start, biome });
+            start += rng.random_range(MIN_BIOME_WIDTH..=MAX_BIOME_WIDTH);
+        }
+
+        Self { regions }
+    }
+
+    /// Builds a layout from regions, which must be sorted by `start` with the first at column 0.
+    /// Returns `None` if they aren't.
+    pub fn from_regions(regions: Vec<BiomeRegion>) -> Option<Self> {
+        let starts_at_zero = regions.first().is_some_and(|region| region.start == 0);
+        let sorted = regions.windows(2).all(|pair| pair[0].start < pair[1].start);
+        (starts_at_zero && sorted).then_some(Self { regions })
+    }
+
+    pub fn regions(&self) -> &[BiomeRegion] {
+        &self.regions
+    }
+
+    /// The biome of the given column.
+    pub fn biome_at(&self, x: u32) -> Biome {
+        let index = self.regions.partition_point(|region| region.start <= x);
+        self.regions[index.saturating_sub(1)].biome
+    }
+
+    /// The layout of the columns `[min_x, max_x)`, shifted so `min_x` becomes column 0.
+    pub fn slice(&self, min_x: u32, max_x: u32) -> Self {
+        let mut regions = vec![BiomeRegion {
+            start: 0,
+            biome: self.biome_at(min_x),
+        }];
+        regions.extend(
+            self.regions
+                .iter()
+                .filter(|region| region.start > min_x && region.start < max_x)
+                .map(|region| BiomeRegion {
+                    start: region.start - min_x,
+                    biome: region.biome,
+                }),
+        );
+
+        Self { regions }
+    }
+}
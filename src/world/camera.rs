@@ -8,7 +8,7 @@ pub struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup_camera)
-            .add_systems(Update, (camera_movement, camera_zoom))
+            .add_systems(Update, (camera_movement, camera_zoom, cycle_zoom_preset))
             .add_systems(
                 PostUpdate,
                 camera_follow_player.before(TransformSystem::TransformPropagate),
@@ -23,6 +23,36 @@ pub struct GameCamera {
     pub zoom_speed: f32,
     pub min_zoom: f32,
     pub max_zoom: f32,
+    /// Projection scales cycled through with Z, smallest (closest) first.
+    pub zoom_presets: Vec<f32>,
+    /// The preset that was applied last.
+    pub zoom_preset_index: usize,
+}
+
+impl Default for GameCamera {
+    fn default() -> Self {
+        Self {
+            speed: 300.0, // Units per second
+            zoom_speed: 1.0,
+            min_zoom: 0.1, // Allow zooming out quite far
+            max_zoom: 5.0, // Allow zooming in quite close
+            zoom_presets: vec![0.25, 0.5, 1.0, 2.0],
+            // Matches the default zoom of 1.0.
+            zoom_preset_index: 2,
+        }
+    }
+}
+
+impl GameCamera {
+    /// Advance to the next zoom preset, wrapping around after the last one.
+    /// Returns the preset's scale clamped to `min_zoom`/`max_zoom`, or `None` without presets.
+    pub fn next_zoom_preset(&mut self) -> Option<f32> {
+        if self.zoom_presets.is_empty() {
+            return None;
+        }
+        self.zoom_preset_index = (self.zoom_preset_index + 1) % self.zoom_presets.len();
+        Some(self.zoom_presets[self.zoom_preset_index].clamp(self.min_zoom, self.max_zoom))
+    }
 }
 
 // Setup the camera with initial position and settings
@@ -39,12 +69,7 @@ fn setup_camera(mut commands: Commands) {
             scale: default_zoom,
             ..OrthographicProjection::default_2d()
         },
-        GameCamera {
-            speed: 300.0, // Units per second
-            zoom_speed: 1.0,
-            min_zoom: 0.1, // Allow zooming out quite far
-            max_zoom: 5.0, // Allow zooming in quite close
-        },
+        GameCamera::default(),
     ));
 
     info!(
@@ -182,3 +207,23 @@ fn camera_zoom(
         }
     }
 }
+
+// System to snap the camera zoom to the next preset with Z
+fn cycle_zoom_preset(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut camera_query: Query<(&mut OrthographicProjection, &mut GameCamera)>,
+) {
+    // Ctrl+Z undoes edits instead.
+    if !keyboard.just_pressed(KeyCode::KeyZ)
+        || keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+    {
+        return;
+    }
+
+    if let Ok((mut projection, mut camera)) = camera_query.get_single_mut() {
+        if let Some(scale) = camera.next_zoom_preset() {
+            projection.scale = scale;
+            debug!("Camera zoom preset: {:.2}x", projection.scale);
+        }
+    }
+}
@@ -1,22 +1,68 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, hash::Hasher, sync::Arc};
 
 use crate::{
-    particle::{Particle, ParticleType},
-    render::chunk_material::INDICE_BUFFER_SIZE,
-    simulation::{fluid::FluidSimulator, SimulationContext, Simulator},
+    particle::{
+        definition::ParticleRegistry, interaction::InteractionRules, Particle, RenderLayer,
+    },
+    render::chunk_material::{
+        cell_variant, encode_animation, encode_draw_order, encode_sprite, encode_variant,
+        pack_indices, pack_light, PackedIndices, PackedLight, INDICE_BUFFER_SIZE, LIQUID_CELL_BIT,
+    },
+    simulation::{
+        fire::FireSimulator, fluid::FluidSimulator, gas::GasSimulator, powder::PowderSimulator,
+        SimulationContext, Simulator,
+    },
+    utils::hash::StableHasher,
 };
 use bevy::prelude::*;
 use dashmap::DashMap;
 
+use super::heat::resting_temperature;
 use super::Map;
 
 /// The square size of a chunk in particle units (not pixels).
 /// Note: If you modify this, you must update the shader's indices buffer size.
 pub(crate) const CHUNK_SIZE: u32 = 32;
 
-/// The range (in chunks) at which chunks are considered active around the player.
+/// The default range (in chunks) at which chunks are considered active around the player, see
+/// [`Config`](crate::config::Config).
 pub(crate) const ACTIVE_CHUNK_RANGE: u32 = 12;
 
+/// The cell storage of a chunk, indexed by local coordinates as `[x][y]`.
+pub type Cells = [[Option<Particle>; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+
+/// The temperature of every cell of a chunk, indexed like [`Cells`].
+pub type Temperatures = [[f32; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+
+/// How much of its hardness every cell of a chunk has lost, indexed like [`Cells`].
+pub type Wear = [[f32; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+
+/// The light level of every cell of a chunk, indexed like [`Cells`]. See [`super::lighting`].
+/// The low four bits of each level hold the cell's light and the high four its sky light.
+pub type LightLevels = [[u8; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+
+/// How far the sky light of a cell is shifted up within its [`LightLevels`] entry.
+pub const SKY_LIGHT_SHIFT: u8 = 4;
+
+/// Converts a chunk-local coordinate into `[x][y]` indices into [`Cells`].
+/// In debug builds, a coordinate outside the chunk panics with `operation` and the offending
+/// coordinate instead of an opaque index-out-of-bounds message. Release builds skip the check.
+#[inline]
+pub fn cell_index(local_pos: UVec2, operation: &str) -> (usize, usize) {
+    debug_assert!(
+        local_pos.x < CHUNK_SIZE && local_pos.y < CHUNK_SIZE,
+        "{}: local coordinate {} is outside the {}x{} chunk",
+        operation,
+        local_pos,
+        CHUNK_SIZE,
+        CHUNK_SIZE
+    );
+    (local_pos.x as usize, local_pos.y as usize)
+}
+
+/// Shared all-air storage handed out for compacted chunks.
+static EMPTY_CELLS: Cells = [[None; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+
 /// Represents a particle that needs to move to a new position. Used in queue system.
 /// Note: This is used in a HashMap where the key is the target position, which is why we don't store it.
 #[derive(Debug, Clone)]
@@ -27,8 +73,37 @@ pub struct ParticleMove {
     pub target_pos: UVec2,
     /// The particle to place at the target position
     pub particle: Particle,
-    /// If true, the source particle is NOT removed (Preserve interaction).
-    pub preserve_source: bool,
+    /// The particle at the source when the move was queued. It stays there until the move
+    /// commits, and the move is dropped if the source no longer holds it. `None` leaves the
+    /// source alone, for particles that spawn others like fire.
+    pub source_particle: Option<Particle>,
+    /// What the source becomes once the move commits: air, the source itself for Preserve
+    /// interactions or the byproduct of a Replace interaction.
+    pub source_result: Option<Particle>,
+    /// If true, the particle overwrites whatever is at the target instead of requiring it to be empty.
+    pub replace_target: bool,
+}
+
+impl ParticleMove {
+    /// Manhattan distance between the source and target, using abs_diff to avoid i32 casts.
+    fn distance(&self) -> u32 {
+        self.source_pos.x.abs_diff(self.target_pos.x)
+            + self.source_pos.y.abs_diff(self.target_pos.y)
+    }
+
+    /// Add this move to `queue`, deduplicating moves that target the same cell as it goes.
+    /// The particle that's closer to the target wins, so the queue never holds more entries
+    /// than there are distinct target cells. The losing particle stays at its source.
+    pub fn enqueue(self, queue: &DashMap<UVec2, ParticleMove>) {
+        queue
+            .entry(self.target_pos)
+            .and_modify(|existing| {
+                if self.distance() < existing.distance() {
+                    *existing = self.clone();
+                }
+            })
+            .or_insert(self);
+    }
 }
 
 /// A chunk represents a square section of the world map
@@ -37,15 +112,35 @@ pub struct Chunk {
     /// Position of this chunk in chunk coordinates (not world coordinates)
     pub position: UVec2,
     /// Particles stored in this chunk, indexed by local coordinates
-    /// Only contains entries for cells that have particles
-    pub cells: [[Option<Particle>; CHUNK_SIZE as usize]; CHUNK_SIZE as usize],
+    /// Only contains entries for cells that have particles.
+    /// `None` when the chunk is entirely air and has been compacted; see [`Chunk::compact`].
+    cells: Option<Box<Cells>>,
+    /// The background layer of walls drawn behind the particles, indexed like `cells`. Walls
+    /// aren't simulated and never block particles. `None` when the chunk has no walls.
+    background_cells: Option<Box<Cells>>,
+    /// The temperature of each cell, see [`super::heat`].
+    /// `None` when every cell is at its resting temperature. Not saved with the map.
+    temperatures: Option<Box<Temperatures>>,
+    /// How much of each cell's hardness has been worn away by digging and erosion, see
+    /// [`Map::wear_particle_at`]. `None` when no cell is worn. Not saved with the map.
+    wear: Option<Box<Wear>>,
+    /// How brightly each cell is lit, see [`super::lighting`]. `None` when every cell is dark.
+    /// Not saved with the map.
+    light: Option<Box<LightLevels>>,
+    /// The version the light levels were last computed for. `None` if they never were.
+    pub(crate) lit_version: Option<u64>,
     /// Whether this chunk has been modified since last update
     pub dirty: bool,
     /// Whether this chunk is non-homogenous and needs active simulation
     pub should_simulate: bool,
+    /// Whether this chunk holds fire or gas, whose lifetimes run out every tick even when they
+    /// don't move, so the chunk must never settle, see [`super::settling`].
+    pub(crate) holds_aging_particles: bool,
     /// Monotonically increasing version counter, bumped on any cell change.
-    /// Used by the renderer to skip unchanged chunks.
+    /// Used by the renderer to skip unchanged chunks, see [`Chunk::render_version`].
     pub version: u64,
+    /// Monotonically increasing counter bumped whenever the light levels change.
+    pub(crate) light_version: u64,
 }
 
 impl Chunk {
@@ -53,10 +148,17 @@ impl Chunk {
     pub fn new(position: UVec2) -> Self {
         Self {
             position,
-            cells: [[None; CHUNK_SIZE as usize]; CHUNK_SIZE as usize],
+            cells: Some(Box::new(EMPTY_CELLS)),
+            background_cells: None,
+            temperatures: None,
+            wear: None,
+            light: None,
+            lit_version: None,
             dirty: false,
             should_simulate: false,
+            holds_aging_particles: false,
             version: 0,
+            light_version: 0,
         }
     }
 
@@ -85,7 +187,7 @@ impl Chunk {
         if !self.is_in_bounds(local_pos) {
             return None;
         }
-        self.cells[local_pos.x as usize][local_pos.y as usize]
+        self.cells()[local_pos.x as usize][local_pos.y as usize]
     }
 
     /// Set a particle at the given local position
@@ -94,23 +196,256 @@ impl Chunk {
             return;
         }
 
-        self.cells[local_pos.x as usize][local_pos.y as usize] = particle;
+        let (x, y) = (local_pos.x as usize, local_pos.y as usize);
+        if self.cells()[x][y] != particle {
+            self.carry_temperature((x, y), particle);
+            self.clear_wear((x, y));
+        }
+        // Clearing a cell of a compacted chunk doesn't need the storage back.
+        if particle.is_some() || !self.is_compacted() {
+            self.cells_mut()[x][y] = particle;
+        }
         self.dirty = true;
         self.version += 1;
     }
 
-    /// Updates the should_simulate flag by checking if the chunk contains any fluid particles.
+    /// Read-only access to all cells of the chunk. Compacted chunks read as all air.
+    pub fn cells(&self) -> &Cells {
+        self.cells.as_deref().unwrap_or(&EMPTY_CELLS)
+    }
+
+    /// Mutable access to all cells of the chunk, re-materializing the storage of a compacted chunk.
+    /// Changes made through this are not tracked; call [`Chunk::mark_dirty`] afterwards.
+    pub fn cells_mut(&mut self) -> &mut Cells {
+        self.cells.get_or_insert_with(|| Box::new(EMPTY_CELLS))
+    }
+
+    /// Get the wall at the given local position. None if out of bounds.
+    pub fn get_background(&self, local_pos: UVec2) -> Option<Particle> {
+        if !self.is_in_bounds(local_pos) {
+            return None;
+        }
+        self.background_cells()[local_pos.x as usize][local_pos.y as usize]
+    }
+
+    /// Set the wall at the given local position.
+    pub fn set_background(&mut self, local_pos: UVec2, particle: Option<Particle>) {
+        if !self.is_in_bounds(local_pos) {
+            return;
+        }
+
+        let (x, y) = (local_pos.x as usize, local_pos.y as usize);
+        // Clearing a wall of a chunk without walls doesn't need the storage.
+        if particle.is_some() || self.background_cells.is_some() {
+            self.background_cells_mut()[x][y] = particle;
+        }
+        self.dirty = true;
+        self.version += 1;
+    }
+
+    /// Read-only access to the background layer. Chunks without walls read as all air.
+    pub fn background_cells(&self) -> &Cells {
+        self.background_cells.as_deref().unwrap_or(&EMPTY_CELLS)
+    }
+
+    /// Mutable access to the background layer, allocating it if the chunk has no walls yet.
+    /// Changes made through this are not tracked; call [`Chunk::mark_dirty`] afterwards.
+    pub fn background_cells_mut(&mut self) -> &mut Cells {
+        self.background_cells
+            .get_or_insert_with(|| Box::new(EMPTY_CELLS))
+    }
+
+    /// The temperature of the cell at the given local position.
+    pub fn temperature(&self, local_pos: UVec2) -> f32 {
+        let (x, y) = cell_index(local_pos, "Chunk::temperature");
+        match &self.temperatures {
+            Some(temperatures) => temperatures[x][y],
+            None => resting_temperature(self.cells()[x][y]),
+        }
+    }
+
+    /// The temperature of every cell, or `None` if they are all at their resting temperature.
+    pub fn temperatures(&self) -> Option<&Temperatures> {
+        self.temperatures.as_deref()
+    }
+
+    pub(crate) fn set_temperatures(&mut self, temperatures: Option<Box<Temperatures>>) {
+        self.temperatures = temperatures;
+    }
+
+    /// Called when `particle` moves into the cell at `(x, y)`. Particles with a resting
+    /// temperature, like lava, bring it with them. Others take on the temperature of the cell.
+    pub(crate) fn carry_temperature(&mut self, (x, y): (usize, usize), particle: Option<Particle>) {
+        let Some(resting) = particle.and_then(|particle| particle.get_resting_temperature()) else {
+            return;
+        };
+        if let Some(temperatures) = &mut self.temperatures {
+            temperatures[x][y] = resting;
+        }
+    }
+
+    /// How much of its hardness the cell at the given local position has lost.
+    pub fn wear(&self, local_pos: UVec2) -> f32 {
+        let (x, y) = cell_index(local_pos, "Chunk::wear");
+        self.wear.as_ref().map_or(0.0, |wear| wear[x][y])
+    }
+
+    /// How much of its hardness every cell has lost, or `None` if no cell is worn.
+    pub fn wear_levels(&self) -> Option<&Wear> {
+        self.wear.as_deref()
+    }
+
+    pub(crate) fn set_wear_levels(&mut self, wear: Option<Box<Wear>>) {
+        self.wear = wear;
+    }
+
+    /// Wears `amount` more of the hardness of the cell at the given local position away,
+    /// returning its total wear.
+    pub(crate) fn add_wear(&mut self, local_pos: UVec2, amount: f32) -> f32 {
+        let (x, y) = cell_index(local_pos, "Chunk::add_wear");
+        let wear = self
+            .wear
+            .get_or_insert_with(|| Box::new([[0.0; CHUNK_SIZE as usize]; CHUNK_SIZE as usize]));
+        wear[x][y] += amount;
+        wear[x][y]
+    }
+
+    /// Called when the particle in the cell at `(x, y)` changes. Wear belongs to the particle
+    /// that took it, so the new one starts out whole.
+    pub(crate) fn clear_wear(&mut self, (x, y): (usize, usize)) {
+        if let Some(wear) = &mut self.wear {
+            wear[x][y] = 0.0;
+        }
+    }
+
+    /// The light level of the cell at the given local position, from 0 to
+    /// [`MAX_LIGHT`](crate::particle::MAX_LIGHT).
+    pub fn light(&self, local_pos: UVec2) -> u8 {
+        let (x, y) = cell_index(local_pos, "Chunk::light");
+        self.light
+            .as_ref()
+            .map_or(0, |light| light[x][y] & ((1 << SKY_LIGHT_SHIFT) - 1))
+    }
+
+    /// How much of the light of the cell at the given local position comes from the sky, from 0
+    /// to [`MAX_LIGHT`](crate::particle::MAX_LIGHT).
+    pub fn sky_light(&self, local_pos: UVec2) -> u8 {
+        let (x, y) = cell_index(local_pos, "Chunk::sky_light");
+        self.light
+            .as_ref()
+            .map_or(0, |light| light[x][y] >> SKY_LIGHT_SHIFT)
+    }
+
+    /// The light level of every cell, or `None` if they are all dark.
+    pub fn light_levels(&self) -> Option<&LightLevels> {
+        self.light.as_deref()
+    }
+
+    pub(crate) fn set_light_levels(&mut self, light: Option<Box<LightLevels>>) {
+        self.light = light;
+    }
+
+    /// Whether the chunk's cell storage has been released by [`Chunk::compact`].
+    pub fn is_compacted(&self) -> bool {
+        self.cells.is_none()
+    }
+
+    /// Whether every cell in the chunk is air.
+    pub fn is_empty(&self) -> bool {
+        self.cells().iter().flatten().all(Option::is_none)
+    }
+
+    /// Releases the cell storage if the chunk is entirely air, along with any temperatures and
+    /// wear, and the background layer if it has no walls. Returns true if the cell storage is
+    /// released afterwards.
+    pub fn compact(&mut self) -> bool {
+        if self.is_empty() {
+            self.cells = None;
+            self.temperatures = None;
+            self.wear = None;
+        }
+        if self
+            .background_cells()
+            .iter()
+            .flatten()
+            .all(Option::is_none)
+        {
+            self.background_cells = None;
+        }
+        self.is_compacted()
+    }
+
+    /// A hash of every cell in the chunk, including per-particle state, that is stable across runs.
+    pub fn content_hash(&self) -> u64 {
+        // Flattened, since serde only implements arrays of up to 32 elements.
+        let bytes = bincode::serialize(self.cells().as_flattened())
+            .expect("Chunk cells are always serializable");
+        let mut hasher = StableHasher::default();
+        hasher.write(&bytes);
+        hasher.finish()
+    }
+
+    /// Approximate number of bytes used by this chunk, including its cell storage.
+    pub fn memory_usage(&self) -> usize {
+        let cells = if self.is_compacted() {
+            0
+        } else {
+            std::mem::size_of::<Cells>()
+        };
+        let background_cells = if self.background_cells.is_some() {
+            std::mem::size_of::<Cells>()
+        } else {
+            0
+        };
+        let temperatures = if self.temperatures.is_some() {
+            std::mem::size_of::<Temperatures>()
+        } else {
+            0
+        };
+        let wear = if self.wear.is_some() {
+            std::mem::size_of::<Wear>()
+        } else {
+            0
+        };
+        let light = if self.light.is_some() {
+            std::mem::size_of::<LightLevels>()
+        } else {
+            0
+        };
+        std::mem::size_of::<Self>() + cells + background_cells + temperatures + wear + light
+    }
+
+    /// Changes whenever the cells, walls or light levels of the chunk change. Used by the renderer
+    /// to skip unchanged chunks.
+    pub fn render_version(&self) -> u64 {
+        self.version + self.light_version
+    }
+
+    /// Flag the chunk as modified so it is refreshed and re-rendered, even if its cells were
+    /// changed without going through `set_particle`.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+        self.version += 1;
+    }
+
+    /// Updates the should_simulate flag by checking if the chunk contains any fluid or fire particles.
+    /// Also updates whether it holds particles that age in place, i.e. fire and gas.
     fn update_active_state(&mut self) {
-        self.should_simulate = false;
+        let mut should_simulate = false;
+        let mut holds_aging_particles = false;
 
-        for y in 0..CHUNK_SIZE {
-            for x in 0..CHUNK_SIZE {
-                if let Some(Particle::Liquid(_)) = self.cells[x as usize][y as usize] {
-                    self.should_simulate = true;
-                    return; // Early return once we find a fluid
-                }
+        for particle in self.cells().iter().flatten().flatten() {
+            if particle.is_dynamic() {
+                should_simulate = true;
+            }
+            if matches!(particle, Particle::Fire(_) | Particle::Gas(_)) {
+                holds_aging_particles = true;
+                break; // Fire and gas are dynamic, so there is nothing left to find
             }
         }
+
+        self.should_simulate = should_simulate;
+        self.holds_aging_particles = holds_aging_particles;
     }
 
     /// Update particles in this chunk if it's dirty
@@ -133,7 +468,9 @@ impl Chunk {
     pub fn simulate(
         &mut self,
         map: &Map,
+        rules: &InteractionRules,
         interchunk_queue: Arc<DashMap<UVec2, ParticleMove>>,
+        cooldowns: &DashMap<UVec2, u64>,
     ) {
         // Only proceed if this chunk has active particles.
         if !self.should_simulate {
@@ -141,7 +478,7 @@ impl Chunk {
         }
 
         // Create a copy of the current state to read from.
-        let original_cells = self.cells;
+        let original_cells = *self.cells();
         // Create a new state to write to (initially empty).
         let mut new_cells = [[None; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
 
@@ -158,82 +495,177 @@ impl Chunk {
                         if let Some(particle_move) = FluidSimulator.simulate(
                             SimulationContext::new(
                                 map,
+                                rules,
                                 self,
                                 interchunk_queue.as_ref(),
                                 &mut new_cells,
+                                cooldowns,
                             ),
                             fluid,
                             x as u32,
                             y as u32,
                         ) {
-                            interchunk_queue
-                                .entry(particle_move.target_pos)
-                                .and_modify(|existing| {
-                                    let particle_move = particle_move.clone();
-                                    // Use abs_diff to avoid i32 casts
-                                    let existing_distance =
-                                        existing.source_pos.x.abs_diff(existing.target_pos.x)
-                                            + existing.source_pos.y.abs_diff(existing.target_pos.y);
-
-                                    let new_distance =
-                                        particle_move.source_pos.x.abs_diff(particle_move.target_pos.x)
-                                            + particle_move.source_pos.y.abs_diff(particle_move.target_pos.y);
-
-                                    // Particle that's closer to the target position wins
-                                    if new_distance < existing_distance {
-                                        *existing = particle_move;
-                                    }
-                                })
-                                .or_insert(particle_move);
+                            particle_move.enqueue(&interchunk_queue);
+                        }
+                    }
+                    Particle::Gas(gas) => {
+                        if let Some(particle_move) = GasSimulator.simulate(
+                            SimulationContext::new(
+                                map,
+                                rules,
+                                self,
+                                interchunk_queue.as_ref(),
+                                &mut new_cells,
+                                cooldowns,
+                            ),
+                            gas,
+                            x as u32,
+                            y as u32,
+                        ) {
+                            particle_move.enqueue(&interchunk_queue);
+                        }
+                    }
+                    Particle::Powder(powder) => {
+                        if let Some(particle_move) = PowderSimulator.simulate(
+                            SimulationContext::new(
+                                map,
+                                rules,
+                                self,
+                                interchunk_queue.as_ref(),
+                                &mut new_cells,
+                                cooldowns,
+                            ),
+                            powder,
+                            x as u32,
+                            y as u32,
+                        ) {
+                            particle_move.enqueue(&interchunk_queue);
                         }
                     }
-                    _ => new_cells[x][y] = Some(particle),
+                    Particle::Fire(fire) => {
+                        // Fire queues its own cross-chunk ignitions, so there is never a move to handle.
+                        FireSimulator.simulate(
+                            SimulationContext::new(
+                                map,
+                                rules,
+                                self,
+                                interchunk_queue.as_ref(),
+                                &mut new_cells,
+                                cooldowns,
+                            ),
+                            fire,
+                            x as u32,
+                            y as u32,
+                        );
+                    }
+                    // Static particles stay put, unless something (like fire) already replaced them this tick.
+                    _ => {
+                        new_cells[x][y].get_or_insert(particle);
+                    }
+                }
+            }
+        }
+
+        // Particle equality ignores per-cell liquid state, so settled liquids that only flip
+        // direction don't count as a change and the chunk isn't needlessly re-rendered.
+        let changed = *self.cells() != new_cells;
+
+        if changed && (self.temperatures.is_some() || self.wear.is_some()) {
+            for (x, column) in new_cells.iter().enumerate() {
+                for (y, &particle) in column.iter().enumerate() {
+                    if original_cells[x][y] != particle {
+                        self.carry_temperature((x, y), particle);
+                        self.clear_wear((x, y));
+                    }
                 }
             }
         }
 
         // Update the chunk with the new state. Swap is fast.
-        self.cells = new_cells;
+        *self.cells_mut() = new_cells;
 
-        // Mark the chunk as dirty after simulation to ensure other systems update.
-        self.dirty = true;
-        self.version += 1;
+        // Mark the chunk as dirty only if something moved, so other systems update.
+        if changed {
+            self.mark_dirty();
+        }
     }
 
-    /// Convert the particles in this chunk to a list of spritesheet indices.
-    /// Returns an array of size (CHUNK_SIZE * CHUNK_SIZE) / 4 with the spritesheet indices packed into UVec4s.
-    /// Cells without particles will have index 0 (transparent).
-    pub fn to_spritesheet_indices(&self) -> [UVec4; INDICE_BUFFER_SIZE / 4] {
-        let mut indices = [UVec4::ZERO; INDICE_BUFFER_SIZE / 4];
-        // Fill in the indices for cells that have particles
+    /// The cell value the shader draws `particle` at local position (`x`, `y`) with: its sprite,
+    /// animation, variant and draw order.
+    fn encode_cell(&self, particles: &ParticleRegistry, particle: Particle, x: u32, y: u32) -> u32 {
+        let (atlas_id, index) = particles.sprite(particle);
+        let (frames, frame_rate) = particles.animation(particle);
+        let position = self.position * CHUNK_SIZE + UVec2::new(x, y);
+        let variant = cell_variant(position, particles.variants(particle));
+        encode_draw_order(
+            encode_variant(
+                encode_animation(encode_sprite(atlas_id, index), frames, frame_rate),
+                variant,
+            ),
+            particles.draw_order(particle),
+        )
+    }
+
+    /// Convert the particles in this chunk to a row-major list of spritesheet indices, one per cell,
+    /// as `particles` define them. Each index is tagged with its atlas id, animation, variant and
+    /// draw order, see [`encode_sprite`], [`encode_animation`], [`encode_variant`] and
+    /// [`encode_draw_order`], and liquids are tagged with [`LIQUID_CELL_BIT`]. Cells without particles will have index 0 (transparent).
+    pub fn to_cell_indices(&self, particles: &ParticleRegistry) -> [u32; INDICE_BUFFER_SIZE] {
+        let mut indices = [0; INDICE_BUFFER_SIZE];
         for y in 0..CHUNK_SIZE {
             for x in 0..CHUNK_SIZE {
-                let index = (y * CHUNK_SIZE + x) as usize;
-                let array_index = index / 4;
-                let component_index = index % 4;
-                if array_index < indices.len() {
-                    if let Some(particle) = self.cells[x as usize][y as usize] {
-                        let sprite_index = particle.get_spritesheet_index();
-                        match component_index {
-                            0 => indices[array_index].x = sprite_index,
-                            1 => indices[array_index].y = sprite_index,
-                            2 => indices[array_index].z = sprite_index,
-                            3 => indices[array_index].w = sprite_index,
-                            _ => unreachable!(),
-                        }
+                if let Some(particle) = self.cells()[x as usize][y as usize] {
+                    let mut value = self.encode_cell(particles, particle, x, y);
+                    if particle.render_layer() == RenderLayer::Liquid {
+                        value |= LIQUID_CELL_BIT;
                     }
+                    indices[(y * CHUNK_SIZE + x) as usize] = value;
                 }
             }
         }
-
         indices
     }
 
+    /// Convert the particles in this chunk to spritesheet indices packed into UVec4s for the shader.
+    /// See [`pack_indices`] for the layout.
+    pub fn to_spritesheet_indices(&self, particles: &ParticleRegistry) -> PackedIndices {
+        pack_indices(&self.to_cell_indices(particles))
+    }
+
+    /// Convert the walls in this chunk to spritesheet indices packed into UVec4s for the shader,
+    /// like [`Chunk::to_spritesheet_indices`]. Cells without walls will have index 0.
+    pub fn to_background_spritesheet_indices(&self, particles: &ParticleRegistry) -> PackedIndices {
+        let mut indices = [0; INDICE_BUFFER_SIZE];
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                if let Some(particle) = self.background_cells()[x as usize][y as usize] {
+                    indices[(y * CHUNK_SIZE + x) as usize] =
+                        self.encode_cell(particles, particle, x, y);
+                }
+            }
+        }
+        pack_indices(&indices)
+    }
+
+    /// Convert the light levels of this chunk to the packed layout the shader reads, see
+    /// [`pack_light`].
+    pub fn to_packed_light(&self) -> PackedLight {
+        let mut levels = [0; INDICE_BUFFER_SIZE];
+        if let Some(light) = &self.light {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    levels[(y * CHUNK_SIZE + x) as usize] = light[x as usize][y as usize];
+                }
+            }
+        }
+        pack_light(&levels)
+    }
+
     pub fn get_composition(&self) -> HashMap<Particle, u32> {
         let mut composition = HashMap::new();
         for y in 0..CHUNK_SIZE {
             for x in 0..CHUNK_SIZE {
-                if let Some(particle) = self.cells[x as usize][y as usize] {
+                if let Some(particle) = self.cells()[x as usize][y as usize] {
                     *composition.entry(particle).or_insert(0) += 1;
                 }
             }
@@ -0,0 +1,212 @@
+//! An opt-in debug check that the simulation neither loses nor duplicates particles.
+//!
+//! With [`ConservationCheck`] enabled, the map's particles are counted by kind before and after
+//! each simulation tick. Most kinds can only move around, so a count that changed points at a
+//! simulator bug. Kinds that can legitimately turn into something else during the tick, like
+//! water touching lava or wood next to a flame, are left out, see
+//! [`ParticleCensus::may_convert`].
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::particle::interaction::InteractionRules;
+use crate::particle::{Particle, ParticleType};
+use crate::world::Map;
+
+/// Whether to check that each simulation tick conserves particles, see the [module
+/// docs](self). Off by default, since counting every particle each tick is slow.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct ConservationCheck {
+    pub enabled: bool,
+    /// Whether a mismatch panics rather than only being logged. On by default in debug builds.
+    pub panic_on_mismatch: bool,
+    /// The counts from the start of the current tick.
+    before: Option<ParticleCensus>,
+}
+
+impl Default for ConservationCheck {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            panic_on_mismatch: cfg!(debug_assertions),
+            before: None,
+        }
+    }
+}
+
+impl ConservationCheck {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..default()
+        }
+    }
+}
+
+/// How many of each kind of particle a map holds. Kinds are told apart by their
+/// [`display_name`](ParticleType::display_name), so per-cell state like a liquid's direction
+/// doesn't matter.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParticleCensus {
+    counts: BTreeMap<&'static str, usize>,
+    /// One particle of each kind counted, to look up what it can turn into.
+    samples: HashMap<&'static str, Particle>,
+    /// Whether any chunk is hotter or colder than its resting temperature.
+    heated: bool,
+}
+
+/// A kind of particle whose count changed when it shouldn't have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConservationMismatch {
+    pub name: &'static str,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl ParticleCensus {
+    /// Count every particle on `map`.
+    pub fn of(map: &Map) -> Self {
+        let mut census = Self::default();
+        for chunk in map.chunks.iter().flatten() {
+            census.heated |= chunk.temperatures().is_some();
+            for particle in chunk.cells().iter().flatten().flatten() {
+                let name = particle.display_name();
+                *census.counts.entry(name).or_default() += 1;
+                census.samples.entry(name).or_insert(*particle);
+            }
+        }
+        census
+    }
+
+    /// How many particles of the kind named `name` were counted.
+    pub fn count(&self, name: &str) -> usize {
+        self.counts.get(name).copied().unwrap_or(0)
+    }
+
+    /// The kinds that may appear or disappear in a tick starting from this census: gases and
+    /// fire, which burn out; anything an interaction rule between two present kinds involves;
+    /// anything that burns while there's a source of heat; liquids that change phase while
+    /// there's heat around; and anything a present liquid erodes.
+    pub fn may_convert(&self, rules: &InteractionRules) -> HashSet<&'static str> {
+        let present = |particle: Particle| self.samples.contains_key(particle.display_name());
+        let mut exempt = HashSet::new();
+
+        for (pair, rule) in rules.iter() {
+            if present(pair.source) && present(pair.target) {
+                let involved = [pair.source, pair.target, rule.result];
+                exempt.extend(
+                    involved
+                        .iter()
+                        .chain(&rule.byproduct)
+                        .map(|p| p.display_name()),
+                );
+            }
+        }
+
+        let heat_source = self
+            .samples
+            .values()
+            .any(|particle| particle.is_heat_source());
+        let heated = self.heated || heat_source;
+        for (&name, particle) in &self.samples {
+            match particle {
+                Particle::Fire(fire) => {
+                    exempt.insert(name);
+                    exempt.extend(fire.get_residue().map(|p| p.display_name()));
+                }
+                Particle::Gas(_) => {
+                    exempt.insert(name);
+                }
+                _ if heat_source && particle.get_flammability() > 0.0 => {
+                    exempt.insert(name);
+                }
+                _ => {}
+            }
+
+            if heated {
+                for temperature in [f32::MIN, f32::MAX] {
+                    if let Some(changed) = particle.get_phase_change(temperature) {
+                        exempt.extend([name, changed.display_name()]);
+                    }
+                }
+            }
+
+            if let Particle::Liquid(liquid) = particle {
+                exempt.extend(
+                    self.samples
+                        .iter()
+                        .filter(|(_, target)| liquid.get_erosion_rate(**target) > 0.0)
+                        .map(|(&target_name, _)| target_name),
+                );
+            }
+        }
+        exempt
+    }
+
+    /// The kinds whose count differs in `after`, ignoring the ones that
+    /// [may convert](Self::may_convert) under `rules`.
+    pub fn mismatches(
+        &self,
+        after: &ParticleCensus,
+        rules: &InteractionRules,
+    ) -> Vec<ConservationMismatch> {
+        let exempt = self.may_convert(rules);
+        let names: BTreeSet<&'static str> = self
+            .counts
+            .keys()
+            .chain(after.counts.keys())
+            .copied()
+            .collect();
+
+        names
+            .into_iter()
+            .filter(|name| !exempt.contains(name))
+            .map(|name| ConservationMismatch {
+                name,
+                before: self.count(name),
+                after: after.count(name),
+            })
+            .filter(|mismatch| mismatch.before != mismatch.after)
+            .collect()
+    }
+}
+
+/// Run condition for the conservation systems.
+pub fn conservation_check_enabled(check: Res<ConservationCheck>) -> bool {
+    check.enabled
+}
+
+/// System that counts the map's particles at the start of a simulation tick.
+pub fn snapshot_particle_counts(map: Res<Map>, mut check: ResMut<ConservationCheck>) {
+    check.before = Some(ParticleCensus::of(&map));
+}
+
+/// System that compares the map's particles at the end of a simulation tick against the counts
+/// from its start, logging and optionally panicking on a mismatch.
+pub fn assert_particle_conservation(
+    map: Res<Map>,
+    rules: Res<InteractionRules>,
+    mut check: ResMut<ConservationCheck>,
+) {
+    let Some(before) = check.before.take() else {
+        return;
+    };
+    let mismatches = before.mismatches(&ParticleCensus::of(&map), &rules);
+    if mismatches.is_empty() {
+        return;
+    }
+
+    for mismatch in &mismatches {
+        error!(
+            "Tick {} changed the number of {} particles from {} to {}",
+            map.simulation_tick(),
+            mismatch.name,
+            mismatch.before,
+            mismatch.after
+        );
+    }
+    if check.panic_on_mismatch {
+        panic!("Particles weren't conserved: {:?}", mismatches);
+    }
+}
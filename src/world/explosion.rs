@@ -0,0 +1,114 @@
+//! Explosions that blow craters into the map.
+//!
+//! Sending an [`Explosion`] breaks the particles around its center. Everything breakable in the
+//! inner half of the blast breaks, while further out the chance falls off towards the edge, giving
+//! craters ragged rims. Some of the solid material that breaks is left behind as loose debris that
+//! tumbles into the crater.
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::particle::{Particle, Powder};
+use crate::player::DebugMode;
+use crate::utils::coords::{cursor_cell, get_chunk_from_world_pos};
+
+use super::Map;
+
+/// The share of the radius within which every breakable particle breaks.
+const CORE_RADIUS: f32 = 0.5;
+
+/// The chance a broken solid particle leaves debris behind instead of vanishing.
+const DEBRIS_CHANCE: f32 = 0.3;
+
+/// What broken solid particles leave behind.
+const DEBRIS: Particle = Particle::Powder(Powder::Gravel);
+
+/// The radius of explosions set off with the debug key.
+pub const DEBUG_EXPLOSION_RADIUS: u32 = 12;
+
+/// Sent to blow up the particles within `radius` cells of `center`, in world coordinates.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Explosion {
+    pub center: UVec2,
+    pub radius: u32,
+}
+
+impl Map {
+    /// Breaks the particles within `radius` cells of `center`, see [`Explosion`]. Particles that
+    /// can't be dug, like bedrock, survive. Every chunk the blast reaches is marked dirty and woken,
+    /// so loose particles around the crater fall into it. Returns how many particles broke.
+    pub fn explode(&mut self, center: UVec2, radius: u32) -> usize {
+        let broken: Vec<(UVec2, Option<Particle>)> = self
+            .query_radius(center, radius)
+            .filter_map(|(position, particle)| {
+                particle.get_hardness()?;
+
+                let distance = center.as_vec2().distance(position.as_vec2()) / radius.max(1) as f32;
+                let break_chance = if distance <= CORE_RADIUS {
+                    1.0
+                } else {
+                    (1.0 - distance) / (1.0 - CORE_RADIUS)
+                };
+                let mut rng = self.particle_rng(position);
+                if rng.random::<f32>() >= break_chance {
+                    return None;
+                }
+
+                let leaves_debris = !particle.is_dynamic() && rng.random::<f32>() < DEBRIS_CHANCE;
+                Some((position, leaves_debris.then_some(DEBRIS)))
+            })
+            .collect();
+
+        for &(position, debris) in &broken {
+            self.set_particle_at(position, debris);
+        }
+
+        let min_chunk = get_chunk_from_world_pos(center.saturating_sub(UVec2::splat(radius)));
+        let max_chunk = get_chunk_from_world_pos(
+            center
+                .saturating_add(UVec2::splat(radius))
+                .min(UVec2::new(self.width, self.height).saturating_sub(UVec2::ONE)),
+        );
+        for cx in min_chunk.x..=max_chunk.x {
+            for cy in min_chunk.y..=max_chunk.y {
+                self.mark_chunk_dirty(UVec2::new(cx, cy));
+                self.wake_chunk(UVec2::new(cx, cy));
+            }
+        }
+
+        broken.len()
+    }
+}
+
+/// System that sets off every [`Explosion`] sent this frame.
+pub fn process_explosions(mut explosions: EventReader<Explosion>, mut map: ResMut<Map>) {
+    for explosion in explosions.read() {
+        let broken = map.explode(explosion.center, explosion.radius);
+        info!(
+            "Explosion at {} broke {} particles",
+            explosion.center, broken
+        );
+    }
+}
+
+/// Debug hotkey that sets off an explosion under the cursor with B.
+pub fn explode_at_cursor(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    debug_mode: Res<DebugMode>,
+    windows: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    map: Res<Map>,
+    mut explosions: EventWriter<Explosion>,
+) {
+    if !debug_mode.enabled || !keyboard.just_pressed(KeyCode::KeyB) {
+        return;
+    }
+    let Some(center) = cursor_cell(&windows, &camera_q, map.width, map.height) else {
+        return;
+    };
+
+    explosions.send(Explosion {
+        center,
+        radius: DEBUG_EXPLOSION_RADIUS,
+    });
+}
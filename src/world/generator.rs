@@ -1,259 +1,989 @@
 use crate::{
-    particle::{Common, Particle, Special},
+    config::Config,
+    particle::{
+        definition::{ParticleRegistry, PARTICLE_DEFINITIONS},
+        Common, Liquid, Ore, Particle, Special,
+    },
+    player::DebugMode,
     utils::coords::{get_chunk_from_world_pos, world_to_chunk_local},
-    world::chunk::Chunk,
+    world::{
+        biome::BiomeLayout,
+        chunk::Chunk,
+        noise::{fractal, NoiseSource, PerlinNoise, SharedNoise},
+        structure::{Placement, Structure},
+    },
 };
-use bevy::{ecs::system::Commands, log::info_span, math::UVec2, prelude::info};
-use rand::Rng;
-use std::{cell::UnsafeCell, sync::Arc};
+use bevy::{ecs::system::Commands, log::info_span, math::UVec2, prelude::*};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use super::{chunk::CHUNK_SIZE, persistence::SaveSlot, Map};
+
+/// The furthest a vein spreads sideways from the cell it starts in, see [`spawn_vein`].
+pub const MAX_VEIN_REACH: u32 = 4;
+
+/// The seed the current map was generated from. Inserting it before startup, e.g. with the
+/// `--seed` command line flag, generates the starting map from that seed.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorldSeed(pub u64);
+
+impl WorldSeed {
+    /// Reads the seed from command line arguments given as `--seed <seed>` or `--seed=<seed>`.
+    /// Returns `Ok(None)` if there's no seed flag, and an error if its value isn't a number.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let value = match arg.strip_prefix("--seed") {
+                Some("") => args.next(),
+                Some(rest) => match rest.strip_prefix('=') {
+                    Some(value) => Some(value.to_string()),
+                    None => continue,
+                },
+                None => continue,
+            };
+            let value = value.ok_or("--seed needs a value")?;
+            return value
+                .parse()
+                .map(|seed| Some(WorldSeed(seed)))
+                .map_err(|_| format!("Invalid seed '{}', expected a whole number", value));
+        }
+        Ok(None)
+    }
+}
 
-use super::{chunk::CHUNK_SIZE, Map};
+/// Tunes the shape of generated terrain. Change the resource to affect maps generated from then
+/// on, e.g. by the regenerate hotkey.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GeneratorConfig {
+    /// How quickly the surface rises and falls. Lower values give wider hills.
+    pub surface_frequency: f32,
+    /// How far the surface rises or falls from its base height, in particles.
+    pub surface_amplitude: f32,
+    /// Noise layers summed for the surface. Each one adds finer detail.
+    pub surface_octaves: u32,
+    /// How quickly the cavern noise changes. Lower values give larger caverns.
+    pub cave_frequency: f32,
+    /// Roughly the share of the underground carved into caverns, from 0 (none) to 0.5 (about
+    /// half).
+    pub cave_density: f32,
+    /// How quickly tunnels wind. Lower values give longer, straighter tunnels.
+    pub tunnel_frequency: f32,
+    /// How wide tunnels are, as a share of the noise range. 0 disables tunnels.
+    pub tunnel_width: f32,
+    /// Caverns and tunnels stay at least this many particles below the surface.
+    pub cave_min_depth: u32,
+    /// How often structures are placed, as a multiple of each kind's chance. 0 disables them.
+    pub structure_frequency: f32,
+    /// How often liquid pockets form, as a multiple of each liquid's chance. 0 disables them.
+    pub pocket_frequency: f32,
+}
 
-pub(crate) struct UnsafeChunkData {
-    pub chunks: UnsafeCell<Vec<Chunk>>,
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            surface_frequency: 0.02,
+            surface_amplitude: 14.0,
+            surface_octaves: 4,
+            cave_frequency: 0.03,
+            cave_density: 0.12,
+            tunnel_frequency: 0.012,
+            tunnel_width: 0.03,
+            cave_min_depth: PARTICLE_DEFINITIONS.depths(Common::Stone.into()).start,
+            structure_frequency: 1.0,
+            pocket_frequency: 1.0,
+        }
+    }
 }
 
-unsafe impl Sync for UnsafeChunkData {}
+/// The parameters a map is generated from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldGenConfig {
+    /// Number of chunks wide the map should be
+    pub width: u32,
+    /// Number of chunks tall the map should be
+    pub height: u32,
+    /// Seed for all generation randomness. The same seed always produces the same map.
+    pub seed: u64,
+    /// The shape of the terrain.
+    pub terrain: GeneratorConfig,
+    /// Where each particle spawns and how often.
+    pub particles: ParticleRegistry,
+    /// The noise the surface, caves, biomes and ore clusters are all shaped by, see
+    /// [`WorldGenConfig::noise`]. `None` uses [`PerlinNoise`] seeded with `seed`.
+    pub noise: Option<SharedNoise>,
+}
 
-/// Generate terrain data for the entire map.
-pub(crate) fn generate_all_data(map_width: u32, map_height: u32) -> Vec<Chunk> {
-    let _ = info_span!("generate_map_data_all").entered();
-    let start_method = std::time::Instant::now();
+impl WorldGenConfig {
+    /// A config with the default terrain shape.
+    pub fn new(width: u32, height: u32, seed: u64) -> Self {
+        Self::with_terrain(width, height, seed, GeneratorConfig::default())
+    }
 
-    // Pre-compute all surface heights
-    let surface_heights = calculate_surface_heights(map_width, map_height);
+    pub fn with_terrain(width: u32, height: u32, seed: u64, terrain: GeneratorConfig) -> Self {
+        Self {
+            width,
+            height,
+            seed,
+            terrain,
+            particles: ParticleRegistry::default(),
+            noise: None,
+        }
+    }
 
-    // Create empty chunks
-    let chunks = create_empty_chunks(map_width, map_height);
+    /// The same config, but spawning particles as `particles` define.
+    pub fn with_particles(self, particles: ParticleRegistry) -> Self {
+        Self { particles, ..self }
+    }
 
-    // Create unsafe wrapper to allow parallel writing
-    let unsafe_data = Arc::new(UnsafeChunkData {
-        chunks: UnsafeCell::new(chunks),
-    });
+    /// The same config, but shaping everything with `noise` instead of the default noise.
+    pub fn with_noise(self, noise: impl NoiseSource + 'static) -> Self {
+        Self {
+            noise: Some(SharedNoise::new(noise)),
+            ..self
+        }
+    }
 
-    // Determine number of threads to use
-    let num_cpus = num_cpus::get();
-    // Used to calculate the number of columns to process per thread.
-    let work_unit = (map_width as usize / num_cpus).max(1);
+    /// The noise every generation feature samples, each at its own offset so they don't line up.
+    pub fn noise(&self) -> SharedNoise {
+        self.noise
+            .clone()
+            .unwrap_or_else(|| SharedNoise::new(PerlinNoise::new(self.seed)))
+    }
 
-    // Process columns in parallel
-    let start_parallel = std::time::Instant::now();
-    let mut handles = Vec::new();
+    /// The map width in particle units.
+    pub fn map_width(&self) -> u32 {
+        self.width * CHUNK_SIZE
+    }
 
-    for thread_id in 0..num_cpus {
-        let unsafe_data_clone = Arc::clone(&unsafe_data);
-        let surface_heights_clone = surface_heights.clone();
+    /// The map height in particle units.
+    pub fn map_height(&self) -> u32 {
+        self.height * CHUNK_SIZE
+    }
+}
 
-        let start_x = thread_id * work_unit;
+/// Cavern noise rarely strays further than this from zero, so cave densities are mapped onto
+/// this part of its range.
+const CAVERN_SPREAD: f32 = 0.35;
+
+/// Where in the generator's noise each feature samples from. Far apart, so features built on the
+/// same noise don't line up with each other or with the surface, which samples near the origin.
+const CAVERN_NOISE_OFFSET: Vec2 = Vec2::new(7_113.3, 1_931.7);
+const TUNNEL_NOISE_OFFSET: Vec2 = Vec2::new(-4_271.9, 6_047.1);
+const ORE_NOISE_OFFSET: Vec2 = Vec2::new(2_857.1, -8_363.9);
+
+/// How quickly ore richness changes, see [`ore_richness`].
+const ORE_FREQUENCY: f32 = 0.05;
+
+/// Decides which underground cells are carved out. Caverns are the blobs where the noise is
+/// high, and tunnels follow the winding lines where another part of it crosses zero.
+struct CaveCarver {
+    config: GeneratorConfig,
+    particles: ParticleRegistry,
+    noise: SharedNoise,
+}
 
-        // If we're the last thread, process the remaining columns.
-        let end_x = if thread_id == num_cpus - 1 {
-            map_width as usize
-        } else {
-            (thread_id + 1) * work_unit
-        };
+impl CaveCarver {
+    fn new(config: &WorldGenConfig) -> Self {
+        Self {
+            config: config.terrain,
+            particles: config.particles.clone(),
+            noise: config.noise(),
+        }
+    }
 
-        handles.push(std::thread::spawn(move || {
-            process_columns_range(
-                start_x,
-                end_x,
-                &surface_heights_clone,
-                map_width,
-                map_height,
-                unsafe_data_clone,
+    /// Whether the cell at `position`, `depth` particles below the surface, is left empty.
+    fn is_carved(&self, position: UVec2, depth: u32) -> bool {
+        // Keep the soil layers and the bedrock floor intact.
+        if depth < self.config.cave_min_depth
+            || self.particles.common_at_depth(depth) == Common::Bedrock
+        {
+            return false;
+        }
+        let (x, y) = (position.x as f32, position.y as f32);
+
+        if self.config.cave_density > 0.0 {
+            let threshold = CAVERN_SPREAD * (1.0 - 2.0 * self.config.cave_density);
+            let cave_frequency = self.config.cave_frequency;
+            let cavern = fractal(
+                &self.noise,
+                x * cave_frequency + CAVERN_NOISE_OFFSET.x,
+                y * cave_frequency + CAVERN_NOISE_OFFSET.y,
+                2,
             );
-        }));
-    }
+            if cavern > threshold {
+                return true;
+            }
+        }
 
-    // Wait for all threads to complete
-    for handle in handles {
-        handle.join().unwrap();
+        // Squash the tunnel field vertically so tunnels run mostly sideways.
+        let tunnel_frequency = self.config.tunnel_frequency;
+        let tunnel = self.noise.sample(
+            x * tunnel_frequency + TUNNEL_NOISE_OFFSET.x,
+            y * tunnel_frequency * 2.0 + TUNNEL_NOISE_OFFSET.y,
+        );
+        tunnel.abs() < self.config.tunnel_width
     }
+}
+
+/// How many times more likely than usual specials are to spawn at `position`, from 0 to 2. Follows
+/// the generator's noise, so ores cluster into rich and barren patches.
+fn ore_richness(noise: &dyn NoiseSource, position: UVec2) -> f32 {
+    let (x, y) = (position.x as f32, position.y as f32);
+    1.0 + fractal(
+        noise,
+        x * ORE_FREQUENCY + ORE_NOISE_OFFSET.x,
+        y * ORE_FREQUENCY + ORE_NOISE_OFFSET.y,
+        2,
+    )
+}
+
+/// Sent whenever the map resource is replaced by a freshly generated or loaded one.
+#[derive(Event)]
+pub struct MapRegenerated;
+
+/// Generate terrain data for the entire map, returning its chunks indexed as `[x][y]` like
+/// [`Map::chunks`]. `surface_heights` holds the precomputed surface height of each column, and
+/// `biomes` the biome of each column.
+pub(crate) fn generate_all_data(
+    surface_heights: &[u32],
+    biomes: &BiomeLayout,
+    config: &WorldGenConfig,
+) -> Vec<Vec<Chunk>> {
+    let _ = info_span!("generate_map_data_all").entered();
+    let start_method = std::time::Instant::now();
+    let caves = CaveCarver::new(config);
 
+    // Each column of chunks is generated by a single worker, so workers never share a chunk.
+    let start_parallel = std::time::Instant::now();
+    let (mut chunks, specials): (Vec<_>, Vec<_>) = (0..config.map_width() / CHUNK_SIZE)
+        .into_par_iter()
+        .map(|chunk_x| generate_chunk_column(chunk_x, surface_heights, biomes, config, &caves))
+        .unzip();
     info!("  Parallel processing took: {:?}", start_parallel.elapsed());
+
+    // Special particles are placed after all columns are done. Veins can spill into a neighboring
+    // column of chunks, so placing them here, in column order, keeps the result independent of
+    // how the columns were scheduled.
+    // Note: Special particles are allowed to overwrite common particles.
+    for (spawn_pos, particle) in specials.into_iter().flatten() {
+        let chunk_pos = get_chunk_from_world_pos(spawn_pos);
+        chunks[chunk_pos.x as usize][chunk_pos.y as usize]
+            .set_particle(world_to_chunk_local(spawn_pos), Some(particle));
+    }
+
+    // Liquid pockets carve through the terrain and specials.
+    for pocket in plan_liquid_pockets(surface_heights, biomes, config) {
+        for (position, particle) in pocket.cells() {
+            let chunk_pos = get_chunk_from_world_pos(position);
+            chunks[chunk_pos.x as usize][chunk_pos.y as usize]
+                .set_particle(world_to_chunk_local(position), particle);
+        }
+    }
+
+    // Structures go in last, carving through whatever was generated under them.
+    for placed in plan_structures(surface_heights, config) {
+        for (position, particle) in placed.cells() {
+            let chunk_pos = get_chunk_from_world_pos(position);
+            chunks[chunk_pos.x as usize][chunk_pos.y as usize]
+                .set_particle(world_to_chunk_local(position), particle);
+        }
+    }
+
+    // Chunks holding liquids or other moving particles start simulating right away.
+    for chunk in chunks.iter_mut().flatten() {
+        chunk.trigger_refresh();
+    }
+
     info!("Total generate_all_data time: {:?}", start_method.elapsed());
+    chunks
+}
 
-    // Return the completed chunks vector
-    unsafe { (*unsafe_data.chunks.get()).clone() }
+/// Creates the RNG for a single column. Each column derives its own RNG from the seed so the
+/// result doesn't depend on how columns are split between threads.
+fn column_rng(seed: u64, x: usize) -> StdRng {
+    StdRng::seed_from_u64(seed ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
 }
 
-/// Process a range of columns in the map
-fn process_columns_range(
-    start_x: usize,
-    end_x: usize,
+/// Generate the column of chunks at `chunk_x`, placing common particles directly.
+/// Returns its chunks from the bottom up, along with the special particles to place once every
+/// column is done.
+fn generate_chunk_column(
+    chunk_x: u32,
     surface_heights: &[u32],
-    map_width: u32,
-    map_height: u32,
-    unsafe_data: Arc<UnsafeChunkData>,
-) {
-    let _ = info_span!(
-        "generate_map_data_thread",
-        width_range = format!("{}..{}", start_x, end_x)
-    )
-    .entered();
-    let mut rng = rand::rng();
+    biomes: &BiomeLayout,
+    config: &WorldGenConfig,
+    caves: &CaveCarver,
+) -> (Vec<Chunk>, Vec<(UVec2, Particle)>) {
+    let _ = info_span!("generate_map_data_column", chunk_x).entered();
+    let mut chunks: Vec<Chunk> = (0..config.map_height() / CHUNK_SIZE)
+        .map(|chunk_y| Chunk::new(UVec2::new(chunk_x, chunk_y)))
+        .collect();
+    let mut specials = Vec::new();
+
+    for (x, &surface_height) in surface_heights
+        .iter()
+        .enumerate()
+        .skip((chunk_x * CHUNK_SIZE) as usize)
+        .take(CHUNK_SIZE as usize)
+    {
+        specials.extend(generate_column(
+            x,
+            surface_height,
+            biomes,
+            config,
+            caves,
+            |position, ground, solid| {
+                let chunk = &mut chunks[(position.y / CHUNK_SIZE) as usize];
+                chunk.set_background(world_to_chunk_local(position), Some(ground));
+                if solid {
+                    chunk.set_particle(world_to_chunk_local(position), Some(ground));
+                }
+            },
+        ));
+    }
+
+    (chunks, specials)
+}
+
+/// Roll every cell of column `x` below the surface, handing each to `place_cell` along with the
+/// ground particle of the column's biome at its depth, see [`Biome::ground_at_depth`], and
+/// whether it's filled with it. Every cell below the surface gets a wall of the ground particle,
+/// even where caves are carved. Returns the special particles to place afterwards, which may
+/// spill into the neighboring columns.
+///
+/// [`Biome::ground_at_depth`]: crate::world::biome::Biome::ground_at_depth
+fn generate_column(
+    x: usize,
+    surface_height: u32,
+    biomes: &BiomeLayout,
+    config: &WorldGenConfig,
+    caves: &CaveCarver,
+    mut place_cell: impl FnMut(UVec2, Particle, bool),
+) -> Vec<(UVec2, Particle)> {
+    let (map_width, map_height) = (config.map_width(), config.map_height());
+    let mut rng = column_rng(config.seed, x);
+    let biome = biomes.biome_at(x as u32);
+    let mut specials = Vec::new();
+
+    for y in 0..map_height {
+        let position = UVec2::new(x as u32, y);
+        // Cells above the surface have no depth and stay empty.
+        let Some(depth) = surface_height.checked_sub(y) else {
+            continue;
+        };
+        let ground = biome.ground_at_depth(depth, &config.particles);
+        if caves.is_carved(position, depth) {
+            place_cell(position, ground, false);
+            continue;
+        }
+
+        let richness = ore_richness(&caves.noise, position);
+        if let Some(Particle::Special(special)) =
+            Map::roll_clustered_special(depth, biome, &config.particles, richness, &mut rng)
+        {
+            place_cell(position, ground, false);
+            specials.extend(process_special_particle(
+                position, special, map_width, map_height, &mut rng,
+            ));
+        } else {
+            // If no special particle was rolled, use the ground particle
+            place_cell(position, ground, true);
+        }
+    }
+
+    specials
+}
+
+/// The parts of generation planned across the whole map at once. Planned once per map, so
+/// chunks generated on their own don't each plan the whole map again.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GenerationPlan {
+    /// See [`plan_liquid_pockets`].
+    pub pockets: Vec<LiquidPocket>,
+    /// See [`plan_structures`].
+    pub structures: Vec<PlacedStructure>,
+}
+
+impl GenerationPlan {
+    pub fn new(surface_heights: &[u32], biomes: &BiomeLayout, config: &WorldGenConfig) -> Self {
+        Self {
+            pockets: plan_liquid_pockets(surface_heights, biomes, config),
+            structures: plan_structures(surface_heights, config),
+        }
+    }
+}
 
-    for (x, _) in surface_heights
+/// The cells `chunk` covers.
+fn chunk_rect(chunk: &Chunk) -> URect {
+    URect::new(chunk.x_min(), chunk.y_min(), chunk.x_max(), chunk.y_max())
+}
+
+/// Generate a single chunk on its own, exactly as [`generate_all_data`] would have produced it.
+/// Veins spill at most [`MAX_VEIN_REACH`] cells sideways, so only the chunk's columns and the
+/// columns that close to it need rolling. `plan` must be the map's [`GenerationPlan`].
+pub(crate) fn generate_chunk_data(
+    chunk_pos: UVec2,
+    surface_heights: &[u32],
+    biomes: &BiomeLayout,
+    config: &WorldGenConfig,
+    plan: &GenerationPlan,
+) -> Chunk {
+    let mut chunk = Chunk::new(chunk_pos);
+    let min_x = chunk.x_min().saturating_sub(MAX_VEIN_REACH) as usize;
+    let max_x = (chunk.x_max() + MAX_VEIN_REACH).min(config.map_width() - 1) as usize;
+    let caves = CaveCarver::new(config);
+
+    let mut specials = Vec::new();
+    for (x, &surface_height) in surface_heights
         .iter()
         .enumerate()
-        .skip(start_x)
-        .take(end_x - start_x)
+        .skip(min_x)
+        .take(max_x + 1 - min_x)
     {
-        let surface_height = surface_heights[x];
-
-        for y in 0..map_height as usize {
-            let position = UVec2::new(x as u32, y as u32);
-            let special_particle = if y as u32 > surface_height {
-                None
-            } else {
-                let depth = surface_height - y as u32;
-                Map::roll_special_particle(depth, &mut rng)
-            };
+        specials.extend(generate_column(
+            x,
+            surface_height,
+            biomes,
+            config,
+            &caves,
+            |position, ground, solid| {
+                if chunk.is_within_chunk(position) {
+                    chunk.set_background(world_to_chunk_local(position), Some(ground));
+                    if solid {
+                        chunk.set_particle(world_to_chunk_local(position), Some(ground));
+                    }
+                }
+            },
+        ));
+    }
 
-            if let Some(Particle::Special(special)) = special_particle {
-                process_special_particle(position, special, map_width, map_height, &unsafe_data);
-            } else if y as u32 <= surface_height {
-                // If no special particle was rolled, use common particle
-                let depth = surface_height - y as u32;
-                process_common_particle(position, depth, &unsafe_data, map_width);
+    // Specials go in afterwards and in column order, matching the full generation.
+    for (position, particle) in specials {
+        if chunk.is_within_chunk(position) {
+            chunk.set_particle(world_to_chunk_local(position), Some(particle));
+        }
+    }
+    let bounds = chunk_rect(&chunk);
+    for pocket in plan
+        .pockets
+        .iter()
+        .filter(|pocket| !pocket.rect().intersect(bounds).is_empty())
+    {
+        for (position, particle) in pocket.cells() {
+            if chunk.is_within_chunk(position) {
+                chunk.set_particle(world_to_chunk_local(position), particle);
+            }
+        }
+    }
+    for placed in plan
+        .structures
+        .iter()
+        .filter(|placed| !placed.rect().intersect(bounds).is_empty())
+    {
+        for (position, particle) in placed.cells() {
+            if chunk.is_within_chunk(position) {
+                chunk.set_particle(world_to_chunk_local(position), particle);
             }
         }
     }
-}
 
-/// Helper function to convert world position to chunk index
-fn world_to_chunk_index(position: UVec2, map_width: u32) -> (UVec2, usize) {
-    let chunk_pos = get_chunk_from_world_pos(position);
-    let local_pos = world_to_chunk_local(position);
-    let chunks_wide = map_width / CHUNK_SIZE;
-    let chunk_index = (chunk_pos.x + chunk_pos.y * chunks_wide) as usize;
-    (local_pos, chunk_index)
+    // Start simulating right away if the chunk holds liquids or other moving particles.
+    chunk.trigger_refresh();
+
+    chunk
 }
 
-/// Process special particles (ores and gems) and place them in the world.
-/// Note: Special particles are allowed to overwrite common particles.
+/// Process special particles (ores and gems), returning every position they occupy.
 fn process_special_particle(
     position: UVec2,
     special: Special,
     map_width: u32,
     map_height: u32,
-    unsafe_data: &Arc<UnsafeChunkData>,
-) {
-    let particles = match special {
-        Special::Ore(_) => spawn_vein(position, Particle::Special(special), map_width, map_height),
+    rng: &mut impl Rng,
+) -> Vec<(UVec2, Particle)> {
+    match special {
+        Special::Ore(ore) => spawn_vein(position, ore, map_width, map_height, rng),
         Special::Gem(_) => vec![(position, Particle::Special(special))],
-    };
+    }
+}
+
+/// Generates and returns a vein of `ore` grown from the specified position, shaped by the ore's
+/// [`VeinProfile`](crate::particle::VeinProfile). Each new cell grows off a neighbor already in
+/// the vein, never further than [`MAX_VEIN_REACH`] columns from the start.
+pub fn spawn_vein(
+    position: UVec2,
+    ore: Ore,
+    map_width: u32,
+    map_height: u32,
+    rng: &mut impl Rng,
+) -> Vec<(UVec2, Particle)> {
+    let profile = ore.vein_profile();
+    let particle = Particle::Special(Special::Ore(ore));
+    let mut cells = vec![position]; // Start with the central particle
 
-    // Place all the spawned particles
-    for (spawn_pos, particle) in particles {
-        let (local_pos, chunk_index) = world_to_chunk_index(spawn_pos, map_width);
+    let vein_size = rng.random_range(profile.min_size..=profile.max_size);
+    for _ in 0..vein_size {
+        // Blobby veins grow anywhere along their edge, stringy ones from their tip.
+        let from = if rng.random::<f32>() < profile.blobbiness {
+            cells[rng.random_range(0..cells.len())]
+        } else {
+            cells[cells.len() - 1]
+        };
+        let offset = if rng.random::<f32>() < profile.horizontal_bias {
+            IVec2::new(if rng.random() { 1 } else { -1 }, 0)
+        } else {
+            IVec2::new(rng.random_range(-1..=1), rng.random_range(-1..=1))
+        };
 
-        // Use unsafe to set the particle in the shared chunk data
-        unsafe {
-            let chunks = &mut *unsafe_data.chunks.get();
-            chunks[chunk_index].set_particle(local_pos, Some(particle));
+        let new_pos = from.as_ivec2() + offset;
+        // Check bounds
+        if new_pos.x < 0
+            || new_pos.y < 0
+            || new_pos.x >= map_width as i32
+            || new_pos.y >= map_height as i32
+            || new_pos.x.abs_diff(position.x as i32) > MAX_VEIN_REACH
+        {
+            continue;
+        }
+        let new_pos = new_pos.as_uvec2();
+        if !cells.contains(&new_pos) {
+            cells.push(new_pos);
         }
     }
+
+    cells.into_iter().map(|cell| (cell, particle)).collect()
 }
 
-/// Process common particles and place them in the world.
-/// Note: Common particles are not allowed to overwrite special particles.
-fn process_common_particle(
-    position: UVec2,
-    depth: u32,
-    unsafe_data: &Arc<UnsafeChunkData>,
-    map_width: u32,
-) {
-    // Get common particle based on depth
-    let common_particle = Common::get_exclusive_at_depth(depth).into();
+/// Mixed into the world seed so liquid pockets don't correlate with terrain randomness.
+const POCKET_SEED_SALT: u64 = 0x1A4E_0C4E_75EE_D003;
+/// How many times each column of chunks tries to form a liquid pocket.
+const POCKET_ATTEMPTS: u32 = 2;
+
+/// An underground pocket carved out and partly filled with a liquid, see [`plan_liquid_pockets`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LiquidPocket {
+    pub liquid: Liquid,
+    /// The middle of the pocket.
+    pub center: UVec2,
+    /// How far the pocket reaches from its middle sideways and up and down.
+    pub half_size: UVec2,
+    /// The highest row holding liquid. The rows above it are left empty.
+    pub level: u32,
+}
 
-    // Convert world position to chunk and local coordinates
-    let (local_pos, chunk_index) = world_to_chunk_index(position, map_width);
+impl LiquidPocket {
+    /// The corners of the box around the pocket, both inclusive.
+    fn bounds(&self) -> (UVec2, UVec2) {
+        (self.center - self.half_size, self.center + self.half_size)
+    }
 
-    // Use unsafe to set the particle in the shared chunk data
-    unsafe {
-        let chunks = &mut *unsafe_data.chunks.get();
+    /// The cells of the box around the pocket.
+    fn rect(&self) -> URect {
+        let (min, max) = self.bounds();
+        URect::from_corners(min, max + UVec2::ONE)
+    }
 
-        if chunks[chunk_index].get_particle(local_pos).is_none() {
-            chunks[chunk_index].set_particle(local_pos, Some(common_particle));
-        }
+    /// Every cell of the map the pocket covers, along with what it puts there.
+    pub fn cells(&self) -> impl Iterator<Item = (UVec2, Option<Particle>)> + '_ {
+        let half = self.half_size.as_ivec2();
+        (-half.x..=half.x).flat_map(move |dx| {
+            (-half.y..=half.y).filter_map(move |dy| {
+                let (nx, ny) = (dx as f32 / half.x as f32, dy as f32 / half.y as f32);
+                if nx * nx + ny * ny > 1.0 {
+                    return None;
+                }
+                let position = (self.center.as_ivec2() + IVec2::new(dx, dy)).as_uvec2();
+                let particle = (position.y <= self.level).then_some(Particle::Liquid(self.liquid));
+                Some((position, particle))
+            })
+        })
     }
 }
 
-/// Generates and returns a vein (a small cluster of ore particles) around the specified position
-pub fn spawn_vein(
-    position: UVec2,
-    particle: Particle,
-    map_width: u32,
-    map_height: u32,
-) -> Vec<(UVec2, Particle)> {
-    let mut rng = rand::rng();
-    let mut vein_particles = vec![(position, particle)]; // Start with the central particle
+/// Picks where liquid pockets form in a map generated from `config` with the given surface and
+/// biomes. Each column of chunks makes [`POCKET_ATTEMPTS`] attempts at a random column and depth,
+/// each rolling out of 100 against the chances of the liquids that spawn at that depth in that
+/// column's biome, see [`Biome::liquid_pocket_chance`], scaled by the config's
+/// [`GeneratorConfig::pocket_frequency`]. Pockets are shaped by the liquid's
+/// [`PocketProfile`](crate::particle::PocketProfile), and dropped if they would poke out of the
+/// map, come closer to the surface than caves do, reach the bedrock, or touch another pocket.
+/// No pockets are planned when caves don't start above the bedrock.
+///
+/// The plan only depends on the seed, the surface and the biomes, so every chunk can be generated
+/// on its own.
+///
+/// [`Biome::liquid_pocket_chance`]: crate::world::biome::Biome::liquid_pocket_chance
+pub fn plan_liquid_pockets(
+    surface_heights: &[u32],
+    biomes: &BiomeLayout,
+    config: &WorldGenConfig,
+) -> Vec<LiquidPocket> {
+    let mut rng = StdRng::seed_from_u64(config.seed ^ POCKET_SEED_SALT);
+    let mut pockets: Vec<LiquidPocket> = Vec::new();
+    let min_depth = config.terrain.cave_min_depth;
+    let max_depth = config.particles.depths(Common::Bedrock.into()).start;
+    // Caves may be set to start at or below the bedrock, leaving nowhere for pockets to go.
+    if min_depth >= max_depth {
+        return pockets;
+    }
 
-    // Determine vein size (3-6 additional particles)
-    let vein_size = rng.random_range(3..=6);
+    for chunk_x in 0..config.width {
+        for _ in 0..POCKET_ATTEMPTS {
+            let x = chunk_x * CHUNK_SIZE + rng.random_range(0..CHUNK_SIZE);
+            let depth = rng.random_range(min_depth..max_depth);
+            let biome = biomes.biome_at(x);
+            let roll = rng.random::<f32>() * 100.0;
+            let mut chance = 0.0;
+            let Some(liquid) = Liquid::iter()
+                .filter(|liquid| config.particles.depths((*liquid).into()).contains(&depth))
+                .find(|&liquid| {
+                    chance += biome.liquid_pocket_chance(liquid, &config.particles) as f32
+                        * config.terrain.pocket_frequency;
+                    roll < chance
+                })
+            else {
+                continue;
+            };
 
-    // Try to spawn additional ore particles in adjacent positions
-    for _ in 0..vein_size {
-        // Random offset between -1 and 1 in both x and y directions
-        let offset_x = rng.random_range(-1..=1);
-        let offset_y = rng.random_range(-1..=1);
+            let profile = liquid.pocket_profile();
+            let half_size = UVec2::new(
+                rng.random_range(profile.min_half_width..=profile.max_half_width),
+                rng.random_range(profile.min_half_height..=profile.max_half_height),
+            );
+            let Some(&surface) = surface_heights.get(x as usize) else {
+                continue;
+            };
+            let Some(center) = surface.checked_sub(depth).map(|y| UVec2::new(x, y)) else {
+                continue;
+            };
+            if center.cmplt(half_size).any() || center.x + half_size.x >= config.map_width() {
+                continue;
+            }
 
-        // Skip if offset is (0,0) as we already placed a particle there
-        if offset_x == 0 && offset_y == 0 {
-            continue;
+            let height = 2 * half_size.y + 1;
+            let filled = ((height as f32 * profile.fill).round() as u32).max(1);
+            let pocket = LiquidPocket {
+                liquid,
+                center,
+                half_size,
+                level: center.y - half_size.y + filled - 1,
+            };
+            let (min, max) = pocket.bounds();
+            let buried = surface_heights[min.x as usize..=max.x as usize]
+                .iter()
+                .all(|&surface| surface >= max.y + min_depth && surface - min.y < max_depth);
+            let apart = pockets.iter().all(|other| {
+                let (other_min, other_max) = other.bounds();
+                max.cmplt(other_min).any() || other_max.cmplt(min).any()
+            });
+            if buried && apart {
+                pockets.push(pocket);
+            }
         }
+    }
 
-        // Calculate new position
-        let new_x = position.x as i32 + offset_x;
-        let new_y = position.y as i32 + offset_y;
+    pockets
+}
 
-        // Check bounds
-        if new_x < 0 || new_y < 0 || new_x >= map_width as i32 || new_y >= map_height as i32 {
-            continue;
+/// Mixed into the world seed so structure placement doesn't correlate with terrain randomness.
+const STRUCTURE_SEED_SALT: u64 = 0x57C7_0C7E_5EED_0002;
+/// Structures stay at least this many cells apart from each other.
+const STRUCTURE_MARGIN: u32 = 4;
+
+const MINESHAFT_TEMPLATE: &[&str] = &[
+    "WWWWWWWWWWWWWWWWWWWWW",
+    "W....T....W....T....W",
+    "W.........W.........W",
+    "W.........W.........W",
+    "W.........W.........W",
+    "#####################",
+];
+
+const DUNGEON_TEMPLATE: &[&str] = &[
+    "OOOOOOOOOOOOOOO",
+    "O.............O",
+    "O..T.......T..O",
+    "O.............O",
+    "O.............O",
+    "O.............O",
+    "O......R......O",
+    "O.....RGR.....O",
+    "OOOOOOOOOOOOOOO",
+];
+
+const TREASURE_POCKET_TEMPLATE: &[&str] = &["#######", "##.R.##", "#.RGR.#", "##.R.##", "#######"];
+
+/// The prefab structures stamped into the map after the terrain is generated, see
+/// [`plan_structures`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter)]
+pub enum StructureKind {
+    /// A corridor held up by wooden supports and lit by torches.
+    Mineshaft,
+    /// An obsidian room with a little treasure in the middle.
+    Dungeon,
+    /// A small cavity lined with rubies around a nugget of gold.
+    TreasurePocket,
+}
+
+impl StructureKind {
+    /// The structure as it appears unmirrored.
+    pub fn template(&self) -> Structure {
+        let rows = match self {
+            StructureKind::Mineshaft => MINESHAFT_TEMPLATE,
+            StructureKind::Dungeon => DUNGEON_TEMPLATE,
+            StructureKind::TreasurePocket => TREASURE_POCKET_TEMPLATE,
+        };
+        Structure::from_template(rows).expect("Built-in structure templates are well formed")
+    }
+
+    /// How far below the surface the top of the structure can be.
+    pub fn depths(&self) -> Range<u32> {
+        match self {
+            StructureKind::Mineshaft => 25..120,
+            StructureKind::Dungeon => 120..500,
+            StructureKind::TreasurePocket => 80..300,
         }
+    }
+
+    /// The chance of trying to place one in each column of chunks, from 0 to 1.
+    pub fn chance(&self) -> f32 {
+        match self {
+            StructureKind::Mineshaft => 0.35,
+            StructureKind::Dungeon => 0.15,
+            StructureKind::TreasurePocket => 0.3,
+        }
+    }
+}
+
+/// A structure picked to be stamped into the map, see [`plan_structures`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacedStructure {
+    pub kind: StructureKind,
+    /// The bottom-left corner of the structure.
+    pub position: UVec2,
+    /// The structure as placed, already mirrored if it was picked to be.
+    pub structure: Structure,
+}
+
+impl PlacedStructure {
+    /// The corner past the top-right of the structure.
+    pub fn max(&self) -> UVec2 {
+        self.position + self.structure.size()
+    }
+
+    /// The cells the structure covers.
+    pub fn rect(&self) -> URect {
+        URect::from_corners(self.position, self.max())
+    }
+
+    /// Whether this structure comes closer than `margin` cells to `other`.
+    fn is_near(&self, other: &PlacedStructure, margin: u32) -> bool {
+        let (min, max) = (
+            self.position.saturating_sub(UVec2::splat(margin)),
+            self.max() + margin,
+        );
+        min.cmplt(other.max()).all() && other.position.cmplt(max).all()
+    }
 
-        let new_position = UVec2::new(new_x as u32, new_y as u32);
+    /// Every cell of the map the structure covers, along with what it puts there. Empty cells of
+    /// the structure are carved out.
+    pub fn cells(&self) -> impl Iterator<Item = (UVec2, Option<Particle>)> + '_ {
+        let size = self.structure.size();
+        (0..size.x).flat_map(move |x| {
+            (0..size.y).map(move |y| {
+                let local = UVec2::new(x, y);
+                (self.position + local, self.structure.get(local))
+            })
+        })
+    }
+}
 
-        // 70% chance to place an ore particle
-        if rng.random_bool(0.7) {
-            vein_particles.push((new_position, particle));
+/// Picks where structures are stamped into a map generated from `config` with the given surface.
+/// Each column of chunks rolls for every [`StructureKind`], scaled by the config's
+/// [`GeneratorConfig::structure_frequency`], and a rolled structure is placed at a
+/// random column and depth from [`StructureKind::depths`]. It's dropped if it would poke out of
+/// the map, come closer to the surface than caves do, reach the bedrock, or come within
+/// [`STRUCTURE_MARGIN`] cells of another structure.
+///
+/// The plan only depends on the seed and the surface, so every chunk can be generated on its own.
+pub fn plan_structures(surface_heights: &[u32], config: &WorldGenConfig) -> Vec<PlacedStructure> {
+    let mut rng = StdRng::seed_from_u64(config.seed ^ STRUCTURE_SEED_SALT);
+    let bedrock_depth = config.particles.depths(Common::Bedrock.into()).start;
+    let mut placed: Vec<PlacedStructure> = Vec::new();
+
+    for chunk_x in 0..config.width {
+        for kind in StructureKind::iter() {
+            if rng.random::<f32>() >= kind.chance() * config.terrain.structure_frequency {
+                continue;
+            }
+            let structure = kind.template().oriented(&Placement {
+                mirror: rng.random(),
+                carve: true,
+                ..default()
+            });
+            let x = chunk_x * CHUNK_SIZE + rng.random_range(0..CHUNK_SIZE);
+            let depth = rng.random_range(kind.depths());
+
+            let size = structure.size();
+            let Some(columns) = surface_heights.get(x as usize..(x + size.x) as usize) else {
+                continue;
+            };
+            let Some(top) = surface_heights[x as usize].checked_sub(depth) else {
+                continue;
+            };
+            let Some(bottom) = (top + 1).checked_sub(size.y) else {
+                continue;
+            };
+            let buried = columns.iter().all(|&surface| {
+                surface >= top + config.terrain.cave_min_depth && surface - bottom < bedrock_depth
+            });
+            let candidate = PlacedStructure {
+                kind,
+                position: UVec2::new(x, bottom),
+                structure,
+            };
+            if buried
+                && !placed
+                    .iter()
+                    .any(|other| candidate.is_near(other, STRUCTURE_MARGIN))
+            {
+                placed.push(candidate);
+            }
         }
     }
 
-    vein_particles
+    placed
 }
 
-pub fn setup_map(mut commands: Commands) {
-    let map = Map::generate(20, 20);
+/// Generates the starting map, unless one was already inserted (e.g. by a test harness) or
+/// the [`SaveSlot`] holds a saved one. A [`WorldSeed`] inserted beforehand always generates a
+/// fresh map from that seed, so a seed from a bug report reproduces its map.
+pub fn setup_map(
+    mut commands: Commands,
+    existing: Option<Res<Map>>,
+    requested_seed: Option<Res<WorldSeed>>,
+    slot: Res<SaveSlot>,
+    terrain: Res<GeneratorConfig>,
+    config: Res<Config>,
+    particles: Res<ParticleRegistry>,
+) {
+    let requested_seed = requested_seed.map(|seed| *seed);
+    let seed = requested_seed.unwrap_or_else(|| WorldSeed(rand::random()));
+    if existing.is_some() {
+        info!(
+            "Using the provided map, seed {} is used for regeneration",
+            seed.0
+        );
+        commands.insert_resource(seed);
+        return;
+    }
+
+    if requested_seed.is_none() && slot.path.exists() {
+        match Map::from_file(&slot.path) {
+            Ok(map) => {
+                info!("Loaded map from {}", slot.path.display());
+                commands.insert_resource(map.seed().map_or(seed, WorldSeed));
+                commands.insert_resource(map);
+                return;
+            }
+            Err(e) => warn!(
+                "Couldn't load {}, generating a new map instead: {}",
+                slot.path.display(),
+                e
+            ),
+        }
+    }
+    info!("Generating map with seed {}", seed.0);
+
+    // Only the chunks around the player are generated, as the player gets near them.
+    let gen_config =
+        WorldGenConfig::with_terrain(config.map_width, config.map_height, seed.0, *terrain)
+            .with_particles(particles.clone());
+    let map = Map::generate_streamed_from_config(&gen_config, config.max_loaded_chunks());
+    commands.insert_resource(seed);
     commands.insert_resource(map);
 }
 
-/// Create and initialize empty chunks.
-/// This function is useful because it can properly assign positions to chunks.
-fn create_empty_chunks(map_width: u32, map_height: u32) -> Vec<Chunk> {
-    let chunks_wide = map_width / CHUNK_SIZE;
-    let chunks_tall = map_height / CHUNK_SIZE;
-    let mut chunks = Vec::with_capacity((chunks_wide * chunks_tall) as usize);
-    for x in 0..chunks_wide {
-        for y in 0..chunks_tall {
-            // We must push in y, x order because the chunks are stored in a 1D vector.
-            chunks.push(Chunk::new(UVec2::new(y, x)));
-        }
+/// Debug hotkey to regenerate the map in place.
+/// R rolls a new random seed, while Shift+R regenerates with the current seed.
+#[allow(clippy::too_many_arguments)]
+pub fn regenerate_map(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    debug_mode: Res<DebugMode>,
+    terrain: Res<GeneratorConfig>,
+    config: Option<Res<Config>>,
+    particles: Res<ParticleRegistry>,
+    mut map: ResMut<Map>,
+    mut seed: ResMut<WorldSeed>,
+    mut regenerated: EventWriter<MapRegenerated>,
+) {
+    if !debug_mode.enabled || !keyboard.just_pressed(KeyCode::KeyR) {
+        return;
     }
-    chunks
+
+    let shift_pressed =
+        keyboard.pressed(KeyCode::ShiftLeft) || keyboard.pressed(KeyCode::ShiftRight);
+    if !shift_pressed {
+        seed.0 = rand::random();
+    }
+
+    info!("Regenerating map with seed {}", seed.0);
+    // Replace the map in place so every system sees the new one this frame.
+    let gen_config = WorldGenConfig::with_terrain(
+        map.width / CHUNK_SIZE,
+        map.height / CHUNK_SIZE,
+        seed.0,
+        *terrain,
+    )
+    .with_particles(particles.clone());
+    // Streamed maps stay streamed, so regenerating doesn't generate every chunk up front.
+    *map = if map.is_streamed() {
+        let config = config.map_or_else(Config::default, |config| *config);
+        Map::generate_streamed_from_config(&gen_config, config.max_loaded_chunks())
+    } else {
+        Map::generate_from_config(&gen_config)
+    };
+    regenerated.send(MapRegenerated);
 }
 
-/// Calculate surface heights for terrain generation
-fn calculate_surface_heights(map_width: u32, map_height: u32) -> Vec<u32> {
+/// Calculate surface heights for terrain generation.
+/// Hills come from octaves of `noise`, shaped by the frequency and amplitude in `terrain`.
+/// Heights are always within the map, i.e. at most `map_height - 1`.
+pub(crate) fn calculate_surface_heights(
+    map_width: u32,
+    map_height: u32,
+    noise: &dyn NoiseSource,
+    terrain: &GeneratorConfig,
+) -> Vec<u32> {
     let _ = info_span!("calculate_surface_heights").entered();
 
-    let base_height = (map_height as f32 * 0.95) as u32;
+    let base_height = (map_height as f32 * 0.95) as i64;
+    let max_height = map_height.saturating_sub(1) as i64;
 
     (0..map_width)
         .map(|x| {
-            let height_variation = (x as f32 * 0.05).sin() * 10.0;
-            base_height + height_variation as u32
+            let height_variation = fractal(
+                noise,
+                x as f32 * terrain.surface_frequency,
+                0.5,
+                terrain.surface_octaves,
+            ) * terrain.surface_amplitude;
+            // Work in signed space so negative variation lowers the surface instead of wrapping.
+            (base_height + height_variation as i64).clamp(0, max_height) as u32
         })
         .collect()
 }
+
+/// The surface heights for `config`, shaped by its noise.
+pub(crate) fn default_surface_heights(config: &WorldGenConfig) -> Vec<u32> {
+    calculate_surface_heights(
+        config.map_width(),
+        config.map_height(),
+        &config.noise(),
+        &config.terrain,
+    )
+}
@@ -0,0 +1,182 @@
+//! Temperature and heat diffusion.
+//!
+//! Every cell has a temperature, stored per chunk alongside its cells. Chunks at rest store none
+//! at all: each of their cells is at the resting temperature of its particle, see
+//! [`resting_temperature`]. Every tick, [`Map::diffuse_heat`] spreads heat between neighboring
+//! cells, pulls each cell back towards its resting temperature, and changes the phase of particles
+//! whose cell got too hot or too cold, like water boiling into steam or lava hardening into
+//! obsidian.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::particle::{Particle, AMBIENT_TEMPERATURE};
+use crate::utils;
+
+use super::chunk::{Temperatures, CHUNK_SIZE};
+use super::Map;
+
+/// The share of the temperature difference between two perfectly conducting neighbors that
+/// evens out per tick. At most 1/4, so a cell never overshoots its four neighbors.
+const DIFFUSION_RATE: f32 = 0.25;
+
+/// The share of the gap to its resting temperature a particle like lava or water makes up per tick.
+const RESTING_PULL: f32 = 0.2;
+
+/// The share of the gap to [`AMBIENT_TEMPERATURE`] every other cell makes up per tick.
+const AMBIENT_PULL: f32 = 0.01;
+
+/// How readily heat flows through empty cells, on the scale of [`Particle::get_conductivity`].
+const AIR_CONDUCTIVITY: f32 = 0.01;
+
+/// How close to its resting temperature every cell of a chunk has to be for the chunk to drop its
+/// temperatures.
+const SETTLED_TOLERANCE: f32 = 0.5;
+
+/// The neighbors heat flows between.
+const NEIGHBOR_OFFSETS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+/// The temperature a cell holding `particle` settles at when left alone.
+pub fn resting_temperature(particle: Option<Particle>) -> f32 {
+    particle
+        .and_then(|particle| particle.get_resting_temperature())
+        .unwrap_or(AMBIENT_TEMPERATURE)
+}
+
+fn conductivity(particle: Option<Particle>) -> f32 {
+    particle.map_or(AIR_CONDUCTIVITY, |particle| particle.get_conductivity())
+}
+
+impl Map {
+    /// The temperature of the cell at `position`. Out of bounds positions are at
+    /// [`AMBIENT_TEMPERATURE`].
+    pub fn get_temperature_at(&self, position: UVec2) -> f32 {
+        if !self.within_bounds(position) {
+            return AMBIENT_TEMPERATURE;
+        }
+        let chunk_pos = utils::coords::get_chunk_from_world_pos(position);
+        let local_pos = utils::coords::world_to_chunk_local(position);
+        self.get_chunk_at(&chunk_pos).temperature(local_pos)
+    }
+
+    /// Runs one tick of heat diffusion over the active chunks that hold heat sources or aren't at
+    /// their resting temperatures yet, along with their active neighbors so heat can spread into
+    /// them. Particles whose cell ends up past a phase change, see [`Particle::get_phase_change`],
+    /// are replaced.
+    pub fn diffuse_heat(&mut self) {
+        let mut heated = HashSet::new();
+        for chunk_pos in &self.active_chunks {
+            let chunk = self.get_chunk_at(chunk_pos);
+            let has_heat_source = chunk.should_simulate
+                && chunk
+                    .cells()
+                    .iter()
+                    .flatten()
+                    .any(|particle| particle.is_some_and(|particle| particle.is_heat_source()));
+            if chunk.temperatures().is_none() && !has_heat_source {
+                continue;
+            }
+
+            heated.insert(*chunk_pos);
+            for offset in NEIGHBOR_OFFSETS {
+                let neighbor = chunk_pos.as_ivec2() + offset;
+                if neighbor.cmpge(IVec2::ZERO).all()
+                    && self.active_chunks.contains(&neighbor.as_uvec2())
+                {
+                    heated.insert(neighbor.as_uvec2());
+                }
+            }
+        }
+        let mut chunk_positions: Vec<UVec2> = heated.into_iter().collect();
+        // Sort for deterministic results, matching the order chunks are stored in.
+        chunk_positions.sort_by_key(|pos| (pos.x, pos.y));
+
+        // Every chunk reads the temperatures of the last tick, so they can be computed in parallel.
+        let temperatures: Vec<Option<Box<Temperatures>>> = chunk_positions
+            .par_iter()
+            .map(|chunk_pos| self.diffuse_chunk_heat(*chunk_pos))
+            .collect();
+        for (chunk_pos, temperatures) in chunk_positions.iter().zip(temperatures) {
+            self.chunks[chunk_pos.x as usize][chunk_pos.y as usize].set_temperatures(temperatures);
+        }
+
+        let mut changes = Vec::new();
+        for chunk_pos in chunk_positions {
+            let chunk = self.get_chunk_at(&chunk_pos);
+            if chunk.temperatures().is_none() {
+                continue;
+            }
+            for (x, column) in chunk.cells().iter().enumerate() {
+                for (y, particle) in column.iter().enumerate() {
+                    let local_pos = UVec2::new(x as u32, y as u32);
+                    let Some(changed) = particle.and_then(|particle| {
+                        particle.get_phase_change(chunk.temperature(local_pos))
+                    }) else {
+                        continue;
+                    };
+                    let position = utils::coords::chunk_local_to_world(chunk_pos, local_pos);
+                    changes.push((position, changed));
+                }
+            }
+        }
+
+        for (position, particle) in changes {
+            self.set_particle_at(position, Some(particle));
+        }
+    }
+
+    /// The temperatures of the chunk at `chunk_pos` after one tick of diffusion, or `None` if
+    /// they have all settled at their resting temperatures.
+    fn diffuse_chunk_heat(&self, chunk_pos: UVec2) -> Option<Box<Temperatures>> {
+        let chunk = self.get_chunk_at(&chunk_pos);
+        let mut temperatures =
+            Box::new([[AMBIENT_TEMPERATURE; CHUNK_SIZE as usize]; CHUNK_SIZE as usize]);
+        let mut settled = true;
+
+        for (x, column) in chunk.cells().iter().enumerate() {
+            for (y, &particle) in column.iter().enumerate() {
+                let local_pos = UVec2::new(x as u32, y as u32);
+                let position = utils::coords::chunk_local_to_world(chunk_pos, local_pos);
+                let temperature = chunk.temperature(local_pos);
+                let cell_conductivity = conductivity(particle);
+
+                // Heat flows as fast as the worse conductor of each pair allows. The map's edges
+                // are insulated.
+                let mut flow = 0.0;
+                for offset in NEIGHBOR_OFFSETS {
+                    let neighbor = position.as_ivec2() + offset;
+                    if neighbor.cmplt(IVec2::ZERO).any() || !self.within_bounds(neighbor.as_uvec2())
+                    {
+                        continue;
+                    }
+                    let neighbor = neighbor.as_uvec2();
+                    let pair_conductivity =
+                        cell_conductivity.min(conductivity(self.get_particle_at(neighbor)));
+                    flow += DIFFUSION_RATE
+                        * pair_conductivity
+                        * (self.get_temperature_at(neighbor) - temperature);
+                }
+
+                let resting = resting_temperature(particle);
+                let pull = match particle.and_then(|particle| particle.get_resting_temperature()) {
+                    Some(_) => RESTING_PULL,
+                    None => AMBIENT_PULL,
+                };
+                let temperature = temperature + flow;
+                let temperature = temperature + pull * (resting - temperature);
+
+                settled &= (temperature - resting).abs() < SETTLED_TOLERANCE;
+                temperatures[x][y] = temperature;
+            }
+        }
+
+        (!settled).then_some(temperatures)
+    }
+}
+
+/// System that spreads heat through the active chunks. Runs after interactions.
+pub fn diffuse_active_heat(mut map: ResMut<Map>) {
+    map.diffuse_heat();
+}
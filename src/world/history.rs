@@ -0,0 +1,217 @@
+//! Undo and redo for particles and walls edited by hand.
+//!
+//! Edits are recorded in an [`EditHistory`] as the cells they changed, each with its contents
+//! before and after. Edits made while a mouse button is held are batched into a single stroke, so
+//! a whole drag is undone at once. Undoing a stroke puts back what was in its cells before, and
+//! redoing it puts back what it left there. Particles that moved away since aren't followed.
+//! Ore and gems dug up during a stroke are taken back out of the [`Inventory`] when it's undone,
+//! so they can't be collected twice. Edited chunks of streamed maps are loaded first, so edits
+//! far from the player land in the chunk's real cells.
+
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::particle::{Particle, Special};
+use crate::player::Inventory;
+use crate::utils::coords::get_chunk_from_world_pos;
+
+use super::generator::MapRegenerated;
+use super::Map;
+
+/// How many strokes [`EditHistory`] keeps by default before dropping the oldest.
+pub const DEFAULT_HISTORY_LENGTH: usize = 64;
+
+/// Which layer of a cell an edit changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EditLayer {
+    /// The particle in the cell.
+    Particle,
+    /// The wall behind the cell.
+    Wall,
+}
+
+/// A single cell changed by an edit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellEdit {
+    pub position: UVec2,
+    pub layer: EditLayer,
+    pub before: Option<Particle>,
+    pub after: Option<Particle>,
+}
+
+impl CellEdit {
+    fn apply(&self, map: &mut Map, contents: Option<Particle>) {
+        map.load_chunk(get_chunk_from_world_pos(self.position));
+        match self.layer {
+            EditLayer::Particle => map.set_particle_at(self.position, contents),
+            EditLayer::Wall => map.set_background_at(self.position, contents),
+        }
+    }
+}
+
+/// Everything one stroke changed.
+#[derive(Debug, Default)]
+struct Stroke {
+    cells: Vec<CellEdit>,
+    /// The ore and gems added to the inventory during the stroke.
+    collected: Vec<Special>,
+}
+
+/// The strokes that can be undone and redone, see the [module docs](self).
+#[derive(Resource, Debug)]
+pub struct EditHistory {
+    /// The most strokes kept to undo. The oldest are dropped first.
+    pub max_strokes: usize,
+    undo: VecDeque<Stroke>,
+    redo: Vec<Stroke>,
+    /// The stroke being recorded, until [`EditHistory::end_stroke`].
+    stroke: Stroke,
+    /// Where each cell of the current stroke is in `stroke.cells`.
+    stroke_cells: HashMap<(UVec2, EditLayer), usize>,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LENGTH)
+    }
+}
+
+impl EditHistory {
+    pub fn new(max_strokes: usize) -> Self {
+        Self {
+            max_strokes,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            stroke: Stroke::default(),
+            stroke_cells: HashMap::new(),
+        }
+    }
+
+    /// Set the particle at `position`, recording the change in the current stroke.
+    pub fn set_particle(&mut self, map: &mut Map, position: UVec2, particle: Option<Particle>) {
+        if !map.within_bounds(position) {
+            return;
+        }
+        map.load_chunk(get_chunk_from_world_pos(position));
+        let before = map.get_particle_at(position);
+        map.set_particle_at(position, particle);
+        self.record(CellEdit {
+            position,
+            layer: EditLayer::Particle,
+            before,
+            after: particle,
+        });
+    }
+
+    /// Set the wall behind `position`, recording the change in the current stroke.
+    pub fn set_wall(&mut self, map: &mut Map, position: UVec2, wall: Option<Particle>) {
+        if !map.within_bounds(position) {
+            return;
+        }
+        map.load_chunk(get_chunk_from_world_pos(position));
+        let before = map.get_background_at(position);
+        map.set_background_at(position, wall);
+        self.record(CellEdit {
+            position,
+            layer: EditLayer::Wall,
+            before,
+            after: wall,
+        });
+    }
+
+    /// Add an edit that was already made to the map to the current stroke. A cell edited several
+    /// times in one stroke keeps what it held before the first edit.
+    pub fn record(&mut self, edit: CellEdit) {
+        match self.stroke_cells.get(&(edit.position, edit.layer)) {
+            Some(&index) => self.stroke.cells[index].after = edit.after,
+            None => {
+                self.stroke_cells
+                    .insert((edit.position, edit.layer), self.stroke.cells.len());
+                self.stroke.cells.push(edit);
+            }
+        }
+    }
+
+    /// Add `special`, which was already added to the inventory, to the current stroke, so undoing
+    /// the stroke takes it back out.
+    pub fn record_collected(&mut self, special: Special) {
+        self.stroke.collected.push(special);
+    }
+
+    /// Finish the current stroke, making it the next one to undo. Strokes that changed nothing
+    /// are dropped. Recording a new stroke discards everything that could be redone.
+    pub fn end_stroke(&mut self) {
+        self.stroke_cells.clear();
+        self.stroke.cells.retain(|edit| edit.before != edit.after);
+        if self.stroke.cells.is_empty() && self.stroke.collected.is_empty() {
+            return;
+        }
+        self.undo.push_back(std::mem::take(&mut self.stroke));
+        self.redo.clear();
+        while self.undo.len() > self.max_strokes {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Undo the last stroke, finishing the current one first. Returns whether there was one.
+    pub fn undo(&mut self, map: &mut Map, inventory: &mut Inventory) -> bool {
+        self.end_stroke();
+        let Some(stroke) = self.undo.pop_back() else {
+            return false;
+        };
+        for edit in stroke.cells.iter().rev() {
+            edit.apply(map, edit.before);
+        }
+        for &special in &stroke.collected {
+            inventory.remove(special);
+        }
+        self.redo.push(stroke);
+        true
+    }
+
+    /// Redo the last undone stroke. Returns whether there was one.
+    pub fn redo(&mut self, map: &mut Map, inventory: &mut Inventory) -> bool {
+        self.end_stroke();
+        let Some(stroke) = self.redo.pop() else {
+            return false;
+        };
+        for edit in &stroke.cells {
+            edit.apply(map, edit.after);
+        }
+        for &special in &stroke.collected {
+            inventory.add(special);
+        }
+        self.undo.push_back(stroke);
+        true
+    }
+
+    /// How many strokes can be undone.
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// How many strokes can be redone.
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Forget every stroke, e.g. when the map is replaced.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.stroke = Stroke::default();
+        self.stroke_cells.clear();
+    }
+}
+
+/// System that forgets the edit history once the map is regenerated or loaded, since its edits
+/// belong to the old map.
+pub fn clear_edit_history(
+    mut regenerated: EventReader<MapRegenerated>,
+    mut history: ResMut<EditHistory>,
+) {
+    if regenerated.read().count() > 0 {
+        history.clear();
+    }
+}
@@ -0,0 +1,283 @@
+//! Light levels and how light spreads.
+//!
+//! Every cell has a light level from 0 (pitch dark) to [`MAX_LIGHT`], stored per chunk. Cells the
+//! sky shines straight down into are fully lit, and glowing particles like lava and torches light
+//! their own cells, see [`Particle::get_light_emission`]. From there light spreads to neighboring
+//! cells, losing [`Particle::get_light_falloff`] levels on its way into each one, so it carries
+//! far through open caves but only a little way into solid ground.
+//!
+//! Each cell also has a sky light level, telling how much of its light comes from the sky. It is
+//! [`MAX_LIGHT`] wherever daylight reaches and fades by a level per cell below that, so the
+//! renderer can tint the surface with the time of day while caves keep their own light.
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::particle::{Particle, MAX_LIGHT};
+use crate::utils;
+
+use super::chunk::{LightLevels, CHUNK_SIZE, SKY_LIGHT_SHIFT};
+use super::Map;
+
+/// How many light levels are lost when light spreads into an empty cell.
+const AIR_LIGHT_FALLOFF: u8 = 1;
+
+/// The neighbors light spreads between.
+const NEIGHBOR_OFFSETS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+const _: () = assert!(
+    MAX_LIGHT < 1 << SKY_LIGHT_SHIFT,
+    "Light and sky light must fit side by side in a byte"
+);
+
+const _: () = assert!(
+    (MAX_LIGHT as u32) < CHUNK_SIZE,
+    "Light must never reach past the neighboring chunks"
+);
+
+fn light_falloff(particle: Option<Particle>) -> u8 {
+    particle.map_or(AIR_LIGHT_FALLOFF, |particle| particle.get_light_falloff())
+}
+
+impl Map {
+    /// The light level of the cell at `position`. Out of bounds positions are dark.
+    pub fn get_light_at(&self, position: UVec2) -> u8 {
+        if !self.within_bounds(position) {
+            return 0;
+        }
+        let chunk_pos = utils::coords::get_chunk_from_world_pos(position);
+        let local_pos = utils::coords::world_to_chunk_local(position);
+        self.get_chunk_at(&chunk_pos).light(local_pos)
+    }
+
+    /// The sky light level of the cell at `position`. Out of bounds positions are dark.
+    pub fn get_sky_light_at(&self, position: UVec2) -> u8 {
+        if !self.within_bounds(position) {
+            return 0;
+        }
+        let chunk_pos = utils::coords::get_chunk_from_world_pos(position);
+        let local_pos = utils::coords::world_to_chunk_local(position);
+        self.get_chunk_at(&chunk_pos).sky_light(local_pos)
+    }
+
+    /// Relights every loaded chunk whose cells changed since it was last lit, along with the
+    /// chunks around it. Light never travels further than a chunk, so the chunks just outside
+    /// keep their levels and shine into the relit ones. Daylight reaches straight down to the
+    /// first particle that blocks it, so chunks below a change to that particle are relit too.
+    pub fn update_lighting(&mut self) {
+        let mut stale: HashSet<UVec2> = self
+            .chunks
+            .iter()
+            .flatten()
+            .filter(|chunk| {
+                chunk.lit_version != Some(chunk.version) && self.is_chunk_loaded(chunk.position)
+            })
+            .map(|chunk| chunk.position)
+            .collect();
+        if stale.is_empty() {
+            return;
+        }
+
+        if self.sky_heights.len() != self.width as usize {
+            self.sky_heights = (0..self.width)
+                .map(|x| self.find_sky_height(x, self.height))
+                .collect();
+        } else {
+            self.update_sky_heights(&mut stale);
+        }
+
+        let mut region = HashSet::new();
+        for chunk_pos in &stale {
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let neighbor = chunk_pos.as_ivec2() + IVec2::new(dx, dy);
+                    if neighbor.cmpge(IVec2::ZERO).all()
+                        && self.within_bounds(neighbor.as_uvec2() * CHUNK_SIZE)
+                        && self.is_chunk_loaded(neighbor.as_uvec2())
+                    {
+                        region.insert(neighbor.as_uvec2());
+                    }
+                }
+            }
+        }
+
+        for (chunk_pos, light) in self.spread_light(&region) {
+            let chunk = &mut self.chunks[chunk_pos.x as usize][chunk_pos.y as usize];
+            let light = light
+                .iter()
+                .flatten()
+                .any(|&level| level > 0)
+                .then_some(light);
+            if chunk.light_levels() != light.as_deref() {
+                chunk.set_light_levels(light);
+                chunk.light_version += 1;
+            }
+            chunk.lit_version = Some(chunk.version);
+        }
+    }
+
+    /// Recomputes how far daylight reaches in the columns of the `stale` chunks, adding the
+    /// chunks whose daylight changed to `stale`.
+    fn update_sky_heights(&mut self, stale: &mut HashSet<UVec2>) {
+        // Cells above the highest changed chunk of each column of chunks are as they were.
+        let mut tops: HashMap<u32, u32> = HashMap::new();
+        for chunk_pos in stale.iter() {
+            let top = tops.entry(chunk_pos.x).or_default();
+            *top = (*top).max((chunk_pos.y + 1) * CHUNK_SIZE);
+        }
+
+        for (chunk_x, top) in tops {
+            for x in chunk_x * CHUNK_SIZE..(chunk_x + 1) * CHUNK_SIZE {
+                let old = self.sky_heights[x as usize];
+                // Daylight stopped above every change, so it still does.
+                if old > top {
+                    continue;
+                }
+                let new = self.find_sky_height(x, top);
+                if new == old {
+                    continue;
+                }
+                self.sky_heights[x as usize] = new;
+                for chunk_y in old.min(new) / CHUNK_SIZE..=(old.max(new) - 1) / CHUNK_SIZE {
+                    let chunk_pos = UVec2::new(chunk_x, chunk_y);
+                    if self.is_chunk_loaded(chunk_pos) {
+                        stale.insert(chunk_pos);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The lowest row daylight reaches in column `x`, given that every cell from `top` up lets
+    /// it through. Chunks that aren't loaded are assumed to be as generated, with daylight
+    /// stopping at the surface.
+    fn find_sky_height(&self, x: u32, top: u32) -> u32 {
+        let surface = self.surface_profile().get(x as usize).copied();
+        for y in (0..top).rev() {
+            let position = UVec2::new(x, y);
+            let blocked = if self.is_chunk_loaded(utils::coords::get_chunk_from_world_pos(position))
+            {
+                self.get_particle_at(position)
+                    .is_some_and(|particle| !particle.is_transparent())
+            } else {
+                surface.is_some_and(|surface| y <= surface)
+            };
+            if blocked {
+                return y + 1;
+            }
+        }
+        0
+    }
+
+    /// Computes the light levels of the chunks in `region` from daylight, glowing particles and
+    /// the light of the loaded chunks bordering the region.
+    fn spread_light(&self, region: &HashSet<UVec2>) -> HashMap<UVec2, Box<LightLevels>> {
+        let mut light: HashMap<UVec2, Box<LightLevels>> = region
+            .iter()
+            .map(|&chunk_pos| {
+                let levels = Box::new([[0; CHUNK_SIZE as usize]; CHUNK_SIZE as usize]);
+                (chunk_pos, levels)
+            })
+            .collect();
+        // Cells waiting to spread their light, bucketed by their level. Brighter cells spread
+        // first, so every cell spreads its light once, at its final level.
+        let mut pending: Vec<Vec<UVec2>> = vec![Vec::new(); MAX_LIGHT as usize + 1];
+
+        let last = CHUNK_SIZE - 1;
+        for &chunk_pos in region {
+            let chunk = self.get_chunk_at(&chunk_pos);
+            let levels = light.get_mut(&chunk_pos).unwrap();
+            for (x, column) in chunk.cells().iter().enumerate() {
+                for (y, &particle) in column.iter().enumerate() {
+                    let local_pos = UVec2::new(x as u32, y as u32);
+                    let position = utils::coords::chunk_local_to_world(chunk_pos, local_pos);
+                    let mut level = particle.map_or(0, |particle| particle.get_light_emission());
+                    if position.y >= self.sky_heights[position.x as usize] {
+                        level = MAX_LIGHT;
+                    }
+
+                    // Light shining in from outside the region.
+                    let on_edge = local_pos.x == 0
+                        || local_pos.y == 0
+                        || local_pos.x == last
+                        || local_pos.y == last;
+                    if on_edge {
+                        for offset in NEIGHBOR_OFFSETS {
+                            let neighbor = position.as_ivec2() + offset;
+                            if neighbor.cmplt(IVec2::ZERO).any()
+                                || !self.within_bounds(neighbor.as_uvec2())
+                            {
+                                continue;
+                            }
+                            let neighbor = neighbor.as_uvec2();
+                            if region.contains(&utils::coords::get_chunk_from_world_pos(neighbor)) {
+                                continue;
+                            }
+                            let incoming = self
+                                .get_light_at(neighbor)
+                                .saturating_sub(light_falloff(particle));
+                            level = level.max(incoming);
+                        }
+                    }
+
+                    if level > 0 {
+                        levels[x][y] = level;
+                        pending[level as usize].push(position);
+                    }
+                }
+            }
+        }
+
+        for level in (1..=MAX_LIGHT).rev() {
+            while let Some(position) = pending[level as usize].pop() {
+                let chunk_pos = utils::coords::get_chunk_from_world_pos(position);
+                let local_pos = utils::coords::world_to_chunk_local(position);
+                // Cells raised again after they were queued spread from their brighter entry.
+                if light[&chunk_pos][local_pos.x as usize][local_pos.y as usize] != level {
+                    continue;
+                }
+                for offset in NEIGHBOR_OFFSETS {
+                    let neighbor = position.as_ivec2() + offset;
+                    if neighbor.cmplt(IVec2::ZERO).any() || !self.within_bounds(neighbor.as_uvec2())
+                    {
+                        continue;
+                    }
+                    let neighbor = neighbor.as_uvec2();
+                    let chunk_pos = utils::coords::get_chunk_from_world_pos(neighbor);
+                    let Some(levels) = light.get_mut(&chunk_pos) else {
+                        continue;
+                    };
+                    let spread =
+                        level.saturating_sub(light_falloff(self.get_particle_at(neighbor)));
+                    let local_pos = utils::coords::world_to_chunk_local(neighbor);
+                    let current = &mut levels[local_pos.x as usize][local_pos.y as usize];
+                    if spread > *current {
+                        *current = spread;
+                        pending[spread as usize].push(neighbor);
+                    }
+                }
+            }
+        }
+
+        // Daylight fades a level per cell below the lowest row it reaches.
+        for (&chunk_pos, levels) in &mut light {
+            for (x, column) in levels.iter_mut().enumerate() {
+                for (y, level) in column.iter_mut().enumerate() {
+                    let local_pos = UVec2::new(x as u32, y as u32);
+                    let position = utils::coords::chunk_local_to_world(chunk_pos, local_pos);
+                    let depth = self.sky_heights[position.x as usize].saturating_sub(position.y);
+                    let sky = MAX_LIGHT.saturating_sub(depth.min(MAX_LIGHT as u32) as u8);
+                    *level |= sky << SKY_LIGHT_SHIFT;
+                }
+            }
+        }
+
+        light
+    }
+}
+
+/// System that relights the chunks that changed since the last frame.
+pub fn update_active_lighting(mut map: ResMut<Map>) {
+    map.update_lighting();
+}
@@ -1,27 +1,96 @@
-use crate::particle::{Particle, Special};
+use crate::config::Config;
+use crate::particle::definition::ParticleRegistry;
+use crate::particle::interaction::{InteractionPair, InteractionRules, InteractionType};
+use crate::particle::{Fire, Liquid, Particle, ParticleType, Special};
 use crate::player::Player;
+use crate::simulation::fire::{CombustionSettings, SPREAD_OFFSETS};
+use crate::simulation::{
+    fluid::{FluidSimulator, LevelChunks, PressureSettings},
+    ReadOnlySimulationContext,
+};
 use crate::utils;
 use crate::utils::coords::{screen_to_world, world_vec2_to_chunk};
-use crate::world::chunk::{Chunk, ParticleMove, ACTIVE_CHUNK_RANGE, CHUNK_SIZE};
-use crate::world::generator::generate_all_data;
+use crate::utils::hash::StableHasher;
+use crate::world::biome::{Biome, BiomeLayout};
+use crate::world::chunk::{Chunk, ParticleMove, CHUNK_SIZE};
+use crate::world::generator::{
+    default_surface_heights, generate_all_data, generate_chunk_data, GenerationPlan,
+    GeneratorConfig, WorldGenConfig,
+};
+use crate::world::noise::NoiseSource;
+use crate::world::persistence::write_atomic;
+use crate::world::settling::ChunkSettling;
+use crate::world::streaming::ChunkStreaming;
 use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
 use dashmap::DashMap;
 use rand::prelude::*;
-use rand::rngs::ThreadRng;
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::hash::Hasher;
+use std::io::{self, Write};
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
-/// The rate at which the map is simulated per second.
+/// The default rate at which the map is simulated per second, see [`Config`].
 pub(crate) const SIMULATION_RATE: f64 = 80.0;
 
+/// How many simulation ticks a chunk stays awake after a dynamic particle is placed in it or
+/// something in it last moved, even when it's outside the player's active range.
+pub const WAKE_TICKS: u32 = 40;
+
+/// The height of the depth bands [`Map::depth_stats`] groups particles into.
+pub const STATS_DEPTH_BAND: u32 = 25;
+
+/// How many cross-chunk moves the interchunk queue reserves room for per simulated chunk.
+/// Flow mostly leaves a chunk through its bottom and one side edge.
+const INTERCHUNK_MOVES_PER_CHUNK: usize = 2 * CHUNK_SIZE as usize;
+
 #[derive(Resource)]
 pub struct Map {
     pub width: u32,
     pub height: u32,
     pub chunks: Vec<Vec<Chunk>>,
     pub active_chunks: HashSet<UVec2>,
+    /// Chunks kept active regardless of the player's position, with the ticks they have left.
+    /// See [`WAKE_TICKS`].
+    pub awake_chunks: HashMap<UVec2, u32>,
+    /// The biome bands chosen during generation.
+    pub biomes: BiomeLayout,
+    /// The generated surface height of each column. Empty for maps that weren't generated.
+    surface_heights: Vec<u32>,
+    /// The parameters the map was generated from. `None` for maps that weren't generated.
+    gen_config: Option<WorldGenConfig>,
+    /// How many moves were in the interchunk queue on the last simulation tick.
+    interchunk_queue_len: usize,
+    /// The largest interchunk queue seen since the map was created.
+    peak_interchunk_queue_len: usize,
+    /// Which chunks are loaded, for maps whose chunks are loaded on demand.
+    pub(crate) streaming: Option<ChunkStreaming>,
+    /// Which chunks have settled, see [`super::settling`].
+    pub(crate) settling: ChunkSettling,
+    /// Seeds every random choice the simulation makes, see [`Map::particle_rng`].
+    simulation_seed: u64,
+    /// How many simulation ticks have run on this map.
+    tick: u64,
+    /// The tick each cell that recently reacted can react again, see
+    /// [`Map::interaction_ready`].
+    interaction_cooldowns: HashMap<UVec2, u64>,
+    /// How fire spreads and smokes on this map.
+    pub combustion: CombustionSettings,
+    /// How quickly bodies of liquid level out on this map.
+    pub pressure: PressureSettings,
+    /// Chunks whose liquid has already leveled out, see [`FluidSimulator::find_pressure_moves`].
+    level_chunks: LevelChunks,
+    /// The definitions the map's particles are simulated and drawn with, see
+    /// [`Map::set_particles`].
+    particles: ParticleRegistry,
+    /// The lowest row daylight reaches in each column, see [`super::lighting`]. Empty until the
+    /// map is first lit.
+    pub(crate) sky_heights: Vec<u32>,
 }
 
 impl Map {
@@ -46,6 +115,22 @@ impl Map {
             height,
             chunks,
             active_chunks: HashSet::new(),
+            awake_chunks: HashMap::new(),
+            biomes: BiomeLayout::default(),
+            surface_heights: Vec::new(),
+            gen_config: None,
+            interchunk_queue_len: 0,
+            peak_interchunk_queue_len: 0,
+            streaming: None,
+            settling: ChunkSettling::default(),
+            simulation_seed: 0,
+            tick: 0,
+            interaction_cooldowns: HashMap::new(),
+            combustion: CombustionSettings::default(),
+            pressure: PressureSettings::default(),
+            level_chunks: LevelChunks::default(),
+            particles: ParticleRegistry::default(),
+            sky_heights: Vec::new(),
         }
     }
 
@@ -88,30 +173,114 @@ impl Map {
         for (particle_type, count) in counts {
             let percentage = (count as f32 / total_cells as f32) * 100.0;
             info!(
-                "{:?}: {} particles ({:.2}%)",
-                particle_type, count, percentage
+                "{}: {} particles ({:.2}%)",
+                particle_type.display_name(),
+                count,
+                percentage
             );
         }
     }
 
+    /// Count every particle by name and depth band, as `((band_min, name), count)` sorted by band
+    /// and then name. Depth is measured down from the column's surface in bands of
+    /// [`STATS_DEPTH_BAND`] cells, so particles above the surface land in negative bands.
+    /// Maps without a surface profile measure depth from the top of the map instead.
+    pub fn depth_stats(&self) -> Vec<((i64, &'static str), u32)> {
+        let mut counts: BTreeMap<(i64, &'static str), u32> = BTreeMap::new();
+        let band = STATS_DEPTH_BAND as i64;
+
+        for chunk in self.chunks.iter().flatten() {
+            for (x, column) in chunk.cells().iter().enumerate() {
+                for (y, &particle) in column.iter().enumerate() {
+                    let Some(particle) = particle else { continue };
+                    let position = utils::coords::chunk_local_to_world(
+                        chunk.position,
+                        UVec2::new(x as u32, y as u32),
+                    );
+                    let surface = self
+                        .surface_heights
+                        .get(position.x as usize)
+                        .copied()
+                        .unwrap_or(self.height - 1);
+                    let depth = surface as i64 - position.y as i64;
+
+                    let band_min = depth.div_euclid(band) * band;
+                    *counts
+                        .entry((band_min, particle.display_name()))
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        counts.into_iter().collect()
+    }
+
+    /// Write [`Map::depth_stats`] to `path` as CSV, one row per depth band and particle, so the
+    /// distribution can be analyzed in a spreadsheet.
+    pub fn export_stats_csv(&self, path: &Path) -> io::Result<()> {
+        let stats = self.depth_stats();
+        write_atomic(path, |writer| {
+            writeln!(writer, "depth_min,depth_max,particle,count")?;
+            for ((band_min, name), count) in stats {
+                let band_max = band_min + STATS_DEPTH_BAND as i64 - 1;
+                writeln!(writer, "{},{},{},{}", band_min, band_max, name, count)?;
+            }
+            Ok(())
+        })
+    }
+
     /// Uses a weighted random roll to determine if a special particle should spawn, and if so, which one.
     /// Returns `None` if no special particle should spawn.
-    pub(crate) fn roll_special_particle(depth: u32, rng: &mut ThreadRng) -> Option<Particle> {
-        // Get valid special particles for this depth
-        let mut valid_particles: Vec<_> = Special::all_variants()
+    /// Depths and spawn chances are those of `particles`, with the chances of `biome`, see
+    /// [`Biome::special_spawn_chance`].
+    pub fn roll_special_particle(
+        depth: u32,
+        biome: Biome,
+        particles: &ParticleRegistry,
+        rng: &mut impl Rng,
+    ) -> Option<Particle> {
+        Self::roll_clustered_special(depth, biome, particles, 1.0, rng)
+    }
+
+    /// Like [`Map::roll_special_particle`], but with every spawn chance scaled by `richness`, so
+    /// the generator can cluster specials where its noise is high.
+    pub fn roll_clustered_special(
+        depth: u32,
+        biome: Biome,
+        particles: &ParticleRegistry,
+        richness: f32,
+        rng: &mut impl Rng,
+    ) -> Option<Particle> {
+        // Get valid special particles for this depth and biome
+        let valid_particles: Vec<_> = Special::all_variants()
             .into_iter()
-            .filter(|p| depth >= p.min_depth() && depth < p.max_depth())
+            .filter(|p| particles.depths(Particle::Special(*p)).contains(&depth))
+            .map(|p| {
+                let chance = biome.special_spawn_chance(p, particles) as f32 * richness;
+                (p, chance.round() as i32)
+            })
+            .filter(|&(_, chance)| chance > 0)
             .collect();
 
-        if valid_particles.is_empty() {
+        Self::roll_weighted_special(valid_particles, rng)
+    }
+
+    /// The weighted roll behind [`Map::roll_special_particle`], over `(particle, spawn chance)`
+    /// candidates. The result only depends on the candidates and the rng, not on their order.
+    pub fn roll_weighted_special(
+        mut candidates: Vec<(Special, i32)>,
+        rng: &mut impl Rng,
+    ) -> Option<Particle> {
+        if candidates.is_empty() {
             return None;
         }
 
-        // Sort particles from lowest to highest spawn chance
-        valid_particles.sort_unstable_by_key(|p| p.spawn_chance());
+        // Sort particles from lowest to highest spawn chance. Ties are broken by variant id so
+        // equal-chance particles always end up in the same order and seeded rolls reproduce.
+        candidates.sort_by_key(|&(special, chance)| (chance, special.variant_id()));
 
         // Calculate total spawn weight
-        let total_weight: i32 = valid_particles.iter().map(|p| p.spawn_chance()).sum();
+        let total_weight: i32 = candidates.iter().map(|&(_, chance)| chance).sum();
 
         // First check: determine if we spawn any special particle
         if rng.random_range(0..1000) >= total_weight {
@@ -121,8 +290,8 @@ impl Map {
         // Second check: weighted selection of which particle to spawn
         let random_val = rng.random_range(0..total_weight);
         let mut acc = 0;
-        for &special in &valid_particles {
-            acc += special.spawn_chance();
+        for (special, chance) in candidates {
+            acc += chance;
             if random_val < acc {
                 return Some(Particle::Special(special));
             }
@@ -130,35 +299,71 @@ impl Map {
         None
     }
 
-    /// Distribute inputted 1D Vec of chunks into the 2D vector structure
-    fn distribute_among_chunks(&mut self, chunks_vec: Vec<Chunk>) {
-        let cw = (self.width / CHUNK_SIZE) as usize;
-        for (i, chunk) in chunks_vec.into_iter().enumerate() {
-            let x = i % cw;
-            let y = i / cw;
-            self.chunks[x][y] = chunk;
-        }
-    }
-
     /// Create a new world with terrain.
     /// - `width`: Number of chunks wide the map should be
     /// - `height`: Number of chunks tall the map should be
-    pub fn generate(width: u32, height: u32) -> Self {
+    /// - `seed`: Seed for all generation randomness. The same seed always produces the same map.
+    pub fn generate(width: u32, height: u32, seed: u64) -> Self {
+        Self::generate_with_config(width, height, seed, &GeneratorConfig::default())
+    }
+
+    /// Like [`Map::generate`], but with the terrain shaped by `terrain` instead of the defaults.
+    pub fn generate_with_config(
+        width: u32,
+        height: u32,
+        seed: u64,
+        terrain: &GeneratorConfig,
+    ) -> Self {
+        Self::generate_from_config(&WorldGenConfig::with_terrain(width, height, seed, *terrain))
+    }
+
+    /// Like [`Map::generate`], but with every parameter taken from `config`.
+    pub fn generate_from_config(config: &WorldGenConfig) -> Self {
+        Self::generate_with_heights(config, default_surface_heights(config))
+    }
+
+    /// Like [`Map::generate`], but with the surface, caves, biomes and ore clusters all shaped by
+    /// the given noise instead of the default [`PerlinNoise`](crate::world::noise::PerlinNoise)
+    /// for the seed.
+    pub fn generate_with_noise(
+        width: u32,
+        height: u32,
+        seed: u64,
+        noise: impl NoiseSource + 'static,
+    ) -> Self {
+        Self::generate_from_config(&WorldGenConfig::new(width, height, seed).with_noise(noise))
+    }
+
+    /// Create a new world whose surface follows `heights` exactly, one height per column, instead
+    /// of the built-in terrain shape. Everything below the surface is filled and carved as usual.
+    /// Returns `None` if there isn't exactly one height per column, or a height is above the map.
+    pub fn generate_from_heightmap(heights: &[u32], config: &WorldGenConfig) -> Option<Self> {
+        if heights.len() != config.map_width() as usize
+            || heights.iter().any(|&height| height >= config.map_height())
+        {
+            return None;
+        }
+        Some(Self::generate_with_heights(config, heights.to_vec()))
+    }
+
+    /// Shared by the generators once the surface heights are known.
+    fn generate_with_heights(config: &WorldGenConfig, surface_heights: Vec<u32>) -> Self {
         let _ = info_span!("map_generate").entered();
         let start_total = std::time::Instant::now();
 
-        // Convert chunk counts to particle dimensions
-        let map_width = width * CHUNK_SIZE;
-        let map_height = height * CHUNK_SIZE;
+        let map_width = config.map_width();
+        let map_height = config.map_height();
 
         // Create an empty map
         let mut map = Map::empty(map_width, map_height);
+        map.biomes = BiomeLayout::generate(map_width, config.seed, &config.noise());
+        map.surface_heights = surface_heights;
+        map.gen_config = Some(config.clone());
+        map.simulation_seed = config.seed;
+        map.particles = config.particles.clone();
 
-        // Generate all map data and get the populated chunks
-        let chunks_vec = generate_all_data(map_width, map_height);
-
-        // Distribute chunks into the 2D vector structure
-        map.distribute_among_chunks(chunks_vec);
+        // Generate all map data into the populated chunks
+        map.chunks = generate_all_data(&map.surface_heights, &map.biomes, config);
 
         // Print composition statistics
         let start_log = std::time::Instant::now();
@@ -170,6 +375,103 @@ impl Map {
         map
     }
 
+    /// The parameters the map was generated from, or `None` if it wasn't generated.
+    pub fn gen_config(&self) -> Option<&WorldGenConfig> {
+        self.gen_config.as_ref()
+    }
+
+    /// The seed the map was generated from, or `None` if it wasn't generated.
+    pub fn seed(&self) -> Option<u64> {
+        self.gen_config.as_ref().map(|config| config.seed)
+    }
+
+    /// Replace the stored generation parameters, e.g. when restoring a saved map.
+    /// Returns false and leaves them untouched if `config` doesn't describe a map of this size.
+    pub fn set_gen_config(&mut self, config: WorldGenConfig) -> bool {
+        if config.map_width() != self.width || config.map_height() != self.height {
+            return false;
+        }
+        self.gen_config = Some(config);
+        true
+    }
+
+    /// Generate the chunk at `chunk_pos` from the stored seed and surface profile, exactly as
+    /// the full generation produced it. Useful to lazily (re)generate parts of the map.
+    /// Returns `None` if the map wasn't generated or the position is outside the map.
+    pub fn generate_chunk(&self, chunk_pos: UVec2) -> Option<Chunk> {
+        let config = self.gen_config.as_ref()?;
+        self.generate_planned_chunk(chunk_pos, &self.generation_plan(config))
+    }
+
+    /// Like [`Map::generate_chunk`], but with the map's generation plan passed in, for callers
+    /// that hold it while the map doesn't.
+    pub(crate) fn generate_planned_chunk(
+        &self,
+        chunk_pos: UVec2,
+        plan: &GenerationPlan,
+    ) -> Option<Chunk> {
+        let config = self.gen_config.as_ref()?;
+        if chunk_pos.x >= config.width
+            || chunk_pos.y >= config.height
+            || self.surface_heights.len() != self.width as usize
+        {
+            return None;
+        }
+
+        Some(generate_chunk_data(
+            chunk_pos,
+            &self.surface_heights,
+            &self.biomes,
+            config,
+            plan,
+        ))
+    }
+
+    /// The definitions the map's particles are simulated and drawn with.
+    pub fn particles(&self) -> &ParticleRegistry {
+        &self.particles
+    }
+
+    /// Simulate and draw the map's particles with `particles` from now on, redrawing every chunk.
+    pub fn set_particles(&mut self, particles: ParticleRegistry) {
+        self.particles = particles;
+        for chunk in self.chunks.iter_mut().flatten() {
+            chunk.mark_dirty();
+        }
+    }
+
+    /// The surface height of every column as generated, indexed by x. Above these heights there
+    /// is only sky. Empty for maps that weren't generated.
+    pub fn surface_profile(&self) -> &[u32] {
+        &self.surface_heights
+    }
+
+    /// How far below the surface `position` is, or `None` above it. Maps without a surface
+    /// profile count down from their top.
+    pub fn depth_at(&self, position: UVec2) -> Option<u32> {
+        let surface = self
+            .surface_heights
+            .get(position.x as usize)
+            .copied()
+            .unwrap_or(self.height);
+        surface.checked_sub(position.y)
+    }
+
+    /// Replace the stored surface profile, e.g. when restoring a saved map.
+    /// Returns false and leaves the profile untouched if it isn't empty or one entry per column.
+    pub fn set_surface_profile(&mut self, heights: Vec<u32>) -> bool {
+        if !heights.is_empty() && heights.len() != self.width as usize {
+            return false;
+        }
+        self.surface_heights = heights;
+        true
+    }
+
+    /// The biome of the given column.
+    pub fn biome_at(&self, x: u32) -> Biome {
+        self.biomes.biome_at(x)
+    }
+
     /// Helper function to get a particle at the specified position.
     /// Returns `None` for out-of-bounds positions.
     pub fn get_particle_at(&self, position: UVec2) -> Option<Particle> {
@@ -184,7 +486,66 @@ impl Map {
         chunk.get_particle(local_pos)
     }
 
+    /// The wall behind the cell at `position`. Returns `None` for out-of-bounds positions.
+    pub fn get_background_at(&self, position: UVec2) -> Option<Particle> {
+        if !self.within_bounds(position) {
+            return None;
+        }
+        let chunk_pos = utils::coords::get_chunk_from_world_pos(position);
+        let local_pos = utils::coords::world_to_chunk_local(position);
+        self.get_chunk_at(&chunk_pos).get_background(local_pos)
+    }
+
+    /// Sets the wall behind the cell at `position`, leaving the particle in front of it alone.
+    /// Unloaded chunks of streamed maps are loaded first, see [`Map::set_particle_at`].
+    pub fn set_background_at(&mut self, position: UVec2, particle: Option<Particle>) {
+        if !self.within_bounds(position) {
+            return;
+        }
+        let chunk_pos = utils::coords::get_chunk_from_world_pos(position);
+        let local_pos = utils::coords::world_to_chunk_local(position);
+        self.load_chunk(chunk_pos);
+        self.chunks[chunk_pos.x as usize][chunk_pos.y as usize].set_background(local_pos, particle);
+    }
+
+    /// How much of its hardness the particle at `position` has lost to digging and erosion.
+    /// Out of bounds positions have no wear.
+    pub fn get_wear_at(&self, position: UVec2) -> f32 {
+        if !self.within_bounds(position) {
+            return 0.0;
+        }
+        let chunk_pos = utils::coords::get_chunk_from_world_pos(position);
+        let local_pos = utils::coords::world_to_chunk_local(position);
+        self.get_chunk_at(&chunk_pos).wear(local_pos)
+    }
+
+    /// Wears `amount` of the hardness of the particle at `position` away, see
+    /// [`Particle::get_hardness`]. Once it's worn through, the particle is removed and returned.
+    /// Particles without a hardness, like bedrock, never wear. Unloaded chunks of streamed maps
+    /// are loaded first, see [`Map::set_particle_at`].
+    pub fn wear_particle_at(&mut self, position: UVec2, amount: f32) -> Option<Particle> {
+        if !self.within_bounds(position) {
+            return None;
+        }
+        let chunk_pos = utils::coords::get_chunk_from_world_pos(position);
+        let local_pos = utils::coords::world_to_chunk_local(position);
+        self.load_chunk(chunk_pos);
+
+        let particle = self.get_particle_at(position)?;
+        let hardness = particle.get_hardness()?;
+        let wear =
+            self.chunks[chunk_pos.x as usize][chunk_pos.y as usize].add_wear(local_pos, amount);
+        if wear < hardness as f32 {
+            return None;
+        }
+
+        self.set_particle_at(position, None);
+        Some(particle)
+    }
+
     /// Helper function to set a particle at the specified map position while handling chunk boundaries.
+    /// Unloaded chunks of streamed maps are loaded first, since a write into their empty stand-in
+    /// would be lost once they're loaded.
     pub fn set_particle_at(&mut self, position: UVec2, particle: Option<Particle>) {
         if position.x >= self.width || position.y >= self.height {
             return;
@@ -192,13 +553,38 @@ impl Map {
 
         let chunk_pos = utils::coords::get_chunk_from_world_pos(position);
         let local_pos = utils::coords::world_to_chunk_local(position);
+        self.load_chunk(chunk_pos);
 
         let chunk = &mut self.chunks[chunk_pos.x as usize][chunk_pos.y as usize];
         chunk.set_particle(local_pos, particle);
+        self.wake_settled_around(position);
+
+        // Edits far from the player should still take effect, so make sure it simulates.
+        if particle.is_some_and(|particle| particle.is_dynamic()) {
+            self.wake_chunk(chunk_pos);
+        }
+    }
+
+    /// Keep a chunk active for the next [`WAKE_TICKS`] simulation ticks, loading it first if the
+    /// map is streamed.
+    pub fn wake_chunk(&mut self, chunk_pos: UVec2) {
+        self.load_chunk(chunk_pos);
+        self.awake_chunks.insert(chunk_pos, WAKE_TICKS);
+        self.active_chunks.insert(chunk_pos);
     }
 
     /// Returns a list of chunk positions within a radius of the given world position
     pub fn get_chunks_near(&self, position: Vec2, range: u32) -> Vec<UVec2> {
+        self.get_chunks_near_weighted(position, range)
+            .into_iter()
+            .map(|(chunk_pos, _weight)| chunk_pos)
+            .collect()
+    }
+
+    /// Like [`Map::get_chunks_near`], but pairs each chunk with its distance from the center
+    /// normalized to the range: 0 for the center chunk, 1 for chunks on the edge.
+    /// Callers can use this to fall off detail or update rates with distance.
+    pub fn get_chunks_near_weighted(&self, position: Vec2, range: u32) -> Vec<(UVec2, f32)> {
         let center_chunk = utils::coords::world_vec2_to_chunk(position);
         let chunk_range = range.div_ceil(CHUNK_SIZE);
 
@@ -234,7 +620,13 @@ impl Map {
                 let squared_range = (chunk_range * chunk_range) as f32;
 
                 if squared_distance <= squared_range {
-                    nearby_chunks.push(chunk_pos);
+                    // A zero range only ever contains the center chunk.
+                    let weight = if chunk_range == 0 {
+                        0.0
+                    } else {
+                        (squared_distance / squared_range).sqrt()
+                    };
+                    nearby_chunks.push((chunk_pos, weight));
                 }
             }
         }
@@ -242,6 +634,338 @@ impl Map {
         nearby_chunks
     }
 
+    /// Dry-runs the fluid simulator over every liquid cell in the region `[min, max)` and returns
+    /// where each one intends to move this tick. Nothing on the map is mutated, so tooling can
+    /// call this to visualize the flow field.
+    pub fn flow_field(
+        &self,
+        rules: &InteractionRules,
+        min: UVec2,
+        max: UVec2,
+    ) -> HashMap<UVec2, UVec2> {
+        let max = max.min(UVec2::new(self.width, self.height));
+        let mut flow = HashMap::new();
+
+        if min.x >= max.x || min.y >= max.y {
+            return flow;
+        }
+
+        // Throwaway queue so cross-chunk targets are validated the same way as a real tick.
+        let scratch_queue = DashMap::new();
+        let min_chunk = utils::coords::get_chunk_from_world_pos(min);
+        let max_chunk = utils::coords::get_chunk_from_world_pos(max - UVec2::ONE);
+
+        for cx in min_chunk.x..=max_chunk.x {
+            for cy in min_chunk.y..=max_chunk.y {
+                let chunk = self.get_chunk_at(&UVec2::new(cx, cy));
+                // Read the chunk's current cells as the "new" state so the dry-run sees every particle.
+                let context = ReadOnlySimulationContext::new(
+                    self,
+                    rules,
+                    chunk,
+                    &scratch_queue,
+                    chunk.cells(),
+                );
+
+                for (x, column) in chunk.cells().iter().enumerate() {
+                    for (y, &particle) in column.iter().enumerate() {
+                        let Some(Particle::Liquid(fluid)) = particle else {
+                            continue;
+                        };
+
+                        let position = utils::coords::chunk_local_to_world(
+                            chunk.position,
+                            UVec2::new(x as u32, y as u32),
+                        );
+                        if position.cmplt(min).any() || position.cmpge(max).any() {
+                            continue;
+                        }
+
+                        let target = FluidSimulator
+                            .calculate_step(&context, fluid, position.x, position.y)
+                            .target_pos();
+                        flow.insert(position, target);
+                    }
+                }
+            }
+        }
+
+        flow
+    }
+
+    /// Replace every cell with `f(position, particle)`, where `position` is in world coordinates.
+    /// Only cells whose particle or its state actually changes are written, so untouched chunks
+    /// stay clean (and compacted chunks stay compacted). Changed chunks are marked dirty, and
+    /// chunks that gained a dynamic particle are woken so they simulate.
+    pub fn map_cells(&mut self, mut f: impl FnMut(UVec2, Option<Particle>) -> Option<Particle>) {
+        let mut woken = Vec::new();
+
+        for chunk in self.chunks.iter_mut().flatten() {
+            let mut changed = false;
+            let mut gained_dynamic = false;
+
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    let (xi, yi) = (x as usize, y as usize);
+                    let old = chunk.cells()[xi][yi];
+                    let position =
+                        utils::coords::chunk_local_to_world(chunk.position, UVec2::new(x, y));
+                    let new = f(position, old);
+                    let unchanged = match (new, old) {
+                        (Some(new), Some(old)) => new.is_identical(&old),
+                        (new, old) => new.is_none() && old.is_none(),
+                    };
+                    if unchanged {
+                        continue;
+                    }
+
+                    chunk.carry_temperature((xi, yi), new);
+                    chunk.clear_wear((xi, yi));
+                    chunk.cells_mut()[xi][yi] = new;
+                    changed = true;
+                    gained_dynamic |= new.is_some_and(|particle| particle.is_dynamic());
+                }
+            }
+
+            if changed {
+                chunk.mark_dirty();
+            }
+            if gained_dynamic {
+                woken.push(chunk.position);
+            }
+        }
+
+        for chunk_pos in woken {
+            self.wake_chunk(chunk_pos);
+        }
+    }
+
+    /// Copies the cells in the region `[min, max)` into a new standalone map, with the region's
+    /// `min` corner at the origin. The new map is padded with air up to chunk alignment.
+    pub fn clone_region(&self, min: UVec2, max: UVec2) -> Map {
+        let max = max.min(UVec2::new(self.width, self.height));
+        let size = max.saturating_sub(min);
+
+        let mut region = Map::empty(
+            size.x.div_ceil(CHUNK_SIZE) * CHUNK_SIZE,
+            size.y.div_ceil(CHUNK_SIZE) * CHUNK_SIZE,
+        );
+        region.biomes = self.biomes.slice(min.x, max.x);
+
+        for x in 0..size.x {
+            for y in 0..size.y {
+                let particle = self.get_particle_at(min + UVec2::new(x, y));
+                if particle.is_some() {
+                    region.set_particle_at(UVec2::new(x, y), particle);
+                }
+            }
+        }
+
+        region
+    }
+
+    /// Releases the cell storage of every chunk that is entirely air, returning how many chunks
+    /// are compacted afterwards. Compacted chunks re-materialize their storage on the next write.
+    pub fn compact(&mut self) -> usize {
+        self.chunks
+            .iter_mut()
+            .flatten()
+            .map(Chunk::compact)
+            .filter(|&compacted| compacted)
+            .count()
+    }
+
+    /// A hash of the whole map that is stable across runs, usable as a world ID.
+    /// Maps with identical dimensions and cells always share a hash.
+    pub fn world_hash(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        hasher.write(&self.width.to_le_bytes());
+        hasher.write(&self.height.to_le_bytes());
+
+        // Chunks are stored x-major, which gives a position-stable order.
+        for chunk in self.chunks.iter().flatten() {
+            hasher.write(&chunk.position.x.to_le_bytes());
+            hasher.write(&chunk.position.y.to_le_bytes());
+            hasher.write(&chunk.content_hash().to_le_bytes());
+        }
+
+        hasher.finish()
+    }
+
+    /// Approximate number of bytes used by the map's chunks, including the saved cells of
+    /// evicted chunks on streamed maps.
+    pub fn memory_usage(&self) -> usize {
+        self.chunks
+            .iter()
+            .flatten()
+            .map(Chunk::memory_usage)
+            .sum::<usize>()
+            + self.evicted_memory_usage()
+    }
+
+    /// Mark a single chunk as dirty so its active state is refreshed and the renderer picks up
+    /// any changes. Out-of-bounds chunk positions are ignored.
+    pub fn mark_chunk_dirty(&mut self, chunk_pos: UVec2) {
+        if let Some(chunk) = self
+            .chunks
+            .get_mut(chunk_pos.x as usize)
+            .and_then(|col| col.get_mut(chunk_pos.y as usize))
+        {
+            chunk.mark_dirty();
+        }
+    }
+
+    /// Mark every chunk in the map as dirty.
+    pub fn mark_all_dirty(&mut self) {
+        for chunk in self.chunks.iter_mut().flatten() {
+            chunk.mark_dirty();
+        }
+    }
+
+    /// Returns every liquid cell with air directly below it. Once the simulation has settled this
+    /// should be empty, so tests and debug tooling can use it to catch liquids stuck mid-air.
+    pub fn find_floating_fluids(&self) -> Vec<UVec2> {
+        let mut floating = Vec::new();
+
+        for chunk in self.chunks.iter().flatten() {
+            for (x, column) in chunk.cells().iter().enumerate() {
+                for (y, particle) in column.iter().enumerate() {
+                    let Some(Particle::Liquid(_)) = particle else {
+                        continue;
+                    };
+
+                    let position = utils::coords::chunk_local_to_world(
+                        chunk.position,
+                        UVec2::new(x as u32, y as u32),
+                    );
+                    if position.y > 0 && self.get_particle_at(position - UVec2::Y).is_none() {
+                        floating.push(position);
+                    }
+                }
+            }
+        }
+
+        floating
+    }
+
+    /// Every particle in the region `[rect.min, rect.max)`, clipped to the map, along with its
+    /// world position. Reads the cells of each chunk overlapping the region directly instead of
+    /// looking every cell up on its own, and skips compacted chunks entirely. Particles come
+    /// chunk by chunk, x-major within each chunk.
+    pub fn query_region(&self, rect: URect) -> impl Iterator<Item = (UVec2, Particle)> + '_ {
+        let size = UVec2::new(self.width, self.height);
+        let min = rect.min.min(size);
+        let max = rect.max.min(size).max(min);
+        let min_chunk = min / CHUNK_SIZE;
+        let max_chunk = UVec2::new(max.x.div_ceil(CHUNK_SIZE), max.y.div_ceil(CHUNK_SIZE));
+
+        (min_chunk.x..max_chunk.x)
+            .flat_map(move |cx| (min_chunk.y..max_chunk.y).map(move |cy| UVec2::new(cx, cy)))
+            .map(|chunk_pos| self.get_chunk_at(&chunk_pos))
+            .filter(|chunk| !chunk.is_compacted())
+            .flat_map(move |chunk| {
+                let origin = UVec2::new(chunk.x_min(), chunk.y_min());
+                let local_min = min.max(origin) - origin;
+                let local_max = max.min(origin + CHUNK_SIZE) - origin;
+                (local_min.x..local_max.x).flat_map(move |x| {
+                    (local_min.y..local_max.y).filter_map(move |y| {
+                        chunk.cells()[x as usize][y as usize]
+                            .map(|particle| (origin + UVec2::new(x, y), particle))
+                    })
+                })
+            })
+    }
+
+    /// Every particle within `radius` cells of `center`, along with its world position.
+    pub(crate) fn query_radius(
+        &self,
+        center: UVec2,
+        radius: u32,
+    ) -> impl Iterator<Item = (UVec2, Particle)> + '_ {
+        let rect = URect::from_corners(
+            center.saturating_sub(UVec2::splat(radius)),
+            center
+                .saturating_add(UVec2::splat(radius))
+                .saturating_add(UVec2::ONE),
+        );
+        let squared_radius = radius as u64 * radius as u64;
+        self.query_region(rect).filter(move |(position, _)| {
+            let dx = center.x.abs_diff(position.x) as u64;
+            let dy = center.y.abs_diff(position.y) as u64;
+            dx * dx + dy * dy <= squared_radius
+        })
+    }
+
+    /// Counts the particles of each type within `radius` cells of `center`. Liquids count by
+    /// type alone, whichever way they flow.
+    pub fn count_particles_in_radius(&self, center: UVec2, radius: u32) -> HashMap<Particle, u32> {
+        let mut counts = HashMap::new();
+        for (_, particle) in self.query_radius(center, radius) {
+            *counts.entry(particle).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Counts the liquid cells of each type within `radius` cells of `center`.
+    pub fn count_liquids_in_radius(&self, center: UVec2, radius: u32) -> HashMap<Liquid, u32> {
+        let mut counts = HashMap::new();
+        for (_, particle) in self.query_radius(center, radius) {
+            if let Particle::Liquid(liquid) = particle {
+                *counts.entry(liquid).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// The chunks in the square of `range` chunks around `center_chunk` in every direction,
+    /// clipped to the map. Empty if the center is outside the map.
+    pub fn compute_active_region(&self, center_chunk: UVec2, range: u32) -> HashSet<UVec2> {
+        let chunks_wide = self.width / CHUNK_SIZE;
+        let chunks_tall = self.height / CHUNK_SIZE;
+        if center_chunk.x >= chunks_wide || center_chunk.y >= chunks_tall {
+            return HashSet::new();
+        }
+
+        // Calculate the rectangular bounds around the center, within the map
+        let min_x = center_chunk.x.saturating_sub(range);
+        let max_x = center_chunk.x.saturating_add(range).min(chunks_wide - 1);
+        let min_y = center_chunk.y.saturating_sub(range);
+        let max_y = center_chunk.y.saturating_add(range).min(chunks_tall - 1);
+
+        (min_x..=max_x)
+            .flat_map(|x| (min_y..=max_y).map(move |y| UVec2::new(x, y)))
+            .collect()
+    }
+
+    /// Replace the active chunks with `chunks`, then refresh the dirty ones.
+    /// Awake chunks (see [`Map::wake_chunk`]) always stay active on top of `chunks`.
+    pub fn set_active_chunks(&mut self, chunks: HashSet<UVec2>) {
+        self.active_chunks = chunks;
+        self.active_chunks.extend(self.awake_chunks.keys().copied());
+
+        // Update any dirty chunks in the active area
+        self.update_dirty_chunks();
+    }
+
+    /// The total number of chunks in the map.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.iter().map(Vec::len).sum()
+    }
+
+    /// The number of chunks currently active, whether or not they have anything to simulate.
+    pub fn active_chunk_count(&self) -> usize {
+        self.active_chunks.len()
+    }
+
+    /// The number of active chunks that are actually simulated, i.e. have `should_simulate` set.
+    /// Includes chunks that have settled, see [`Map::settled_chunk_count`].
+    pub fn simulating_chunk_count(&self) -> usize {
+        self.active_chunks
+            .iter()
+            .filter(|pos| self.get_chunk_at(pos).should_simulate)
+            .count()
+    }
+
     /// Update all active chunks that are marked as dirty.
     pub fn update_dirty_chunks(&mut self) {
         for chunk_pos in self.active_chunks.iter() {
@@ -257,66 +981,491 @@ impl Map {
     /// Uses a two-phase approach:
     /// 1. First simulate each chunk internally (for in-chunk particle updates)
     /// 2. Then handle cross-chunk particle movement with a message queue system
-    pub fn simulate_active_chunks(&mut self) {
-        // Parallel-safe interchunk queue.
-        let interchunk_queue = Arc::new(DashMap::new());
+    pub fn simulate_active_chunks(&mut self, rules: &InteractionRules) {
+        let awake_versions: Vec<(UVec2, u64)> = self
+            .awake_chunks
+            .keys()
+            .map(|pos| (*pos, self.get_chunk_at(pos).version))
+            .collect();
+
         // Copy only chunks that need simulation
         let mut active_chunks = self.copy_simulatable_chunks();
+        // Parallel-safe interchunk queue, pre-sized so typical flow never has to grow it.
+        let capacity = active_chunks.len() * INTERCHUNK_MOVES_PER_CHUNK;
+        let interchunk_queue = Arc::new(DashMap::with_capacity(capacity));
+        let cooldowns = DashMap::new();
 
         // Parallel simulation: Process each chunk in parallel
         active_chunks
             .par_iter_mut()
-            .for_each(|chunk| chunk.simulate(self, interchunk_queue.clone()));
+            .for_each(|chunk| chunk.simulate(self, rules, interchunk_queue.clone(), &cooldowns));
 
         // Write back only modified chunks
         for chunk in active_chunks {
             self.set_chunk_at(chunk.position, chunk);
         }
 
+        let interchunk_queue = Arc::try_unwrap(interchunk_queue).unwrap();
+        self.record_interchunk_queue_len(interchunk_queue.len(), capacity);
+
         // We do this at the end for a second pass of processing.
         // For example, we can process from the lowest y-value to the highest.
-        self.apply_particle_moves(Arc::try_unwrap(interchunk_queue).unwrap());
+        self.apply_particle_moves(interchunk_queue);
+        self.settle_by_density();
+
+        self.tick_awake_chunks(&awake_versions);
+        self.update_settled_chunks();
+        self.tick += 1;
+        self.interaction_cooldowns.extend(cooldowns);
+        let tick = self.tick;
+        self.interaction_cooldowns.retain(|_, ready| *ready > tick);
     }
 
-    /// Apply all particle moves in a consistent way that avoids conflicts.
-    fn apply_particle_moves(&mut self, interchunk_queue: DashMap<UVec2, ParticleMove>) {
-        // Collect queue into a Vec.
-        let mut moves: Vec<(UVec2, ParticleMove)> = interchunk_queue
+    /// Advances the simulation by `ticks` fixed ticks right away, running everything the
+    /// simulation systems run each tick: movement, liquid pressure, interactions, combustion,
+    /// erosion and heat. Like in the app, only active and awake chunks are simulated, see
+    /// [`Map::set_active_chunks`].
+    pub fn step(&mut self, rules: &InteractionRules, ticks: u32) {
+        for _ in 0..ticks {
+            self.update_dirty_chunks();
+            self.simulate_active_chunks(rules);
+            self.equalize_liquids();
+            self.process_interactions(rules);
+            self.process_combustion();
+            self.process_erosion(rules);
+            self.diffuse_heat();
+        }
+    }
+
+    /// The seed behind every random choice the simulation makes. Generated maps use their
+    /// generation seed, other maps start at 0.
+    pub fn simulation_seed(&self) -> u64 {
+        self.simulation_seed
+    }
+
+    pub fn set_simulation_seed(&mut self, seed: u64) {
+        self.simulation_seed = seed;
+    }
+
+    /// How many simulation ticks have run on this map.
+    pub fn simulation_tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub(crate) fn set_simulation_tick(&mut self, tick: u64) {
+        self.tick = tick;
+    }
+
+    /// Whether the cell at `position` can take part in an interaction this tick. Cells that an
+    /// interaction rule with a [`cooldown`](crate::particle::interaction::InteractionRule::cooldown)
+    /// turned into its result wait that many ticks before reacting again.
+    pub fn interaction_ready(&self, position: UVec2) -> bool {
+        self.interaction_cooldowns
+            .get(&position)
+            .is_none_or(|&ready| self.tick >= ready)
+    }
+
+    /// The random number generator for the particle at `position` during the current tick.
+    /// It only depends on the simulation seed, the tick and the position, so every tie-break
+    /// comes out the same for the same seed no matter which thread simulates the chunk.
+    pub fn particle_rng(&self, position: UVec2) -> SmallRng {
+        let mut hasher = StableHasher::default();
+        hasher.write(&self.simulation_seed.to_le_bytes());
+        hasher.write(&self.tick.to_le_bytes());
+        hasher.write(&position.x.to_le_bytes());
+        hasher.write(&position.y.to_le_bytes());
+        SmallRng::seed_from_u64(hasher.finish())
+    }
+
+    /// Sinks denser particles through lighter liquids and gases by swapping vertically adjacent
+    /// cells in simulating active chunks, one cell per tick. This is what makes oil float on
+    /// water, sand sink in it without destroying the water, and steam bubble up through it.
+    /// Each cell takes part in at most one swap per call.
+    fn settle_by_density(&mut self) {
+        let mut chunk_positions: Vec<UVec2> = self
+            .active_chunks
             .iter()
-            .map(|entry| (*entry.key(), entry.value().clone()))
+            .filter(|pos| self.is_chunk_moving(**pos))
+            .copied()
             .collect();
+        // Sort for deterministic results, matching the order chunks are stored in.
+        chunk_positions.sort_by_key(|pos| (pos.x, pos.y));
+
+        let mut swapped = HashSet::new();
+        let mut swaps = Vec::new();
+
+        for chunk_pos in chunk_positions {
+            let chunk = self.get_chunk_at(&chunk_pos);
+            for (x, column) in chunk.cells().iter().enumerate() {
+                for (y, &particle) in column.iter().enumerate() {
+                    // Only fluids make way, so solids and resting powder hold up what's above.
+                    let Some(below @ (Particle::Liquid(_) | Particle::Gas(_))) = particle else {
+                        continue;
+                    };
+                    let position = utils::coords::chunk_local_to_world(
+                        chunk_pos,
+                        UVec2::new(x as u32, y as u32),
+                    );
+                    let above_pos = position + UVec2::Y;
+                    if swapped.contains(&position) || swapped.contains(&above_pos) {
+                        continue;
+                    }
+                    let Some(above) = self.get_particle_at(above_pos) else {
+                        continue;
+                    };
+
+                    if above.get_density() > below.get_density() {
+                        swaps.push((position, above, above_pos, below));
+                        swapped.insert(position);
+                        swapped.insert(above_pos);
+                    }
+                }
+            }
+        }
+
+        for (position, above, above_pos, below) in swaps {
+            self.set_particle_at(position, Some(above));
+            self.set_particle_at(above_pos, Some(below));
+        }
+    }
+
+    /// Remember the size of this tick's interchunk queue, warning when it reaches a new peak
+    /// beyond its pre-sized capacity so runaway flow shows up in the logs.
+    fn record_interchunk_queue_len(&mut self, len: usize, capacity: usize) {
+        // Moves are keyed by target cell, so there can never be more of them than cells.
+        debug_assert!(
+            len <= (self.width * self.height) as usize,
+            "Interchunk queue holds {len} moves, more than the map has cells"
+        );
+        if len > capacity && len > self.peak_interchunk_queue_len {
+            warn!("Interchunk queue grew to {len} moves (pre-sized for {capacity})");
+        }
+
+        self.interchunk_queue_len = len;
+        self.peak_interchunk_queue_len = self.peak_interchunk_queue_len.max(len);
+    }
+
+    /// How many cross-chunk moves were queued on the last simulation tick.
+    pub fn interchunk_queue_len(&self) -> usize {
+        self.interchunk_queue_len
+    }
 
-        // Sort moves to ensure deterministic behavior.
-        moves.sort_by_key(|m| (m.0.y, m.0.x)); // Process bottom-to-top
+    /// The largest number of cross-chunk moves queued in a single tick so far.
+    pub fn peak_interchunk_queue_len(&self) -> usize {
+        self.peak_interchunk_queue_len
+    }
 
-        // First, remove particles from source positions (only for non-preserve moves).
-        for movement in &moves {
-            if !movement.1.preserve_source {
-                self.set_particle_at(movement.1.source_pos, None);
+    /// Count down awake chunks, keeping the ones that changed this tick awake for longer.
+    fn tick_awake_chunks(&mut self, versions_before: &[(UVec2, u64)]) {
+        for (chunk_pos, version) in versions_before {
+            if self.get_chunk_at(chunk_pos).version != *version {
+                self.awake_chunks.insert(*chunk_pos, WAKE_TICKS);
+            } else if let Some(ticks) = self.awake_chunks.get_mut(chunk_pos) {
+                *ticks = ticks.saturating_sub(1);
             }
         }
+        self.awake_chunks.retain(|_, ticks| *ticks > 0);
+    }
+
+    /// Commit the particle moves queued across chunk borders, conserving every particle.
+    ///
+    /// Moving particles were left at their sources while the chunks simulated, so a move only
+    /// commits if its source still holds the particle and its target is still free (or may be
+    /// replaced). Otherwise the move is dropped and the particle stays where it was, and so do the
+    /// particles of an interaction across the border. Moves are committed bottom to top, so the
+    /// results don't depend on the order chunks finished in.
+    fn apply_particle_moves(&mut self, interchunk_queue: DashMap<UVec2, ParticleMove>) {
+        let mut moves: Vec<ParticleMove> = interchunk_queue.into_iter().map(|(_, m)| m).collect();
+        moves.sort_by_key(|m| (m.target_pos.y, m.target_pos.x));
 
-        // Then, try to place particles at target positions if they're still empty.
         for movement in moves {
-            if self.get_particle_at(movement.0).is_none() {
-                self.set_particle_at(movement.0, Some(movement.1.particle));
-            } else if !movement.1.preserve_source {
-                // Target is occupied; restore the particle to its source position.
-                // Only needed for non-preserve moves since preserve sources were never removed.
-                self.set_particle_at(movement.1.source_pos, Some(movement.1.particle));
+            self.commit_particle_move(movement);
+        }
+    }
+
+    /// Commit a single move queued by [`Map::apply_particle_moves`], unless it conflicts.
+    fn commit_particle_move(&mut self, movement: ParticleMove) {
+        // Something else, like a fire that spread into it, took the particle's place.
+        if movement.source_particle.is_some()
+            && self.get_particle_at(movement.source_pos) != movement.source_particle
+        {
+            return;
+        }
+        if !movement.replace_target && self.get_particle_at(movement.target_pos).is_some() {
+            return;
+        }
+
+        if movement.source_particle.is_some() {
+            self.set_particle_at(movement.source_pos, movement.source_result);
+        }
+        self.set_particle_at(movement.target_pos, Some(movement.particle));
+    }
+
+    /// Reacts adjacent particles in simulating active chunks that have an interaction rule.
+    /// This covers particles that touch without moving into each other, like settled water next
+    /// to settled lava. A particle resting on top of another counts as moving into it, so
+    /// directional rules prefer the upper particle as their source. Each particle takes part in
+    /// at most one reaction per call.
+    pub fn process_interactions(&mut self, rules: &InteractionRules) {
+        let mut chunk_positions: Vec<UVec2> = self
+            .active_chunks
+            .iter()
+            .filter(|pos| self.get_chunk_at(pos).should_simulate)
+            .copied()
+            .collect();
+        // Sort for deterministic results, matching the order chunks are stored in.
+        chunk_positions.sort_by_key(|pos| (pos.x, pos.y));
+        let processed: HashSet<UVec2> = chunk_positions.iter().copied().collect();
+
+        let mut reacted = HashSet::new();
+        let mut changes = Vec::new();
+        let mut cooldowns = Vec::new();
+
+        for chunk_pos in chunk_positions {
+            let chunk = self.get_chunk_at(&chunk_pos);
+            for (x, column) in chunk.cells().iter().enumerate() {
+                for (y, &particle) in column.iter().enumerate() {
+                    let Some(particle) = particle else { continue };
+                    let position = utils::coords::chunk_local_to_world(
+                        chunk_pos,
+                        UVec2::new(x as u32, y as u32),
+                    );
+                    if reacted.contains(&position) {
+                        continue;
+                    }
+
+                    // Checking only the right and upper neighbors visits every adjacent pair once.
+                    // Cells on the left and bottom edges of the chunk also check across the edge
+                    // when the neighboring chunk isn't processed, so particles still react with
+                    // settled ground in chunks that aren't simulating.
+                    let left = (x == 0 && position.x > 0).then(|| position - UVec2::X);
+                    let below = (y == 0 && position.y > 0).then(|| position - UVec2::Y);
+                    let neighbors = [position + UVec2::X, position + UVec2::Y]
+                        .into_iter()
+                        .chain(left)
+                        .chain(below);
+                    for neighbor_pos in neighbors {
+                        if reacted.contains(&neighbor_pos) {
+                            continue;
+                        }
+                        let neighbor_chunk = utils::coords::get_chunk_from_world_pos(neighbor_pos);
+                        let behind = neighbor_pos.x < position.x || neighbor_pos.y < position.y;
+                        if behind && processed.contains(&neighbor_chunk) {
+                            continue;
+                        }
+                        let Some(neighbor) = self.get_particle_at(neighbor_pos) else {
+                            continue;
+                        };
+                        // A particle resting on another reacts as if it fell onto it.
+                        let (first, first_pos, second, second_pos) = if neighbor_pos.y > position.y
+                        {
+                            (neighbor, neighbor_pos, particle, position)
+                        } else {
+                            (particle, position, neighbor, neighbor_pos)
+                        };
+                        let Some((rule, first_is_source)) = rules
+                            .get_oriented(first, second)
+                            .filter(|(rule, _)| rule.reacts_on_contact())
+                        else {
+                            continue;
+                        };
+
+                        let (source_pos, target_pos) = if first_is_source {
+                            (first_pos, second_pos)
+                        } else {
+                            (second_pos, first_pos)
+                        };
+                        if !self.interaction_ready(target_pos)
+                            || !rule.roll(&mut self.particle_rng(target_pos))
+                        {
+                            continue;
+                        }
+                        if let InteractionType::Replace = rule.interaction_type {
+                            changes.push((source_pos, rule.byproduct));
+                        }
+                        changes.push((target_pos, Some(rule.result)));
+                        if rule.cooldown > 0 {
+                            cooldowns.push((target_pos, self.tick + 1 + rule.cooldown as u64));
+                        }
+
+                        reacted.insert(position);
+                        reacted.insert(neighbor_pos);
+                        break;
+                    }
+                }
+            }
+        }
+
+        for (position, particle) in changes {
+            self.set_particle_at(position, particle);
+        }
+        self.interaction_cooldowns.extend(cooldowns);
+    }
+
+    /// Sets flammable particles next to heat sources like lava on fire, with the same chance as
+    /// if a fire was next to them. Fire spreads by itself while simulating, so this covers the
+    /// heat sources that don't burn.
+    pub fn process_combustion(&mut self) {
+        let mut chunk_positions: Vec<UVec2> = self
+            .active_chunks
+            .iter()
+            .filter(|pos| self.get_chunk_at(pos).should_simulate)
+            .copied()
+            .collect();
+        // Sort for deterministic results, matching the order chunks are stored in.
+        chunk_positions.sort_by_key(|pos| (pos.x, pos.y));
+
+        let mut ignitions = Vec::new();
+        for chunk_pos in chunk_positions {
+            let chunk = self.get_chunk_at(&chunk_pos);
+            for (x, column) in chunk.cells().iter().enumerate() {
+                for (y, &particle) in column.iter().enumerate() {
+                    if !particle.is_some_and(|particle| particle.is_heat_source()) {
+                        continue;
+                    }
+                    let position = utils::coords::chunk_local_to_world(
+                        chunk_pos,
+                        UVec2::new(x as u32, y as u32),
+                    );
+                    let mut rng = self.particle_rng(position);
+
+                    for offset in SPREAD_OFFSETS {
+                        let neighbor = position.as_ivec2() + offset;
+                        if neighbor.cmplt(IVec2::ZERO).any() {
+                            continue;
+                        }
+                        let neighbor = neighbor.as_uvec2();
+                        let Some(fuel) = self.get_particle_at(neighbor) else {
+                            continue;
+                        };
+                        let chance = fuel.get_flammability() * self.combustion.spread_chance;
+                        if rng.random::<f32>() < chance {
+                            ignitions.push((neighbor, Fire::igniting(fuel)));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (position, fire) in ignitions {
+            self.set_particle_at(position, Some(Particle::Fire(fire)));
+        }
+    }
+
+    /// Levels connected bodies of liquid in active chunks by moving liquid from their highest
+    /// surfaces to the lowest empty cells next to them, see [`FluidSimulator::find_pressure_moves`].
+    pub fn equalize_liquids(&mut self) {
+        let mut level_chunks = std::mem::take(&mut self.level_chunks);
+        let moves =
+            FluidSimulator::find_pressure_moves(self, self.pressure.flow_rate, &mut level_chunks);
+        self.level_chunks = level_chunks;
+        for (source, target) in moves {
+            // Something may have moved in since the target was picked.
+            if self.get_particle_at(target).is_some() {
+                continue;
+            }
+            // The moved liquid lands gently, so it hasn't fallen anywhere.
+            let particle = match self.get_particle_at(source) {
+                Some(Particle::Liquid(liquid)) => Particle::Liquid(liquid.with_fall_distance(0)),
+                _ => continue,
+            };
+            self.set_particle_at(source, None);
+            self.set_particle_at(target, Some(particle));
+        }
+    }
+
+    /// Wears down particles next to liquids that erode them, like acid dissolving stone and water
+    /// washing dirt away, see [`Liquid::get_erosion_rate`]. When a particle is worn through, a
+    /// matching [`InteractionType::Dissolve`] rule decides whether the liquid is used up.
+    pub fn process_erosion(&mut self, rules: &InteractionRules) {
+        let mut chunk_positions: Vec<UVec2> = self
+            .active_chunks
+            .iter()
+            .filter(|pos| self.get_chunk_at(pos).should_simulate)
+            .copied()
+            .collect();
+        // Sort for deterministic results, matching the order chunks are stored in.
+        chunk_positions.sort_by_key(|pos| (pos.x, pos.y));
+
+        let mut erosions = Vec::new();
+        for chunk_pos in chunk_positions {
+            let chunk = self.get_chunk_at(&chunk_pos);
+            for (x, column) in chunk.cells().iter().enumerate() {
+                for (y, &particle) in column.iter().enumerate() {
+                    let Some(Particle::Liquid(liquid)) = particle else {
+                        continue;
+                    };
+                    let position = utils::coords::chunk_local_to_world(
+                        chunk_pos,
+                        UVec2::new(x as u32, y as u32),
+                    );
+
+                    for offset in SPREAD_OFFSETS {
+                        let neighbor = position.as_ivec2() + offset;
+                        if neighbor.cmplt(IVec2::ZERO).any() {
+                            continue;
+                        }
+                        let neighbor = neighbor.as_uvec2();
+                        let Some(target) = self.get_particle_at(neighbor) else {
+                            continue;
+                        };
+                        let rate = liquid.get_erosion_rate(target);
+                        if rate > 0.0 {
+                            erosions.push((position, Particle::Liquid(liquid), neighbor, rate));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (source_pos, source, position, rate) in erosions {
+            // The liquid may already have been used up dissolving another neighbor.
+            if self.get_particle_at(source_pos) != Some(source) {
+                continue;
+            }
+            let Some(dissolved) = self.wear_particle_at(position, rate) else {
+                continue;
+            };
+            let pair = InteractionPair {
+                source,
+                target: dissolved,
+            };
+            let Some(rule) = rules
+                .get(&pair)
+                .filter(|rule| matches!(rule.interaction_type, InteractionType::Dissolve))
+            else {
+                continue;
+            };
+            if rule.roll(&mut self.particle_rng(source_pos)) {
+                self.set_particle_at(source_pos, Some(rule.result));
             }
         }
     }
 
     // Get a chunk at a specific position in local map coordinates.
     pub fn get_chunk_at(&self, position: &UVec2) -> &Chunk {
+        self.debug_assert_chunk_in_bounds(*position, "get_chunk_at");
         &self.chunks[position.x as usize][position.y as usize]
     }
 
     pub fn set_chunk_at(&mut self, position: UVec2, chunk: Chunk) {
+        self.debug_assert_chunk_in_bounds(position, "set_chunk_at");
         self.chunks[position.x as usize][position.y as usize] = chunk;
     }
 
+    /// In debug builds, panic with a descriptive message if `position` isn't a chunk of this map.
+    #[inline]
+    fn debug_assert_chunk_in_bounds(&self, position: UVec2, operation: &str) {
+        debug_assert!(
+            position.x < self.width / CHUNK_SIZE && position.y < self.height / CHUNK_SIZE,
+            "{}: chunk {} is outside the {}x{} chunk map",
+            operation,
+            position,
+            self.width / CHUNK_SIZE,
+            self.height / CHUNK_SIZE
+        );
+    }
+
     /// Check if a possible position is within the map bounds.
     pub fn within_bounds(&self, position: UVec2) -> bool {
         position.x < self.width && position.y < self.height
@@ -327,25 +1476,33 @@ impl Map {
         self.within_bounds(position) && self.get_particle_at(position).is_none()
     }
 
-    /// Copy only the active chunks that need simulation.
+    /// Copy only the active chunks that need simulation and haven't settled. Unloaded chunks of
+    /// streamed maps are skipped, since they only hold an empty stand-in.
     fn copy_simulatable_chunks(&self) -> Vec<Chunk> {
         self.active_chunks
             .iter()
-            .map(|pos| &self.chunks[pos.x as usize][pos.y as usize])
-            .filter(|chunk| chunk.should_simulate)
-            .cloned()
+            .filter(|pos| self.is_chunk_loaded(**pos) && self.is_chunk_moving(**pos))
+            .map(|pos| self.chunks[pos.x as usize][pos.y as usize].clone())
             .collect()
     }
 }
 
-/// Updates the active chunks to be those around the player.
-pub fn update_active_chunks(mut map: ResMut<Map>, player_query: Query<&Transform, With<Player>>) {
-    // Use ACTIVE_CHUNK_RANGE from the chunk module for consistency
-    const UPDATE_RANGE: u32 = ACTIVE_CHUNK_RANGE;
+/// Updates the active chunks to be those around the player, within
+/// [`Config::active_chunk_range`]. Without a player the active chunks are left as they are, but
+/// the dirty ones are still refreshed.
+pub fn update_active_chunks(
+    mut map: ResMut<Map>,
+    config: Option<Res<Config>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let config = config.map_or_else(Config::default, |config| *config);
 
     let player_transform = match player_query.get_single() {
         Ok(transform) => transform,
-        Err(_) => return,
+        Err(_) => {
+            map.update_dirty_chunks();
+            return;
+        }
     };
 
     // Convert screen position to world position
@@ -358,37 +1515,214 @@ pub fn update_active_chunks(mut map: ResMut<Map>, player_query: Query<&Transform
     // Convert player world position to chunk position
     let center_chunk = world_vec2_to_chunk(player_pos);
 
-    // Calculate map bounds in chunk coordinates
-    let max_chunk_x = map.width / CHUNK_SIZE - 1;
-    let max_chunk_y = map.height / CHUNK_SIZE - 1;
-
-    // Calculate the rectangular bounds around the player
-    let min_x = center_chunk.x.saturating_sub(UPDATE_RANGE);
-    let max_x = (center_chunk.x + UPDATE_RANGE).min(max_chunk_x);
-    let min_y = center_chunk.y.saturating_sub(UPDATE_RANGE);
-    let max_y = (center_chunk.y + UPDATE_RANGE).min(max_chunk_y);
-
     // Debug information
     debug!(
-        "Player at world coords: ({}, {}), updating rectangular chunk region: x={}..{}, y={}..{}",
-        player_pos.x, player_pos.y, min_x, max_x, min_y, max_y
+        "Player at world coords: ({}, {}), updating chunks within {} of chunk {}",
+        player_pos.x, player_pos.y, config.active_chunk_range, center_chunk
     );
 
-    // Clear the current active chunks
-    map.active_chunks.clear();
+    // Streamed maps load chunks a little past the active range, so activity never reaches
+    // unloaded chunks.
+    map.stream_chunks(center_chunk, config.stream_chunk_range());
+    let region = map.compute_active_region(center_chunk, config.active_chunk_range);
+    map.set_active_chunks(region);
+}
+
+/// Whether the simulation pauses while the game window is in the background, to save CPU.
+#[derive(Resource)]
+pub struct PauseWhenUnfocused {
+    pub enabled: bool,
+}
+
+impl Default for PauseWhenUnfocused {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Run condition for the simulation systems. Without a primary window (e.g. headless) the
+/// simulation always runs.
+pub fn simulation_allowed(
+    settings: Res<PauseWhenUnfocused>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) -> bool {
+    !settings.enabled
+        || window_query
+            .get_single()
+            .map_or(true, |window| window.focused)
+}
+
+/// The slowest speed the simulation can be set to, as a multiple of the configured rate.
+pub const MIN_SIMULATION_SPEED: f64 = 0.25;
+/// The fastest speed the simulation can be set to, as a multiple of the configured rate.
+pub const MAX_SIMULATION_SPEED: f64 = 8.0;
+
+/// Debug controls for the simulation: pausing it, stepping it one tick at a time while paused,
+/// and speeding it up or slowing it down. Faster speeds are still limited by
+/// [`SimulationStepCap`].
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct SimulationControl {
+    pub paused: bool,
+    /// Ticks still to run while paused, see [`SimulationControl::step`].
+    pub pending_steps: u32,
+    /// A multiple of [`Config::simulation_rate`], between [`MIN_SIMULATION_SPEED`] and
+    /// [`MAX_SIMULATION_SPEED`].
+    pub speed: f64,
+}
+
+impl Default for SimulationControl {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            pending_steps: 0,
+            speed: 1.0,
+        }
+    }
+}
+
+impl SimulationControl {
+    /// Pause or resume the simulation. Steps that haven't run yet are dropped.
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.pending_steps = 0;
+    }
 
-    // Add all chunks in the rectangular region to active_chunks
-    for x in min_x..=max_x {
-        for y in min_y..=max_y {
-            map.active_chunks.insert(UVec2::new(x, y));
+    /// Run a single tick while paused. Does nothing while the simulation is running.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
         }
     }
 
-    // Update any dirty chunks in the active area
-    map.update_dirty_chunks();
+    /// Double the speed, up to [`MAX_SIMULATION_SPEED`].
+    pub fn speed_up(&mut self) {
+        self.speed = (self.speed * 2.0).min(MAX_SIMULATION_SPEED);
+    }
+
+    /// Halve the speed, down to [`MIN_SIMULATION_SPEED`].
+    pub fn slow_down(&mut self) {
+        self.speed = (self.speed / 2.0).max(MIN_SIMULATION_SPEED);
+    }
+
+    /// Whether the simulation should run a tick.
+    pub fn should_run(&self) -> bool {
+        !self.paused || self.pending_steps > 0
+    }
+}
+
+/// Run condition that holds the simulation while [`SimulationControl`] has it paused.
+pub fn simulation_unpaused(control: Res<SimulationControl>) -> bool {
+    control.should_run()
+}
+
+/// System that uses up one paused step after the simulation ran a tick.
+pub fn finish_simulation_step(mut control: ResMut<SimulationControl>) {
+    if control.pending_steps > 0 {
+        control.pending_steps -= 1;
+    }
+}
+
+/// System that applies the [`SimulationControl`] speed to the fixed timestep.
+pub fn apply_simulation_speed(
+    control: Res<SimulationControl>,
+    config: Res<Config>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+) {
+    if control.is_changed() || config.is_changed() {
+        fixed_time.set_timestep_hz(config.simulation_rate * control.speed);
+    }
+}
+
+/// Hotkeys for [`SimulationControl`]: P pauses, period steps a single tick while paused, and
+/// plus and minus change the speed.
+pub fn simulation_control_hotkeys(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut control: ResMut<SimulationControl>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyP) {
+        control.toggle_pause();
+        info!(
+            "Simulation {}",
+            if control.paused { "paused" } else { "resumed" }
+        );
+    }
+    if keyboard.just_pressed(KeyCode::Period) {
+        control.step();
+    }
+    if keyboard.any_just_pressed([KeyCode::Equal, KeyCode::NumpadAdd]) {
+        control.speed_up();
+        info!("Simulation speed: {}x", control.speed);
+    }
+    if keyboard.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        control.slow_down();
+        info!("Simulation speed: {}x", control.speed);
+    }
+}
+
+/// Limits how many fixed simulation steps may run in a single frame. After a slow frame Bevy
+/// would otherwise run enough catch-up steps to cover the whole delay, making the next frame
+/// slower still. Time beyond the cap is dropped, so the simulation runs slower instead.
+#[derive(Resource)]
+pub struct SimulationStepCap {
+    /// Values below 1 are treated as 1.
+    pub max_steps_per_frame: u32,
+}
+
+impl Default for SimulationStepCap {
+    fn default() -> Self {
+        Self {
+            max_steps_per_frame: 4,
+        }
+    }
+}
+
+/// System that discards accumulated fixed time beyond [`SimulationStepCap`] on the first fixed
+/// step of each frame, so at most the configured number of simulation steps run that frame.
+pub fn cap_simulation_steps(
+    cap: Res<SimulationStepCap>,
+    virtual_time: Res<Time<Virtual>>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut last_frame: Local<Option<Duration>>,
+) {
+    // Virtual time only advances between frames, so a new value means a new frame.
+    if *last_frame == Some(virtual_time.elapsed()) {
+        return;
+    }
+    *last_frame = Some(virtual_time.elapsed());
+
+    // This step has already been taken out of the overstep, so it counts towards the cap.
+    let budget = fixed_time.timestep() * (cap.max_steps_per_frame.max(1) - 1);
+    let pending = fixed_time.overstep();
+    if pending > budget {
+        let dropped = pending - budget;
+        debug!(
+            "Dropping {:?} of simulation time to stay within {} steps per frame",
+            dropped, cap.max_steps_per_frame
+        );
+        fixed_time.discard_overstep(dropped);
+    }
+}
+
+/// System that hands the [`ParticleRegistry`] to the map whenever either of them changes, e.g.
+/// when the particle definitions are reloaded or the map is replaced.
+pub fn apply_particle_registry(registry: Res<ParticleRegistry>, map: Option<ResMut<Map>>) {
+    let Some(mut map) = map else {
+        return;
+    };
+    if map.particles() != &*registry {
+        map.set_particles(registry.clone());
+    }
 }
 
 /// System that simulates active particles in chunks
-pub fn simulate_active_particles(mut map: ResMut<Map>) {
-    map.simulate_active_chunks();
+pub fn simulate_active_particles(mut map: ResMut<Map>, rules: Res<InteractionRules>) {
+    map.simulate_active_chunks(&rules);
+    map.equalize_liquids();
+}
+
+/// System that reacts adjacent particles in active chunks. Runs after movement.
+pub fn process_active_interactions(mut map: ResMut<Map>, rules: Res<InteractionRules>) {
+    map.process_interactions(&rules);
+    map.process_combustion();
+    map.process_erosion(&rules);
 }
@@ -1,24 +1,131 @@
+pub mod ambience;
+pub mod biome;
 pub mod camera;
 pub mod chunk;
+pub mod conservation;
+pub mod explosion;
 pub mod generator;
+pub mod heat;
+pub mod history;
+pub mod lighting;
 pub mod map;
+pub mod noise;
+pub mod persistence;
+pub mod scenario;
+pub mod settling;
+pub mod streaming;
+pub mod structure;
+pub mod time_of_day;
+use crate::config::Config;
+use crate::particle::definition::ParticleRegistry;
+use crate::particle::interaction::InteractionRules;
+use ambience::{
+    update_current_biome, update_nearby_fluid, AmbienceSettings, CurrentBiome, NearbyFluid,
+};
 use bevy::{
-    app::{App, FixedUpdate, Plugin, Startup, Update},
+    app::{App, FixedFirst, FixedUpdate, Plugin, Startup, Update},
+    ecs::schedule::IntoSystemConfigs,
     time::{Fixed, Time},
 };
-use generator::setup_map;
-use map::{simulate_active_particles, update_active_chunks, SIMULATION_RATE};
+use conservation::{
+    assert_particle_conservation, conservation_check_enabled, snapshot_particle_counts,
+    ConservationCheck,
+};
+use explosion::{explode_at_cursor, process_explosions, Explosion};
+use generator::{regenerate_map, setup_map, GeneratorConfig, MapRegenerated};
+use heat::diffuse_active_heat;
+use history::{clear_edit_history, EditHistory};
+use lighting::update_active_lighting;
+use map::{
+    apply_particle_registry, apply_simulation_speed, cap_simulation_steps, finish_simulation_step,
+    process_active_interactions, simulate_active_particles, simulation_allowed,
+    simulation_control_hotkeys, simulation_unpaused, update_active_chunks, PauseWhenUnfocused,
+    SimulationControl, SimulationStepCap,
+};
+use persistence::{auto_save_map, save_load_hotkeys, AutoSaveSettings, SaveSlot};
+use time_of_day::{advance_time_of_day, update_sky_color, DayPhaseChanged, TimeOfDay};
 
 pub use self::map::Map;
 
+/// Plugin that runs the particle simulation on the [`Map`] resource at a fixed timestep, without
+/// any rendering, input or windowing. Works with `MinimalPlugins`, which makes it suitable for
+/// headless tests and CI. The [`Map`] must be inserted by the user, and chunks are only simulated
+/// once they're activated, either around a [`Player`](crate::player::Player) or with
+/// [`Map::set_active_chunks`].
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Config>();
+        let simulation_rate = app.world().resource::<Config>().simulation_rate;
+        app.insert_resource(Time::<Fixed>::from_hz(simulation_rate))
+            .init_resource::<InteractionRules>()
+            .init_resource::<ParticleRegistry>()
+            .init_resource::<PauseWhenUnfocused>()
+            .init_resource::<SimulationStepCap>()
+            .init_resource::<SimulationControl>()
+            .init_resource::<ConservationCheck>()
+            .add_event::<Explosion>()
+            .add_systems(
+                Update,
+                (
+                    update_active_chunks,
+                    process_explosions,
+                    apply_simulation_speed,
+                    apply_particle_registry,
+                ),
+            )
+            .add_systems(FixedFirst, cap_simulation_steps)
+            .add_systems(
+                FixedUpdate,
+                (
+                    snapshot_particle_counts.run_if(conservation_check_enabled),
+                    simulate_active_particles,
+                    process_active_interactions,
+                    diffuse_active_heat,
+                    assert_particle_conservation.run_if(conservation_check_enabled),
+                    finish_simulation_step,
+                )
+                    .chain()
+                    .run_if(simulation_allowed)
+                    .run_if(simulation_unpaused),
+            );
+    }
+}
+
 /// Plugin that handles the map systems
 pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(Time::<Fixed>::from_hz(SIMULATION_RATE))
+        app.add_plugins(SimulationPlugin)
+            .init_resource::<AutoSaveSettings>()
+            .init_resource::<SaveSlot>()
+            .init_resource::<GeneratorConfig>()
+            .init_resource::<AmbienceSettings>()
+            .init_resource::<NearbyFluid>()
+            .init_resource::<CurrentBiome>()
+            .init_resource::<EditHistory>()
+            .init_resource::<TimeOfDay>()
+            .add_event::<MapRegenerated>()
+            .add_event::<DayPhaseChanged>()
             .add_systems(Startup, setup_map)
-            .add_systems(Update, update_active_chunks)
-            .add_systems(FixedUpdate, simulate_active_particles);
+            .add_systems(
+                Update,
+                (
+                    regenerate_map,
+                    clear_edit_history
+                        .after(regenerate_map)
+                        .after(save_load_hotkeys),
+                    auto_save_map,
+                    save_load_hotkeys,
+                    update_nearby_fluid,
+                    update_current_biome,
+                    explode_at_cursor.before(process_explosions),
+                    simulation_control_hotkeys.before(apply_simulation_speed),
+                    update_active_lighting,
+                    (advance_time_of_day, update_sky_color).chain(),
+                ),
+            );
     }
 }
@@ -0,0 +1,164 @@
+//! Coherent noise shared by the world generator.
+
+use std::fmt;
+use std::sync::Arc;
+
+/// A continuous 2D noise field. Nearby coordinates return similar values, so features built on
+/// it (surface height, caves, ore clusters) vary smoothly instead of per cell.
+///
+/// Implement this to plug a custom noise into [`Map::generate_with_noise`](super::Map::generate_with_noise).
+pub trait NoiseSource: Send + Sync {
+    /// Sample the field at `(x, y)`. Values are in `[-1, 1]`.
+    fn sample(&self, x: f32, y: f32) -> f32;
+}
+
+/// A [`NoiseSource`] that can be cloned along with the
+/// [`WorldGenConfig`](super::generator::WorldGenConfig) holding it. Two are only equal if they
+/// share the same source.
+#[derive(Clone)]
+pub struct SharedNoise(Arc<dyn NoiseSource>);
+
+impl SharedNoise {
+    pub fn new(noise: impl NoiseSource + 'static) -> Self {
+        Self(Arc::new(noise))
+    }
+}
+
+impl NoiseSource for SharedNoise {
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        self.0.sample(x, y)
+    }
+}
+
+impl PartialEq for SharedNoise {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for SharedNoise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedNoise(..)")
+    }
+}
+
+/// Seeded value noise: random values on an integer lattice, smoothly interpolated in between.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValueNoise {
+    seed: u64,
+}
+
+impl ValueNoise {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// The random value at an integer lattice point, in `[-1, 1]`.
+    fn lattice(&self, x: i32, y: i32) -> f32 {
+        let hash = mix(self.seed ^ mix(((x as u32 as u64) << 32) | y as u32 as u64));
+        // Use the top 24 bits so the value is exactly representable as an f32.
+        (hash >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+impl NoiseSource for ValueNoise {
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (smoothstep(x - x0), smoothstep(y - y0));
+        let (x0, y0) = (x0 as i32, y0 as i32);
+
+        let bottom = lerp(self.lattice(x0, y0), self.lattice(x0 + 1, y0), tx);
+        let top = lerp(self.lattice(x0, y0 + 1), self.lattice(x0 + 1, y0 + 1), tx);
+        lerp(bottom, top, ty)
+    }
+}
+
+/// Seeded Perlin (gradient) noise: random gradients on an integer lattice, blended in between.
+/// Smoother than [`ValueNoise`], with fewer grid-aligned artifacts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PerlinNoise {
+    seed: u64,
+}
+
+impl PerlinNoise {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// The contribution of the gradient at lattice point `(x, y)` to the offset `(dx, dy)`.
+    fn gradient(&self, x: i32, y: i32, dx: f32, dy: f32) -> f32 {
+        let hash = mix(self.seed ^ mix(((x as u32 as u64) << 32) | y as u32 as u64));
+        // Eight evenly spread directions, the diagonals scaled to unit length.
+        const DIAGONAL: f32 = std::f32::consts::FRAC_1_SQRT_2;
+        let (gx, gy) = match hash >> 61 {
+            0 => (1.0, 0.0),
+            1 => (-1.0, 0.0),
+            2 => (0.0, 1.0),
+            3 => (0.0, -1.0),
+            4 => (DIAGONAL, DIAGONAL),
+            5 => (-DIAGONAL, DIAGONAL),
+            6 => (DIAGONAL, -DIAGONAL),
+            _ => (-DIAGONAL, -DIAGONAL),
+        };
+        gx * dx + gy * dy
+    }
+}
+
+impl NoiseSource for PerlinNoise {
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (tx, ty) = (fade(dx), fade(dy));
+        let (x0, y0) = (x0 as i32, y0 as i32);
+
+        let bottom = lerp(
+            self.gradient(x0, y0, dx, dy),
+            self.gradient(x0 + 1, y0, dx - 1.0, dy),
+            tx,
+        );
+        let top = lerp(
+            self.gradient(x0, y0 + 1, dx, dy - 1.0),
+            self.gradient(x0 + 1, y0 + 1, dx - 1.0, dy - 1.0),
+            tx,
+        );
+        // Unit gradients peak at sqrt(1/2), so scale the result up to fill [-1, 1].
+        (lerp(bottom, top, ty) * std::f32::consts::SQRT_2).clamp(-1.0, 1.0)
+    }
+}
+
+/// Sums `octaves` layers of `noise`, each at twice the frequency and half the weight of the
+/// one before, so large features get progressively finer detail. The result stays in `[-1, 1]`.
+pub fn fractal(noise: &dyn NoiseSource, x: f32, y: f32, octaves: u32) -> f32 {
+    let (mut total, mut weight_sum) = (0.0, 0.0);
+    let (mut frequency, mut weight) = (1.0, 1.0);
+    for octave in 0..octaves.max(1) {
+        // Shift each octave so their lattices don't line up, which would leave visible seams.
+        let offset = octave as f32 * 19.19;
+        total += noise.sample(x * frequency + offset, y * frequency + offset) * weight;
+        weight_sum += weight;
+        frequency *= 2.0;
+        weight *= 0.5;
+    }
+    total / weight_sum
+}
+
+/// SplitMix64 finalizer. Spreads every input bit across the whole output.
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Perlin's quintic fade curve. Unlike smoothstep, its second derivative is also zero at the ends.
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
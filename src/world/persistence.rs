@@ -0,0 +1,424 @@
+//! Saving and loading the map to disk.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::particle::Particle;
+
+use super::biome::{BiomeLayout, BiomeRegion};
+use super::chunk::{Chunk, CHUNK_SIZE};
+use super::generator::{GeneratorConfig, MapRegenerated, WorldGenConfig, WorldSeed};
+use super::Map;
+
+/// Bumped whenever the on-disk layout changes so old saves are rejected instead of misread.
+const SAVE_VERSION: u32 = 12;
+
+/// Settings for periodically writing the map to disk.
+#[derive(Resource)]
+pub struct AutoSaveSettings {
+    pub enabled: bool,
+    /// How often the map is written while auto-save is enabled.
+    pub interval: Duration,
+    pub path: PathBuf,
+}
+
+impl Default for AutoSaveSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: Duration::from_secs(60),
+            path: PathBuf::from("saves/autosave.cvb"),
+        }
+    }
+}
+
+/// The file F6 saves the map to and F7 loads it from. If it exists on startup, the map is
+/// loaded from it instead of being generated.
+#[derive(Resource)]
+pub struct SaveSlot {
+    pub path: PathBuf,
+}
+
+impl Default for SaveSlot {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("saves/world.cvb"),
+        }
+    }
+}
+
+/// Everything in a save file except the chunk cells.
+///
+/// A save file is laid out as the `u32` version, the `u64` byte length of this header, the
+/// header itself, and then every chunk's cells and walls back to back. The header indexes the chunks by
+/// position, so any chunk can be read without reading the ones before it.
+#[derive(Serialize, Deserialize)]
+struct SaveHeader {
+    width: u32,
+    height: u32,
+    /// The generation seed, so lazily generated chunks still match after loading.
+    seed: Option<u64>,
+    /// The terrain shape the map was generated with, meaningless without a seed.
+    terrain: GeneratorConfig,
+    /// The simulation seed and tick, so the simulation carries on exactly as it would have.
+    simulation_seed: u64,
+    tick: u64,
+    biomes: Vec<BiomeRegion>,
+    surface_heights: Vec<u32>,
+    /// Chunks that were being simulated, sorted so identical maps produce identical files.
+    active_chunks: Vec<(u32, u32)>,
+    /// Chunks kept awake outside the player's range, with the ticks they had left.
+    awake_chunks: Vec<(u32, u32, u32)>,
+    chunks: Vec<ChunkEntry>,
+}
+
+/// Where a chunk's cells and walls are stored, relative to the end of the header.
+/// Both are flattened in the same x-major order as `Chunk::cells`. Chunks without walls store
+/// none at all.
+#[derive(Serialize, Deserialize)]
+struct ChunkEntry {
+    x: u32,
+    y: u32,
+    offset: u64,
+    len: u64,
+}
+
+impl Map {
+    /// Write the map to `path`. The file is replaced atomically, so a crash mid-save never
+    /// leaves a corrupt file behind.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut entries = Vec::new();
+        let mut data = Vec::new();
+        for chunk in self.chunks.iter().flatten() {
+            // Streamed maps are saved in full, so they load like any other map.
+            let unloaded = self.unloaded_chunk(chunk.position);
+            let chunk = unloaded.as_ref().unwrap_or(chunk);
+            let cells: Vec<Option<Particle>> = chunk.cells().iter().flatten().copied().collect();
+            let walls: Vec<Option<Particle>> = match chunk.background_cells() {
+                walls if walls.iter().flatten().any(Option::is_some) => {
+                    walls.iter().flatten().copied().collect()
+                }
+                _ => Vec::new(),
+            };
+            let bytes = bincode::serialize(&(cells, walls)).map_err(to_io_error)?;
+            entries.push(ChunkEntry {
+                x: chunk.position.x,
+                y: chunk.position.y,
+                offset: data.len() as u64,
+                len: bytes.len() as u64,
+            });
+            data.extend(bytes);
+        }
+
+        let mut active_chunks: Vec<(u32, u32)> = self
+            .active_chunks
+            .iter()
+            .map(|pos| (pos.x, pos.y))
+            .collect();
+        active_chunks.sort_unstable();
+        let mut awake_chunks: Vec<(u32, u32, u32)> = self
+            .awake_chunks
+            .iter()
+            .map(|(pos, &ticks)| (pos.x, pos.y, ticks))
+            .collect();
+        awake_chunks.sort_unstable();
+
+        let header = SaveHeader {
+            width: self.width,
+            height: self.height,
+            seed: self.seed(),
+            terrain: self
+                .gen_config()
+                .map_or_else(GeneratorConfig::default, |config| config.terrain),
+            simulation_seed: self.simulation_seed(),
+            tick: self.simulation_tick(),
+            biomes: self.biomes.regions().to_vec(),
+            surface_heights: self.surface_profile().to_vec(),
+            active_chunks,
+            awake_chunks,
+            chunks: entries,
+        };
+        let header = bincode::serialize(&header).map_err(to_io_error)?;
+
+        write_atomic(path, |writer| {
+            bincode::serialize_into(&mut *writer, &SAVE_VERSION).map_err(to_io_error)?;
+            bincode::serialize_into(&mut *writer, &(header.len() as u64)).map_err(to_io_error)?;
+            writer.write_all(&header)?;
+            writer.write_all(&data)
+        })
+    }
+
+    /// Read a map previously written with [`Map::save`].
+    pub fn from_file(path: &Path) -> io::Result<Map> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let header = read_header(&mut reader)?;
+        let data_start = reader.stream_position()?;
+
+        let mut map = Map::empty(header.width, header.height);
+        map.biomes = BiomeLayout::from_regions(header.biomes)
+            .ok_or_else(|| invalid_data("Malformed biome layout in save file"))?;
+        if !map.set_surface_profile(header.surface_heights) {
+            return Err(invalid_data("Malformed surface profile in save file"));
+        }
+        if let Some(seed) = header.seed {
+            let config = WorldGenConfig::with_terrain(
+                header.width / CHUNK_SIZE,
+                header.height / CHUNK_SIZE,
+                seed,
+                header.terrain,
+            );
+            map.set_gen_config(config);
+        }
+        map.set_simulation_seed(header.simulation_seed);
+        map.set_simulation_tick(header.tick);
+
+        for entry in &header.chunks {
+            let position = UVec2::new(entry.x, entry.y);
+            if position.x >= map.width / CHUNK_SIZE || position.y >= map.height / CHUNK_SIZE {
+                return Err(invalid_data(format!(
+                    "Malformed chunk at {} in save file",
+                    position
+                )));
+            }
+            seek_to_chunk(&mut reader, data_start, entry)?;
+            let cells = read_chunk_cells(&mut reader, entry)?;
+            load_chunk(&mut map.chunks[entry.x as usize][entry.y as usize], cells);
+        }
+
+        let chunks_size = UVec2::new(map.width / CHUNK_SIZE, map.height / CHUNK_SIZE);
+        let in_map = |pos: &UVec2| pos.cmplt(chunks_size).all();
+        map.active_chunks = header
+            .active_chunks
+            .iter()
+            .map(|&(x, y)| UVec2::new(x, y))
+            .filter(in_map)
+            .collect();
+        map.awake_chunks = header
+            .awake_chunks
+            .iter()
+            .map(|&(x, y, ticks)| (UVec2::new(x, y), ticks))
+            .filter(|(pos, _)| in_map(pos))
+            .collect();
+
+        Ok(map)
+    }
+
+    /// Read only the chunks in the chunk range `[min, max)` of a map written with [`Map::save`],
+    /// without reading the rest of the file. The result is a standalone map of just that range,
+    /// with the `min` chunk at the origin, like [`Map::clone_region`].
+    pub fn load_region(path: &Path, min: UVec2, max: UVec2) -> io::Result<Map> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let header = read_header(&mut reader)?;
+        let data_start = reader.stream_position()?;
+
+        let max = max.min(UVec2::new(
+            header.width / CHUNK_SIZE,
+            header.height / CHUNK_SIZE,
+        ));
+        if min.x >= max.x || min.y >= max.y {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Chunk range {}..{} is empty or outside the map", min, max),
+            ));
+        }
+
+        let size = max - min;
+        let mut map = Map::empty(size.x * CHUNK_SIZE, size.y * CHUNK_SIZE);
+        let full_biomes = BiomeLayout::from_regions(header.biomes)
+            .ok_or_else(|| invalid_data("Malformed biome layout in save file"))?;
+        map.biomes = full_biomes.slice(min.x * CHUNK_SIZE, max.x * CHUNK_SIZE);
+        if !header.surface_heights.is_empty() {
+            let columns = (min.x * CHUNK_SIZE) as usize..(max.x * CHUNK_SIZE) as usize;
+            let heights = header
+                .surface_heights
+                .get(columns)
+                .ok_or_else(|| invalid_data("Malformed surface profile in save file"))?;
+            map.set_surface_profile(heights.to_vec());
+        }
+
+        for entry in &header.chunks {
+            let position = UVec2::new(entry.x, entry.y);
+            if position.cmplt(min).any() || position.cmpge(max).any() {
+                continue;
+            }
+
+            seek_to_chunk(&mut reader, data_start, entry)?;
+            let cells = read_chunk_cells(&mut reader, entry)?;
+            let local = position - min;
+            load_chunk(&mut map.chunks[local.x as usize][local.y as usize], cells);
+        }
+
+        Ok(map)
+    }
+}
+
+/// Read and validate the version and header at the start of a save file, leaving `reader` at
+/// the start of the chunk data.
+fn read_header(reader: &mut impl Read) -> io::Result<SaveHeader> {
+    let version: u32 = bincode::deserialize_from(&mut *reader).map_err(to_io_error)?;
+    if version != SAVE_VERSION {
+        return Err(invalid_data(format!(
+            "Unsupported save version {} (expected {})",
+            version, SAVE_VERSION
+        )));
+    }
+
+    let header_len: u64 = bincode::deserialize_from(&mut *reader).map_err(to_io_error)?;
+    let header: SaveHeader =
+        bincode::deserialize_from(reader.take(header_len)).map_err(to_io_error)?;
+    Ok(header)
+}
+
+/// The cells and walls of a saved chunk, see [`ChunkEntry`].
+type SavedChunk = (Vec<Option<Particle>>, Vec<Option<Particle>>);
+
+/// Move `reader` to the cells of the chunk described by `entry`, where the chunk data starts at
+/// `data_start`. Seeking relative to the current position keeps the buffered data, so reading
+/// chunks in the order they were saved doesn't read anything twice.
+fn seek_to_chunk(
+    reader: &mut BufReader<File>,
+    data_start: u64,
+    entry: &ChunkEntry,
+) -> io::Result<()> {
+    let position = reader.stream_position()?;
+    reader.seek_relative((data_start + entry.offset) as i64 - position as i64)
+}
+
+/// Read the cells and walls of the chunk described by `entry`, which must start at the reader's
+/// position.
+fn read_chunk_cells(reader: &mut impl Read, entry: &ChunkEntry) -> io::Result<SavedChunk> {
+    let (cells, walls): SavedChunk =
+        bincode::deserialize_from(reader.take(entry.len)).map_err(to_io_error)?;
+    let cell_count = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+    if cells.len() != cell_count || !(walls.is_empty() || walls.len() == cell_count) {
+        return Err(invalid_data(format!(
+            "Malformed chunk at {} in save file",
+            UVec2::new(entry.x, entry.y)
+        )));
+    }
+    Ok((cells, walls))
+}
+
+/// Fill `chunk` with its saved cells and walls.
+fn load_chunk(chunk: &mut Chunk, (cells, walls): SavedChunk) {
+    for (cell, particle) in chunk.cells_mut().iter_mut().flatten().zip(cells) {
+        *cell = particle;
+    }
+    if !walls.is_empty() {
+        for (cell, particle) in chunk.background_cells_mut().iter_mut().flatten().zip(walls) {
+            *cell = particle;
+        }
+    }
+
+    // Re-evaluate whether the loaded chunk needs simulating.
+    chunk.mark_dirty();
+    chunk.trigger_refresh();
+}
+
+/// Write a file by streaming into a sibling temp file and renaming it over `path` once
+/// everything succeeded. If `write` fails, the temp file is removed and `path` is untouched.
+pub fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = File::create(&temp_path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()?;
+        // Make sure the bytes are on disk before the rename makes them visible.
+        writer.get_ref().sync_all()
+    });
+
+    match result {
+        Ok(()) => fs::rename(&temp_path, path),
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+fn to_io_error(error: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// System that saves the map every `AutoSaveSettings::interval` while auto-save is enabled.
+pub fn auto_save_map(
+    time: Res<Time>,
+    settings: Res<AutoSaveSettings>,
+    map: Res<Map>,
+    mut since_last_save: Local<Duration>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    *since_last_save += time.delta();
+    if *since_last_save < settings.interval {
+        return;
+    }
+    *since_last_save = Duration::ZERO;
+
+    let start = std::time::Instant::now();
+    match map.save(&settings.path) {
+        Ok(()) => info!(
+            "Auto-saved map to {} in {:?}",
+            settings.path.display(),
+            start.elapsed()
+        ),
+        Err(e) => warn!("Auto-save to {} failed: {}", settings.path.display(), e),
+    }
+}
+
+/// Hotkeys to save the map to the [`SaveSlot`] with F6 and load it back with F7.
+pub fn save_load_hotkeys(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    slot: Res<SaveSlot>,
+    mut map: ResMut<Map>,
+    seed: Option<ResMut<WorldSeed>>,
+    mut regenerated: EventWriter<MapRegenerated>,
+) {
+    if keyboard.just_pressed(KeyCode::F6) {
+        let start = std::time::Instant::now();
+        match map.save(&slot.path) {
+            Ok(()) => info!(
+                "Saved map to {} in {:?}",
+                slot.path.display(),
+                start.elapsed()
+            ),
+            Err(e) => warn!("Saving to {} failed: {}", slot.path.display(), e),
+        }
+    }
+
+    if keyboard.just_pressed(KeyCode::F7) {
+        match Map::from_file(&slot.path) {
+            Ok(loaded) => {
+                info!("Loaded map from {}", slot.path.display());
+                if let (Some(mut seed), Some(loaded_seed)) = (seed, loaded.seed()) {
+                    seed.0 = loaded_seed;
+                }
+                // Replace the map in place so every system sees the loaded one this frame.
+                *map = loaded;
+                regenerated.send(MapRegenerated);
+            }
+            Err(e) => warn!("Loading {} failed: {}", slot.path.display(), e),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Scenarios: exact starting layouts of cells written as text, for reproducible simulation tests
+//! and bug reports.
+//!
+//! A scenario is a grid with one symbol per cell, see [`particle_symbol`], and one row per line
+//! with the top row first. `.` is air. Lines are trimmed, and blank lines and lines starting with
+//! `//` are skipped, so scenarios can be indented and annotated:
+//!
+//! ```text
+//! // Water poured onto a sand pile
+//! ..www..
+//! .......
+//! ..sss..
+//! #######
+//! ```
+//!
+//! [`Map::from_scenario`] loads one into an empty map, and [`Map::to_scenario`] writes the map
+//! back out the same way, so a test can run some ticks and compare the result to the layout it
+//! expects. The map around the grid is bedrock, so nothing can wander off where the comparison
+//! wouldn't see it.
+
+use bevy::prelude::*;
+
+use crate::particle::{Common, Fire, Gas, Gem, Liquid, Ore, Particle, Powder, Solid, Special};
+
+use super::chunk::CHUNK_SIZE;
+use super::Map;
+
+/// The symbol of an empty cell.
+pub const AIR_SYMBOL: char = '.';
+
+/// The symbol a particle is written as in a scenario. Every kind of particle has its own.
+pub fn particle_symbol(particle: Particle) -> char {
+    match particle {
+        Particle::Common(Common::Grass) => ',',
+        Particle::Common(Common::Dirt) => 'd',
+        Particle::Common(Common::Clay) => 'c',
+        Particle::Common(Common::Stone) => '#',
+        Particle::Common(Common::Bedrock) => '@',
+        Particle::Special(Special::Ore(Ore::Gold)) => '$',
+        Particle::Special(Special::Ore(Ore::Iron)) => 'i',
+        Particle::Special(Special::Ore(Ore::Copper)) => 'p',
+        Particle::Special(Special::Ore(Ore::Coal)) => 'k',
+        Particle::Special(Special::Ore(Ore::Diamond)) => '*',
+        Particle::Special(Special::Gem(Gem::Ruby)) => 'r',
+        Particle::Liquid(Liquid::Water(_)) => 'w',
+        Particle::Liquid(Liquid::Lava(_)) => 'l',
+        Particle::Liquid(Liquid::Acid(_)) => 'a',
+        Particle::Liquid(Liquid::Oil(_)) => 'o',
+        Particle::Solid(Solid::Obsidian) => 'O',
+        Particle::Solid(Solid::Wood) => 'W',
+        Particle::Solid(Solid::Torch) => 't',
+        Particle::Fire(Fire::Flame(_)) => 'f',
+        Particle::Fire(Fire::Ember(_)) => 'e',
+        Particle::Powder(Powder::Sand) => 's',
+        Particle::Powder(Powder::Gravel) => 'g',
+        Particle::Powder(Powder::Ash) => 'h',
+        Particle::Gas(Gas::Steam(_)) => 'S',
+        Particle::Gas(Gas::Smoke(_)) => 'm',
+        Particle::Gas(Gas::ToxicFumes(_)) => 'x',
+    }
+}
+
+/// The cell written as `symbol`: `Some(None)` for air, or `None` if no particle uses the symbol.
+/// Stateful particles start out in their default state.
+pub fn symbol_cell(symbol: char) -> Option<Option<Particle>> {
+    if symbol == AIR_SYMBOL {
+        return Some(None);
+    }
+    Particle::all_variants()
+        .into_iter()
+        .find(|&particle| particle_symbol(particle) == symbol)
+        .map(Some)
+}
+
+impl Map {
+    /// Loads a [scenario](self) into an empty map. The grid's bottom left cell ends up at the
+    /// origin, and the map is rounded up to whole chunks with bedrock. No chunk is active yet.
+    pub fn from_scenario(text: &str) -> Result<Map, String> {
+        let rows: Vec<(usize, &str)> = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with("//"))
+            .collect();
+        let Some(&(_, first)) = rows.first() else {
+            return Err("the scenario has no rows".to_string());
+        };
+        let width = first.chars().count() as u32;
+        let height = rows.len() as u32;
+
+        let mut map = Map::empty(
+            width.div_ceil(CHUNK_SIZE) * CHUNK_SIZE,
+            height.div_ceil(CHUNK_SIZE) * CHUNK_SIZE,
+        );
+        // Wall the grid in, so its particles stay where the scenario can see them.
+        let bedrock = Some(Particle::Common(Common::Bedrock));
+        for x in 0..map.width {
+            for y in 0..map.height {
+                if x >= width || y >= height {
+                    map.set_particle_at(UVec2::new(x, y), bedrock);
+                }
+            }
+        }
+        for (row, &(line_number, line)) in rows.iter().enumerate() {
+            if line.chars().count() as u32 != width {
+                return Err(format!(
+                    "line {} is {} cells wide, but the scenario is {}",
+                    line_number,
+                    line.chars().count(),
+                    width
+                ));
+            }
+            let y = height - 1 - row as u32;
+            for (x, symbol) in line.chars().enumerate() {
+                let cell = symbol_cell(symbol).ok_or_else(|| {
+                    format!("unknown symbol '{}' on line {}", symbol, line_number)
+                })?;
+                map.set_particle_at(UVec2::new(x as u32, y), cell);
+            }
+        }
+        Ok(map)
+    }
+
+    /// Writes the `width` by `height` cells at the bottom left of the map as a [scenario](self),
+    /// top row first, with rows separated by newlines.
+    pub fn to_scenario(&self, width: u32, height: u32) -> String {
+        let rows: Vec<String> = (0..height.min(self.height))
+            .rev()
+            .map(|y| {
+                (0..width.min(self.width))
+                    .map(|x| {
+                        self.get_particle_at(UVec2::new(x, y))
+                            .map_or(AIR_SYMBOL, particle_symbol)
+                    })
+                    .collect()
+            })
+            .collect();
+        rows.join("\n")
+    }
+}
@@ -0,0 +1,135 @@
+//! Letting chunks whose particles have come to rest stop moving them.
+//!
+//! A lake that has leveled out is still a chunk full of liquid, so it keeps `should_simulate`
+//! set, and every tick each of its cells would be simulated only to stay where it is. Chunks
+//! whose cells haven't changed for [`SETTLE_TICKS`] ticks in a row are settled instead: their
+//! particles aren't moved, sunk by density or leveled by pressure until something changes in or
+//! next to them. Interactions, erosion, combustion and heat still reach settled chunks, and any
+//! cell they change wakes the chunk back up. Chunks holding fire or gas never settle, since those
+//! burn out, condense or fade without their cells changing.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::chunk::CHUNK_SIZE;
+use super::Map;
+use crate::utils;
+
+/// How many ticks in a row a chunk's cells have to stay unchanged before it settles.
+pub const SETTLE_TICKS: u32 = 30;
+
+/// Bookkeeping for [`Map`] chunks that may settle, see the [module docs](self).
+#[derive(Default)]
+pub(crate) struct ChunkSettling {
+    /// The chunks that could simulate, keyed by position.
+    chunks: HashMap<UVec2, SettleState>,
+}
+
+struct SettleState {
+    /// The chunk's version when it last changed.
+    version: u64,
+    /// How many ticks in a row the chunk has stayed at that version.
+    still_ticks: u32,
+}
+
+impl Map {
+    /// Whether the chunk at `chunk_pos` has settled, so its particles aren't moved until
+    /// something near them changes.
+    pub fn is_chunk_settled(&self, chunk_pos: UVec2) -> bool {
+        self.settling
+            .chunks
+            .get(&chunk_pos)
+            .is_some_and(|state| state.still_ticks >= SETTLE_TICKS)
+    }
+
+    /// The number of active chunks that would simulate, but have settled.
+    pub fn settled_chunk_count(&self) -> usize {
+        self.active_chunks
+            .iter()
+            .filter(|pos| self.is_chunk_settled(**pos))
+            .count()
+    }
+
+    /// Whether the particles of the chunk at `chunk_pos` are moved this tick: it has something
+    /// to simulate and hasn't settled.
+    pub(crate) fn is_chunk_moving(&self, chunk_pos: UVec2) -> bool {
+        self.get_chunk_at(&chunk_pos).should_simulate && !self.is_chunk_settled(chunk_pos)
+    }
+
+    /// Wakes the chunk holding `position`, along with the chunks next to it when the cell is on
+    /// a chunk's edge, since particles there can now move into or out of it.
+    pub(crate) fn wake_settled_around(&mut self, position: UVec2) {
+        if self.settling.chunks.is_empty() {
+            return;
+        }
+        let local = utils::coords::world_to_chunk_local(position);
+        let on_edge =
+            local.x == 0 || local.y == 0 || local.x == CHUNK_SIZE - 1 || local.y == CHUNK_SIZE - 1;
+        if !on_edge {
+            let chunk_pos = utils::coords::get_chunk_from_world_pos(position);
+            self.settling.chunks.remove(&chunk_pos);
+            return;
+        }
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let neighbor = position.as_ivec2() + IVec2::new(dx, dy);
+                if neighbor.cmplt(IVec2::ZERO).any() {
+                    continue;
+                }
+                let chunk_pos = utils::coords::get_chunk_from_world_pos(neighbor.as_uvec2());
+                self.settling.chunks.remove(&chunk_pos);
+            }
+        }
+    }
+
+    /// Counts another tick for every active chunk that could simulate, settling the ones that
+    /// stayed unchanged long enough. Chunks that changed wake their neighbors.
+    pub(crate) fn update_settled_chunks(&mut self) {
+        let mut settling = std::mem::take(&mut self.settling);
+        settling.chunks.retain(|pos, _| {
+            self.active_chunks.contains(pos) && self.get_chunk_at(pos).should_simulate
+        });
+
+        let mut changed = Vec::new();
+        for chunk_pos in &self.active_chunks {
+            let chunk = self.get_chunk_at(chunk_pos);
+            if !chunk.should_simulate {
+                continue;
+            }
+            match settling.chunks.get_mut(chunk_pos) {
+                Some(state) if state.version == chunk.version => {
+                    // Fire and gas change their state every tick without changing the cells.
+                    if !chunk.holds_aging_particles {
+                        state.still_ticks += 1;
+                    }
+                }
+                _ => {
+                    settling.chunks.insert(
+                        *chunk_pos,
+                        SettleState {
+                            version: chunk.version,
+                            still_ticks: 0,
+                        },
+                    );
+                    changed.push(*chunk_pos);
+                }
+            }
+        }
+
+        for chunk_pos in changed {
+            for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+                let neighbor = chunk_pos.as_ivec2() + offset;
+                if neighbor.cmplt(IVec2::ZERO).any() {
+                    continue;
+                }
+                if let Some(state) = settling.chunks.get_mut(&neighbor.as_uvec2()) {
+                    state.still_ticks = 0;
+                }
+            }
+        }
+
+        self.settling = settling;
+    }
+}
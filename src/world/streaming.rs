@@ -0,0 +1,329 @@
+//! Loading chunks around the player on demand instead of generating the whole map up front.
+//!
+//! A streamed map starts with every chunk unloaded. [`Map::stream_chunks`] generates the chunks
+//! around a center from the map's seed as they come into range, and evicts the least recently
+//! used ones once more than the budget are loaded. Untouched chunks are simply dropped, since
+//! regenerating them gives the same cells. Chunks that changed, hold heat or have worn cells after
+//! loading keep a serialized copy of their cells, temperatures and wear instead, so their changes
+//! survive eviction.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::particle::Particle;
+use crate::utils::coords::{get_chunk_from_world_pos, world_to_chunk_local};
+
+use super::biome::BiomeLayout;
+use super::chunk::{Chunk, CHUNK_SIZE};
+use super::generator::{default_surface_heights, GenerationPlan, WorldGenConfig};
+use super::Map;
+
+/// Bookkeeping for maps created with [`Map::generate_streamed`].
+pub(crate) struct ChunkStreaming {
+    /// The most chunks kept loaded at once. Chunks in the requested range are always loaded,
+    /// even if that exceeds the budget.
+    max_loaded: usize,
+    /// Every loaded chunk, see [`LoadedChunk`].
+    loaded: HashMap<UVec2, LoadedChunk>,
+    /// The serialized cells, walls, temperatures and wear of evicted chunks that changed, held
+    /// heat or had worn cells after they were loaded.
+    evicted: HashMap<UVec2, Vec<u8>>,
+    /// Advances on every [`Map::stream_chunks`] call, ordering chunks by when they were last used.
+    clock: u64,
+    /// The map's generation plan, kept so loading a chunk doesn't plan the whole map again.
+    plan: GenerationPlan,
+}
+
+struct LoadedChunk {
+    /// The [`ChunkStreaming::clock`] when the chunk was last in range.
+    last_used: u64,
+    /// The chunk's version right after loading. Any other version means it changed.
+    clean_version: u64,
+    /// Whether the chunk was restored from its evicted cells, so it differs from the generated
+    /// chunk even if it hasn't changed since.
+    restored: bool,
+}
+
+impl Map {
+    /// Like [`Map::generate`], but without generating any chunks yet. Chunks are generated by
+    /// [`Map::stream_chunks`] as they come into range, keeping at most `max_loaded_chunks`.
+    pub fn generate_streamed(width: u32, height: u32, seed: u64, max_loaded_chunks: usize) -> Self {
+        Self::generate_streamed_from_config(
+            &WorldGenConfig::new(width, height, seed),
+            max_loaded_chunks,
+        )
+    }
+
+    /// Like [`Map::generate_streamed`], but with every generation parameter taken from `config`.
+    pub fn generate_streamed_from_config(
+        config: &WorldGenConfig,
+        max_loaded_chunks: usize,
+    ) -> Self {
+        let mut map = Map::empty(config.map_width(), config.map_height());
+        map.biomes = BiomeLayout::generate(config.map_width(), config.seed, &config.noise());
+        map.set_surface_profile(default_surface_heights(config));
+        map.set_simulation_seed(config.seed);
+        map.set_particles(config.particles.clone());
+        map.set_gen_config(config.clone());
+
+        // Unloaded chunks hold no storage at all.
+        map.compact();
+        map.streaming = Some(ChunkStreaming {
+            max_loaded: max_loaded_chunks,
+            loaded: HashMap::new(),
+            evicted: HashMap::new(),
+            clock: 0,
+            plan: GenerationPlan::new(map.surface_profile(), &map.biomes, config),
+        });
+        map
+    }
+
+    /// Whether chunks of this map are loaded on demand, see [`Map::generate_streamed`].
+    pub fn is_streamed(&self) -> bool {
+        self.streaming.is_some()
+    }
+
+    /// Whether the chunk at `chunk_pos` holds its real cells. Always true for maps that aren't
+    /// streamed.
+    pub fn is_chunk_loaded(&self, chunk_pos: UVec2) -> bool {
+        self.streaming
+            .as_ref()
+            .is_none_or(|streaming| streaming.loaded.contains_key(&chunk_pos))
+    }
+
+    /// How many chunks currently hold their real cells.
+    pub fn loaded_chunk_count(&self) -> usize {
+        self.streaming
+            .as_ref()
+            .map_or(self.chunk_count(), |streaming| streaming.loaded.len())
+    }
+
+    /// Load every chunk within `range` chunks of `center_chunk`, then evict the least recently
+    /// used chunks outside that range until the map is back within its budget. Awake chunks are
+    /// never evicted. Does nothing for maps that aren't streamed.
+    pub fn stream_chunks(&mut self, center_chunk: UVec2, range: u32) {
+        let Some(mut streaming) = self.streaming.take() else {
+            return;
+        };
+        streaming.clock += 1;
+
+        let mut wanted: Vec<UVec2> = self
+            .compute_active_region(center_chunk, range)
+            .into_iter()
+            .collect();
+        // Sort so chunks are loaded in the same order every run.
+        wanted.sort_by_key(|pos| (pos.x, pos.y));
+
+        for &chunk_pos in &wanted {
+            self.load_streamed_chunk(&mut streaming, chunk_pos);
+        }
+
+        let excess = streaming.loaded.len().saturating_sub(streaming.max_loaded);
+        if excess > 0 {
+            let mut candidates: Vec<(u64, UVec2)> = streaming
+                .loaded
+                .iter()
+                .filter(|(pos, _)| !wanted.contains(pos) && !self.awake_chunks.contains_key(pos))
+                .map(|(pos, entry)| (entry.last_used, *pos))
+                .collect();
+            candidates.sort_by_key(|(last_used, pos)| (*last_used, pos.x, pos.y));
+
+            for (_, chunk_pos) in candidates.into_iter().take(excess) {
+                let entry = streaming.loaded.remove(&chunk_pos).unwrap();
+                let chunk = self.get_chunk_at(&chunk_pos);
+                // Heat and wear don't change the version, but regenerating would lose them all the
+                // same.
+                if entry.restored
+                    || chunk.version != entry.clean_version
+                    || chunk.temperatures().is_some()
+                    || chunk.wear_levels().is_some()
+                {
+                    streaming.evicted.insert(chunk_pos, serialize_chunk(chunk));
+                }
+
+                let mut unloaded = Chunk::new(chunk_pos);
+                unloaded.compact();
+                self.swap_chunk(chunk_pos, unloaded);
+                self.active_chunks.remove(&chunk_pos);
+            }
+        }
+
+        self.streaming = Some(streaming);
+    }
+
+    /// Load the chunk at `chunk_pos` if it isn't yet, e.g. before reading it far from the player.
+    /// Writes like [`Map::set_particle_at`] load their chunk on their own. It stays loaded until
+    /// [`Map::stream_chunks`] evicts it like any other chunk. Does nothing for maps that aren't
+    /// streamed.
+    pub fn load_chunk(&mut self, chunk_pos: UVec2) {
+        let Some(mut streaming) = self.streaming.take() else {
+            return;
+        };
+        self.load_streamed_chunk(&mut streaming, chunk_pos);
+        self.streaming = Some(streaming);
+    }
+
+    /// The chunk at `chunk_pos` with its real cells, whether it is loaded or not. Unloaded chunks
+    /// of streamed maps are restored or generated, without loading them.
+    pub fn full_chunk(&self, chunk_pos: UVec2) -> Cow<'_, Chunk> {
+        match self.unloaded_chunk(chunk_pos) {
+            Some(chunk) => Cow::Owned(chunk),
+            None => Cow::Borrowed(self.get_chunk_at(&chunk_pos)),
+        }
+    }
+
+    /// Calls `f` with the position, particle and wall of every cell in `rect`, clipped to the
+    /// map. Cells of unloaded chunks are read from [`Map::full_chunk`], so streamed maps read as
+    /// if every chunk was loaded. Cells are visited a chunk at a time.
+    pub fn for_each_cell_in(
+        &self,
+        rect: URect,
+        mut f: impl FnMut(UVec2, Option<Particle>, Option<Particle>),
+    ) {
+        let rect = rect.intersect(URect::new(0, 0, self.width, self.height));
+        if rect.is_empty() {
+            return;
+        }
+        let min_chunk = get_chunk_from_world_pos(rect.min);
+        let max_chunk = get_chunk_from_world_pos(rect.max - UVec2::ONE);
+        for chunk_x in min_chunk.x..=max_chunk.x {
+            for chunk_y in min_chunk.y..=max_chunk.y {
+                let chunk = self.full_chunk(UVec2::new(chunk_x, chunk_y));
+                let cells = URect::new(chunk.x_min(), chunk.y_min(), chunk.x_max(), chunk.y_max())
+                    .intersect(rect);
+                for x in cells.min.x..cells.max.x {
+                    for y in cells.min.y..cells.max.y {
+                        let position = UVec2::new(x, y);
+                        let local_pos = world_to_chunk_local(position);
+                        f(
+                            position,
+                            chunk.get_particle(local_pos),
+                            chunk.get_background(local_pos),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// The chunk at `chunk_pos` as it would be if it was loaded, or `None` if it already is.
+    /// Used to save streamed maps in full.
+    pub(crate) fn unloaded_chunk(&self, chunk_pos: UVec2) -> Option<Chunk> {
+        let streaming = self.streaming.as_ref()?;
+        if streaming.loaded.contains_key(&chunk_pos) {
+            return None;
+        }
+        match streaming.evicted.get(&chunk_pos) {
+            Some(bytes) => Some(restore_chunk(chunk_pos, bytes)),
+            None => self.generate_chunk(chunk_pos),
+        }
+    }
+
+    /// The generation plan chunks of this map are generated with. Streamed maps keep theirs, while
+    /// other maps plan it again on every call.
+    pub(crate) fn generation_plan(&self, config: &WorldGenConfig) -> Cow<'_, GenerationPlan> {
+        match &self.streaming {
+            Some(streaming) => Cow::Borrowed(&streaming.plan),
+            None => Cow::Owned(GenerationPlan::new(
+                self.surface_profile(),
+                &self.biomes,
+                config,
+            )),
+        }
+    }
+
+    /// Bytes held by the serialized cells, walls, temperatures and wear of evicted chunks.
+    pub(crate) fn evicted_memory_usage(&self) -> usize {
+        self.streaming.as_ref().map_or(0, |streaming| {
+            streaming.evicted.values().map(Vec::len).sum()
+        })
+    }
+
+    /// Load the chunk at `chunk_pos` from its evicted cells or the generator, or mark it as just
+    /// used if it is already loaded.
+    fn load_streamed_chunk(&mut self, streaming: &mut ChunkStreaming, chunk_pos: UVec2) {
+        if let Some(entry) = streaming.loaded.get_mut(&chunk_pos) {
+            entry.last_used = streaming.clock;
+            return;
+        }
+
+        let restored = streaming.evicted.remove(&chunk_pos);
+        let chunk = match &restored {
+            Some(bytes) => restore_chunk(chunk_pos, bytes),
+            // The streaming state is taken out of the map while loading, so pass its plan along.
+            None => self
+                .generate_planned_chunk(chunk_pos, &streaming.plan)
+                .unwrap_or_else(|| Chunk::new(chunk_pos)),
+        };
+        let clean_version = self.swap_chunk(chunk_pos, chunk);
+        streaming.loaded.insert(
+            chunk_pos,
+            LoadedChunk {
+                last_used: streaming.clock,
+                clean_version,
+                restored: restored.is_some(),
+            },
+        );
+    }
+
+    /// Replace the chunk at `chunk_pos`, returning the new chunk's version. Versions keep
+    /// increasing across swaps so the renderer notices the new cells.
+    fn swap_chunk(&mut self, chunk_pos: UVec2, mut chunk: Chunk) -> u64 {
+        let old = self.get_chunk_at(&chunk_pos);
+        chunk.version = old.version + 1;
+        chunk.light_version = old.light_version;
+        // Re-evaluate whether the chunk needs simulating.
+        chunk.mark_dirty();
+        chunk.trigger_refresh();
+        let version = chunk.version;
+        self.set_chunk_at(chunk_pos, chunk);
+        version
+    }
+}
+
+/// The cells, walls, temperatures and wear [`serialize_chunk`] keeps of an evicted chunk.
+type EvictedChunk = (
+    Vec<Option<Particle>>,
+    Vec<Option<Particle>>,
+    Option<Vec<f32>>,
+    Option<Vec<f32>>,
+);
+
+// Cells are flattened, since serde only implements arrays of up to 32 elements.
+fn serialize_chunk(chunk: &Chunk) -> Vec<u8> {
+    bincode::serialize(&(
+        chunk.cells().as_flattened(),
+        chunk.background_cells().as_flattened(),
+        chunk
+            .temperatures()
+            .map(|temperatures| temperatures.as_flattened()),
+        chunk.wear_levels().map(|wear| wear.as_flattened()),
+    ))
+    .expect("Chunk cells are always serializable")
+}
+
+fn restore_chunk(chunk_pos: UVec2, bytes: &[u8]) -> Chunk {
+    let mut chunk = Chunk::new(chunk_pos);
+    let (cells, walls, temperatures, wear): EvictedChunk =
+        bincode::deserialize(bytes).expect("Evicted chunk cells were serialized by this map");
+    chunk.cells_mut().as_flattened_mut().copy_from_slice(&cells);
+    chunk.set_temperatures(temperatures.map(|temperatures| restore_grid(&temperatures)));
+    chunk.set_wear_levels(wear.map(|wear| restore_grid(&wear)));
+    // Chunks without walls don't keep the storage.
+    if walls.iter().any(Option::is_some) {
+        chunk
+            .background_cells_mut()
+            .as_flattened_mut()
+            .copy_from_slice(&walls);
+    }
+    chunk
+}
+
+/// Unflattens the temperatures or wear of a chunk serialized by [`serialize_chunk`].
+fn restore_grid(values: &[f32]) -> Box<[[f32; CHUNK_SIZE as usize]; CHUNK_SIZE as usize]> {
+    let mut grid = Box::new([[0.0; CHUNK_SIZE as usize]; CHUNK_SIZE as usize]);
+    grid.as_flattened_mut().copy_from_slice(values);
+    grid
+}
@@ -0,0 +1,249 @@
+//! Prefabricated grids of particles that can be stamped into the map.
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::particle::{Common, Direction, Gem, LiquidState, Ore, Particle, Solid, Special};
+
+use super::persistence::write_atomic;
+use super::Map;
+
+/// A rectangular grid of particles, such as a dungeon room or an ore deposit, that can be
+/// placed into the map. Empty cells are `None`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Structure {
+    size: UVec2,
+    /// Cells stored x-major, like `Chunk::cells`.
+    cells: Vec<Option<Particle>>,
+}
+
+/// A clockwise rotation in quarter turns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+/// How a structure is oriented and written when placed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Placement {
+    pub rotation: Rotation,
+    /// Flip the structure left to right before rotating it.
+    pub mirror: bool,
+    /// Clear map cells under the structure's empty cells, e.g. to carve out a room. Otherwise
+    /// empty cells leave the map untouched.
+    pub carve: bool,
+}
+
+impl Structure {
+    /// Creates an empty structure of the given size.
+    pub fn new(size: UVec2) -> Self {
+        Self {
+            size,
+            cells: vec![None; size.x as usize * size.y as usize],
+        }
+    }
+
+    /// Parses a structure from rows of text, top row first. Every row must be as long as the
+    /// first, and each character is one cell:
+    ///
+    /// - `.` is empty
+    /// - `#` is stone, `O` obsidian and `W` wood
+    /// - `T` is a torch
+    /// - `R` is a ruby and `G` gold ore
+    ///
+    /// Returns `None` if the rows are ragged or use any other character.
+    pub fn from_template(rows: &[&str]) -> Option<Self> {
+        let width = rows.first()?.chars().count() as u32;
+        let mut structure = Self::new(UVec2::new(width, rows.len() as u32));
+        for (row, line) in rows.iter().enumerate() {
+            if line.chars().count() as u32 != width {
+                return None;
+            }
+            // Rows are written top down, but y points up.
+            let y = rows.len() as u32 - 1 - row as u32;
+            for (x, cell) in line.chars().enumerate() {
+                let particle = match cell {
+                    '.' => None,
+                    '#' => Some(Particle::Common(Common::Stone)),
+                    'O' => Some(Particle::Solid(Solid::Obsidian)),
+                    'W' => Some(Particle::Solid(Solid::Wood)),
+                    'T' => Some(Particle::Solid(Solid::Torch)),
+                    'R' => Some(Particle::Special(Special::Gem(Gem::Ruby))),
+                    'G' => Some(Particle::Special(Special::Ore(Ore::Gold))),
+                    _ => return None,
+                };
+                structure.set(UVec2::new(x as u32, y), particle);
+            }
+        }
+        Some(structure)
+    }
+
+    /// Copies the cells of `map` in the region `[min, max)` into a structure.
+    pub fn from_region(map: &Map, min: UVec2, max: UVec2) -> Self {
+        let max = max.min(UVec2::new(map.width, map.height));
+        let mut structure = Self::new(max.saturating_sub(min));
+        for x in 0..structure.size.x {
+            for y in 0..structure.size.y {
+                let local = UVec2::new(x, y);
+                structure.set(local, map.get_particle_at(min + local));
+            }
+        }
+        structure
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    /// Returns the particle at `position`, or `None` for empty or out of bounds cells.
+    pub fn get(&self, position: UVec2) -> Option<Particle> {
+        self.index(position).and_then(|i| self.cells[i])
+    }
+
+    /// Sets the particle at `position`. Out of bounds positions are ignored.
+    pub fn set(&mut self, position: UVec2, particle: Option<Particle>) {
+        if let Some(i) = self.index(position) {
+            self.cells[i] = particle;
+        }
+    }
+
+    fn index(&self, position: UVec2) -> Option<usize> {
+        position
+            .cmplt(self.size)
+            .all()
+            .then(|| position.x as usize * self.size.y as usize + position.y as usize)
+    }
+
+    /// Returns a copy of the structure flipped left to right. Liquids flowing left now flow right.
+    pub fn mirrored(&self) -> Self {
+        let mut mirrored = Self::new(self.size);
+        for x in 0..self.size.x {
+            for y in 0..self.size.y {
+                let particle = self.get(UVec2::new(x, y)).map(flip_direction);
+                mirrored.set(UVec2::new(self.size.x - 1 - x, y), particle);
+            }
+        }
+        mirrored
+    }
+
+    /// Returns a copy of the structure turned clockwise by `rotation`. Quarter turns swap the
+    /// width and height. Liquid directions turn along with the structure: a half turn flips
+    /// them, while a quarter turn points them up or down, which liquids can't flow, so they
+    /// come out still.
+    pub fn rotated(&self, rotation: Rotation) -> Self {
+        let (w, h) = (self.size.x, self.size.y);
+        let size = match rotation {
+            Rotation::None | Rotation::Clockwise180 => self.size,
+            Rotation::Clockwise90 | Rotation::Clockwise270 => UVec2::new(h, w),
+        };
+
+        let mut rotated = Self::new(size);
+        for x in 0..w {
+            for y in 0..h {
+                // y points up, so a clockwise turn takes the top edge to the right edge.
+                let (target, particle) = match rotation {
+                    Rotation::None => (UVec2::new(x, y), self.get(UVec2::new(x, y))),
+                    Rotation::Clockwise90 => (
+                        UVec2::new(y, w - 1 - x),
+                        self.get(UVec2::new(x, y)).map(stop_direction),
+                    ),
+                    Rotation::Clockwise180 => (
+                        UVec2::new(w - 1 - x, h - 1 - y),
+                        self.get(UVec2::new(x, y)).map(flip_direction),
+                    ),
+                    Rotation::Clockwise270 => (
+                        UVec2::new(h - 1 - y, x),
+                        self.get(UVec2::new(x, y)).map(stop_direction),
+                    ),
+                };
+                rotated.set(target, particle);
+            }
+        }
+        rotated
+    }
+
+    /// Returns the structure as it would be placed with `placement`.
+    pub fn oriented(&self, placement: &Placement) -> Self {
+        if placement.mirror {
+            self.mirrored().rotated(placement.rotation)
+        } else {
+            self.rotated(placement.rotation)
+        }
+    }
+
+    /// Writes the structure to `path`.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        write_atomic(path, |writer| {
+            bincode::serialize_into(writer, self)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    }
+
+    /// Reads a structure previously written with [`Structure::save`].
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let structure: Structure = bincode::deserialize_from(reader)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let expected = structure
+            .size
+            .x
+            .checked_mul(structure.size.y)
+            .map(|count| count as usize);
+        if expected != Some(structure.cells.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Structure cell count does not match its size",
+            ));
+        }
+        Ok(structure)
+    }
+}
+
+impl Map {
+    /// Places `structure` with its bottom-left corner at `position`, oriented by `placement`.
+    /// Cells that fall outside the map are clipped.
+    pub fn place_structure(
+        &mut self,
+        structure: &Structure,
+        position: UVec2,
+        placement: &Placement,
+    ) {
+        let oriented = structure.oriented(placement);
+        for x in 0..oriented.size.x {
+            for y in 0..oriented.size.y {
+                let local = UVec2::new(x, y);
+                let particle = oriented.get(local);
+                let target = position + local;
+                if (particle.is_some() || placement.carve) && self.within_bounds(target) {
+                    self.set_particle_at(target, particle);
+                }
+            }
+        }
+    }
+}
+
+/// Reverses the direction of a liquid. Other particles have no direction.
+fn flip_direction(particle: Particle) -> Particle {
+    match particle {
+        Particle::Liquid(liquid) => Particle::Liquid(liquid.get_flipped_direction()),
+        particle => particle,
+    }
+}
+
+fn stop_direction(particle: Particle) -> Particle {
+    match particle {
+        Particle::Liquid(liquid) => Particle::Liquid(liquid.with_state(LiquidState {
+            direction: Direction::Still,
+            ..*liquid.get_state()
+        })),
+        particle => particle,
+    }
+}
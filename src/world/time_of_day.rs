@@ -0,0 +1,187 @@
+//! The day/night cycle.
+//!
+//! [`TimeOfDay`] advances in real time and wraps around once a day. The sky, i.e. the clear color,
+//! and the tint chunk materials shade lit cells with follow it through dawn, day, dusk and night.
+//! Gameplay that depends on the time of day, like spawning more creatures at night, can read
+//! [`TimeOfDay::phase`] or listen for [`DayPhaseChanged`].
+
+use bevy::prelude::*;
+
+/// How long a whole day lasts by default, in seconds.
+pub const DEFAULT_DAY_LENGTH: f32 = 600.0;
+
+/// The sky color at the height of the day.
+const DAY_SKY: Color = Color::srgb(0.45, 0.68, 0.92);
+/// The sky color at dawn and dusk.
+const TWILIGHT_SKY: Color = Color::srgb(0.85, 0.52, 0.38);
+/// The sky color at night.
+const NIGHT_SKY: Color = Color::srgb(0.03, 0.04, 0.1);
+
+/// How lit cells are tinted during the day, i.e. not at all.
+const DAY_TINT: Color = Color::WHITE;
+/// How lit cells are tinted at dawn and dusk.
+const TWILIGHT_TINT: Color = Color::srgb(1.0, 0.78, 0.62);
+/// How lit cells are tinted at night.
+const NIGHT_TINT: Color = Color::srgb(0.32, 0.36, 0.55);
+
+/// The tint only changes in this many steps a day, so chunk materials are re-uploaded every few
+/// seconds instead of every frame.
+const TINT_STEPS: f32 = 256.0;
+
+/// The parts of a day.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DayPhase {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+impl DayPhase {
+    /// Where each phase starts, as a fraction of the day from midnight. Night runs past midnight
+    /// until dawn.
+    const STARTS: [(f32, DayPhase); 4] = [
+        (0.2, DayPhase::Dawn),
+        (0.3, DayPhase::Day),
+        (0.7, DayPhase::Dusk),
+        (0.8, DayPhase::Night),
+    ];
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            DayPhase::Dawn => "Dawn",
+            DayPhase::Day => "Day",
+            DayPhase::Dusk => "Dusk",
+            DayPhase::Night => "Night",
+        }
+    }
+
+    /// The phase at `time`, a fraction of the day from midnight.
+    pub fn at(time: f32) -> Self {
+        Self::STARTS
+            .iter()
+            .rev()
+            .find(|(start, _)| time >= *start)
+            .map_or(DayPhase::Night, |(_, phase)| *phase)
+    }
+}
+
+/// Sent when the day moves into another [`DayPhase`].
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DayPhaseChanged {
+    pub phase: DayPhase,
+}
+
+/// The time of day, see the [module docs](self).
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct TimeOfDay {
+    /// How far into the day it is, from 0 at midnight to 1 at the next midnight.
+    pub time: f32,
+    /// How long a whole day lasts, in seconds.
+    pub day_length: f32,
+    /// Stop the clock, e.g. to look at the map in a fixed light.
+    pub paused: bool,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            // Start in the morning.
+            time: 0.3,
+            day_length: DEFAULT_DAY_LENGTH,
+            paused: false,
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// Moves the clock on by `secs` seconds, wrapping around at midnight. Returns the new phase
+    /// if that moved the day into another one.
+    pub fn advance(&mut self, secs: f32) -> Option<DayPhase> {
+        if self.paused || self.day_length <= 0.0 {
+            return None;
+        }
+        let before = self.phase();
+        self.time = (self.time + secs / self.day_length).rem_euclid(1.0);
+        let after = self.phase();
+        (after != before).then_some(after)
+    }
+
+    pub fn phase(&self) -> DayPhase {
+        DayPhase::at(self.time)
+    }
+
+    /// Whether it's night, when the surface is dark.
+    pub fn is_night(&self) -> bool {
+        self.phase() == DayPhase::Night
+    }
+
+    /// How much of the day's light there is, from 0 at night to 1 during the day. Ramps up
+    /// through dawn and down through dusk.
+    pub fn daylight(&self) -> f32 {
+        let ramp = |from: f32, to: f32| ((self.time - from) / (to - from)).clamp(0.0, 1.0);
+        ramp(0.2, 0.3) * (1.0 - ramp(0.7, 0.8))
+    }
+
+    /// How close it is to dawn or dusk, from 0 at full day or night to 1 halfway through either.
+    fn twilight(&self) -> f32 {
+        let peak = |center: f32| (1.0 - (self.time - center).abs() / 0.05).max(0.0);
+        peak(0.25).max(peak(0.75))
+    }
+
+    /// The color of the sky, which the window is cleared to.
+    pub fn sky_color(&self) -> Color {
+        blend(
+            DAY_SKY,
+            TWILIGHT_SKY,
+            NIGHT_SKY,
+            self.daylight(),
+            self.twilight(),
+        )
+    }
+
+    /// The tint lit cells are drawn with. Changes in coarse steps through the day, so it doesn't
+    /// force every chunk material to be re-uploaded each frame.
+    pub fn surface_tint(&self) -> Color {
+        let stepped = TimeOfDay {
+            time: (self.time * TINT_STEPS).floor() / TINT_STEPS,
+            ..*self
+        };
+        blend(
+            DAY_TINT,
+            TWILIGHT_TINT,
+            NIGHT_TINT,
+            stepped.daylight(),
+            stepped.twilight(),
+        )
+    }
+}
+
+/// Mixes between the night and day colors by `daylight`, then toward the twilight color by
+/// `twilight`.
+fn blend(day: Color, twilight_color: Color, night: Color, daylight: f32, twilight: f32) -> Color {
+    night.mix(&day, daylight).mix(&twilight_color, twilight)
+}
+
+/// System that advances the [`TimeOfDay`] in real time and sends [`DayPhaseChanged`] when the
+/// phase changes.
+pub fn advance_time_of_day(
+    time: Res<Time>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut phase_changed: EventWriter<DayPhaseChanged>,
+) {
+    if let Some(phase) = time_of_day.advance(time.delta_secs()) {
+        info!("{} begins", phase.display_name());
+        phase_changed.send(DayPhaseChanged { phase });
+    }
+}
+
+/// System that clears the window to the [sky color](TimeOfDay::sky_color).
+pub fn update_sky_color(time_of_day: Res<TimeOfDay>, clear_color: Option<ResMut<ClearColor>>) {
+    if let Some(mut clear_color) = clear_color {
+        let sky = time_of_day.sky_color();
+        if clear_color.0 != sky {
+            clear_color.0 = sky;
+        }
+    }
+}
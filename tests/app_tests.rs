@@ -0,0 +1,885 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::scratch_dir;
+    use std::time::Duration;
+
+    use bevy::input::keyboard::{Key, KeyboardInput};
+    use bevy::input::{ButtonState, InputPlugin};
+    use bevy::prelude::*;
+    use bevy::time::TimeUpdateStrategy;
+    use bevy::window::PrimaryWindow;
+    use cavernborn::config::Config;
+    use cavernborn::creatures::{
+        collides, lava_near, roll_spawn, spawn_position, Creature, CreatureKind, CreaturesPlugin,
+    };
+    use cavernborn::health::{spawn_point, Health, HealthPlugin, Knockback, MAX_HEALTH};
+    use cavernborn::particle::interaction::InteractionRules;
+    use cavernborn::particle::{Common, Direction, Liquid, Particle};
+    use cavernborn::player::{DebugMode, Player};
+    use cavernborn::projectile::{march_cells, Flight, Projectile, ProjectileKind, BOMB_RADIUS};
+    use cavernborn::swimming::{
+        measure_submersion, Breath, Submersion, SwimmingPlugin, MAX_BREATH,
+    };
+    use cavernborn::utils::coords::world_to_screen;
+    use cavernborn::world::ambience::NearbyFluid;
+    use cavernborn::world::conservation::ConservationCheck;
+    use cavernborn::world::generator::WorldSeed;
+    use cavernborn::world::map::{
+        PauseWhenUnfocused, SimulationControl, SimulationStepCap, MAX_SIMULATION_SPEED,
+        MIN_SIMULATION_SPEED,
+    };
+    use cavernborn::world::persistence::SaveSlot;
+    use cavernborn::world::{Map, MapPlugin, SimulationPlugin};
+
+    /// Builds an app running the map plugin without windowing or rendering, so it works without a GPU.
+    /// Every update advances time by exactly one simulation tick.
+    fn headless_app(map: Map) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, InputPlugin, MapPlugin))
+            .init_resource::<DebugMode>()
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                1.0 / 80.0,
+            )))
+            .insert_resource(map);
+        // Stands in for the player plugin, which needs assets and a window.
+        app.world_mut()
+            .spawn((Player, Transform::from_xyz(0.0, 0.0, 0.0)));
+        app
+    }
+
+    /// Test that the map plugin runs headlessly for several frames and advances the simulation
+    #[test]
+    fn test_headless_plugin_stack_simulates() {
+        // A 128x128 map is centered on the origin, so the player sits at world (64, 64).
+        let mut map = Map::empty(128, 128);
+        for x in 0..map.width {
+            map.set_particle_at(UVec2::new(x, 0), Some(Particle::Common(Common::Stone)));
+        }
+        let start = UVec2::new(64, 100);
+        map.set_particle_at(
+            start,
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+        );
+
+        let mut app = headless_app(map);
+        // Every tick panics if it doesn't conserve the particles.
+        app.insert_resource(ConservationCheck::new(true));
+        for _ in 0..40 {
+            app.update();
+        }
+
+        let map = app.world().resource::<Map>();
+        assert_eq!(map.width, 128, "The provided map should not be replaced");
+        assert!(!map.active_chunks.is_empty());
+
+        // The water fell all the way down onto the stone floor.
+        assert!(map.get_particle_at(start).is_none());
+        let water: Vec<UVec2> = (0..map.width)
+            .flat_map(|x| (0..map.height).map(move |y| UVec2::new(x, y)))
+            .filter(|&pos| matches!(map.get_particle_at(pos), Some(Particle::Liquid(_))))
+            .collect();
+        assert_eq!(water.len(), 1, "Water should be conserved");
+        assert_eq!(water[0].y, 1);
+        assert!((0..map.width).all(
+            |x| map.get_particle_at(UVec2::new(x, 0)) == Some(Particle::Common(Common::Stone))
+        ));
+
+        assert_eq!(app.world().resource::<NearbyFluid>().total(), 1);
+    }
+
+    /// Test that the simulation plugin runs with only the minimal plugins, and that stepping the
+    /// map directly gives the same result as running the app for as many ticks
+    #[test]
+    fn test_simulation_plugin_matches_map_step() {
+        fn scene() -> Map {
+            let mut map = Map::empty(64, 64);
+            for x in 0..map.width {
+                map.set_particle_at(UVec2::new(x, 0), Some(Particle::Common(Common::Stone)));
+            }
+            for x in 20..30 {
+                map.set_particle_at(
+                    UVec2::new(x, 1),
+                    Some(Particle::Liquid(Liquid::Lava(Direction::Left.into()))),
+                );
+                map.set_particle_at(
+                    UVec2::new(x, 20),
+                    Some(Particle::Liquid(Liquid::Water(Direction::Right.into()))),
+                );
+            }
+            let chunks = map.compute_active_region(UVec2::ZERO, 1);
+            map.set_active_chunks(chunks);
+            map
+        }
+
+        const TICKS: u32 = 40;
+        let config = Config::default();
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, SimulationPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                1.0 / config.simulation_rate,
+            )))
+            .insert_resource(scene());
+        // The first update only starts the clock.
+        for _ in 0..=TICKS {
+            app.update();
+        }
+
+        let mut stepped = scene();
+        stepped.step(&InteractionRules::default(), TICKS);
+
+        let simulated = app.world().resource::<Map>();
+        assert_eq!(simulated.simulation_tick(), TICKS as u64);
+        assert_eq!(stepped.simulation_tick(), TICKS as u64);
+        for x in 0..stepped.width {
+            for y in 0..stepped.height {
+                let position = UVec2::new(x, y);
+                assert_eq!(
+                    simulated.get_particle_at(position),
+                    stepped.get_particle_at(position),
+                    "Cell {} differs",
+                    position
+                );
+            }
+        }
+        // The water fell onto the lava and reacted with it.
+        assert!((20..30).all(|x| stepped.get_particle_at(UVec2::new(x, 20)).is_none()));
+        assert_ne!(
+            (20..30)
+                .filter(|&x| matches!(
+                    stepped.get_particle_at(UVec2::new(x, 1)),
+                    Some(Particle::Liquid(Liquid::Lava(_)))
+                ))
+                .count(),
+            10
+        );
+    }
+
+    /// Test that settings missing from the config file keep their defaults, invalid ones are
+    /// rejected, streamed maps keep every simulated chunk loaded, and the map plugin simulates at
+    /// the configured rate
+    #[test]
+    fn test_config_file() {
+        let config = Config::from_toml("map_width = 40\nsimulation_rate = 60.0\n").unwrap();
+        assert_eq!(
+            config,
+            Config {
+                map_width: 40,
+                simulation_rate: 60.0,
+                ..Config::default()
+            }
+        );
+        assert!(Config::from_toml("map_width = 0").is_err());
+        assert!(Config::from_toml("simulation_rate = -1.0").is_err());
+        assert!(
+            Config::from_toml("map_widht = 40").is_err(),
+            "Misspelled settings shouldn't be silently ignored"
+        );
+
+        let short_sighted = Config {
+            active_chunk_range: 6,
+            render_distance: 3,
+            ..Config::default()
+        };
+        assert_eq!(short_sighted.stream_chunk_range(), 7);
+
+        let missing = std::env::temp_dir().join("cavernborn_missing_config.toml");
+        assert_eq!(Config::load(&missing), Ok(Config::default()));
+
+        let mut app = App::new();
+        app.insert_resource(config)
+            .add_plugins((MinimalPlugins, InputPlugin, MapPlugin));
+        assert_eq!(
+            app.world().resource::<Time<Fixed>>().timestep(),
+            Duration::from_secs_f64(1.0 / 60.0)
+        );
+    }
+
+    /// Test that the started map is streamed, generating far away chunks only once the player
+    /// gets near them
+    #[test]
+    fn test_started_map_streams_chunks() {
+        let config = Config {
+            map_width: 20,
+            map_height: 4,
+            active_chunk_range: 2,
+            render_distance: 2,
+            ..Config::default()
+        };
+        let mut app = App::new();
+        app.insert_resource(config)
+            .insert_resource(WorldSeed(7))
+            .add_plugins((MinimalPlugins, InputPlugin, MapPlugin))
+            .init_resource::<DebugMode>();
+        // The player starts in the middle of the map, at chunk (10, 2).
+        let player = app
+            .world_mut()
+            .spawn((Player, Transform::from_xyz(0.0, 0.0, 0.0)))
+            .id();
+        app.update();
+
+        let far = UVec2::new(0, 0);
+        let map = app.world().resource::<Map>();
+        assert!(map.is_streamed());
+        assert!(map.is_chunk_loaded(UVec2::new(10, 2)));
+        assert!(
+            !map.is_chunk_loaded(far),
+            "Far away chunks shouldn't be generated yet"
+        );
+        assert!(map.get_chunk_at(&far).is_compacted());
+        assert!(map.loaded_chunk_count() < map.chunk_count());
+
+        let near_far = world_to_screen(Vec2::new(16.0, 16.0), map.width, map.height);
+        app.world_mut()
+            .entity_mut(player)
+            .insert(Transform::from_translation(near_far.extend(0.0)));
+        app.update();
+
+        let map = app.world().resource::<Map>();
+        assert!(map.is_chunk_loaded(far));
+        assert!(!map.get_chunk_at(&far).is_compacted());
+        assert!(map.loaded_chunk_count() <= config.max_loaded_chunks());
+    }
+
+    /// Test that the simulation pauses while the window is unfocused and resumes on focus
+    #[test]
+    fn test_simulation_pauses_when_unfocused() {
+        let mut map = Map::empty(128, 128);
+        let start = UVec2::new(64, 100);
+        map.set_particle_at(
+            start,
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+        );
+
+        let mut app = headless_app(map);
+        // A window entity is all the focus check looks at, so no real window is needed.
+        let window = app
+            .world_mut()
+            .spawn((
+                Window {
+                    focused: false,
+                    ..default()
+                },
+                PrimaryWindow,
+            ))
+            .id();
+
+        for _ in 0..10 {
+            app.update();
+        }
+        assert!(
+            app.world()
+                .resource::<Map>()
+                .get_particle_at(start)
+                .is_some(),
+            "Water should not move while unfocused"
+        );
+
+        app.world_mut().get_mut::<Window>(window).unwrap().focused = true;
+        for _ in 0..10 {
+            app.update();
+        }
+        assert!(app
+            .world()
+            .resource::<Map>()
+            .get_particle_at(start)
+            .is_none());
+
+        // The setting can be turned off to keep simulating in the background.
+        app.world_mut().get_mut::<Window>(window).unwrap().focused = false;
+        app.world_mut().resource_mut::<PauseWhenUnfocused>().enabled = false;
+        let hash = app.world().resource::<Map>().world_hash();
+        for _ in 0..10 {
+            app.update();
+        }
+        assert_ne!(app.world().resource::<Map>().world_hash(), hash);
+    }
+
+    /// Counts how many fixed simulation steps have run.
+    #[derive(Resource, Default)]
+    struct FixedSteps(u32);
+
+    fn count_fixed_steps(mut steps: ResMut<FixedSteps>) {
+        steps.0 += 1;
+    }
+
+    /// Test that a long frame runs no more simulation steps than the configured cap
+    #[test]
+    fn test_simulation_steps_per_frame_are_capped() {
+        let mut app = headless_app(Map::empty(128, 128));
+        app.init_resource::<FixedSteps>()
+            .add_systems(FixedUpdate, count_fixed_steps)
+            .insert_resource(SimulationStepCap {
+                max_steps_per_frame: 3,
+            });
+        // The first update only starts the clock.
+        app.update();
+
+        // A frame long enough for dozens of steps, as if the game had stalled.
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            200,
+        )));
+        for _ in 0..3 {
+            app.world_mut().resource_mut::<FixedSteps>().0 = 0;
+            app.update();
+            assert_eq!(app.world().resource::<FixedSteps>().0, 3);
+        }
+
+        // Normal frames are unaffected.
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            1.0 / 80.0,
+        )));
+        app.world_mut().resource_mut::<FixedSteps>().0 = 0;
+        for _ in 0..10 {
+            app.update();
+        }
+        assert_eq!(app.world().resource::<FixedSteps>().0, 10);
+    }
+
+    /// Test that the time of day cycles through its phases, announcing each one, and the sky and
+    /// surface tint follow it
+    #[test]
+    fn test_day_night_cycle() {
+        use cavernborn::world::time_of_day::{DayPhase, DayPhaseChanged, TimeOfDay};
+
+        let mut app = headless_app(Map::empty(64, 64));
+        app.insert_resource(ClearColor::default())
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .insert_resource(TimeOfDay {
+                time: 0.0,
+                day_length: 8.0,
+                paused: false,
+            });
+        // The first update only starts the clock.
+        app.update();
+        assert!(app.world().resource::<TimeOfDay>().is_night());
+
+        let mut cursor = app
+            .world()
+            .resource::<Events<DayPhaseChanged>>()
+            .get_cursor();
+        let mut phases = Vec::new();
+        let mut night_sky = None;
+        let mut day_sky = None;
+        // A little over a whole day.
+        for _ in 0..84 {
+            app.update();
+            let world = app.world();
+            let events = world.resource::<Events<DayPhaseChanged>>();
+            phases.extend(cursor.read(events).map(|event| event.phase));
+
+            let sky = world.resource::<ClearColor>().0;
+            match world.resource::<TimeOfDay>().phase() {
+                DayPhase::Night => night_sky = Some(sky),
+                DayPhase::Day => day_sky = Some(sky),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            phases,
+            [
+                DayPhase::Dawn,
+                DayPhase::Day,
+                DayPhase::Dusk,
+                DayPhase::Night
+            ]
+        );
+        let (night_sky, day_sky) = (night_sky.unwrap(), day_sky.unwrap());
+        assert!(night_sky.luminance() < day_sky.luminance());
+
+        // The surface is tinted dark at night and left alone at noon.
+        let at = |time: f32| TimeOfDay {
+            time,
+            ..TimeOfDay::default()
+        };
+        assert!((at(0.5).surface_tint().luminance() - 1.0).abs() < 1e-4);
+        assert!(at(0.0).surface_tint().luminance() < 0.5);
+        assert_ne!(at(0.25).surface_tint(), at(0.5).surface_tint());
+
+        // A paused clock stays put.
+        let mut paused = TimeOfDay {
+            paused: true,
+            ..at(0.5)
+        };
+        assert_eq!(paused.advance(1000.0), None);
+        assert_eq!(paused.time, 0.5);
+    }
+
+    /// Test that F6 saves the map to the save slot and F7 loads it back in place
+    #[test]
+    fn test_save_and_load_hotkeys() {
+        let dir = scratch_dir("hotkeys");
+        let path = dir.join("world.cvb");
+        let stone_pos = UVec2::new(10, 10);
+
+        let mut map = Map::empty(128, 128);
+        map.set_particle_at(stone_pos, Some(Particle::Common(Common::Stone)));
+        let mut app = headless_app(map);
+        app.insert_resource(SaveSlot { path: path.clone() });
+        app.update();
+
+        // Goes through the input plugin like a real key press would.
+        let press = |app: &mut App, key_code: KeyCode, logical_key: Key| {
+            for state in [ButtonState::Pressed, ButtonState::Released] {
+                app.world_mut().send_event(KeyboardInput {
+                    key_code,
+                    logical_key: logical_key.clone(),
+                    state,
+                    repeat: false,
+                    window: Entity::PLACEHOLDER,
+                });
+                app.update();
+            }
+        };
+
+        press(&mut app, KeyCode::F6, Key::F6);
+        assert!(path.exists(), "F6 should write the save slot");
+
+        app.world_mut()
+            .resource_mut::<Map>()
+            .set_particle_at(stone_pos, None);
+        press(&mut app, KeyCode::F7, Key::F7);
+        assert_eq!(
+            app.world().resource::<Map>().get_particle_at(stone_pos),
+            Some(Particle::Common(Common::Stone)),
+            "F7 should restore the saved map"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test that P pauses the simulation, period runs a single tick while paused, and plus and
+    /// minus change the fixed timestep within the speed limits
+    #[test]
+    fn test_pause_step_and_speed_hotkeys() {
+        let mut app = headless_app(Map::empty(128, 128));
+        app.update();
+
+        let press = |app: &mut App, key_code: KeyCode, logical_key: Key| {
+            for state in [ButtonState::Pressed, ButtonState::Released] {
+                app.world_mut().send_event(KeyboardInput {
+                    key_code,
+                    logical_key: logical_key.clone(),
+                    state,
+                    repeat: false,
+                    window: Entity::PLACEHOLDER,
+                });
+                app.update();
+            }
+        };
+        let tick = |app: &App| app.world().resource::<Map>().simulation_tick();
+        let timestep = |app: &App| app.world().resource::<Time<Fixed>>().timestep();
+
+        press(&mut app, KeyCode::KeyP, Key::Character("p".into()));
+        assert!(app.world().resource::<SimulationControl>().paused);
+        let paused_tick = tick(&app);
+        for _ in 0..5 {
+            app.update();
+        }
+        assert_eq!(tick(&app), paused_tick, "Nothing should run while paused");
+
+        press(&mut app, KeyCode::Period, Key::Character(".".into()));
+        for _ in 0..5 {
+            app.update();
+        }
+        assert_eq!(
+            tick(&app),
+            paused_tick + 1,
+            "A step should run exactly one tick"
+        );
+
+        let rate = Config::default().simulation_rate;
+        for _ in 0..2 {
+            press(&mut app, KeyCode::Equal, Key::Character("+".into()));
+        }
+        assert_eq!(timestep(&app), Duration::from_secs_f64(1.0 / (rate * 4.0)));
+        for _ in 0..10 {
+            press(&mut app, KeyCode::Equal, Key::Character("+".into()));
+        }
+        assert_eq!(
+            app.world().resource::<SimulationControl>().speed,
+            MAX_SIMULATION_SPEED
+        );
+        for _ in 0..10 {
+            press(&mut app, KeyCode::Minus, Key::Character("-".into()));
+        }
+        assert_eq!(
+            app.world().resource::<SimulationControl>().speed,
+            MIN_SIMULATION_SPEED
+        );
+        assert_eq!(
+            timestep(&app),
+            Duration::from_secs_f64(1.0 / (rate * MIN_SIMULATION_SPEED))
+        );
+        assert_eq!(tick(&app), paused_tick + 1);
+
+        press(&mut app, KeyCode::KeyP, Key::Character("p".into()));
+        for _ in 0..10 {
+            app.update();
+        }
+        assert!(tick(&app) > paused_tick + 1, "The simulation should resume");
+    }
+
+    /// Test that lava hurts and knocks back the player, that hits are spaced out by
+    /// invulnerability, and that a player who dies respawns above the surface with full health
+    #[test]
+    fn test_hazard_damage_knockback_and_respawn() {
+        let mut map = Map::empty(64, 64);
+        for x in 28..36 {
+            for y in 28..36 {
+                map.set_particle_at(
+                    UVec2::new(x, y),
+                    Some(Particle::Liquid(Liquid::Lava(Direction::Left.into()))),
+                );
+            }
+        }
+        assert!(map.set_surface_profile(vec![40; 64]));
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, HealthPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                0.1,
+            )))
+            .insert_resource(map);
+        // The middle of the map, right in the lava.
+        let player = app
+            .world_mut()
+            .spawn((
+                Player,
+                Health::default(),
+                Knockback::default(),
+                Transform::from_xyz(0.0, 0.0, 0.0),
+            ))
+            .id();
+        app.update();
+
+        let health = *app.world().get::<Health>(player).unwrap();
+        assert_eq!(health.current, MAX_HEALTH - 20.0);
+        assert!(
+            health.burning_secs > 0.0,
+            "Lava should set the player burning"
+        );
+        let knockback = *app.world().get::<Knockback>(player).unwrap();
+        assert!(
+            knockback.velocity.y > 0.0,
+            "The hit should knock the player out"
+        );
+
+        app.update();
+        assert_eq!(
+            app.world().get::<Health>(player).unwrap().current,
+            MAX_HEALTH - 20.0,
+            "The player should be invulnerable right after a hit"
+        );
+        for _ in 0..10 {
+            app.update();
+        }
+        let health = *app.world().get::<Health>(player).unwrap();
+        assert!(
+            health.current < MAX_HEALTH - 20.0,
+            "Burning should keep hurting"
+        );
+        assert!(app.world().get::<Transform>(player).unwrap().translation.y > 0.0);
+
+        // One more lava hit finishes the player off.
+        app.world_mut().entity_mut(player).insert((
+            Health {
+                current: 5.0,
+                ..default()
+            },
+            Transform::from_xyz(0.0, 0.0, 0.0),
+        ));
+        app.update();
+        let spawn = spawn_point(app.world().resource::<Map>());
+        let translation = app.world().get::<Transform>(player).unwrap().translation;
+        assert_eq!(translation.truncate(), spawn);
+        assert_eq!(
+            *app.world().get::<Health>(player).unwrap(),
+            Health::default()
+        );
+        assert!(spawn.y > 0.0, "The spawn point should be above the surface");
+    }
+
+    /// Test that a player under water floats up, runs out of breath and starts drowning, and gets
+    /// their breath back once out of the water
+    #[test]
+    fn test_swimming_buoyancy_and_breath() {
+        let mut map = Map::empty(64, 64);
+        for x in 0..64 {
+            for y in 0..48 {
+                map.set_particle_at(
+                    UVec2::new(x, y),
+                    Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+                );
+            }
+        }
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, HealthPlugin, SwimmingPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                0.25,
+            )))
+            .insert_resource(map);
+        // The middle of the map, well under the water.
+        let player = app
+            .world_mut()
+            .spawn((
+                Player,
+                Health::default(),
+                Knockback::default(),
+                Submersion::default(),
+                Breath::default(),
+                Transform::from_xyz(0.0, 0.0, 0.0),
+            ))
+            .id();
+        app.update();
+        app.update();
+
+        let submersion = *app.world().get::<Submersion>(player).unwrap();
+        assert_eq!(submersion.fraction, 1.0);
+        assert!(submersion.head_under);
+        assert!(submersion.speed_factor() < 1.0);
+        assert!(app.world().get::<Transform>(player).unwrap().translation.y > 0.0);
+        assert!(app.world().get::<Breath>(player).unwrap().current < MAX_BREATH);
+
+        // Hold the player under until their breath runs out.
+        for _ in 0..48 {
+            app.world_mut()
+                .get_mut::<Transform>(player)
+                .unwrap()
+                .translation
+                .y = 0.0;
+            app.update();
+        }
+        assert!(app.world().get::<Breath>(player).unwrap().is_empty());
+        assert!(app.world().get::<Health>(player).unwrap().current < MAX_HEALTH);
+
+        // Float up out of the water and breathe again.
+        for _ in 0..20 {
+            app.update();
+        }
+        let submersion = *app.world().get::<Submersion>(player).unwrap();
+        assert!(!submersion.head_under);
+        assert!(submersion.fraction < 1.0);
+        assert!(app.world().get::<Breath>(player).unwrap().current > 0.0);
+
+        // Other liquids buoy the player up too, but only water takes their breath.
+        let mut oil = Map::empty(32, 32);
+        for x in 0..8 {
+            for y in 0..8 {
+                oil.set_particle_at(
+                    UVec2::new(x, y),
+                    Some(Particle::Liquid(Liquid::Oil(Direction::Left.into()))),
+                );
+            }
+        }
+        let submersion = measure_submersion(&oil, Vec2::ZERO, Vec2::splat(4.0));
+        assert_eq!(submersion.fraction, 1.0);
+        assert!(!submersion.head_under);
+    }
+
+    /// Test that creatures spawn at their depths, move without passing through walls or near
+    /// lava, and die in acid or by drowning
+    #[test]
+    fn test_creatures_move_and_take_damage() {
+        let stone = Some(Particle::Common(Common::Stone));
+        let lava = Some(Particle::Liquid(Liquid::Lava(Direction::Left.into())));
+        // A closed box of stone with a lava pool in one corner.
+        let mut map = Map::empty(128, 128);
+        for x in 0..128 {
+            for y in 0..128 {
+                if !(4..124).contains(&x) || !(4..124).contains(&y) {
+                    map.set_particle_at(UVec2::new(x, y), stone);
+                } else if x < 20 && y < 10 {
+                    map.set_particle_at(UVec2::new(x, y), lava);
+                }
+            }
+        }
+
+        // Without a surface profile, depth counts down from the top of the map.
+        assert!(spawn_position(&map, CreatureKind::Bat, UVec2::new(60, 100)).is_some());
+        assert!(spawn_position(&map, CreatureKind::Bat, UVec2::new(60, 125)).is_none());
+        // Slimes live deeper, and only spawn on the ground away from lava.
+        assert!(spawn_position(&map, CreatureKind::Slime, UVec2::new(60, 4)).is_some());
+        assert!(spawn_position(&map, CreatureKind::Slime, UVec2::new(60, 30)).is_none());
+        assert!(spawn_position(&map, CreatureKind::Slime, UVec2::new(21, 4)).is_none());
+
+        // Spawns only depend on the map's seed and tick and how many were tried before.
+        map.set_active_chunks(
+            (0..4)
+                .flat_map(|x| (0..4).map(move |y| UVec2::new(x, y)))
+                .collect(),
+        );
+        map.set_simulation_seed(7);
+        let spawns: Vec<_> = (0..8).map(|round| roll_spawn(&map, round)).collect();
+        assert!(spawns.iter().any(Option::is_some));
+        assert_eq!(
+            spawns,
+            (0..8)
+                .map(|round| roll_spawn(&map, round))
+                .collect::<Vec<_>>()
+        );
+        map.set_simulation_seed(8);
+        assert_ne!(
+            spawns,
+            (0..8)
+                .map(|round| roll_spawn(&map, round))
+                .collect::<Vec<_>>()
+        );
+
+        for kind in [CreatureKind::Bat, CreatureKind::Slime] {
+            let mut creature = Creature::new(kind);
+            let mut center = Vec2::new(64.0, 64.0);
+            let mut rng = map.particle_rng(UVec2::ZERO);
+            let mut lowest = center.y;
+            for _ in 0..2000 {
+                center = creature.step(&map, center, 0.05, &mut rng);
+                let (min, max) = creature.bounds(center);
+                assert!(!collides(&map, min, max), "{:?} went into a wall", kind);
+                assert!(!lava_near(&map, min, max), "{:?} went near lava", kind);
+                lowest = lowest.min(center.y);
+            }
+            if kind == CreatureKind::Slime {
+                // It fell down and hopped along the floor.
+                assert!(lowest < 7.0);
+                assert_ne!(center.x, 64.0);
+            }
+        }
+
+        // Acid on the left, water on the right.
+        let mut map = Map::empty(64, 64);
+        for x in 0..64 {
+            for y in 0..64 {
+                let liquid = if x < 32 {
+                    Liquid::Acid(Direction::Left.into())
+                } else {
+                    Liquid::Water(Direction::Left.into())
+                };
+                map.set_particle_at(UVec2::new(x, y), Some(Particle::Liquid(liquid)));
+            }
+        }
+        map.set_active_chunks(
+            (0..2)
+                .flat_map(|x| (0..2).map(move |y| UVec2::new(x, y)))
+                .collect(),
+        );
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, CreaturesPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                0.25,
+            )))
+            .insert_resource(map);
+        let mut spawn = |kind: CreatureKind, center: Vec2| {
+            let screen = world_to_screen(center, 64, 64);
+            app.world_mut()
+                .spawn((
+                    Creature::new(kind),
+                    Health::new(kind.max_health()),
+                    Breath {
+                        current: 3.0,
+                        max: 3.0,
+                    },
+                    Transform::from_xyz(screen.x, screen.y, 0.0),
+                ))
+                .id()
+        };
+        let slime = spawn(CreatureKind::Slime, Vec2::new(16.0, 32.0));
+        let bat = spawn(CreatureKind::Bat, Vec2::new(48.0, 32.0));
+
+        app.update();
+        app.update();
+        let health = app.world().get::<Health>(slime).unwrap();
+        assert!(health.current < health.max, "Acid should hurt the slime");
+        assert!(app.world().get::<Breath>(bat).unwrap().current < 3.0);
+
+        for _ in 0..40 {
+            app.update();
+        }
+        assert!(app.world().get_entity(slime).is_err());
+        assert!(app.world().get_entity(bat).is_err(), "The bat should drown");
+    }
+
+    /// Test that thrown projectiles arc down, stop at the first particle in their path even when
+    /// moving several cells a step, and splash water or explode where they hit
+    #[test]
+    fn test_projectiles_hit_terrain() {
+        // Marching visits every cell on the way, stopping at the first blocking one.
+        let wall_at_3 = |cell: IVec2| cell.x == 3;
+        assert_eq!(
+            march_cells(Vec2::new(0.5, 0.5), Vec2::new(9.5, 0.5), wall_at_3),
+            Some((IVec2::new(3, 0), IVec2::new(2, 0)))
+        );
+        assert_eq!(
+            march_cells(Vec2::new(0.5, 0.5), Vec2::new(2.5, 0.5), wall_at_3),
+            None
+        );
+        let diagonal_wall = |cell: IVec2| cell.x + cell.y == 10;
+        let (hit, before) =
+            march_cells(Vec2::new(0.5, 0.5), Vec2::new(9.5, 9.5), diagonal_wall).unwrap();
+        assert_eq!(hit.x + hit.y, 10);
+        assert!(before.x + before.y < 10);
+
+        let stone = Some(Particle::Common(Common::Stone));
+        let mut map = Map::empty(64, 64);
+        for x in 0..64 {
+            for y in 0..8 {
+                map.set_particle_at(UVec2::new(x, y), stone);
+            }
+        }
+        // A wall one cell thick.
+        for y in 8..64 {
+            map.set_particle_at(UVec2::new(50, y), stone);
+        }
+
+        // A fast throw straight at the wall stops at it in a single step.
+        let mut projectile = Projectile::thrown(
+            ProjectileKind::Bomb,
+            Vec2::new(20.5, 40.5),
+            Vec2::new(60.0, 40.5),
+        );
+        let Flight::Hit { cell, before } = projectile.step(&map, Vec2::new(20.5, 40.5), 0.5) else {
+            panic!("The bomb should hit the wall");
+        };
+        assert_eq!((cell.x, before.x), (50, 49));
+
+        // A throw almost straight up arcs over, above the top of the map, and comes down onto the
+        // floor.
+        let mut projectile = Projectile::thrown(
+            ProjectileKind::WaterFlask,
+            Vec2::new(10.5, 20.5),
+            Vec2::new(11.5, 30.5),
+        );
+        let mut position = Vec2::new(10.5, 20.5);
+        let mut highest = position.y;
+        let (cell, before) = loop {
+            match projectile.step(&map, position, 1.0 / 60.0) {
+                Flight::Flying(next) => {
+                    highest = highest.max(next.y);
+                    position = next;
+                }
+                Flight::Hit { cell, before } => break (cell, before),
+                Flight::Lost => panic!("The flask should land on the floor"),
+            }
+        };
+        assert!(highest > 64.0);
+        assert_eq!(cell.y, 7);
+        assert!(cell.x > 20 && cell.x < 50);
+        assert!(map.get_particle_at(before).is_none());
+
+        // The flask splashes water around where it landed, and a bomb would explode there.
+        assert!(ProjectileKind::WaterFlask
+            .impact(&mut map, cell, before)
+            .is_none());
+        assert!(matches!(
+            map.get_particle_at(before),
+            Some(Particle::Liquid(Liquid::Water(_)))
+        ));
+        assert_eq!(map.get_particle_at(cell), stone);
+        let explosion = ProjectileKind::Bomb.impact(&mut map, cell, before).unwrap();
+        assert_eq!((explosion.center, explosion.radius), (cell, BOMB_RADIUS));
+    }
+}
@@ -0,0 +1,87 @@
+//! Helpers shared between integration tests.
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use bevy::math::UVec2;
+use cavernborn::particle::interaction::InteractionRules;
+use cavernborn::particle::Particle;
+use cavernborn::world::Map;
+
+/// Upper bound on simulation steps used by helpers that run until the map settles.
+pub const MAX_SETTLE_STEPS: usize = 256;
+
+/// Creates a fresh scratch directory for a single test.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cavernborn_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Test-only conveniences for driving the simulation.
+pub trait MapTestExt {
+    /// Mark every chunk active so nothing freezes at a chunk boundary.
+    fn activate_all_chunks(&mut self);
+
+    /// Run `n` full simulation steps with every chunk active.
+    fn step_n(&mut self, rules: &InteractionRules, n: usize);
+
+    /// Place `particle` at the top of column `x` and step the simulation until it stops moving,
+    /// or until `MAX_SETTLE_STEPS` is reached. Returns where the particle ended up, or `None` if it
+    /// was consumed along the way.
+    fn drop_column(
+        &mut self,
+        x: u32,
+        particle: Particle,
+        rules: &InteractionRules,
+    ) -> Option<UVec2>;
+}
+
+impl MapTestExt for Map {
+    fn activate_all_chunks(&mut self) {
+        let positions: Vec<UVec2> = self.chunks.iter().flatten().map(|c| c.position).collect();
+        self.active_chunks.extend(positions);
+    }
+
+    fn step_n(&mut self, rules: &InteractionRules, n: usize) {
+        self.activate_all_chunks();
+        for _ in 0..n {
+            self.update_dirty_chunks();
+            self.simulate_active_chunks(rules);
+        }
+    }
+
+    fn drop_column(
+        &mut self,
+        x: u32,
+        particle: Particle,
+        rules: &InteractionRules,
+    ) -> Option<UVec2> {
+        // Anything matching the particle before the drop is not the one we are following.
+        let existing = matching_positions(self, particle);
+        let start = UVec2::new(x, self.height - 1);
+        self.set_particle_at(start, Some(particle));
+
+        let mut position = start;
+        for _ in 0..MAX_SETTLE_STEPS {
+            self.step_n(rules, 1);
+            let next = matching_positions(self, particle)
+                .into_iter()
+                .find(|pos| !existing.contains(pos))?;
+            if next == position {
+                break;
+            }
+            position = next;
+        }
+        Some(position)
+    }
+}
+
+/// All positions holding a particle equal to `particle`.
+fn matching_positions(map: &Map, particle: Particle) -> Vec<UVec2> {
+    (0..map.width)
+        .flat_map(|x| (0..map.height).map(move |y| UVec2::new(x, y)))
+        .filter(|&pos| map.get_particle_at(pos) == Some(particle))
+        .collect()
+}
@@ -0,0 +1,86 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::MapTestExt;
+    use bevy::math::UVec2;
+    use cavernborn::particle::interaction::{InteractionRule, InteractionRules, InteractionType};
+    use cavernborn::particle::{Common, Direction, Liquid, Particle, Solid};
+    use cavernborn::utils::debug::{
+        inspect_cell, interaction_rule_rows, CellInspection, InteractionRuleRow,
+    };
+    use cavernborn::world::Map;
+
+    /// Test that the interaction rules panel lists the built-in rules and rules registered at runtime
+    #[test]
+    fn test_interaction_rule_rows_include_runtime_rules() {
+        let mut rules = InteractionRules::default();
+        let builtin_rows = interaction_rule_rows(&rules);
+
+        assert_eq!(builtin_rows.len(), rules.len());
+        assert!(builtin_rows.contains(&InteractionRuleRow {
+            source: "Water".to_string(),
+            target: "Acid".to_string(),
+            interaction_type: "Preserve".to_string(),
+            result: "Water".to_string(),
+        }));
+
+        rules.register(
+            Particle::Liquid(Liquid::Lava(Direction::Still.into())),
+            Particle::Common(Common::Dirt),
+            InteractionRule {
+                interaction_type: InteractionType::Replace,
+                result: Particle::Solid(Solid::Obsidian),
+                byproduct: None,
+                probability: 1.0,
+                cooldown: 0,
+                symmetric: true,
+            },
+        );
+
+        let rows = interaction_rule_rows(&rules);
+        assert_eq!(rows.len(), builtin_rows.len() + 1);
+        assert!(rows.contains(&InteractionRuleRow {
+            source: "Lava".to_string(),
+            target: "Dirt".to_string(),
+            interaction_type: "Replace".to_string(),
+            result: "Obsidian".to_string(),
+        }));
+    }
+
+    /// Test that the cell inspector reports the particle, coordinates and chunk flags of a cell
+    #[test]
+    fn test_inspect_cell() {
+        let mut map = Map::empty(64, 64);
+        let position = UVec2::new(35, 5);
+        let water = Particle::Liquid(Liquid::Water(Direction::Still.into()));
+        map.set_particle_at(position, Some(water));
+
+        let inspection = inspect_cell(&map, position).unwrap();
+        assert_eq!(
+            inspection,
+            CellInspection {
+                particle: Some(water),
+                position,
+                chunk_pos: UVec2::new(1, 0),
+                local_pos: UVec2::new(3, 5),
+                dirty: true,
+                // Not worked out until the dirty chunks are updated.
+                should_simulate: false,
+            }
+        );
+
+        map.activate_all_chunks();
+        map.update_dirty_chunks();
+        let inspection = inspect_cell(&map, position).unwrap();
+        assert!(!inspection.dirty);
+        assert!(inspection.should_simulate);
+
+        let air = inspect_cell(&map, UVec2::new(1, 1)).unwrap();
+        assert_eq!(air.particle, None);
+        assert_eq!(air.chunk_pos, UVec2::ZERO);
+        assert!(!air.should_simulate);
+
+        assert_eq!(inspect_cell(&map, UVec2::new(64, 0)), None);
+    }
+}
@@ -0,0 +1,243 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::scratch_dir;
+    use bevy::math::UVec2;
+    use cavernborn::particle::interaction::InteractionRules;
+    use cavernborn::particle::{Common, Direction, Liquid, Particle};
+    use cavernborn::world::generator::{GeneratorConfig, WorldGenConfig};
+    use cavernborn::world::persistence::write_atomic;
+    use cavernborn::world::structure::Structure;
+    use cavernborn::world::Map;
+    use std::io::{self, Write};
+
+    /// Test that a failed atomic write leaves the previous file intact and cleans up after itself
+    #[test]
+    fn test_write_atomic_keeps_original_on_failure() {
+        let dir = scratch_dir("atomic");
+        let path = dir.join("map.cvb");
+
+        write_atomic(&path, |writer| writer.write_all(b"original")).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"original");
+
+        // Fail halfway through the write.
+        let result = write_atomic(&path, |writer| {
+            writer.write_all(b"partial")?;
+            Err(io::Error::other("simulated crash"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            b"original",
+            "A failed write must not touch the existing file"
+        );
+        assert_eq!(
+            std::fs::read_dir(&dir).unwrap().count(),
+            1,
+            "The temp file should be removed after a failed write"
+        );
+
+        // A successful write replaces the file.
+        write_atomic(&path, |writer| writer.write_all(b"updated")).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"updated");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test that a saved map loads back with identical cells and simulation state
+    #[test]
+    fn test_save_load_round_trip() {
+        let dir = scratch_dir("round_trip");
+        let path = dir.join("map.cvb");
+
+        let mut map = Map::empty(64, 64);
+        map.set_particle_at(UVec2::new(3, 4), Some(Particle::Common(Common::Stone)));
+        map.set_particle_at(
+            UVec2::new(40, 50),
+            Some(Particle::Liquid(Liquid::Water(Direction::Right.into()))),
+        );
+        map.set_background_at(UVec2::new(40, 50), Some(Particle::Common(Common::Dirt)));
+        map.set_background_at(UVec2::new(3, 5), Some(Particle::Common(Common::Stone)));
+        assert!(map.set_surface_profile((0..64).collect()));
+        let terrain = GeneratorConfig {
+            cave_density: 0.3,
+            ..Default::default()
+        };
+        assert!(map.set_gen_config(WorldGenConfig::with_terrain(2, 2, 99, terrain)));
+        map.active_chunks.insert(UVec2::new(0, 1));
+        map.set_simulation_seed(31);
+        for _ in 0..3 {
+            map.simulate_active_chunks(&InteractionRules::default());
+        }
+
+        map.save(&path).unwrap();
+        let loaded = Map::from_file(&path).unwrap();
+
+        assert_eq!((loaded.width, loaded.height), (map.width, map.height));
+        assert_eq!(loaded.surface_profile(), map.surface_profile());
+        assert_eq!(loaded.gen_config(), map.gen_config());
+        assert_eq!(loaded.simulation_seed(), 31);
+        assert_eq!(loaded.simulation_tick(), 3);
+        for x in 0..map.width {
+            for y in 0..map.height {
+                let pos = UVec2::new(x, y);
+                assert_eq!(loaded.get_particle_at(pos), map.get_particle_at(pos));
+                assert_eq!(loaded.get_background_at(pos), map.get_background_at(pos));
+            }
+        }
+        assert_eq!(
+            loaded.get_background_at(UVec2::new(40, 50)),
+            Some(Particle::Common(Common::Dirt))
+        );
+        assert!(loaded.get_chunk_at(&UVec2::new(1, 1)).should_simulate);
+        assert!(!loaded.get_chunk_at(&UVec2::new(0, 0)).should_simulate);
+        // Placing the water woke its chunk, which should still be awake after loading.
+        assert_eq!(loaded.active_chunks, map.active_chunks);
+        assert_eq!(loaded.awake_chunks, map.awake_chunks);
+        assert!(loaded.awake_chunks.contains_key(&UVec2::new(1, 1)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test that loading a chunk range yields the same chunks as a full load restricted to it
+    #[test]
+    fn test_load_region_matches_full_load() {
+        let dir = scratch_dir("load_region");
+        let path = dir.join("map.cvb");
+
+        let map = Map::generate(6, 4, 1234);
+        map.save(&path).unwrap();
+        let full = Map::from_file(&path).unwrap();
+
+        let (min, max) = (UVec2::new(2, 1), UVec2::new(5, 3));
+        let region = Map::load_region(&path, min, max).unwrap();
+        assert_eq!((region.width, region.height), (3 * 32, 2 * 32));
+        for chunk in region.chunks.iter().flatten() {
+            let expected = full.get_chunk_at(&(min + chunk.position));
+            assert!(
+                chunk.cells() == expected.cells()
+                    && chunk.background_cells() == expected.background_cells(),
+                "Chunk {} differs from the full load",
+                min + chunk.position
+            );
+            assert_eq!(chunk.should_simulate, expected.should_simulate);
+        }
+        assert_eq!(
+            region.surface_profile(),
+            &full.surface_profile()[64..160],
+            "The surface profile should be sliced to the region"
+        );
+
+        // Ranges past the edge are clipped, and empty ranges are rejected.
+        let clipped = Map::load_region(&path, UVec2::new(4, 3), UVec2::new(10, 10)).unwrap();
+        assert_eq!((clipped.width, clipped.height), (2 * 32, 32));
+        assert!(Map::load_region(&path, UVec2::new(6, 0), UVec2::new(8, 1)).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test that the stats CSV has the expected header and per-band counts for a hand-built map
+    #[test]
+    fn test_export_stats_csv() {
+        let dir = scratch_dir("stats_csv");
+        let path = dir.join("stats.csv");
+
+        let mut map = Map::empty(64, 64);
+        assert!(map.set_surface_profile(vec![60; 64]));
+        for x in 0..3 {
+            // Depth 10, in the first band.
+            map.set_particle_at(UVec2::new(x, 50), Some(Particle::Common(Common::Dirt)));
+            // Depth 30, in the second band.
+            map.set_particle_at(
+                UVec2::new(x + 40, 30),
+                Some(Particle::Common(Common::Stone)),
+            );
+        }
+        map.set_particle_at(UVec2::new(10, 40), Some(Particle::Common(Common::Stone)));
+        // Above the surface.
+        map.set_particle_at(
+            UVec2::new(5, 62),
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+        );
+
+        map.export_stats_csv(&path).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "depth_min,depth_max,particle,count",
+                "-25,-1,Water,1",
+                "0,24,Dirt,3",
+                "0,24,Stone,1",
+                "25,49,Stone,3",
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test that a structure copied from the map survives a save and load round trip
+    #[test]
+    fn test_structure_round_trip() {
+        let dir = scratch_dir("structure");
+        let path = dir.join("room.cvs");
+
+        let mut map = Map::empty(32, 32);
+        map.set_particle_at(UVec2::new(3, 4), Some(Particle::Common(Common::Stone)));
+        map.set_particle_at(
+            UVec2::new(5, 6),
+            Some(Particle::Liquid(Liquid::Lava(Direction::Right.into()))),
+        );
+        let structure = Structure::from_region(&map, UVec2::new(2, 2), UVec2::new(8, 7));
+        assert_eq!(structure.size(), UVec2::new(6, 5));
+        assert_eq!(
+            structure.get(UVec2::new(1, 2)),
+            Some(Particle::Common(Common::Stone))
+        );
+
+        structure.save(&path).unwrap();
+        assert_eq!(Structure::load(&path).unwrap(), structure);
+
+        std::fs::write(&path, b"not a structure").unwrap();
+        assert!(Structure::load(&path).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test that a structure whose cell count overflows is rejected rather than wrapping around
+    #[test]
+    fn test_structure_rejects_oversized_size() {
+        let dir = scratch_dir("oversized");
+        let path = dir.join("room.cvs");
+
+        // 65536 * 65536 wraps to 0 in u32, matching the empty cell list.
+        let oversized = (UVec2::new(65536, 65536), Vec::<Option<Particle>>::new());
+        std::fs::write(&path, bincode::serialize(&oversized).unwrap()).unwrap();
+        let error = Structure::load(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test that saving a streamed map writes every chunk, loaded or not
+    #[test]
+    fn test_streamed_map_saves_in_full() {
+        let dir = scratch_dir("streamed");
+        let path = dir.join("map.cvb");
+
+        let mut map = Map::generate_streamed(3, 3, 12, 4);
+        map.stream_chunks(UVec2::new(0, 0), 0);
+        map.set_particle_at(UVec2::new(5, 5), Some(Particle::Common(Common::Stone)));
+        map.save(&path).unwrap();
+
+        let mut expected = Map::generate(3, 3, 12);
+        expected.set_particle_at(UVec2::new(5, 5), Some(Particle::Common(Common::Stone)));
+        let loaded = Map::from_file(&path).unwrap();
+        assert_eq!(loaded.world_hash(), expected.world_hash());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
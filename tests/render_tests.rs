@@ -0,0 +1,650 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bevy::prelude::*;
+    use cavernborn::particle::definition::ParticleRegistry;
+    use cavernborn::particle::{Common, Direction, Liquid, Particle, DEFAULT_ATLAS_ID};
+    use cavernborn::player::Player;
+    use cavernborn::render::chunk_material::{
+        decode_sprite, encode_sprite, ChunkMaterial, MAX_ATLASES,
+    };
+    use cavernborn::render::map_renderer::{
+        prune_despawned_renderers, render_map, ChunkRenderer, MapRenderResources,
+        MapRenderSettings, MapRenderer,
+    };
+    use cavernborn::render::palette::ParticlePalette;
+    use cavernborn::world::generator::MapRegenerated;
+    use cavernborn::world::Map;
+    use strum::IntoEnumIterator;
+
+    /// An app holding `map` and what every renderer of it reads.
+    fn map_app(map: Map) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_event::<MapRegenerated>()
+            .insert_resource(map)
+            .init_resource::<ParticlePalette>();
+        app
+    }
+
+    /// An app rendering `map` with `settings`, around a player at the origin.
+    fn render_app(map: Map, settings: MapRenderSettings) -> App {
+        let mut app = map_app(map);
+        app.init_asset::<ChunkMaterial>()
+            .insert_resource(settings)
+            .insert_resource(MapRenderResources::new(
+                Handle::default(),
+                Handle::default(),
+            ))
+            .add_systems(Update, (prune_despawned_renderers, render_map).chain());
+        app.world_mut()
+            .spawn((Player, Transform::from_xyz(0.0, 0.0, 0.0)));
+        app.world_mut().spawn(MapRenderer::new());
+        app
+    }
+
+    /// The flags `material` hands the shader.
+    fn uniform_flags(material: &ChunkMaterial) -> u32 {
+        use bevy::render::render_asset::RenderAssets;
+        use bevy::render::render_resource::AsBindGroupShaderType;
+        use bevy::render::texture::GpuImage;
+        use cavernborn::render::chunk_material::ChunkMaterialUniform;
+
+        let images = RenderAssets::<GpuImage>::default();
+        AsBindGroupShaderType::<ChunkMaterialUniform>::as_bind_group_shader_type(material, &images)
+            .flags
+    }
+
+    /// Test that `render_map` never spawns more chunk renderers per call than the spawn budget
+    #[test]
+    fn test_render_map_respects_spawn_budget() {
+        let settings = MapRenderSettings {
+            spawn_budget: 5,
+            ..default()
+        };
+        let mut app = render_app(Map::empty(256, 256), settings);
+
+        let mut spawned = 0;
+        for _ in 0..3 {
+            app.update();
+
+            let world = app.world_mut();
+            let total = world
+                .query_filtered::<(), With<ChunkRenderer>>()
+                .iter(world)
+                .count();
+            assert!(
+                total - spawned <= 5,
+                "Spawned {} renderers in one frame",
+                total - spawned
+            );
+            spawned = total;
+        }
+        assert_eq!(spawned, 15);
+
+        let world = app.world_mut();
+        let renderer = world.query::<&MapRenderer>().single(world);
+        assert_eq!(renderer.chunk_renderers.len(), 15);
+    }
+
+    /// Test that renderers of chunks leaving the render range are reused for chunks entering it
+    #[test]
+    fn test_render_map_reuses_renderers() {
+        let settings = MapRenderSettings {
+            spawn_budget: 1000,
+            ..default()
+        };
+        let mut app = render_app(Map::empty(2048, 64), settings);
+        // Screen x = world x * 3 - 3072 on a 2048 wide map, so this is near world x 100.
+        let world = app.world_mut();
+        let player = world.query_filtered::<Entity, With<Player>>().single(world);
+        world
+            .entity_mut(player)
+            .insert(Transform::from_xyz(-2772.0, 0.0, 0.0));
+
+        app.update();
+        let world = app.world_mut();
+        let before: HashSet<Entity> = world
+            .query_filtered::<Entity, With<ChunkRenderer>>()
+            .iter(world)
+            .collect();
+        assert!(!before.is_empty());
+
+        // Move to the other end of the map, near world x 1948, as far from the edge as before.
+        app.world_mut()
+            .entity_mut(player)
+            .insert(Transform::from_xyz(2772.0, 0.0, 0.0));
+        app.update();
+
+        let world = app.world_mut();
+        let after: HashSet<Entity> = world
+            .query_filtered::<Entity, With<ChunkRenderer>>()
+            .iter(world)
+            .collect();
+        assert_eq!(after, before, "Renderers should be reused, not respawned");
+
+        let renderer = world.query::<&MapRenderer>().single(world);
+        assert_eq!(renderer.chunk_renderers.len(), before.len());
+        assert!(renderer
+            .chunk_renderers
+            .keys()
+            .all(|chunk_pos| chunk_pos.x * 32 > 1024));
+    }
+
+    /// Test that chunk materials are updated in place rather than re-created, and that the
+    /// materials of despawned renderers are freed
+    #[test]
+    fn test_chunk_materials_are_reused_and_freed() {
+        let mut app = render_app(Map::empty(256, 64), MapRenderSettings::default());
+        let world = app.world_mut();
+        let player = world.query_filtered::<Entity, With<Player>>().single(world);
+
+        app.update();
+        let material_count = app.world().resource::<Assets<ChunkMaterial>>().len();
+        assert!(material_count > 0);
+
+        // Changing a cell rewrites its chunk's material instead of adding another.
+        let chunk_pos = UVec2::new(4, 1);
+        let handle = {
+            let world = app.world_mut();
+            let renderer = world.query::<&MapRenderer>().single(world);
+            renderer.chunk_renderers[&chunk_pos].1.clone()
+        };
+        app.world_mut()
+            .resource_mut::<Map>()
+            .set_particle_at(UVec2::new(130, 33), Some(Particle::Common(Common::Stone)));
+        for _ in 0..3 {
+            app.update();
+        }
+        let materials = app.world().resource::<Assets<ChunkMaterial>>();
+        assert_eq!(materials.len(), material_count);
+        assert!(materials
+            .get(&handle)
+            .unwrap()
+            .indices
+            .iter()
+            .any(|packed| *packed != UVec4::ZERO));
+
+        // Despawned renderers are forgotten, and their materials freed.
+        drop(handle);
+        app.world_mut().entity_mut(player).despawn();
+        let world = app.world_mut();
+        let renderers: Vec<Entity> = world
+            .query_filtered::<Entity, With<ChunkRenderer>>()
+            .iter(world)
+            .collect();
+        for entity in renderers {
+            world.entity_mut(entity).despawn();
+        }
+        for _ in 0..2 {
+            app.update();
+        }
+        let world = app.world_mut();
+        let renderer = world.query::<&MapRenderer>().single(world);
+        assert!(renderer.chunk_renderers.is_empty());
+        assert!(renderer.spare_renderers.is_empty());
+        assert_eq!(app.world().resource::<Assets<ChunkMaterial>>().len(), 0);
+    }
+
+    /// Test that every particle resolves to an (atlas id, index) pair that survives the shader encoding
+    #[test]
+    fn test_particle_sprites_resolve_to_atlas_and_index() {
+        let particles = ParticleRegistry::default();
+        for particle in Particle::iter() {
+            let (atlas_id, index) = particles.sprite(particle);
+            assert_eq!(atlas_id, DEFAULT_ATLAS_ID, "{:?}", particle);
+            assert_eq!(
+                index,
+                particles.get(particle).spritesheet_index,
+                "{:?}",
+                particle
+            );
+
+            // Default atlas sprites encode to their plain index, so single-atlas rendering is unchanged.
+            assert_eq!(encode_sprite(atlas_id, index), index, "{:?}", particle);
+            assert_eq!(
+                decode_sprite(encode_sprite(atlas_id, index)),
+                (atlas_id, index)
+            );
+        }
+
+        for atlas_id in 0..MAX_ATLASES as u32 {
+            assert_eq!(decode_sprite(encode_sprite(atlas_id, 12)), (atlas_id, 12));
+        }
+    }
+
+    /// Test that animated particles carry their frame count and rate into the cells, without
+    /// changing which sprite the cell decodes to
+    #[test]
+    fn test_animated_sprites_reach_cells() {
+        use cavernborn::particle::definition::ParticleDefinitions;
+        use cavernborn::render::chunk_material::{decode_animation, encode_animation};
+        use cavernborn::world::chunk::Chunk;
+
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let stone = Particle::Common(Common::Stone);
+        let particles = ParticleRegistry::default();
+        let (frames, frame_rate) = particles.animation(water);
+        assert!(frames > 1 && frame_rate > 0, "Water should shimmer");
+        assert_eq!(particles.animation(stone), (1, 0));
+
+        let mut chunk = Chunk::new(UVec2::ZERO);
+        chunk.set_particle(UVec2::new(0, 0), Some(water));
+        chunk.set_particle(UVec2::new(1, 0), Some(stone));
+        let cells = chunk.to_cell_indices(&particles);
+        assert_eq!(decode_animation(cells[0]), (frames, frame_rate));
+        assert_eq!(decode_sprite(cells[0]), particles.sprite(water));
+        assert_eq!(decode_animation(cells[1]), (1, 0));
+        assert_eq!(decode_animation(0), (1, 0), "Air never animates");
+
+        let value = encode_animation(encode_sprite(2, 40), 16, 15);
+        assert_eq!(decode_animation(value), (16, 15));
+        assert_eq!(decode_sprite(value), (2, 40));
+
+        // Animations and variants that don't fit the cell encoding are rejected.
+        let text = include_str!("../assets/particles.toml");
+        for (frames, frame_rate, variants) in [(0, 0, 1), (17, 1, 1), (2, 16, 1), (1, 0, 5)] {
+            let mut definitions = ParticleDefinitions::from_toml(text).unwrap();
+            let sand = definitions.particles.get_mut("Sand").unwrap();
+            sand.frames = frames;
+            sand.frame_rate = frame_rate;
+            sand.variants = variants;
+            assert!(ParticleRegistry::from_definitions(&definitions).is_err());
+        }
+    }
+
+    /// Test that cell variants are picked deterministically by world position, vary across a
+    /// stretch of terrain, and don't change which sprite a cell decodes to
+    #[test]
+    fn test_cell_variants_by_position() {
+        use cavernborn::render::chunk_material::{cell_variant, decode_variant, MAX_VARIANTS};
+        use cavernborn::world::chunk::Chunk;
+
+        let dirt = Particle::Common(Common::Dirt);
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let particles = ParticleRegistry::default();
+        let variants = particles.variants(dirt);
+        assert!((2..=MAX_VARIANTS).contains(&variants));
+        assert_eq!(particles.variants(water), 1);
+
+        // Two chunks at different positions, filled the same way.
+        let mut left = Chunk::new(UVec2::new(0, 0));
+        let mut right = Chunk::new(UVec2::new(1, 0));
+        for x in 0..32 {
+            left.set_particle(UVec2::new(x, 0), Some(dirt));
+            right.set_particle(UVec2::new(x, 0), Some(dirt));
+        }
+        left.set_particle(UVec2::new(0, 1), Some(water));
+        let cells = left.to_cell_indices(&particles);
+        assert_eq!(cells, left.to_cell_indices(&particles));
+        assert_ne!(cells, right.to_cell_indices(&particles));
+
+        let used: HashSet<u32> = cells[..32]
+            .iter()
+            .map(|&cell| decode_variant(cell))
+            .collect();
+        assert_eq!(
+            used.len() as u32,
+            variants,
+            "Every variant shows up in a row"
+        );
+        for x in 0..32 {
+            let cell = cells[x as usize];
+            assert_eq!(
+                decode_variant(cell),
+                cell_variant(UVec2::new(x, 0), variants)
+            );
+            assert_eq!(decode_sprite(cell), particles.sprite(dirt));
+        }
+        // The water above the first cell, which has a single variant.
+        assert_eq!(decode_variant(cells[32]), 0);
+    }
+
+    /// Test that changing a palette entry recolors that particle's cells in the chunk materials
+    #[test]
+    fn test_palette_overrides_reach_material() {
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let mut map = Map::empty(32, 32);
+        map.set_particle_at(UVec2::new(2, 0), Some(water));
+        map.set_particle_at(UVec2::new(3, 0), Some(Particle::Common(Common::Stone)));
+        let (water_cell, stone_cell) = (2, 3);
+
+        let mut app = render_app(map, MapRenderSettings::default());
+
+        let material = |app: &mut App| {
+            let world = app.world_mut();
+            let renderer = world.query::<&MapRenderer>().single(world);
+            let (_entity, handle, _version) = &renderer.chunk_renderers[&UVec2::ZERO];
+            world
+                .resource::<Assets<ChunkMaterial>>()
+                .get(handle.id())
+                .unwrap()
+                .clone()
+        };
+
+        // The default palette keeps the atlas colors.
+        app.update();
+        assert_eq!(material(&mut app).cell_color(water_cell), None);
+
+        let blue = Color::srgb(0.0, 0.0, 1.0);
+        app.world_mut()
+            .resource_mut::<ParticlePalette>()
+            .set(water, blue);
+        app.update();
+
+        let material = material(&mut app);
+        let expected: Vec4 = LinearRgba::from(blue).to_f32_array().into();
+        assert_eq!(material.cell_color(water_cell), Some(expected));
+        assert_eq!(material.cell_color(stone_cell), None);
+        // Air is never recolored.
+        assert_eq!(material.cell_color(0), None);
+    }
+
+    /// Test that the edge darkening flag on the material reaches the shader uniform
+    #[test]
+    fn test_edge_darkening_flag_reaches_uniform() {
+        use cavernborn::render::chunk_material::ChunkMaterialFlags;
+
+        let mut material = ChunkMaterial::default();
+        let flags = uniform_flags(&material);
+        assert_eq!(flags & ChunkMaterialFlags::EDGE_DARKENING.bits(), 0);
+
+        material.edge_darkening = true;
+        let flags = uniform_flags(&material);
+        assert_ne!(flags & ChunkMaterialFlags::EDGE_DARKENING.bits(), 0);
+        // The flag doesn't disturb the alpha mode bits.
+        assert_ne!(flags & ChunkMaterialFlags::ALPHA_MODE_BLEND.bits(), 0);
+    }
+
+    /// Test that the smooth fluids flag reaches the uniform and liquid cells are tagged for it
+    #[test]
+    fn test_smooth_fluids_flag_and_liquid_cells() {
+        use cavernborn::render::chunk_material::{is_liquid_cell, ChunkMaterialFlags};
+        use cavernborn::world::chunk::Chunk;
+
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let lava = Particle::Liquid(Liquid::Lava(Direction::Left.into()));
+        let stone = Particle::Common(Common::Stone);
+        let particles = ParticleRegistry::default();
+        let mut chunk = Chunk::new(UVec2::ZERO);
+        chunk.set_particle(UVec2::new(0, 0), Some(water));
+        chunk.set_particle(UVec2::new(1, 0), Some(lava));
+        chunk.set_particle(UVec2::new(2, 0), Some(stone));
+
+        let mut material = ChunkMaterial::from_indices(
+            Handle::default(),
+            chunk.to_spritesheet_indices(&particles),
+        );
+        assert_eq!(
+            uniform_flags(&material) & ChunkMaterialFlags::SMOOTH_FLUIDS.bits(),
+            0
+        );
+        material.smooth_fluids = true;
+        assert_ne!(
+            uniform_flags(&material) & ChunkMaterialFlags::SMOOTH_FLUIDS.bits(),
+            0
+        );
+
+        let cells = chunk.to_cell_indices(&particles);
+        assert!(is_liquid_cell(cells[0]));
+        assert!(is_liquid_cell(cells[1]));
+        assert!(!is_liquid_cell(cells[2]));
+        assert!(!is_liquid_cell(cells[3]), "Air is never liquid");
+        // The tag doesn't change which sprite is drawn.
+        assert_eq!(decode_sprite(cells[0]), particles.sprite(water));
+        assert_eq!(decode_sprite(cells[1]), particles.sprite(lava));
+    }
+
+    /// Test that the cell buffer lays out the indices, walls and light levels back to back, and is
+    /// only uploaded when built with storage buffers
+    #[test]
+    fn test_cell_buffer_layout_and_upload() {
+        use bevy::render::storage::ShaderStorageBuffer;
+        use cavernborn::render::chunk_material::{
+            PACKED_INDICE_BUFFER_SIZE, PACKED_LIGHT_BUFFER_SIZE, STORAGE_BUFFERS,
+        };
+
+        let mut material = ChunkMaterial::default();
+        material.indices[0] = UVec4::new(1, 2, 3, 4);
+        material.background_indices[0] = UVec4::new(5, 6, 7, 8);
+        material.light[PACKED_LIGHT_BUFFER_SIZE - 1] = UVec4::splat(9);
+
+        let data = material.cell_buffer_data();
+        assert_eq!(
+            data.len(),
+            PACKED_INDICE_BUFFER_SIZE * 2 + PACKED_LIGHT_BUFFER_SIZE
+        );
+        assert_eq!(data[0], UVec4::new(1, 2, 3, 4));
+        assert_eq!(data[PACKED_INDICE_BUFFER_SIZE], UVec4::new(5, 6, 7, 8));
+        assert_eq!(*data.last().unwrap(), UVec4::splat(9));
+
+        let mut buffers = Assets::<ShaderStorageBuffer>::default();
+        material.upload_cell_buffer(&mut buffers);
+        assert_eq!(buffers.len(), usize::from(STORAGE_BUFFERS));
+        assert_eq!(material.cell_buffer != Handle::default(), STORAGE_BUFFERS);
+    }
+
+    /// Test that inactive chunks are drawn dimmed relative to active ones when enabled
+    #[test]
+    fn test_inactive_chunks_are_dimmed() {
+        let mut map = Map::empty(64, 32);
+        map.active_chunks.insert(UVec2::new(0, 0));
+
+        let settings = MapRenderSettings {
+            dim_inactive_chunks: true,
+            ..default()
+        };
+        let mut app = render_app(map, settings);
+
+        let luminance = |app: &mut App, chunk_pos: UVec2| {
+            let world = app.world_mut();
+            let renderer = world.query::<&MapRenderer>().single(world);
+            let (_entity, handle, _version) = &renderer.chunk_renderers[&chunk_pos];
+            let material = world.resource::<Assets<ChunkMaterial>>().get(handle.id());
+            material.unwrap().color.luminance()
+        };
+
+        app.update();
+        let active = luminance(&mut app, UVec2::new(0, 0));
+        let inactive = luminance(&mut app, UVec2::new(1, 0));
+        assert!(
+            inactive < active,
+            "{} should be dimmer than {}",
+            inactive,
+            active
+        );
+
+        // Activating the chunk restores its brightness.
+        app.world_mut()
+            .resource_mut::<Map>()
+            .active_chunks
+            .insert(UVec2::new(1, 0));
+        app.update();
+        assert_eq!(luminance(&mut app, UVec2::new(1, 0)), active);
+    }
+
+    /// Test that the minimap shows the particle most cells of each block hold, in its atlas or
+    /// palette color, and only redraws chunks that changed
+    #[test]
+    fn test_minimap_draws_changed_chunks() {
+        use bevy::render::render_asset::RenderAssetUsages;
+        use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+        use cavernborn::render::minimap::{
+            update_minimap, Minimap, MinimapImage, MinimapNode, MinimapSettings,
+        };
+
+        let stone = Particle::Common(Common::Stone);
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let mut map = Map::empty(64, 64);
+        // Three quarters of the bottom left block are stone.
+        for (x, y) in [
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (3, 0),
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (3, 1),
+        ] {
+            map.set_particle_at(UVec2::new(x, y), Some(stone));
+        }
+        for (x, y) in [(0, 2), (1, 2), (2, 2), (3, 2)] {
+            map.set_particle_at(UVec2::new(x, y), Some(water));
+        }
+
+        // Each particle gets a color of its own.
+        let particles = ParticleRegistry::default();
+        let color = |particle: Particle| [particles.sprite(particle).1 as u8, 0, 0, 255];
+        let mut image = MinimapImage::new(map.width, map.height, 4);
+        assert_eq!((image.width(), image.height()), (16, 16));
+        assert_eq!(image.update(&map, color), 4);
+        assert_eq!(image.pixel(0, 0), color(stone));
+        assert_ne!(image.pixel(1, 0), color(stone));
+        assert_eq!(image.update(&map, color), 0);
+        map.set_particle_at(UVec2::new(40, 40), Some(stone));
+        assert_eq!(image.update(&map, color), 1);
+
+        // Drawn in an app, colors come from the atlas and the palette.
+        let atlas = Image::new(
+            Extent3d {
+                width: 32,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            (0..32u8).flat_map(|index| [index, 0, 0, 255]).collect(),
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        let mut app = map_app(map);
+        app.init_asset::<Image>()
+            .init_resource::<MinimapSettings>()
+            .add_systems(Update, update_minimap);
+        let atlas = app.world_mut().resource_mut::<Assets<Image>>().add(atlas);
+        app.insert_resource(MapRenderResources::new(atlas, Handle::default()));
+        app.world_mut()
+            .spawn((MinimapNode, ImageNode::default(), Node::default()));
+
+        let texture_pixel = |app: &mut App, x: u32, y: u32| {
+            let world = app.world();
+            let minimap = world.resource::<Minimap>();
+            let texture = world
+                .resource::<Assets<Image>>()
+                .get(&minimap.texture)
+                .unwrap();
+            let start =
+                (((minimap.image.height() - 1 - y) * minimap.image.width() + x) * 4) as usize;
+            texture.data[start..start + 4].to_vec()
+        };
+        app.update();
+        app.update();
+        assert_eq!(texture_pixel(&mut app, 0, 0), color(stone));
+
+        let green = Color::srgb(0.0, 1.0, 0.0);
+        app.world_mut()
+            .resource_mut::<ParticlePalette>()
+            .set(stone, green);
+        app.update();
+        assert_eq!(texture_pixel(&mut app, 0, 0), vec![0, 255, 0, 255]);
+    }
+
+    /// Test that the minimap draws unloaded chunks of streamed maps as they were generated
+    #[test]
+    fn test_minimap_draws_unloaded_streamed_chunks() {
+        use cavernborn::render::minimap::MinimapImage;
+
+        let seed = 29;
+        let full = Map::generate(3, 3, seed);
+        let mut streamed = Map::generate_streamed(3, 3, seed, 1);
+        streamed.stream_chunks(UVec2::new(1, 1), 0);
+        assert!(!streamed.is_chunk_loaded(UVec2::ZERO));
+
+        let particles = ParticleRegistry::default();
+        let color = |particle: Particle| [particles.sprite(particle).1 as u8, 0, 0, 255];
+        let mut full_image = MinimapImage::new(full.width, full.height, 4);
+        let mut streamed_image = MinimapImage::new(streamed.width, streamed.height, 4);
+        full_image.update(&full, color);
+        streamed_image.update(&streamed, color);
+        assert!(
+            streamed_image.data() == full_image.data(),
+            "The minimap shouldn't depend on which chunks are loaded"
+        );
+    }
+
+    /// Test that exports draw one opaque pixel per cell from the top row down, crop to the
+    /// requested region, and round trip through a timestamped PNG file
+    #[test]
+    fn test_map_export_rasterizes_and_saves_png() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        use bevy::image::{CompressedImageFormats, ImageSampler, ImageType};
+        use bevy::render::render_asset::RenderAssetUsages;
+        use cavernborn::render::export::{export_path, rasterize_map, save_png};
+
+        let stone = Particle::Common(Common::Stone);
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let mut map = Map::empty(64, 32);
+        map.set_particle_at(UVec2::new(0, 0), Some(stone));
+        map.set_particle_at(UVec2::new(10, 20), Some(water));
+
+        let particles = ParticleRegistry::default();
+        let color = |particle: Particle| [particles.sprite(particle).1 as u8, 0, 0, 100];
+        let pixel = |image: &Image, x: u32, y: u32| {
+            let start = ((y * image.width() + x) * 4) as usize;
+            image.data[start..start + 4].to_vec()
+        };
+
+        let image = rasterize_map(&map, URect::new(0, 0, 64, 32), color);
+        assert_eq!((image.width(), image.height()), (64, 32));
+        // The bottom row of the map is the last row of the image.
+        let [r, g, b, _] = color(stone);
+        assert_eq!(pixel(&image, 0, 31), vec![r, g, b, 255]);
+        assert_eq!(pixel(&image, 0, 0)[3], 255);
+
+        // Regions are cropped to the map.
+        let region = rasterize_map(&map, URect::new(8, 16, 100, 24), color);
+        assert_eq!((region.width(), region.height()), (56, 8));
+        let [r, g, b, _] = color(water);
+        assert_eq!(pixel(&region, 2, 3), vec![r, g, b, 255]);
+
+        let dir = std::env::temp_dir().join(format!("cavernborn_export_{}", std::process::id()));
+        let path = export_path(&dir, UNIX_EPOCH + Duration::from_millis(1234));
+        assert_eq!(path, dir.join("map-1234.png"));
+        save_png(region, &path).unwrap();
+        let loaded = Image::from_buffer(
+            &std::fs::read(&path).unwrap(),
+            ImageType::Extension("png"),
+            CompressedImageFormats::NONE,
+            true,
+            ImageSampler::Default,
+            RenderAssetUsages::default(),
+        )
+        .unwrap();
+        assert_eq!((loaded.width(), loaded.height()), (56, 8));
+        assert_eq!(pixel(&loaded, 2, 3), vec![r, g, b, 255]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Test that exporting a streamed map draws its unloaded chunks as they were generated
+    #[test]
+    fn test_map_export_draws_unloaded_streamed_chunks() {
+        use cavernborn::render::export::rasterize_map;
+
+        let seed = 23;
+        let full = Map::generate(3, 3, seed);
+        let mut streamed = Map::generate_streamed(3, 3, seed, 1);
+        streamed.stream_chunks(UVec2::new(1, 1), 0);
+        assert!(!streamed.is_chunk_loaded(UVec2::ZERO));
+
+        let particles = ParticleRegistry::default();
+        let color = |particle: Particle| [particles.sprite(particle).1 as u8, 0, 0, 255];
+        let everything = URect::new(0, 0, full.width, full.height);
+        assert!(
+            rasterize_map(&streamed, everything, color).data
+                == rasterize_map(&full, everything, color).data,
+            "The export shouldn't depend on which chunks are loaded"
+        );
+    }
+}
@@ -0,0 +1,1504 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+    use cavernborn::particle::definition::{ParticleDefinitions, ParticleRegistry};
+    use cavernborn::particle::interaction::{
+        InteractionPair, InteractionRule, InteractionRules, InteractionType,
+    };
+    use cavernborn::particle::{
+        Common, Direction, Fire, Gas, GasState, Liquid, Particle, Powder, Solid,
+        AMBIENT_TEMPERATURE,
+    };
+    use cavernborn::simulation::fire::CombustionSettings;
+    use cavernborn::simulation::{fluid::FluidSimulator, MoveResult, ReadOnlySimulationContext};
+    use cavernborn::world::chunk::ParticleMove;
+    use cavernborn::world::conservation::{ConservationMismatch, ParticleCensus};
+    use cavernborn::world::Map;
+    use dashmap::DashMap;
+    use rand::Rng;
+
+    use crate::common::MapTestExt;
+
+    /// Computes a single water step at `pos` on an otherwise empty map with the given viscosity.
+    fn step_water(pos: UVec2, viscosity: i32) -> MoveResult {
+        let water = Liquid::Water(Direction::Left.into());
+        let mut map = Map::empty(64, 64);
+        map.set_particle_at(pos, Some(Particle::Liquid(water)));
+
+        let rules = InteractionRules::default();
+        let queue = DashMap::new();
+        let chunk = map.get_chunk_at(&UVec2::ZERO);
+        let context = ReadOnlySimulationContext::new(&map, &rules, chunk, &queue, chunk.cells());
+
+        FluidSimulator.calculate_step_with_viscosity(&context, water, viscosity, pos.x, pos.y)
+    }
+
+    /// Test that a liquid with zero viscosity stays in place
+    #[test]
+    fn test_zero_viscosity_is_stationary() {
+        let pos = UVec2::new(16, 16);
+        match step_water(pos, 0) {
+            MoveResult::Move(new_pos, _) => assert_eq!(new_pos, pos),
+            MoveResult::Preserve { .. } => panic!("Stationary liquid should not interact"),
+        }
+    }
+
+    /// Test that a liquid with the lowest moving viscosity still falls, and negative values behave the same
+    #[test]
+    fn test_low_viscosity_still_falls() {
+        let pos = UVec2::new(16, 16);
+        for viscosity in [1, -3] {
+            match step_water(pos, viscosity) {
+                MoveResult::Move(new_pos, _) => {
+                    assert_eq!(new_pos, UVec2::new(16, 15), "viscosity {}", viscosity)
+                }
+                MoveResult::Preserve { .. } => panic!("Falling into air should not interact"),
+            }
+        }
+    }
+
+    /// Returns the position and state of the only liquid on the map.
+    fn find_liquid(map: &Map) -> (UVec2, Liquid) {
+        let mut liquids = (0..map.width)
+            .flat_map(|x| (0..map.height).map(move |y| UVec2::new(x, y)))
+            .filter_map(|pos| match map.get_particle_at(pos) {
+                Some(Particle::Liquid(liquid)) => Some((pos, liquid)),
+                _ => None,
+            });
+        let found = liquids.next().expect("The map should contain a liquid");
+        assert!(
+            liquids.next().is_none(),
+            "The map should contain one liquid"
+        );
+        found
+    }
+
+    /// Test that a falling liquid accumulates its fall distance and resets it on landing
+    #[test]
+    fn test_fall_distance_accumulates_and_resets() {
+        let mut map = Map::empty(64, 64);
+        map.set_particle_at(
+            UVec2::new(16, 5),
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+        );
+        map.active_chunks.insert(UVec2::ZERO);
+        let rules = InteractionRules::default();
+
+        // Water falls up to 4 cells per step, so it reaches the floor after two steps.
+        for _ in 0..2 {
+            map.update_dirty_chunks();
+            map.simulate_active_chunks(&rules);
+        }
+        let (pos, water) = find_liquid(&map);
+        assert_eq!(pos.y, 0, "Water should have fallen to the floor");
+        assert_eq!(water.get_fall_distance(), 5);
+
+        // Once it can no longer fall the distance resets.
+        map.update_dirty_chunks();
+        map.simulate_active_chunks(&rules);
+        let (_, water) = find_liquid(&map);
+        assert_eq!(water.get_fall_distance(), 0);
+    }
+
+    /// Test that fire burns through a line of flammable cells, across a chunk border, and stops at non-flammable ones
+    #[test]
+    fn test_fire_spreads_through_flammable_cells() {
+        let grass = Some(Particle::Common(Common::Grass));
+        let stone = Some(Particle::Common(Common::Stone));
+
+        // Grass from x = 1 to 40 crosses into the second chunk, then a stone wall protects more grass.
+        let mut map = Map::empty(64, 32);
+        map.set_particle_at(UVec2::new(0, 0), Some(Particle::Fire(Fire::default())));
+        for x in 1..=40 {
+            map.set_particle_at(UVec2::new(x, 0), grass);
+        }
+        map.set_particle_at(UVec2::new(41, 0), stone);
+        for x in 42..=44 {
+            map.set_particle_at(UVec2::new(x, 0), grass);
+        }
+        map.active_chunks.insert(UVec2::new(0, 0));
+        map.active_chunks.insert(UVec2::new(1, 0));
+        let rules = InteractionRules::default();
+
+        let has_fire = |map: &Map| {
+            (0..map.width).any(|x| {
+                matches!(
+                    map.get_particle_at(UVec2::new(x, 0)),
+                    Some(Particle::Fire(_))
+                )
+            })
+        };
+
+        let mut ticks = 0;
+        while has_fire(&map) {
+            assert!(ticks < 5000, "Fire should burn out");
+            map.update_dirty_chunks();
+            map.simulate_active_chunks(&rules);
+            ticks += 1;
+        }
+
+        for x in 0..=40 {
+            assert_eq!(
+                map.get_particle_at(UVec2::new(x, 0)),
+                None,
+                "Cell {} should have burned away",
+                x
+            );
+        }
+        assert_eq!(map.get_particle_at(UVec2::new(41, 0)), stone);
+        for x in 42..=44 {
+            assert_eq!(
+                map.get_particle_at(UVec2::new(x, 0)),
+                grass,
+                "Grass behind the stone should not burn"
+            );
+        }
+    }
+
+    /// Test that settled, touching water and acid react once interactions are processed
+    #[test]
+    fn test_stationary_liquids_react() {
+        let stone = Some(Particle::Common(Common::Stone));
+        let water = Some(Particle::Liquid(Liquid::Water(Direction::Still.into())));
+        let acid = Some(Particle::Liquid(Liquid::Acid(Direction::Still.into())));
+        let (water_pos, acid_pos) = (UVec2::new(5, 2), UVec2::new(6, 2));
+
+        // Still liquids walled in on a stone floor never try to move into each other.
+        let mut map = Map::empty(32, 32);
+        for x in 0..32 {
+            map.set_particle_at(UVec2::new(x, 0), stone);
+            map.set_particle_at(UVec2::new(x, 1), stone);
+        }
+        map.set_particle_at(UVec2::new(4, 2), stone);
+        map.set_particle_at(UVec2::new(7, 2), stone);
+        map.set_particle_at(water_pos, water);
+        map.set_particle_at(acid_pos, acid);
+        map.active_chunks.insert(UVec2::ZERO);
+        let rules = InteractionRules::default();
+
+        for _ in 0..5 {
+            map.update_dirty_chunks();
+            map.simulate_active_chunks(&rules);
+        }
+        assert_eq!(
+            map.get_particle_at(water_pos),
+            water,
+            "Movement alone should not react"
+        );
+        assert_eq!(
+            map.get_particle_at(acid_pos),
+            acid,
+            "Movement alone should not react"
+        );
+
+        for _ in 0..3 {
+            map.update_dirty_chunks();
+            map.simulate_active_chunks(&rules);
+            map.process_interactions(&rules);
+        }
+        assert_eq!(map.get_particle_at(water_pos), water);
+        assert_eq!(map.get_particle_at(acid_pos), water);
+    }
+
+    /// Test that a move can be computed from a read-only context without changing any state
+    #[test]
+    fn test_read_only_step_has_no_side_effects() {
+        let water = Liquid::Water(Direction::Left.into());
+        let position = UVec2::new(10, 10);
+        let mut map = Map::empty(64, 64);
+        map.set_particle_at(position, Some(Particle::Liquid(water)));
+        let hash = map.world_hash();
+
+        let rules = InteractionRules::default();
+        let queue = DashMap::new();
+        let chunk = map.get_chunk_at(&UVec2::ZERO);
+        let context = ReadOnlySimulationContext::new(&map, &rules, chunk, &queue, chunk.cells());
+
+        let target = match FluidSimulator.calculate_step(&context, water, position.x, position.y) {
+            MoveResult::Move(target, _) => target,
+            MoveResult::Preserve { .. } => panic!("Falling into air should not interact"),
+        };
+        assert!(
+            target.y < position.y,
+            "Water should move down, not to {}",
+            target
+        );
+
+        assert_eq!(map.world_hash(), hash, "The map should be untouched");
+        assert!(queue.is_empty(), "Nothing should be queued");
+        assert_eq!(map.get_particle_at(position), Some(Particle::Liquid(water)));
+    }
+
+    /// Test that a dropped column of water settles without leaving any liquid floating mid-air
+    #[test]
+    fn test_dropped_water_settles_without_floating() {
+        let mut map = Map::empty(64, 64);
+        for y in 30..40 {
+            map.set_particle_at(
+                UVec2::new(20, y),
+                Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+            );
+        }
+        // Water spreading into an inactive chunk would freeze there, so simulate everything.
+        for x in 0..2 {
+            for y in 0..2 {
+                map.active_chunks.insert(UVec2::new(x, y));
+            }
+        }
+        let rules = InteractionRules::default();
+
+        assert!(
+            !map.find_floating_fluids().is_empty(),
+            "The dropped column starts mid-air"
+        );
+
+        for _ in 0..200 {
+            map.update_dirty_chunks();
+            map.simulate_active_chunks(&rules);
+        }
+
+        assert_eq!(map.find_floating_fluids(), Vec::<UVec2>::new());
+    }
+
+    /// Test that water dropped down an empty column lands on top of a solid floor
+    #[test]
+    fn test_drop_column_lands_on_floor() {
+        // Fluids can skip over thin obstacles, so fill everything below the floor surface.
+        let mut map = Map::empty(64, 64);
+        for x in 0..map.width {
+            for y in 0..=10 {
+                map.set_particle_at(UVec2::new(x, y), Some(Particle::Common(Common::Stone)));
+            }
+        }
+        let rules = InteractionRules::default();
+
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let rest = map
+            .drop_column(20, water, &rules)
+            .expect("Water should not be consumed by stone");
+        assert_eq!(rest.y, 11);
+    }
+
+    /// Counts every liquid cell on the map.
+    fn count_liquids(map: &Map) -> usize {
+        (0..map.width)
+            .flat_map(|x| (0..map.height).map(move |y| UVec2::new(x, y)))
+            .filter(|&pos| matches!(map.get_particle_at(pos), Some(Particle::Liquid(_))))
+            .count()
+    }
+
+    /// Test that deleting cells under falling water never creates water out of thin air
+    #[test]
+    fn test_deleting_under_falling_water_conserves_it() {
+        let rules = InteractionRules::default();
+
+        // Randomized movement means a dup may only show up on some runs, so try a few.
+        for _ in 0..10 {
+            let mut map = Map::empty(64, 64);
+            for x in 0..map.width {
+                for y in 0..=4 {
+                    map.set_particle_at(UVec2::new(x, y), Some(Particle::Common(Common::Stone)));
+                }
+            }
+            // A block of water straddling all four chunks.
+            for x in 24..40 {
+                for y in 28..60 {
+                    map.set_particle_at(
+                        UVec2::new(x, y),
+                        Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+                    );
+                }
+            }
+
+            let mut count = count_liquids(&map);
+            for step in 0..120u32 {
+                // Carve a 5x5 hole, like the delete tool, sweeping through the falling column.
+                if step % 3 == 0 {
+                    let center = UVec2::new(26 + step % 12, 8 + step % 40);
+                    for x in center.x - 2..=center.x + 2 {
+                        for y in center.y - 2..=center.y + 2 {
+                            map.set_particle_at(UVec2::new(x, y), None);
+                        }
+                    }
+                    let after_delete = count_liquids(&map);
+                    assert!(after_delete <= count);
+                    count = after_delete;
+                }
+
+                map.step_n(&rules, 1);
+                let after_step = count_liquids(&map);
+                assert!(
+                    after_step <= count,
+                    "Water went from {} to {} on step {}",
+                    count,
+                    after_step,
+                    step
+                );
+                count = after_step;
+            }
+        }
+    }
+
+    /// Test that water splashing across chunk borders is never lost or duplicated, however the
+    /// moves between chunks conflict
+    #[test]
+    fn test_liquid_is_conserved_across_chunk_borders() {
+        let rules = InteractionRules::default();
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let mut map = Map::empty(64, 64);
+        // A pillar in the middle splits falling water between the chunks on either side.
+        for y in 0..24 {
+            for x in 30..34 {
+                map.set_particle_at(UVec2::new(x, y), Some(Particle::Common(Common::Stone)));
+            }
+        }
+        for x in 16..48 {
+            for y in 24..48 {
+                map.set_particle_at(UVec2::new(x, y), Some(water));
+            }
+        }
+        let water_count = count_liquids(&map);
+
+        for tick in 0..10_000 {
+            // Keep the water moving by lifting the lowest water in each column back to the top,
+            // so it keeps falling through the borders instead of settling.
+            if tick % 100 == 0 {
+                for x in (0..64).step_by(3) {
+                    let lowest = (0..63)
+                        .map(|y| UVec2::new(x, y))
+                        .find(|&pos| map.get_particle_at(pos) == Some(water));
+                    let top = UVec2::new(x, 63);
+                    if let (Some(lowest), None) = (lowest, map.get_particle_at(top)) {
+                        map.set_particle_at(lowest, None);
+                        map.set_particle_at(top, Some(water));
+                    }
+                }
+            }
+
+            map.step_n(&rules, 1);
+            assert_eq!(
+                count_liquids(&map),
+                water_count,
+                "Water count changed on tick {}",
+                tick
+            );
+        }
+    }
+
+    /// Test that interactions with a particle across a chunk border put the result at the target
+    /// and leave the right particle behind at the source
+    #[test]
+    fn test_interactions_resolve_across_chunk_borders() {
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let stone = Particle::Common(Common::Stone);
+        let obsidian = Particle::Solid(Solid::Obsidian);
+        let steam = Particle::Gas(Gas::Steam(GasState::default()));
+        let rule = |interaction_type, byproduct| InteractionRule {
+            interaction_type,
+            result: obsidian,
+            byproduct,
+            probability: 1.0,
+            cooldown: 0,
+            symmetric: false,
+        };
+        // Water resting on the chunk border, on top of stone filling the chunks below.
+        let source = UVec2::new(10, 32);
+        let react = |rule| {
+            let mut rules = InteractionRules::default();
+            rules.register(water, stone, rule);
+            let mut map = Map::empty(64, 64);
+            for x in 0..map.width {
+                for y in 0..source.y {
+                    map.set_particle_at(UVec2::new(x, y), Some(stone));
+                }
+            }
+            map.set_particle_at(source, Some(water));
+            map.step_n(&rules, 1);
+            let obsidian_count = (0..map.width)
+                .flat_map(|x| (0..source.y).map(move |y| UVec2::new(x, y)))
+                .filter(|&pos| map.get_particle_at(pos) == Some(obsidian))
+                .count();
+            (map.get_particle_at(source), obsidian_count)
+        };
+
+        assert_eq!(react(rule(InteractionType::Replace, None)), (None, 1));
+        assert_eq!(
+            react(rule(InteractionType::Replace, Some(steam))),
+            (Some(steam), 1)
+        );
+        assert_eq!(
+            react(rule(InteractionType::Preserve, None)),
+            (Some(water), 1)
+        );
+    }
+
+    /// Test that the conservation check catches lost particles but allows for interactions
+    #[test]
+    fn test_conservation_check_ignores_legitimate_conversions() {
+        let rules = InteractionRules::default();
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let mut map = Map::empty(64, 64);
+        for x in 20..44 {
+            for y in 20..30 {
+                map.set_particle_at(UVec2::new(x, y), Some(water));
+            }
+        }
+
+        let before = ParticleCensus::of(&map);
+        assert_eq!(before.count("Water"), 240);
+        map.step_n(&rules, 20);
+        assert!(before
+            .mismatches(&ParticleCensus::of(&map), &rules)
+            .is_empty());
+
+        // A particle vanishing out from under the simulation is reported.
+        let before = ParticleCensus::of(&map);
+        let lost = (0..64)
+            .flat_map(|x| (0..64).map(move |y| UVec2::new(x, y)))
+            .find(|&pos| map.get_particle_at(pos) == Some(water))
+            .unwrap();
+        map.set_particle_at(lost, None);
+        assert_eq!(
+            before.mismatches(&ParticleCensus::of(&map), &rules),
+            vec![ConservationMismatch {
+                name: "Water",
+                before: 240,
+                after: 239,
+            }]
+        );
+
+        // Water boiling away on lava is a legitimate conversion.
+        let lava = Particle::Liquid(Liquid::Lava(Direction::Left.into()));
+        map.set_particle_at(UVec2::new(30, 50), Some(lava));
+        let before = ParticleCensus::of(&map);
+        assert!(before.may_convert(&rules).contains("Water"));
+        assert!(!before.may_convert(&rules).contains("Stone"));
+        map.step_n(&rules, 20);
+        assert!(before
+            .mismatches(&ParticleCensus::of(&map), &rules)
+            .is_empty());
+    }
+
+    /// Test that moves targeting the same cell are deduplicated, keeping the closest one
+    #[test]
+    fn test_interchunk_queue_dedups_targets() {
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let targets = [UVec2::new(32, 10), UVec2::new(32, 11), UVec2::new(31, 32)];
+        let queue = DashMap::new();
+
+        for target in targets {
+            for offset in (1..=4).rev() {
+                ParticleMove {
+                    source_pos: target - UVec2::new(offset, 0),
+                    target_pos: target,
+                    particle: water,
+                    source_particle: Some(water),
+                    source_result: None,
+                    replace_target: false,
+                }
+                .enqueue(&queue);
+            }
+        }
+
+        assert_eq!(queue.len(), targets.len());
+        for target in targets {
+            assert_eq!(queue.get(&target).unwrap().source_pos, target - UVec2::X);
+        }
+    }
+
+    /// Test that the recorded interchunk queue never exceeds the cells moves could target
+    #[test]
+    fn test_interchunk_queue_len_is_bounded() {
+        let rules = InteractionRules::default();
+        let mut map = Map::empty(64, 64);
+        map.activate_all_chunks();
+        // A full-width sheet of water resting on the chunk border, all of it trying to fall at once.
+        for x in 0..64 {
+            map.set_particle_at(
+                UVec2::new(x, 32),
+                Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+            );
+        }
+
+        map.step_n(&rules, 1);
+
+        assert!(map.interchunk_queue_len() > 0);
+        assert!(map.interchunk_queue_len() <= 64);
+        assert_eq!(map.peak_interchunk_queue_len(), map.interchunk_queue_len());
+    }
+
+    /// Fills the 8x8 box from (6, 6) to (13, 13) with `fill(x, y)`, and walls it in with stone
+    /// from (0, 0) to (19, 19). The walls are thicker than the furthest a liquid can jump in one
+    /// step, so a completely full box only lets its contents trade places.
+    fn fill_sealed_box(map: &mut Map, fill: impl Fn(u32, u32) -> Option<Particle>) {
+        for x in 0..20 {
+            for y in 0..20 {
+                let particle = if !(6..14).contains(&x) || !(6..14).contains(&y) {
+                    Some(Particle::Common(Common::Stone))
+                } else {
+                    fill(x, y)
+                };
+                map.set_particle_at(UVec2::new(x, y), particle);
+            }
+        }
+    }
+
+    /// Test that oil poured under water rises through it until it floats on top
+    #[test]
+    fn test_oil_floats_on_water() {
+        let rules = InteractionRules::default();
+        let mut map = Map::empty(32, 32);
+        fill_sealed_box(&mut map, |_, y| {
+            let liquid = if y < 10 {
+                Liquid::Oil(Direction::Left.into())
+            } else {
+                Liquid::Water(Direction::Left.into())
+            };
+            Some(Particle::Liquid(liquid))
+        });
+
+        map.step_n(&rules, 200);
+
+        let heights_of = |matches: fn(&Particle) -> bool| -> Vec<u32> {
+            (6..14)
+                .flat_map(|x| (6..14).map(move |y| UVec2::new(x, y)))
+                .filter(|&pos| map.get_particle_at(pos).as_ref().is_some_and(matches))
+                .map(|pos| pos.y)
+                .collect()
+        };
+        let water = heights_of(|p| matches!(p, Particle::Liquid(Liquid::Water(_))));
+        let oil = heights_of(|p| matches!(p, Particle::Liquid(Liquid::Oil(_))));
+        assert_eq!(
+            (water.len(), oil.len()),
+            (32, 32),
+            "No liquid should be lost"
+        );
+        assert!(
+            water.iter().max() < oil.iter().min(),
+            "All water should have sunk below the oil"
+        );
+    }
+
+    /// Test that oil touching lava catches fire while the lava survives
+    #[test]
+    fn test_oil_ignites_next_to_lava() {
+        let stone = Some(Particle::Common(Common::Stone));
+        let oil = Particle::Liquid(Liquid::Oil(Direction::Still.into()));
+        let lava = Some(Particle::Liquid(Liquid::Lava(Direction::Still.into())));
+        let (oil_pos, lava_pos) = (UVec2::new(5, 2), UVec2::new(6, 2));
+        assert!(oil.is_flammable());
+
+        let mut map = Map::empty(32, 32);
+        for x in 0..32 {
+            map.set_particle_at(UVec2::new(x, 1), stone);
+        }
+        map.set_particle_at(oil_pos, Some(oil));
+        map.set_particle_at(lava_pos, lava);
+        map.active_chunks.insert(UVec2::ZERO);
+        map.update_dirty_chunks();
+
+        map.process_interactions(&InteractionRules::default());
+
+        assert!(matches!(
+            map.get_particle_at(oil_pos),
+            Some(Particle::Fire(_))
+        ));
+        assert_eq!(map.get_particle_at(lava_pos), lava);
+    }
+
+    /// Test that wood next to lava catches fire as a slow ember that leaves ash behind
+    #[test]
+    fn test_wood_burns_into_ash_next_to_lava() {
+        let rules = InteractionRules::default();
+        let stone = Some(Particle::Common(Common::Stone));
+        let lava = Some(Particle::Liquid(Liquid::Lava(Direction::Still.into())));
+        let wood_pos = UVec2::new(5, 6);
+
+        // The lava sits in a thick stone basin with the wood as its left wall.
+        let mut map = Map::empty(32, 32);
+        for x in 0..20 {
+            for y in 0..6 {
+                map.set_particle_at(UVec2::new(x, y), stone);
+            }
+        }
+        for x in 7..20 {
+            map.set_particle_at(UVec2::new(x, 6), stone);
+        }
+        map.set_particle_at(wood_pos, Some(Particle::Solid(Solid::Wood)));
+        map.set_particle_at(UVec2::new(6, 6), lava);
+        map.active_chunks.insert(UVec2::ZERO);
+
+        let mut ticks = 0;
+        while map.get_particle_at(wood_pos) == Some(Particle::Solid(Solid::Wood)) {
+            assert!(ticks < 1000, "Wood should catch fire next to lava");
+            map.update_dirty_chunks();
+            map.simulate_active_chunks(&rules);
+            map.process_combustion();
+            ticks += 1;
+        }
+        assert!(matches!(
+            map.get_particle_at(wood_pos),
+            Some(Particle::Fire(Fire::Ember(_)))
+        ));
+
+        map.step_n(&rules, Fire::EMBER_LIFETIME as usize + 10);
+        assert_eq!(
+            map.get_particle_at(wood_pos),
+            Some(Particle::Powder(Powder::Ash))
+        );
+        assert_eq!(map.get_particle_at(UVec2::new(6, 6)), lava);
+    }
+
+    /// Test that fire gives off smoke into the cell above it when configured to always smoke
+    #[test]
+    fn test_fire_emits_smoke() {
+        let rules = InteractionRules::default();
+        let mut map = Map::empty(32, 32);
+        map.combustion = CombustionSettings {
+            spread_chance: 0.0,
+            smoke_chance: 1.0,
+        };
+        map.set_particle_at(UVec2::new(16, 0), Some(Particle::Fire(Fire::default())));
+
+        map.step_n(&rules, 1);
+
+        assert!(matches!(
+            map.get_particle_at(UVec2::new(16, 1)),
+            Some(Particle::Gas(Gas::Smoke(_)))
+        ));
+        assert!(matches!(
+            map.get_particle_at(UVec2::new(16, 0)),
+            Some(Particle::Fire(_))
+        ));
+    }
+
+    /// Drops `count` grains of `powder` onto the middle of a stone floor and returns the height
+    /// of every column afterwards, counted from the floor.
+    fn pile_powder(powder: Powder, count: usize) -> Vec<u32> {
+        let rules = InteractionRules::default();
+        let mut map = Map::empty(32, 32);
+        for x in 0..map.width {
+            map.set_particle_at(UVec2::new(x, 0), Some(Particle::Common(Common::Stone)));
+        }
+        for _ in 0..count {
+            map.drop_column(16, Particle::Powder(powder), &rules);
+        }
+
+        (0..map.width)
+            .map(|x| {
+                (1..map.height)
+                    .filter(|&y| map.get_particle_at(UVec2::new(x, y)).is_some())
+                    .count() as u32
+            })
+            .collect()
+    }
+
+    /// Test that powders fall into supported piles whose steepness depends on the powder
+    #[test]
+    fn test_powder_piles_with_angle_of_repose() {
+        let sand = pile_powder(Powder::Sand, 16);
+        let gravel = pile_powder(Powder::Gravel, 16);
+
+        for heights in [&sand, &gravel] {
+            assert_eq!(heights.iter().sum::<u32>(), 16, "No grain should be lost");
+            // Grains slide off instead of stacking in a single column.
+            assert!(heights.iter().filter(|&&h| h > 0).count() > 1);
+        }
+        // Neighboring sand columns never differ by more than a single step.
+        assert!(sand.windows(2).all(|w| w[0].abs_diff(w[1]) <= 1));
+        assert!(
+            gravel.iter().max() > sand.iter().max(),
+            "Gravel should pile up steeper than sand"
+        );
+    }
+
+    /// Test that sand sinks through water by trading places with it instead of destroying it
+    #[test]
+    fn test_sand_sinks_through_water() {
+        let rules = InteractionRules::default();
+        let mut map = Map::empty(32, 32);
+        // A sand layer on top of a water pool.
+        fill_sealed_box(&mut map, |_, y| {
+            if y >= 12 {
+                Some(Particle::Powder(Powder::Sand))
+            } else {
+                Some(Particle::Liquid(Liquid::Water(Direction::Left.into())))
+            }
+        });
+        let water_before = count_liquids(&map);
+
+        map.step_n(&rules, 100);
+
+        assert_eq!(count_liquids(&map), water_before);
+        for x in 6..14 {
+            for y in 6..8 {
+                assert_eq!(
+                    map.get_particle_at(UVec2::new(x, y)),
+                    Some(Particle::Powder(Powder::Sand)),
+                    "Sand should have sunk to the bottom at ({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    /// Counts every gas cell on the map.
+    fn count_gases(map: &Map) -> usize {
+        (0..map.width)
+            .flat_map(|x| (0..map.height).map(move |y| UVec2::new(x, y)))
+            .filter(|&pos| matches!(map.get_particle_at(pos), Some(Particle::Gas(_))))
+            .count()
+    }
+
+    /// Test that water touching lava boils into steam while the lava cools and hardens
+    #[test]
+    fn test_water_and_lava_boil_into_steam() {
+        let water = Some(Particle::Liquid(Liquid::Water(Direction::Still.into())));
+        let lava = Some(Particle::Liquid(Liquid::Lava(Direction::Still.into())));
+        let (water_pos, lava_pos) = (UVec2::new(5, 2), UVec2::new(6, 2));
+
+        let mut map = Map::empty(32, 32);
+        map.set_particle_at(water_pos, water);
+        map.set_particle_at(lava_pos, lava);
+        map.active_chunks.insert(UVec2::ZERO);
+        map.update_dirty_chunks();
+
+        // There is no interaction rule for the pair, only their temperatures react.
+        map.process_interactions(&InteractionRules::default());
+        assert_eq!(map.get_particle_at(water_pos), water);
+        assert_eq!(map.get_particle_at(lava_pos), lava);
+
+        map.diffuse_heat();
+
+        assert!(matches!(
+            map.get_particle_at(water_pos),
+            Some(Particle::Gas(Gas::Steam(_)))
+        ));
+        assert_eq!(
+            map.get_particle_at(lava_pos),
+            Some(Particle::Solid(Solid::Obsidian))
+        );
+    }
+
+    /// Test that lava heats the stone next to it, which cools back down once the lava is gone
+    #[test]
+    fn test_lava_heats_neighbors_until_removed() {
+        let lava = Some(Particle::Liquid(Liquid::Lava(Direction::Still.into())));
+        let (lava_pos, stone_pos) = (UVec2::new(10, 10), UVec2::new(11, 10));
+
+        let mut map = Map::empty(32, 32);
+        map.set_particle_at(lava_pos, lava);
+        map.set_particle_at(stone_pos, Some(Particle::Common(Common::Stone)));
+        map.active_chunks.insert(UVec2::ZERO);
+        map.update_dirty_chunks();
+        assert_eq!(map.get_temperature_at(stone_pos), AMBIENT_TEMPERATURE);
+
+        for _ in 0..100 {
+            map.diffuse_heat();
+        }
+        let heated = map.get_temperature_at(stone_pos);
+        assert!(
+            heated > AMBIENT_TEMPERATURE + 100.0 && heated < Liquid::LAVA_TEMPERATURE,
+            "Stone next to lava should warm up, but is at {}",
+            heated
+        );
+        assert_eq!(map.get_particle_at(lava_pos), lava);
+
+        map.set_particle_at(lava_pos, None);
+        map.update_dirty_chunks();
+        for _ in 0..2000 {
+            map.diffuse_heat();
+        }
+        assert!(map.get_chunk_at(&UVec2::ZERO).temperatures().is_none());
+        assert_eq!(map.get_temperature_at(stone_pos), AMBIENT_TEMPERATURE);
+    }
+
+    /// A map with a steam particle under a stone ceiling, above a stone floor.
+    fn steam_under_ceiling() -> Map {
+        let mut map = Map::empty(32, 32);
+        for x in 0..map.width {
+            map.set_particle_at(UVec2::new(x, 0), Some(Particle::Common(Common::Stone)));
+            map.set_particle_at(UVec2::new(x, 20), Some(Particle::Common(Common::Stone)));
+        }
+        map.set_particle_at(UVec2::new(16, 5), Some(Particle::Gas(Gas::default())));
+        map
+    }
+
+    /// Test that steam rises to a ceiling and condenses back into water after lingering there as
+    /// long as its definition says
+    #[test]
+    fn test_steam_rises_and_condenses_under_ceiling() {
+        let rules = InteractionRules::default();
+        let mut map = steam_under_ceiling();
+
+        // Rising one cell per tick, the steam reaches the ceiling but doesn't condense yet.
+        map.step_n(&rules, 16);
+        let steam: Vec<UVec2> = (0..map.width)
+            .map(|x| UVec2::new(x, 19))
+            .filter(|&pos| matches!(map.get_particle_at(pos), Some(Particle::Gas(_))))
+            .collect();
+        assert_eq!(steam.len(), 1, "Steam should be right under the ceiling");
+        assert_eq!(count_liquids(&map), 0);
+
+        let condense_ticks = map.particles().condense_ticks(Gas::default().into());
+        map.step_n(&rules, condense_ticks as usize + 5);
+        assert_eq!(count_gases(&map), 0);
+        assert_eq!(count_liquids(&map), 1, "Steam should condense into water");
+
+        // Steam defined to never condense stays steam.
+        let mut definitions =
+            ParticleDefinitions::from_toml(include_str!("../assets/particles.toml")).unwrap();
+        definitions
+            .particles
+            .get_mut("Steam")
+            .unwrap()
+            .condense_ticks = 0;
+        let mut map = steam_under_ceiling();
+        map.set_particles(ParticleRegistry::from_definitions(&definitions).unwrap());
+        map.step_n(&rules, 16 + condense_ticks as usize + 5);
+        assert_eq!(count_gases(&map), 1);
+        assert_eq!(count_liquids(&map), 0);
+    }
+
+    /// Test that gases other than steam simply dissipate once their lifetime runs out
+    #[test]
+    fn test_smoke_dissipates() {
+        let rules = InteractionRules::default();
+        let smoke = Gas::Smoke(GasState::default()).fresh();
+        let mut map = Map::empty(32, 32);
+        map.set_particle_at(UVec2::new(16, 0), Some(Particle::Gas(smoke)));
+
+        map.step_n(&rules, 10);
+        assert_eq!(count_gases(&map), 1);
+
+        map.step_n(&rules, smoke.get_max_lifetime() as usize);
+        assert_eq!(count_gases(&map), 0);
+        assert_eq!(count_liquids(&map), 0);
+    }
+
+    /// Test that the same simulation seed always makes the same random choices
+    #[test]
+    fn test_simulation_is_deterministic_for_seed() {
+        let rules = InteractionRules::default();
+        // Water and sand piling up in a single chunk take plenty of random tie-breaks.
+        let run = |seed: u64| {
+            let mut map = Map::empty(32, 32);
+            map.set_simulation_seed(seed);
+            for x in 8..24 {
+                for y in 10..14 {
+                    let water = Liquid::Water(Direction::Left.into());
+                    map.set_particle_at(UVec2::new(x, y), Some(Particle::Liquid(water)));
+                    let sand = Particle::Powder(Powder::Sand);
+                    map.set_particle_at(UVec2::new(x, y + 10), Some(sand));
+                }
+            }
+            let mut hashes = Vec::new();
+            for _ in 0..30 {
+                map.step_n(&rules, 1);
+                hashes.push(map.world_hash());
+            }
+            assert_eq!(map.simulation_tick(), 30);
+            hashes
+        };
+
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+
+        let map = Map::empty(32, 32);
+        let position = UVec2::new(3, 4);
+        assert_eq!(
+            map.particle_rng(position).random::<u64>(),
+            map.particle_rng(position).random::<u64>()
+        );
+        assert_ne!(
+            map.particle_rng(position).random::<u64>(),
+            map.particle_rng(position + UVec2::X).random::<u64>()
+        );
+    }
+
+    /// Test that water in one arm of a U-bend flows over until both arms share a surface height
+    #[test]
+    fn test_water_levels_out_across_u_bend() {
+        let rules = InteractionRules::default();
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let mut map = Map::empty(64, 64);
+        // Two arms joined by a channel along the bottom. The walls are thicker than the furthest
+        // a liquid can jump in one step.
+        let left_arm = 10..16;
+        let right_arm = 40..46;
+        for x in 0..64 {
+            for y in 0..64 {
+                let open = (y >= 10 && (left_arm.contains(&x) || right_arm.contains(&x)))
+                    || ((10..46).contains(&x) && (10..16).contains(&y));
+                let particle = if !open {
+                    Some(Particle::Common(Common::Stone))
+                } else if y < 16 || (left_arm.contains(&x) && y < 44) {
+                    Some(water)
+                } else {
+                    None
+                };
+                map.set_particle_at(UVec2::new(x, y), particle);
+            }
+        }
+        let water_count = count_liquids(&map);
+
+        let surface = |map: &Map, arm: &std::ops::Range<u32>| -> u32 {
+            arm.clone()
+                .flat_map(|x| (16..64).map(move |y| UVec2::new(x, y)))
+                .filter(|&pos| map.get_particle_at(pos).is_some())
+                .map(|pos| pos.y)
+                .max()
+                .unwrap_or(15)
+        };
+        assert_eq!(
+            (surface(&map, &left_arm), surface(&map, &right_arm)),
+            (43, 15)
+        );
+
+        map.activate_all_chunks();
+        for _ in 0..300 {
+            map.update_dirty_chunks();
+            map.simulate_active_chunks(&rules);
+            map.equalize_liquids();
+        }
+
+        assert_eq!(count_liquids(&map), water_count, "No water should be lost");
+        let (left, right) = (surface(&map, &left_arm), surface(&map, &right_arm));
+        assert!(
+            left.abs_diff(right) <= 2,
+            "The arms should level out, but the surfaces are at {} and {}",
+            left,
+            right
+        );
+        assert!((27..=31).contains(&left), "Surface at {}", left);
+
+        // Level bodies are left alone, until more water poured into one arm raises both.
+        for x in left_arm.clone() {
+            for y in 50..54 {
+                map.set_particle_at(UVec2::new(x, y), Some(water));
+            }
+        }
+        for _ in 0..300 {
+            map.update_dirty_chunks();
+            map.simulate_active_chunks(&rules);
+            map.equalize_liquids();
+        }
+        let (raised_left, raised_right) = (surface(&map, &left_arm), surface(&map, &right_arm));
+        assert!(raised_left.abs_diff(raised_right) <= 2);
+        assert!(raised_right > right, "The far arm should rise too");
+    }
+
+    /// Test that two bodies leveling into the same empty cell don't overwrite each other
+    #[test]
+    fn test_separate_bodies_sharing_a_target_conserve_liquid() {
+        let water = Particle::Liquid(Liquid::Water(Direction::Still.into()));
+        let oil = Particle::Liquid(Liquid::Oil(Direction::Still.into()));
+        let mut map = Map::empty(32, 32);
+        // A column of water and a column of oil on either side of an open shaft, whose floor is
+        // the only cell either of them can level into.
+        for x in 0..32 {
+            for y in 0..32 {
+                let particle = match (x, y) {
+                    (10, 10..30) => Some(water),
+                    (12, 10..30) => Some(oil),
+                    (10..=12, 10..) => None,
+                    _ => Some(Particle::Common(Common::Stone)),
+                };
+                map.set_particle_at(UVec2::new(x, y), particle);
+            }
+        }
+        let liquid_count = count_liquids(&map);
+
+        map.activate_all_chunks();
+        map.update_dirty_chunks();
+        map.equalize_liquids();
+
+        assert!(
+            map.get_particle_at(UVec2::new(11, 10)).is_some(),
+            "One of the bodies should level into the shared cell"
+        );
+        assert_eq!(
+            count_liquids(&map),
+            liquid_count,
+            "No liquid should be lost"
+        );
+    }
+
+    /// Test that acid dissolves the stone under it over time while bedrock holds
+    #[test]
+    fn test_acid_gradually_dissolves_stone() {
+        let rules = InteractionRules::default();
+        let mut map = Map::empty(32, 32);
+        let acid = Particle::Liquid(Liquid::Acid(Direction::Left.into()));
+        let stone = UVec2::new(10, 10);
+        let bedrock = UVec2::new(11, 10);
+        map.set_particle_at(stone, Some(Particle::Common(Common::Stone)));
+        map.set_particle_at(bedrock, Some(Particle::Common(Common::Bedrock)));
+        map.set_particle_at(UVec2::new(10, 11), Some(acid));
+        map.set_particle_at(UVec2::new(11, 11), Some(acid));
+        map.activate_all_chunks();
+        map.update_dirty_chunks();
+
+        map.process_erosion(&rules);
+        assert_eq!(map.get_wear_at(stone), Liquid::ACID_EROSION_RATE);
+        assert_eq!(
+            map.get_particle_at(stone),
+            Some(Particle::Common(Common::Stone))
+        );
+
+        // Stone is 4 hard, so it gives way after about 80 ticks of erosion.
+        let ticks = (2..=100).find(|_| {
+            map.process_erosion(&rules);
+            map.get_particle_at(stone).is_none()
+        });
+        assert!(
+            ticks.is_some_and(|ticks| (80..=81).contains(&ticks)),
+            "{:?}",
+            ticks
+        );
+        assert_eq!(map.get_wear_at(bedrock), 0.0);
+        assert_eq!(
+            map.get_particle_at(bedrock),
+            Some(Particle::Common(Common::Bedrock))
+        );
+
+        // Water only wears dirt, and far more slowly.
+        let water = Liquid::Water(Direction::Left.into());
+        assert_eq!(
+            water.get_erosion_rate(Particle::Common(Common::Dirt)),
+            Liquid::WATER_EROSION_RATE
+        );
+        assert_eq!(water.get_erosion_rate(Particle::Common(Common::Stone)), 0.0);
+    }
+
+    /// Test that acid used up dissolving one neighbor doesn't go on to wear the others
+    #[test]
+    fn test_used_up_acid_stops_eroding() {
+        let stone = Particle::Common(Common::Stone);
+        let acid = Particle::Liquid(Liquid::Acid(Direction::Still.into()));
+        let mut rules = InteractionRules::default();
+        let mut rule = *rules
+            .get(&InteractionPair {
+                source: acid,
+                target: stone,
+            })
+            .unwrap();
+        rule.probability = 1.0;
+        rules.register(acid, stone, rule);
+
+        let mut map = Map::empty(32, 32);
+        let (left, right) = (UVec2::new(9, 10), UVec2::new(11, 10));
+        for position in [left, right] {
+            map.set_particle_at(position, Some(stone));
+            // One more tick of erosion wears the stone through.
+            map.wear_particle_at(position, 4.0 - Liquid::ACID_EROSION_RATE / 2.0);
+        }
+        map.set_particle_at(UVec2::new(10, 10), Some(acid));
+        map.activate_all_chunks();
+        map.update_dirty_chunks();
+
+        map.process_erosion(&rules);
+        let dissolved = [left, right]
+            .iter()
+            .filter(|&&position| map.get_particle_at(position).is_none())
+            .count();
+        assert_eq!(dissolved, 1, "The acid is used up by the first stone");
+        assert_ne!(map.get_particle_at(UVec2::new(10, 10)), Some(acid));
+    }
+
+    /// Test that acid dissolves the terrain it touches by wearing it through rather than on
+    /// contact, reaching into chunks that aren't simulating, and is sometimes used up in the
+    /// process, giving off toxic fumes
+    #[test]
+    fn test_acid_dissolves_terrain_into_fumes() {
+        let rules = InteractionRules::default();
+        let stone = Particle::Common(Common::Stone);
+        let acid = Particle::Liquid(Liquid::Acid(Direction::Still.into()));
+        let mut map = Map::empty(64, 64);
+        for x in 0..64 {
+            for y in 0..10 {
+                map.set_particle_at(UVec2::new(x, y), Some(stone));
+            }
+        }
+        // A wall of stone across the chunk border from the acid.
+        for x in 32..64 {
+            for y in 10..20 {
+                map.set_particle_at(UVec2::new(x, y), Some(stone));
+            }
+        }
+        for x in 24..32 {
+            for y in 10..14 {
+                map.set_particle_at(UVec2::new(x, y), Some(acid));
+            }
+        }
+        map.activate_all_chunks();
+
+        let count = |map: &Map, kind: fn(&Particle) -> bool| {
+            (0..64)
+                .flat_map(|x| (0..64).map(move |y| UVec2::new(x, y)))
+                .filter(|&pos| map.get_particle_at(pos).as_ref().is_some_and(kind))
+                .count()
+        };
+        let is_stone = |p: &Particle| *p == Particle::Common(Common::Stone);
+        let is_acid = |p: &Particle| matches!(p, Particle::Liquid(Liquid::Acid(_)));
+        let is_fumes = |p: &Particle| matches!(p, Particle::Gas(Gas::ToxicFumes(_)));
+        let stone_count = count(&map, is_stone);
+        let acid_count = count(&map, is_acid);
+
+        // Touching isn't enough, the stone has to be worn through first.
+        map.step(&rules, 1);
+        assert_eq!(count(&map, is_stone), stone_count);
+        assert_eq!(count(&map, is_acid), acid_count);
+        // The acid keeps its own chunk simulating, while the wall's chunk rests.
+        assert!(map.get_chunk_at(&UVec2::new(0, 0)).should_simulate);
+        assert!(!map.get_chunk_at(&UVec2::new(1, 0)).should_simulate);
+
+        map.step(&rules, 200);
+        assert!(count(&map, is_stone) < stone_count);
+        assert!(
+            (10..14).any(|y| map.get_particle_at(UVec2::new(32, y)) != Some(stone)),
+            "The wall across the chunk border should be eaten into"
+        );
+        let remaining = count(&map, is_acid);
+        assert!(remaining < acid_count, "Some acid should be used up");
+        assert!(remaining > 0, "Not all acid should be used up");
+        assert!(count(&map, is_fumes) > 0);
+    }
+
+    /// Test that chunks of liquid that stopped moving settle, and wake back up along with their
+    /// neighbors once a cell next to them changes
+    #[test]
+    fn test_settled_liquid_chunks_sleep_until_disturbed() {
+        use cavernborn::world::settling::SETTLE_TICKS;
+
+        let rules = InteractionRules::default();
+        let water = Particle::Liquid(Liquid::Water(Direction::Still.into()));
+        let mut map = Map::empty(64, 64);
+        for x in 0..64 {
+            map.set_particle_at(UVec2::new(x, 0), Some(Particle::Common(Common::Stone)));
+            for y in 1..6 {
+                map.set_particle_at(UVec2::new(x, y), Some(water));
+            }
+        }
+        map.activate_all_chunks();
+        let (left, right) = (UVec2::new(0, 0), UVec2::new(1, 0));
+
+        map.step(&rules, SETTLE_TICKS + 5);
+        assert!(map.is_chunk_settled(left) && map.is_chunk_settled(right));
+        assert_eq!(map.settled_chunk_count(), 2);
+        assert_eq!(map.simulating_chunk_count(), 2);
+        let hash = map.world_hash();
+        map.step(&rules, 10);
+        assert_eq!(map.world_hash(), hash);
+
+        // A hole in the floor wakes the chunk, and the water drains into it.
+        map.set_particle_at(UVec2::new(10, 0), None);
+        assert!(!map.is_chunk_settled(left));
+        assert!(map.is_chunk_settled(right));
+        map.step(&rules, 3);
+        assert_eq!(map.get_particle_at(UVec2::new(10, 0)), Some(water));
+
+        // Changes on a chunk's edge wake the chunk across it too.
+        map.step(&rules, SETTLE_TICKS * 2);
+        assert_eq!(map.settled_chunk_count(), 2);
+        map.set_particle_at(UVec2::new(32, 0), None);
+        assert!(!map.is_chunk_settled(left) && !map.is_chunk_settled(right));
+        map.step(&rules, 3);
+        assert_eq!(map.get_particle_at(UVec2::new(32, 0)), Some(water));
+    }
+
+    /// Test that chunks holding gas never settle, so trapped steam still condenses and trapped
+    /// smoke still fades even though neither moves
+    #[test]
+    fn test_trapped_gas_keeps_aging_without_settling() {
+        use cavernborn::world::settling::SETTLE_TICKS;
+
+        let rules = InteractionRules::default();
+        let smoke = Gas::Smoke(GasState::default()).fresh();
+        let mut map = Map::empty(32, 32);
+        // Each gas sits in a one-cell pocket, so it can't rise or spread sideways. The pockets
+        // rest on a stone floor, so the condensed water can't fall through them either.
+        let (steam_pos, smoke_pos) = (UVec2::new(5, 1), UVec2::new(15, 1));
+        for x in 0..32 {
+            map.set_particle_at(UVec2::new(x, 0), Some(Particle::Common(Common::Stone)));
+        }
+        for center in [steam_pos, smoke_pos] {
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let pos = center.as_ivec2() + IVec2::new(dx, dy);
+                    map.set_particle_at(pos.as_uvec2(), Some(Particle::Common(Common::Stone)));
+                }
+            }
+        }
+        map.set_particle_at(steam_pos, Some(Particle::Gas(Gas::default())));
+        map.set_particle_at(smoke_pos, Some(Particle::Gas(smoke)));
+        map.activate_all_chunks();
+
+        map.step(&rules, SETTLE_TICKS + 5);
+        assert!(!map.is_chunk_settled(UVec2::ZERO));
+        assert!(
+            matches!(
+                map.get_particle_at(steam_pos),
+                Some(Particle::Liquid(Liquid::Water(_)))
+            ),
+            "Steam should condense into water"
+        );
+
+        map.step(&rules, smoke.get_max_lifetime() as u32);
+        assert_eq!(count_gases(&map), 0, "Smoke should fade");
+    }
+
+    /// Test that interaction rules with a probability take effect gradually, and that cells a
+    /// rule with a cooldown reacted in wait before reacting again
+    #[test]
+    fn test_interaction_probability_and_cooldown() {
+        let oil = Particle::Liquid(Liquid::Oil(Direction::Still.into()));
+        let stone = Particle::Common(Common::Stone);
+        let obsidian = Particle::Solid(Solid::Obsidian);
+        let rule = |result, probability, cooldown| InteractionRule {
+            interaction_type: InteractionType::Preserve,
+            result,
+            byproduct: None,
+            probability,
+            cooldown,
+            symmetric: true,
+        };
+        // A layer of oil covering a stone floor, too full to move sideways.
+        let scene = || {
+            let mut map = Map::empty(64, 64);
+            for x in 0..map.width {
+                map.set_particle_at(UVec2::new(x, 0), Some(stone));
+                map.set_particle_at(UVec2::new(x, 1), Some(oil));
+            }
+            map.activate_all_chunks();
+            map
+        };
+        let floor = |map: &Map, particle| {
+            (0..map.width)
+                .filter(|&x| map.get_particle_at(UVec2::new(x, 0)) == Some(particle))
+                .count()
+        };
+
+        let mut rules = InteractionRules::default();
+        rules.register(oil, stone, rule(obsidian, 0.1, 0));
+        let mut map = scene();
+        map.step(&rules, 1);
+        let converted = floor(&map, obsidian);
+        assert!(
+            converted > 0 && converted < 32,
+            "{converted} cells reacted at once"
+        );
+        map.step(&rules, 100);
+        assert_eq!(floor(&map, obsidian), 64);
+
+        // Stone turns into obsidian, which has to cool down before it turns back.
+        let mut rules = InteractionRules::default();
+        rules.register(oil, stone, rule(obsidian, 1.0, 10));
+        rules.register(oil, obsidian, rule(stone, 1.0, 0));
+        let mut map = scene();
+        map.step(&rules, 1);
+        assert_eq!(floor(&map, obsidian), 64);
+        assert!(!map.interaction_ready(UVec2::new(10, 0)));
+        assert!(map.interaction_ready(UVec2::new(10, 1)));
+        map.step(&rules, 9);
+        assert_eq!(floor(&map, obsidian), 64);
+        map.step(&rules, 1);
+        assert_eq!(floor(&map, stone), 64);
+        map.step(&rules, 1);
+        assert_eq!(floor(&map, obsidian), 64);
+    }
+
+    /// Test that directional interaction rules only apply when their source moves into their
+    /// target, while symmetric rules apply both ways
+    #[test]
+    fn test_directional_interaction_rules() {
+        use cavernborn::particle::interaction::InteractionPair;
+
+        let oil = Particle::Liquid(Liquid::Oil(Direction::Still.into()));
+        let stone = Particle::Common(Common::Stone);
+        let dirt = Particle::Common(Common::Dirt);
+        let obsidian = Particle::Solid(Solid::Obsidian);
+        let sand = Particle::Powder(Powder::Sand);
+        let rule = |result, symmetric| InteractionRule {
+            interaction_type: InteractionType::Preserve,
+            result,
+            byproduct: None,
+            probability: 1.0,
+            cooldown: 0,
+            symmetric,
+        };
+        let pair = |source, target| InteractionPair { source, target };
+
+        let mut rules = InteractionRules::default();
+        rules.register(stone, oil, rule(obsidian, false));
+        assert!(rules.get(&pair(stone, oil)).is_some());
+        assert!(rules.get(&pair(oil, stone)).is_none());
+
+        // Oil falling onto stone doesn't match the stone-into-oil rule.
+        let mut map = Map::empty(64, 64);
+        map.set_particle_at(UVec2::new(10, 0), Some(stone));
+        map.drop_column(10, oil, &rules);
+        assert_eq!(map.get_particle_at(UVec2::new(10, 0)), Some(stone));
+
+        // A symmetric rule replaces the directional one and applies both ways.
+        assert!(rules.register(oil, stone, rule(obsidian, true)).is_some());
+        assert_eq!(rules.len(), InteractionRules::default().len() + 1);
+        assert!(rules.get(&pair(stone, oil)).is_some());
+        let mut map = Map::empty(64, 64);
+        map.set_particle_at(UVec2::new(10, 0), Some(stone));
+        map.drop_column(10, oil, &rules);
+        assert_eq!(map.get_particle_at(UVec2::new(10, 0)), Some(obsidian));
+
+        // Touching particles react as if the upper one fell onto the lower one.
+        let mut rules = InteractionRules::default();
+        rules.register(stone, dirt, rule(obsidian, false));
+        rules.register(dirt, stone, rule(sand, false));
+        let mut map = Map::empty(64, 64);
+        map.set_particle_at(UVec2::new(5, 1), Some(dirt));
+        map.set_particle_at(UVec2::new(5, 2), Some(stone));
+        map.set_particle_at(UVec2::new(10, 1), Some(stone));
+        map.set_particle_at(UVec2::new(10, 2), Some(dirt));
+        // Keeps the chunk simulating.
+        map.set_particle_at(UVec2::new(20, 0), Some(oil));
+        map.activate_all_chunks();
+        map.update_dirty_chunks();
+        map.process_interactions(&rules);
+        assert_eq!(map.get_particle_at(UVec2::new(5, 1)), Some(obsidian));
+        assert_eq!(map.get_particle_at(UVec2::new(5, 2)), Some(stone));
+        assert_eq!(map.get_particle_at(UVec2::new(10, 1)), Some(sand));
+        assert_eq!(map.get_particle_at(UVec2::new(10, 2)), Some(dirt));
+
+        // A lone one-way rule only applies when its source rests on its target.
+        let mut rules = InteractionRules::default();
+        rules.register(stone, dirt, rule(obsidian, false));
+        let mut map = Map::empty(64, 64);
+        map.set_particle_at(UVec2::new(5, 1), Some(dirt));
+        map.set_particle_at(UVec2::new(5, 2), Some(stone));
+        map.set_particle_at(UVec2::new(10, 1), Some(stone));
+        map.set_particle_at(UVec2::new(10, 2), Some(dirt));
+        map.set_particle_at(UVec2::new(20, 0), Some(oil));
+        map.activate_all_chunks();
+        map.update_dirty_chunks();
+        map.process_interactions(&rules);
+        assert_eq!(map.get_particle_at(UVec2::new(5, 1)), Some(obsidian));
+        assert_eq!(map.get_particle_at(UVec2::new(10, 1)), Some(stone));
+        assert_eq!(map.get_particle_at(UVec2::new(10, 2)), Some(dirt));
+    }
+
+    /// Test that touching particles react across chunk borders, even when the chunk on the other
+    /// side isn't simulating
+    #[test]
+    fn test_interactions_cross_chunk_borders() {
+        let oil = Particle::Liquid(Liquid::Oil(Direction::Still.into()));
+        let stone = Particle::Common(Common::Stone);
+        let obsidian = Particle::Solid(Solid::Obsidian);
+        let mut rules = InteractionRules::default();
+        rules.register(
+            oil,
+            stone,
+            InteractionRule {
+                interaction_type: InteractionType::Preserve,
+                result: obsidian,
+                byproduct: None,
+                probability: 1.0,
+                cooldown: 0,
+                symmetric: true,
+            },
+        );
+
+        let mut map = Map::empty(96, 96);
+        // Stone left of and below chunk (1, 1), in chunks holding nothing else.
+        map.set_particle_at(UVec2::new(31, 40), Some(stone));
+        map.set_particle_at(UVec2::new(40, 31), Some(stone));
+        map.set_particle_at(UVec2::new(32, 40), Some(oil));
+        map.set_particle_at(UVec2::new(40, 32), Some(oil));
+        // Stone right of and above chunk (1, 1), in chunks that are simulating.
+        map.set_particle_at(UVec2::new(64, 50), Some(stone));
+        map.set_particle_at(UVec2::new(70, 50), Some(oil));
+        map.set_particle_at(UVec2::new(50, 64), Some(stone));
+        map.set_particle_at(UVec2::new(50, 70), Some(oil));
+        map.set_particle_at(UVec2::new(63, 50), Some(oil));
+        map.set_particle_at(UVec2::new(50, 63), Some(oil));
+        map.activate_all_chunks();
+        map.update_dirty_chunks();
+        assert!(!map.get_chunk_at(&UVec2::new(0, 1)).should_simulate);
+        assert!(!map.get_chunk_at(&UVec2::new(1, 0)).should_simulate);
+
+        map.process_interactions(&rules);
+        for position in [
+            UVec2::new(31, 40),
+            UVec2::new(40, 31),
+            UVec2::new(64, 50),
+            UVec2::new(50, 64),
+        ] {
+            assert_eq!(
+                map.get_particle_at(position),
+                Some(obsidian),
+                "at {position}"
+            );
+        }
+    }
+
+    /// Test that scenarios load an exact layout, reject malformed grids, and replay the same way
+    /// every time
+    #[test]
+    fn test_scenarios_are_reproducible() {
+        use cavernborn::world::scenario::{particle_symbol, symbol_cell, AIR_SYMBOL};
+
+        // Every particle has a symbol of its own.
+        for particle in Particle::all_variants() {
+            assert_eq!(symbol_cell(particle_symbol(particle)), Some(Some(particle)));
+        }
+        assert_eq!(symbol_cell(AIR_SYMBOL), Some(None));
+
+        let rules = InteractionRules::default();
+        let text = "
+            // Sand dropped next to a drop of water
+            #..s..#
+            #..s..#
+            #.w...#
+            #.....#
+            #######
+        ";
+        let mut map = Map::from_scenario(text).unwrap();
+        assert_eq!((map.width, map.height), (32, 32));
+        assert_eq!(
+            map.get_particle_at(UVec2::new(3, 4)),
+            Some(Particle::Powder(Powder::Sand))
+        );
+        // The padding out to whole chunks is walled off.
+        let bedrock = Some(Particle::Common(Common::Bedrock));
+        assert_eq!(map.get_particle_at(UVec2::new(7, 0)), bedrock);
+        assert_eq!(map.get_particle_at(UVec2::new(1, 5)), bedrock);
+        assert_eq!(
+            map.to_scenario(7, 5),
+            "#..s..#\n#..s..#\n#.w...#\n#.....#\n#######"
+        );
+        assert_eq!(
+            Map::from_scenario("#.\n#").err(),
+            Some("line 2 is 1 cells wide, but the scenario is 2".to_string())
+        );
+        assert!(Map::from_scenario("#?#").is_err());
+        assert!(Map::from_scenario("// Nothing here").is_err());
+
+        map.activate_all_chunks();
+        map.step(&rules, 3);
+        let layout = map.to_scenario(7, 5);
+        assert_eq!(layout, "#.....#\n#.....#\n#.....#\n#w.ss.#\n#######");
+
+        // Replaying with the same seed ends up the same.
+        let mut replay = Map::from_scenario(text).unwrap();
+        replay.activate_all_chunks();
+        replay.step(&rules, 3);
+        assert_eq!(replay.to_scenario(7, 5), layout);
+
+        // Liquid can't flow out of an open grid into the padding.
+        let mut spill = Map::from_scenario("..ww\n....").unwrap();
+        spill.activate_all_chunks();
+        spill.step(&rules, 20);
+        assert_eq!(spill.to_scenario(4, 2).matches('w').count(), 2);
+    }
+}
@@ -1,27 +1,30 @@
-// Include the crate's source code
-#[path = "../src/particle/mod.rs"]
-mod particle;
-
 use strum::IntoEnumIterator;
 
 #[cfg(test)]
 mod tests {
-    use super::particle::Common;
     use super::*;
+    use bevy::math::{UVec2, Vec2};
+    use cavernborn::particle::definition::ParticleRegistry;
+    use cavernborn::particle::interaction::InteractionRules;
+    use cavernborn::particle::{Common, Direction, Liquid, LiquidState, Particle};
+    use cavernborn::world::structure::{Placement, Rotation, Structure};
+    use cavernborn::world::Map;
 
     /// Test to ensure all Common particle variants have exclusive depth ranges
     #[test]
     fn test_common_variants_have_exclusive_ranges() {
+        let particles = ParticleRegistry::default();
+        let depths = |common: Common| particles.depths(common.into());
         // Get all pairs of Common variants
         let variants: Vec<Common> = Common::iter().collect();
 
         for (i, variant1) in variants.iter().enumerate() {
             for variant2 in variants.iter().skip(i + 1) {
                 // Get the depth ranges for both variants
-                let min1 = variant1.min_depth();
-                let max1 = variant1.max_depth();
-                let min2 = variant2.min_depth();
-                let max2 = variant2.max_depth();
+                let min1 = depths(*variant1).start;
+                let max1 = depths(*variant1).end;
+                let min2 = depths(*variant2).start;
+                let max2 = depths(*variant2).end;
 
                 // Check if the ranges overlap using half-open intervals [min, max)
                 // Range 1: [min1, max1) and Range 2: [min2, max2)
@@ -38,19 +41,21 @@ mod tests {
         }
     }
 
-    /// Test to ensure get_exclusive_at_depth returns the correct variant for each depth
+    /// Test to ensure common_at_depth returns the correct variant for each depth
     #[test]
-    fn test_get_exclusive_at_depth() {
+    fn test_common_at_depth() {
+        let particles = ParticleRegistry::default();
+        let depths = |common: Common| particles.depths(common.into());
         // Test each Common variant's range
         for variant in Common::iter() {
-            let min_depth = variant.min_depth();
-            let max_depth = variant.max_depth();
+            let min_depth = depths(variant).start;
+            let max_depth = depths(variant).end;
 
             // Test at the minimum depth (inclusive)
             assert_eq!(
-                Common::get_exclusive_at_depth(min_depth),
+                particles.common_at_depth(min_depth),
                 variant,
-                "get_exclusive_at_depth({}) should return {:?}",
+                "common_at_depth({}) should return {:?}",
                 min_depth,
                 variant
             );
@@ -58,9 +63,9 @@ mod tests {
             // Test at the maximum depth minus 1 (since max is exclusive)
             if max_depth > min_depth + 1 {
                 assert_eq!(
-                    Common::get_exclusive_at_depth(max_depth - 1),
+                    particles.common_at_depth(max_depth - 1),
                     variant,
-                    "get_exclusive_at_depth({}) should return {:?}",
+                    "common_at_depth({}) should return {:?}",
                     max_depth - 1,
                     variant
                 );
@@ -70,13 +75,2315 @@ mod tests {
             if max_depth > min_depth + 2 {
                 let mid_depth = min_depth + (max_depth - min_depth) / 2;
                 assert_eq!(
-                    Common::get_exclusive_at_depth(mid_depth),
+                    particles.common_at_depth(mid_depth),
                     variant,
-                    "get_exclusive_at_depth({}) should return {:?}",
+                    "common_at_depth({}) should return {:?}",
                     mid_depth,
                     variant
                 );
             }
         }
     }
+
+    /// Test that the Common layers stack without gaps from the surface down to bedrock
+    #[test]
+    fn test_common_layers_are_contiguous() {
+        let particles = ParticleRegistry::default();
+        let depths = |common: Common| particles.depths(common.into());
+        let expected_order = [
+            Common::Grass,
+            Common::Dirt,
+            Common::Clay,
+            Common::Stone,
+            Common::Bedrock,
+        ];
+
+        // Every variant must be accounted for in the layer order
+        assert_eq!(Common::iter().count(), expected_order.len());
+
+        assert_eq!(
+            depths(expected_order[0]).start,
+            0,
+            "The top layer must start at the surface"
+        );
+        assert_eq!(
+            depths(expected_order[expected_order.len() - 1]).end,
+            u32::MAX,
+            "The bottom layer must extend to the deepest possible depth"
+        );
+
+        for pair in expected_order.windows(2) {
+            assert_eq!(
+                depths(pair[0]).end,
+                depths(pair[1]).start,
+                "{:?} should end exactly where {:?} begins",
+                pair[0],
+                pair[1]
+            );
+            assert_eq!(particles.common_at_depth(depths(pair[1]).start), pair[1]);
+            assert_eq!(particles.common_at_depth(depths(pair[0]).end - 1), pair[0]);
+        }
+    }
+
+    /// Test that particle definitions are read from the asset file, rejected when incomplete or
+    /// gapped, and that edited definitions take effect on an existing map
+    #[test]
+    fn test_particle_definitions() {
+        use cavernborn::particle::definition::ParticleDefinitions;
+
+        let definitions =
+            ParticleDefinitions::from_toml(include_str!("../assets/particles.toml")).unwrap();
+        assert_eq!(
+            ParticleRegistry::from_definitions(&definitions),
+            Ok(ParticleRegistry::default())
+        );
+        assert!(ParticleDefinitions::from_toml("[Sand]\nspritesheet_index = \"14\"").is_err());
+        assert!(ParticleDefinitions::from_toml("[Sand]\nsprite = 14").is_err());
+
+        let mut missing = definitions.clone();
+        missing.particles.remove("Sand");
+        assert!(ParticleRegistry::from_definitions(&missing).is_err());
+
+        let mut unknown = definitions.clone();
+        unknown
+            .particles
+            .insert("Mud".to_string(), definitions.particles["Sand"]);
+        assert!(ParticleRegistry::from_definitions(&unknown).is_err());
+
+        let mut gapped = definitions.clone();
+        gapped.particles.get_mut("Dirt").unwrap().max_depth -= 1;
+        assert!(ParticleRegistry::from_definitions(&gapped).is_err());
+
+        let mut slow_steam = definitions.clone();
+        slow_steam
+            .particles
+            .get_mut("Steam")
+            .unwrap()
+            .condense_ticks = 256;
+        assert!(ParticleRegistry::from_definitions(&slow_steam).is_err());
+
+        // Water that can't flow stays where it's placed.
+        let mut still = definitions.clone();
+        still.particles.get_mut("Water").unwrap().viscosity = 0;
+        let still = ParticleRegistry::from_definitions(&still).unwrap();
+        assert_eq!(
+            still.viscosity(Liquid::Water(Direction::Left.into()).into()),
+            0
+        );
+
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let mut map = Map::empty(32, 32);
+        map.set_particle_at(UVec2::new(10, 20), Some(water));
+        map.active_chunks.insert(UVec2::ZERO);
+        map.update_dirty_chunks();
+        map.set_particles(still.clone());
+        assert!(map.get_chunk_at(&UVec2::ZERO).dirty, "Chunks are redrawn");
+        map.update_dirty_chunks();
+        map.simulate_active_chunks(&InteractionRules::default());
+        assert_eq!(map.get_particle_at(UVec2::new(10, 20)), Some(water));
+        assert_eq!(map.particles(), &still);
+    }
+
+    /// Test that the dry-run flow field points fluids down a slope
+    #[test]
+    fn test_flow_field_points_downhill() {
+        let mut map = Map::empty(32, 32);
+        let stone = Some(Particle::Common(Common::Stone));
+
+        // A one-cell step: the floor is two cells tall up to x = 5, then drops to one cell.
+        for x in 0..32 {
+            map.set_particle_at(UVec2::new(x, 0), stone);
+            if x <= 5 {
+                map.set_particle_at(UVec2::new(x, 1), stone);
+            }
+        }
+
+        let source = UVec2::new(5, 2);
+        map.set_particle_at(
+            source,
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+        );
+
+        let flow = map.flow_field(
+            &InteractionRules::default(),
+            UVec2::ZERO,
+            UVec2::new(32, 32),
+        );
+        let target = flow[&source];
+
+        assert_eq!(
+            flow.len(),
+            1,
+            "Only the single water cell should be in the flow field"
+        );
+        assert!(
+            target.y < source.y && target.x > source.x,
+            "Water at {} should flow down the step, but targets {}",
+            source,
+            target
+        );
+        // The dry-run must not have moved anything.
+        assert!(map.get_particle_at(source).is_some());
+    }
+
+    /// Test that marking a chunk dirty makes `update_dirty_chunks` pick up direct cell edits
+    #[test]
+    fn test_mark_chunk_dirty_refreshes_active_state() {
+        let mut map = Map::empty(64, 64);
+        let chunk_pos = UVec2::new(1, 0);
+        map.active_chunks.insert(chunk_pos);
+
+        // Poke a cell directly, bypassing `set_particle_at` and its dirty tracking.
+        map.chunks[1][0].cells_mut()[5][5] =
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into())));
+        let version = map.get_chunk_at(&chunk_pos).version;
+
+        map.update_dirty_chunks();
+        assert!(
+            !map.get_chunk_at(&chunk_pos).should_simulate,
+            "A direct cell poke should go unnoticed until the chunk is marked dirty"
+        );
+
+        map.mark_chunk_dirty(chunk_pos);
+        assert!(map.get_chunk_at(&chunk_pos).version > version);
+
+        map.update_dirty_chunks();
+        let chunk = map.get_chunk_at(&chunk_pos);
+        assert!(chunk.should_simulate);
+        assert!(!chunk.dirty);
+
+        // Marking everything dirty flags chunks outside the active set too.
+        map.mark_all_dirty();
+        assert!(map.chunks.iter().flatten().all(|chunk| chunk.dirty));
+    }
+
+    /// Test that simulating settled water leaves its chunk clean, while moving water dirties it
+    #[test]
+    fn test_settled_water_does_not_dirty_chunk() {
+        let mut map = Map::empty(32, 32);
+        for x in 0..32 {
+            for y in 0..32 {
+                map.set_particle_at(
+                    UVec2::new(x, y),
+                    Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+                );
+            }
+        }
+        map.active_chunks.insert(UVec2::ZERO);
+        map.update_dirty_chunks();
+        let rules = InteractionRules::default();
+
+        // A full chunk of water has nowhere to go.
+        let version = map.get_chunk_at(&UVec2::ZERO).version;
+        map.simulate_active_chunks(&rules);
+        let chunk = map.get_chunk_at(&UVec2::ZERO);
+        assert!(!chunk.dirty);
+        assert_eq!(chunk.version, version);
+
+        // Opening a hole lets the water above fall into it.
+        map.set_particle_at(UVec2::ZERO, None);
+        map.update_dirty_chunks();
+        map.simulate_active_chunks(&rules);
+        assert!(map.get_chunk_at(&UVec2::ZERO).dirty);
+    }
+
+    /// Test that weighted nearby chunks fall off from 0 at the center to 1 at the edge
+    #[test]
+    fn test_get_chunks_near_weighted() {
+        let map = Map::empty(512, 512);
+        // The middle of chunk (8, 8), with a range of 4 chunks.
+        let center = Vec2::new(8.0 * 32.0 + 16.0, 8.0 * 32.0 + 16.0);
+        let weighted = map.get_chunks_near_weighted(center, 4 * 32);
+        let weight_of = |chunk_pos: UVec2| {
+            weighted
+                .iter()
+                .find(|(pos, _)| *pos == chunk_pos)
+                .map(|(_, weight)| *weight)
+                .unwrap_or_else(|| panic!("Chunk {} should be in range", chunk_pos))
+        };
+
+        assert!(weight_of(UVec2::new(8, 8)).abs() < 1e-6);
+        assert!((weight_of(UVec2::new(12, 8)) - 1.0).abs() < 1e-6);
+        assert!((weight_of(UVec2::new(8, 4)) - 1.0).abs() < 1e-6);
+        assert!((weight_of(UVec2::new(10, 8)) - 0.5).abs() < 1e-6);
+        assert!(weighted
+            .iter()
+            .all(|(_, weight)| (0.0..=1.0).contains(weight)));
+
+        // The weighted variant covers exactly the same chunks.
+        let positions: Vec<UVec2> = weighted.iter().map(|(pos, _)| *pos).collect();
+        assert_eq!(positions, map.get_chunks_near(center, 4 * 32));
+    }
+
+    /// Test that the nearby fluid count includes liquids around the player and skips distant ones
+    #[test]
+    fn test_nearby_fluid_counts_only_close_liquids() {
+        use bevy::prelude::*;
+        use cavernborn::player::Player;
+        use cavernborn::world::ambience::{update_nearby_fluid, AmbienceSettings, NearbyFluid};
+        use std::time::Duration;
+
+        // A 128x128 map is centered on the origin, so a player at (0, 0) sits at world (64, 64).
+        let mut map = Map::empty(128, 128);
+        let water = Liquid::Water(Direction::Left.into());
+        let lava = Liquid::Lava(Direction::Left.into());
+        map.set_particle_at(UVec2::new(66, 64), Some(Particle::Liquid(water)));
+        map.set_particle_at(UVec2::new(60, 60), Some(Particle::Liquid(lava)));
+        map.set_particle_at(UVec2::new(120, 120), Some(Particle::Liquid(water)));
+        map.set_particle_at(UVec2::new(64, 40), Some(Particle::Liquid(water)));
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(map)
+            .insert_resource(AmbienceSettings {
+                radius: 16,
+                update_interval: Duration::ZERO,
+            })
+            .init_resource::<NearbyFluid>()
+            .add_systems(Update, update_nearby_fluid);
+        app.world_mut()
+            .spawn((Player, Transform::from_xyz(0.0, 0.0, 0.0)));
+
+        app.update();
+
+        let nearby = app.world().resource::<NearbyFluid>();
+        assert_eq!(
+            nearby.count(water),
+            1,
+            "Only the close water cell should count"
+        );
+        assert_eq!(nearby.count(lava), 1);
+        assert_eq!(nearby.total(), 2);
+    }
+
+    /// Test that water placed far outside the player's active range still falls
+    #[test]
+    fn test_placed_water_falls_far_from_player() {
+        use bevy::prelude::*;
+        use cavernborn::player::Player;
+        use cavernborn::world::map::{simulate_active_particles, update_active_chunks};
+
+        // The player sits at the center of the map, more than the active range from the far right.
+        let mut map = Map::empty(1024, 256);
+        let start = UVec2::new(1000, 200);
+        map.set_particle_at(
+            start,
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+        );
+        assert!(map.awake_chunks.contains_key(&UVec2::new(31, 6)));
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(map)
+            .init_resource::<InteractionRules>()
+            .add_systems(
+                Update,
+                (update_active_chunks, simulate_active_particles).chain(),
+            );
+        app.world_mut()
+            .spawn((Player, Transform::from_xyz(0.0, 0.0, 0.0)));
+
+        for _ in 0..10 {
+            app.update();
+        }
+
+        let map = app.world().resource::<Map>();
+        assert!(
+            map.get_particle_at(start).is_none(),
+            "The water should have moved"
+        );
+        let fallen = (0..map.width)
+            .flat_map(|x| (0..start.y).map(move |y| UVec2::new(x, y)))
+            .any(|pos| matches!(map.get_particle_at(pos), Some(Particle::Liquid(_))));
+        assert!(
+            fallen,
+            "The water should have fallen below where it was placed"
+        );
+    }
+
+    /// Test that the stored surface profile has one height per column matching the topmost particle
+    #[test]
+    fn test_surface_profile_matches_terrain() {
+        let map = Map::generate(4, 20, 11);
+        let profile = map.surface_profile();
+        assert_eq!(profile.len(), map.width as usize);
+
+        for (x, &height) in profile.iter().enumerate() {
+            let topmost = (0..map.height)
+                .rev()
+                .find(|&y| map.get_particle_at(UVec2::new(x as u32, y)).is_some());
+            assert_eq!(topmost, Some(height), "column {}", x);
+        }
+
+        assert!(Map::empty(64, 64).surface_profile().is_empty());
+    }
+
+    /// Test that the noise sources are deterministic for a seed and stay within range
+    #[test]
+    fn test_noise_sources_are_seeded() {
+        use cavernborn::world::generator::{GeneratorConfig, WorldGenConfig};
+        use cavernborn::world::noise::{fractal, NoiseSource, PerlinNoise, ValueNoise};
+
+        let coords = [(0.0, 0.0), (1.5, -2.25), (123.4, 56.7), (-800.1, 3.3)];
+        let sources: [(&dyn NoiseSource, &dyn NoiseSource, &dyn NoiseSource); 2] = [
+            (
+                &ValueNoise::new(7),
+                &ValueNoise::new(7),
+                &ValueNoise::new(8),
+            ),
+            (
+                &PerlinNoise::new(7),
+                &PerlinNoise::new(7),
+                &PerlinNoise::new(8),
+            ),
+        ];
+        for (noise, same, other) in sources {
+            for (x, y) in coords {
+                let value = noise.sample(x, y);
+                assert_eq!(value, same.sample(x, y));
+                assert!((-1.0..=1.0).contains(&value), "{} at ({}, {})", value, x, y);
+                let layered = fractal(noise, x, y, 4);
+                assert!(
+                    (-1.0..=1.0).contains(&layered),
+                    "{} at ({}, {})",
+                    layered,
+                    x,
+                    y
+                );
+            }
+            assert!(coords
+                .iter()
+                .any(|&(x, y)| noise.sample(x + 0.3, y) != other.sample(x + 0.3, y)));
+        }
+
+        // Custom sources can be plugged into generation. A flat field gives flat terrain.
+        struct Flat;
+        impl NoiseSource for Flat {
+            fn sample(&self, _x: f32, _y: f32) -> f32 {
+                0.0
+            }
+        }
+        let map = Map::generate_with_noise(2, 20, 7, Flat);
+        assert_eq!(map.surface_profile().len(), map.width as usize);
+        assert!(map
+            .surface_profile()
+            .windows(2)
+            .all(|pair| pair[0] == pair[1]));
+
+        // The same noise shapes the caves and ore clusters. A field that's low everywhere carves
+        // nothing and leaves no ore rich enough to spawn.
+        struct Low;
+        impl NoiseSource for Low {
+            fn sample(&self, _x: f32, _y: f32) -> f32 {
+                -1.0
+            }
+        }
+        let underground = |map: &Map| {
+            let mut cells = Vec::new();
+            for (x, &surface) in map.surface_profile().iter().enumerate() {
+                for y in 0..=surface {
+                    cells.push(map.get_particle_at(UVec2::new(x as u32, y)));
+                }
+            }
+            cells
+        };
+        let terrain = GeneratorConfig {
+            structure_frequency: 0.0,
+            pocket_frequency: 0.0,
+            ..Default::default()
+        };
+        let config = WorldGenConfig::with_terrain(4, 20, 7, terrain);
+        let default_cells = underground(&Map::generate_from_config(&config));
+        assert!(default_cells.contains(&None));
+        assert!(default_cells
+            .iter()
+            .any(|cell| matches!(cell, Some(Particle::Special(_)))));
+        let low_cells = underground(&Map::generate_from_config(&config.with_noise(Low)));
+        assert!(low_cells.iter().all(|cell| matches!(
+            cell,
+            Some(Particle::Common(_) | Particle::Powder(_) | Particle::Solid(_))
+        )));
+    }
+
+    /// Test that a map generated from a heightmap has exactly the given surface
+    #[test]
+    fn test_generate_from_heightmap() {
+        use cavernborn::world::generator::WorldGenConfig;
+
+        let config = WorldGenConfig::new(2, 4, 5);
+        let heights: Vec<u32> = (0..config.map_width()).map(|x| 20 + (x * 7) % 90).collect();
+        let map = Map::generate_from_heightmap(&heights, &config).unwrap();
+
+        assert_eq!(map.surface_profile(), heights.as_slice());
+        for (x, &height) in heights.iter().enumerate() {
+            let topmost = (0..map.height)
+                .rev()
+                .find(|&y| map.get_particle_at(UVec2::new(x as u32, y)).is_some());
+            assert_eq!(topmost, Some(height), "column {}", x);
+        }
+
+        // One height per column, and none above the map.
+        assert!(Map::generate_from_heightmap(&heights[1..], &config).is_none());
+        let mut too_tall = heights.clone();
+        too_tall[3] = config.map_height();
+        assert!(Map::generate_from_heightmap(&too_tall, &config).is_none());
+    }
+
+    /// Test that zoom presets cycle in order, wrap around, and respect the zoom clamps
+    #[test]
+    fn test_zoom_presets_cycle_and_wrap() {
+        use cavernborn::world::camera::GameCamera;
+
+        let mut camera = GameCamera {
+            zoom_presets: vec![0.05, 0.5, 1.0, 8.0],
+            zoom_preset_index: 0,
+            ..GameCamera::default()
+        };
+
+        assert_eq!(camera.next_zoom_preset(), Some(0.5));
+        assert_eq!(camera.next_zoom_preset(), Some(1.0));
+        // Presets outside the allowed zoom range are clamped.
+        assert_eq!(camera.next_zoom_preset(), Some(camera.max_zoom));
+        assert_eq!(camera.next_zoom_preset(), Some(camera.min_zoom));
+        assert_eq!(camera.zoom_preset_index, 0);
+
+        camera.zoom_presets.clear();
+        assert_eq!(camera.next_zoom_preset(), None);
+    }
+
+    /// Test that surface heights stay inside short maps, so no column's depth math underflows
+    #[test]
+    fn test_surface_heights_clamped_to_map() {
+        // One chunk tall puts the base surface within a few cells of the top of the map.
+        for seed in 0..4 {
+            let map = Map::generate(4, 1, seed);
+            let profile = map.surface_profile();
+            assert!(profile.iter().all(|&height| height < map.height));
+            assert!(
+                profile.contains(&(map.height - 1)),
+                "Peaks should be clamped"
+            );
+            // Valleys dip below the base height instead of wrapping or flattening out.
+            assert!(profile.iter().any(|&height| height < 30));
+
+            for (x, &height) in profile.iter().enumerate() {
+                let topmost = (0..map.height)
+                    .rev()
+                    .find(|&y| map.get_particle_at(UVec2::new(x as u32, y)).is_some());
+                assert_eq!(topmost, Some(height), "column {}", x);
+            }
+        }
+    }
+
+    /// Test that the active region is clipped at map corners and edges
+    #[test]
+    fn test_compute_active_region_clips_to_map() {
+        // 8x8 chunks.
+        let map = Map::empty(256, 256);
+        let region = |x, y, range| map.compute_active_region(UVec2::new(x, y), range);
+
+        // Interior regions are a full square.
+        assert_eq!(region(4, 4, 2).len(), 25);
+        assert_eq!(region(4, 4, 0), [UVec2::new(4, 4)].into());
+
+        // Corners keep only the quarter inside the map.
+        for (x, y) in [(0, 0), (7, 0), (0, 7), (7, 7)] {
+            let corner = region(x, y, 2);
+            assert_eq!(corner.len(), 9, "corner ({}, {})", x, y);
+            assert!(corner.iter().all(|pos| pos.x < 8 && pos.y < 8));
+        }
+
+        // Edges keep the half inside the map.
+        let left = region(0, 4, 2);
+        assert_eq!(left.len(), 15);
+        assert!(left.contains(&UVec2::new(2, 6)) && !left.contains(&UVec2::new(3, 4)));
+        assert_eq!(region(4, 7, 2).len(), 15);
+
+        // Huge ranges cover the map without overflowing, and outside centers cover nothing.
+        assert_eq!(region(3, 3, u32::MAX).len(), 64);
+        assert!(region(8, 3, 2).is_empty());
+    }
+
+    /// Test that setting the active chunks keeps awake chunks active
+    #[test]
+    fn test_set_active_chunks_keeps_awake_chunks() {
+        let mut map = Map::empty(256, 256);
+        map.wake_chunk(UVec2::new(7, 7));
+
+        let region = map.compute_active_region(UVec2::ZERO, 1);
+        map.set_active_chunks(region.clone());
+
+        assert_eq!(map.active_chunks.len(), region.len() + 1);
+        assert!(map.active_chunks.is_superset(&region));
+        assert!(map.active_chunks.contains(&UVec2::new(7, 7)));
+    }
+
+    /// Test that every particle variant has a non-empty, unique display name
+    #[test]
+    fn test_display_names_are_unique() {
+        use cavernborn::particle::ParticleType;
+        use std::collections::HashSet;
+
+        let variants = Particle::all_variants();
+        assert!(variants.contains(&Particle::Common(Common::Bedrock)));
+        assert!(variants.contains(&Particle::Liquid(Liquid::Acid(Direction::Left.into()))));
+
+        let mut names = HashSet::new();
+        for particle in &variants {
+            let name = particle.display_name();
+            assert!(
+                !name.trim().is_empty(),
+                "{:?} has no display name",
+                particle
+            );
+            assert!(names.insert(name), "{} is used more than once", name);
+            if let Some(description) = particle.description() {
+                assert!(
+                    !description.is_empty(),
+                    "{:?} has an empty description",
+                    particle
+                );
+            }
+        }
+        assert_eq!(names.len(), variants.len());
+
+        // Per-cell state never shows up in the name.
+        let water = Liquid::Water(Direction::Right.into()).with_fall_distance(3);
+        assert_eq!(Particle::Liquid(water).display_name(), "Water");
+    }
+
+    /// Returns true if both maps have the same dimensions and identical cells
+    fn same_cells(a: &Map, b: &Map) -> bool {
+        a.width == b.width
+            && a.height == b.height
+            && (0..a.width).all(|x| {
+                (0..a.height).all(|y| {
+                    let pos = UVec2::new(x, y);
+                    a.get_particle_at(pos) == b.get_particle_at(pos)
+                })
+            })
+    }
+
+    /// Test that generation is reproducible for a seed and varies between seeds
+    #[test]
+    fn test_generation_is_seeded() {
+        let first = Map::generate(4, 4, 1234);
+        let regenerated = Map::generate(4, 4, 1234);
+        let reseeded = Map::generate(4, 4, 5678);
+
+        assert!(
+            same_cells(&first, &regenerated),
+            "Regenerating with the same seed should reproduce the identical map"
+        );
+        assert!(
+            !same_cells(&first, &reseeded),
+            "A different seed should produce a different map"
+        );
+    }
+
+    /// Test that a cloned region matches the source cells and is padded with air to chunk alignment
+    #[test]
+    fn test_clone_region_copies_cells() {
+        let source = Map::generate(4, 4, 7);
+        let min = UVec2::new(20, 40);
+        let max = UVec2::new(70, 90);
+
+        let region = source.clone_region(min, max);
+
+        assert_eq!(region.width, 64);
+        assert_eq!(region.height, 64);
+        for x in 0..region.width {
+            for y in 0..region.height {
+                let local = UVec2::new(x, y);
+                let expected = if local.cmplt(max - min).all() {
+                    source.get_particle_at(min + local)
+                } else {
+                    None
+                };
+                assert_eq!(
+                    region.get_particle_at(local),
+                    expected,
+                    "Cell {} of the region should match the source",
+                    local
+                );
+            }
+        }
+    }
+
+    /// Test that region and radius queries see the same particles as reading every cell
+    #[test]
+    fn test_query_region_matches_cell_reads() {
+        use bevy::math::URect;
+        use std::collections::HashMap;
+
+        let map = Map::generate(4, 4, 7);
+        // Straddles chunk borders and runs off the right edge of the map.
+        let rect = URect::new(20, 40, 300, 90);
+        let mut queried: Vec<(UVec2, Particle)> = map.query_region(rect).collect();
+        queried.sort_by_key(|(pos, _)| (pos.x, pos.y));
+
+        let expected: Vec<(UVec2, Particle)> = (20..map.width)
+            .flat_map(|x| (40..90).map(move |y| UVec2::new(x, y)))
+            .filter_map(|pos| map.get_particle_at(pos).map(|particle| (pos, particle)))
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(queried, expected);
+
+        assert_eq!(map.query_region(URect::new(500, 500, 600, 600)).count(), 0);
+        assert_eq!(map.query_region(URect::new(30, 30, 30, 60)).count(), 0);
+
+        let center = UVec2::new(64, 64);
+        let mut counts: HashMap<Particle, u32> = HashMap::new();
+        for x in 54..=74u32 {
+            for y in 54..=74u32 {
+                let (dx, dy) = (x.abs_diff(center.x), y.abs_diff(center.y));
+                if dx * dx + dy * dy > 100 {
+                    continue;
+                }
+                if let Some(particle) = map.get_particle_at(UVec2::new(x, y)) {
+                    *counts.entry(particle).or_insert(0) += 1;
+                }
+            }
+        }
+        assert_eq!(map.count_particles_in_radius(center, 10), counts);
+    }
+
+    /// Test that an explosion clears its core, leaves bedrock and far cells alone, and drops debris
+    #[test]
+    fn test_explosion_blows_crater_with_debris() {
+        use cavernborn::particle::Powder;
+
+        let mut map = Map::empty(64, 64);
+        let stone = Some(Particle::Common(Common::Stone));
+        for x in 0..64 {
+            for y in 0..64 {
+                map.set_particle_at(UVec2::new(x, y), stone);
+            }
+        }
+        let bedrock = UVec2::new(33, 32);
+        map.set_particle_at(bedrock, Some(Particle::Common(Common::Bedrock)));
+        map.active_chunks.clear();
+        map.awake_chunks.clear();
+
+        let center = UVec2::new(32, 32);
+        let broken = map.explode(center, 10);
+
+        let cells_within = |map: &Map, radius: f32| -> Vec<Option<Particle>> {
+            (0..64u32)
+                .flat_map(|x| (0..64u32).map(move |y| UVec2::new(x, y)))
+                .filter(|pos| pos.as_vec2().distance(center.as_vec2()) <= radius && *pos != bedrock)
+                .map(|pos| map.get_particle_at(pos))
+                .collect()
+        };
+        let core = cells_within(&map, 5.0);
+        assert!(
+            core.iter().all(|cell| cell != &stone),
+            "Every particle in the core should break"
+        );
+        let gravel = Some(Particle::Powder(Powder::Gravel));
+        assert!(
+            core.contains(&gravel),
+            "Some stone should be left as debris"
+        );
+        assert!(core.contains(&None));
+        assert!(broken >= core.len());
+
+        assert_eq!(
+            map.get_particle_at(bedrock),
+            Some(Particle::Common(Common::Bedrock))
+        );
+        assert_eq!(map.get_particle_at(UVec2::new(32, 43)), stone);
+        assert_eq!(map.get_particle_at(UVec2::new(5, 5)), stone);
+
+        // Every chunk the blast reached wakes up, so the debris falls.
+        for chunk_pos in [UVec2::new(0, 0), UVec2::new(1, 1)] {
+            assert!(map.active_chunks.contains(&chunk_pos));
+            assert!(map.awake_chunks.contains_key(&chunk_pos));
+        }
+    }
+
+    /// Test that daylight and torches light the map, spread across chunk borders, and are
+    /// relit when the cells around them change
+    #[test]
+    fn test_light_spreads_from_sky_and_torches() {
+        use cavernborn::particle::{Solid, MAX_LIGHT};
+
+        // Solid stone up to y = 40, with a cave spanning two chunks.
+        let mut map = Map::empty(64, 64);
+        for x in 0..64 {
+            for y in 0..40 {
+                let cave = (10..50).contains(&x) && (10..20).contains(&y);
+                if !cave {
+                    map.set_particle_at(UVec2::new(x, y), Some(Particle::Common(Common::Stone)));
+                }
+            }
+        }
+        map.update_lighting();
+        assert_eq!(map.get_light_at(UVec2::new(5, 50)), MAX_LIGHT);
+        // Light only reaches a few cells into the ground.
+        assert_eq!(map.get_light_at(UVec2::new(5, 38)), MAX_LIGHT - 4 * 2);
+        assert_eq!(map.get_light_at(UVec2::new(20, 15)), 0);
+        // Sky light fades with depth below the surface, whatever else lights the cell.
+        assert_eq!(map.get_sky_light_at(UVec2::new(5, 50)), MAX_LIGHT);
+        assert_eq!(map.get_sky_light_at(UVec2::new(5, 38)), MAX_LIGHT - 2);
+        assert_eq!(map.get_sky_light_at(UVec2::new(20, 15)), 0);
+
+        let torch = UVec2::new(30, 15);
+        map.set_particle_at(torch, Some(Particle::Solid(Solid::Torch)));
+        let version = map.get_chunk_at(&UVec2::new(1, 0)).render_version();
+        map.update_lighting();
+        assert_eq!(map.get_light_at(torch), 14);
+        assert_eq!(map.get_light_at(UVec2::new(25, 15)), 9);
+        // Across the chunk border, and into the cave floor.
+        assert_eq!(map.get_light_at(UVec2::new(34, 15)), 10);
+        assert_eq!(map.get_light_at(UVec2::new(30, 9)), 14 - 5 - 4);
+        assert_ne!(
+            map.get_chunk_at(&UVec2::new(1, 0)).render_version(),
+            version,
+            "Chunks lit from their neighbors should be redrawn"
+        );
+
+        // The shader reads cell 15 * 32 + 30 from byte 2 of component 3 of element 31.
+        let packed = map.get_chunk_at(&UVec2::ZERO).to_packed_light();
+        assert_eq!((packed[31][3] >> 16) & 0xff, 14);
+        // Sky light goes in the high four bits, here for cell 6 * 32 + 5 of the chunk above.
+        let packed = map.get_chunk_at(&UVec2::new(0, 1)).to_packed_light();
+        let expected = ((MAX_LIGHT - 2) << 4) | (MAX_LIGHT - 4 * 2);
+        assert_eq!((packed[12][1] >> 8) & 0xff, u32::from(expected));
+
+        map.set_particle_at(torch, None);
+        map.update_lighting();
+        assert_eq!(map.get_light_at(UVec2::new(34, 15)), 0);
+        assert_eq!(
+            map.get_sky_light_at(torch),
+            0,
+            "Torches don't light caves like the sky"
+        );
+
+        // A shaft down from the surface lets daylight into the cave.
+        for y in 20..40 {
+            map.set_particle_at(UVec2::new(40, y), None);
+        }
+        map.update_lighting();
+        assert_eq!(map.get_light_at(UVec2::new(40, 10)), MAX_LIGHT);
+        assert_eq!(map.get_light_at(UVec2::new(34, 15)), MAX_LIGHT - 6);
+    }
+
+    /// Test that generated walls fill everything below the surface, caves included, and can be
+    /// dug and placed without touching the particles in front of them
+    #[test]
+    fn test_background_walls() {
+        use cavernborn::player::dig_wall;
+        use cavernborn::render::chunk_material::decode_sprite;
+
+        let mut map = Map::generate(4, 4, 7);
+        let mut caves = 0;
+        for x in 0..map.width {
+            let surface = map.surface_profile()[x as usize];
+            for y in 0..map.height {
+                let pos = UVec2::new(x, y);
+                let wall = map.get_background_at(pos);
+                if y > surface {
+                    assert_eq!(wall, None, "No walls above the surface at {}", pos);
+                    continue;
+                }
+                let expected = map
+                    .biome_at(x)
+                    .ground_at_depth(surface - y, map.particles());
+                assert_eq!(wall, Some(expected), "Wrong wall at {}", pos);
+                if map.get_particle_at(pos).is_none() {
+                    caves += 1;
+                }
+            }
+        }
+        assert!(caves > 0, "Caves should be carved in front of the walls");
+
+        // Walls behind particles are out of reach.
+        let buried = UVec2::new(10, 10);
+        map.set_particle_at(buried, Some(Particle::Common(Common::Stone)));
+        assert!(!dig_wall(&mut map, buried));
+        map.set_particle_at(buried, None);
+        assert!(dig_wall(&mut map, buried));
+        assert_eq!(map.get_background_at(buried), None);
+
+        map.set_background_at(buried, Some(Particle::Common(Common::Clay)));
+        assert_eq!(map.get_particle_at(buried), None);
+        assert_eq!(
+            map.get_background_at(buried),
+            Some(Particle::Common(Common::Clay))
+        );
+
+        // Walls are drawn from their own indices.
+        let chunk = map.get_chunk_at(&UVec2::ZERO);
+        let cell = 10 * 32 + 10;
+        assert_eq!(
+            decode_sprite(
+                chunk.to_background_spritesheet_indices(map.particles())[cell / 4][cell % 4]
+            ),
+            map.particles().sprite(Particle::Common(Common::Clay))
+        );
+        assert_eq!(
+            chunk.to_spritesheet_indices(map.particles())[cell / 4][cell % 4],
+            0
+        );
+    }
+
+    /// Test that chunk sprite indices are packed four cells per UVec4 in the order the shader reads them
+    #[test]
+    fn test_spritesheet_indices_packing() {
+        use bevy::math::UVec4;
+        use cavernborn::render::chunk_material::{
+            cell_variant, encode_animation, encode_draw_order, encode_variant, LIQUID_CELL_BIT,
+            PACKED_INDICE_BUFFER_SIZE,
+        };
+        use cavernborn::world::chunk::Chunk;
+
+        let dirt = Particle::Common(Common::Dirt);
+        let stone = Particle::Common(Common::Stone);
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+
+        let mut chunk = Chunk::new(UVec2::ZERO);
+        chunk.set_particle(UVec2::new(1, 0), Some(dirt));
+        chunk.set_particle(UVec2::new(3, 0), Some(water));
+        chunk.set_particle(UVec2::new(4, 0), Some(stone));
+        // Second row starts at cell 32, i.e. element 8.
+        chunk.set_particle(UVec2::new(0, 1), Some(stone));
+        // Last cell of the chunk.
+        chunk.set_particle(UVec2::new(31, 31), Some(dirt));
+
+        let particles = ParticleRegistry::default();
+        let index = |particle: Particle, x: u32, y: u32| {
+            let (frames, frame_rate) = particles.animation(particle);
+            let variant = cell_variant(UVec2::new(x, y), particles.variants(particle));
+            encode_draw_order(
+                encode_variant(
+                    encode_animation(particles.sprite(particle).1, frames, frame_rate),
+                    variant,
+                ),
+                particles.draw_order(particle),
+            )
+        };
+        let mut expected = [UVec4::ZERO; PACKED_INDICE_BUFFER_SIZE];
+        expected[0] = UVec4::new(
+            0,
+            index(dirt, 1, 0),
+            0,
+            index(water, 3, 0) | LIQUID_CELL_BIT,
+        );
+        expected[1].x = index(stone, 4, 0);
+        expected[8].x = index(stone, 0, 1);
+        expected[PACKED_INDICE_BUFFER_SIZE - 1].w = index(dirt, 31, 31);
+
+        assert_eq!(chunk.to_spritesheet_indices(&particles), expected);
+    }
+
+    /// Test that compacting empty chunks frees memory and compacted chunks re-materialize on write
+    #[test]
+    fn test_compact_releases_empty_chunks() {
+        let mut map = Map::empty(128, 128);
+        let stone = Some(Particle::Common(Common::Stone));
+        map.set_particle_at(UVec2::new(5, 5), stone);
+        let before = map.memory_usage();
+
+        // All but the chunk holding the stone are air.
+        assert_eq!(map.compact(), 15);
+        assert!(map.memory_usage() < before);
+        assert!(!map.get_chunk_at(&UVec2::ZERO).is_compacted());
+
+        let chunk_pos = UVec2::new(2, 3);
+        let position = UVec2::new(2 * 32 + 7, 3 * 32 + 9);
+        assert!(map.get_chunk_at(&chunk_pos).is_compacted());
+        assert_eq!(map.get_particle_at(position), None);
+
+        // Clearing a cell doesn't need the storage back.
+        map.set_particle_at(position, None);
+        assert!(map.get_chunk_at(&chunk_pos).is_compacted());
+
+        map.set_particle_at(position, stone);
+        let chunk = map.get_chunk_at(&chunk_pos);
+        assert!(!chunk.is_compacted());
+        assert!(chunk.dirty);
+        assert_eq!(map.get_particle_at(position), stone);
+        assert_eq!(
+            chunk
+                .cells()
+                .iter()
+                .flatten()
+                .filter(|c| c.is_some())
+                .count(),
+            1,
+            "Only the written cell should be filled"
+        );
+        assert_eq!(
+            map.memory_usage(),
+            before - 14 * std::mem::size_of_val(chunk.cells())
+        );
+    }
+
+    /// Test that identically seeded maps share a world hash while a single cell edit changes it
+    #[test]
+    fn test_world_hash_identifies_map_contents() {
+        let first = Map::generate(4, 4, 99);
+        let mut second = Map::generate(4, 4, 99);
+        assert_eq!(first.world_hash(), second.world_hash());
+
+        let position = UVec2::new(70, 45);
+        let replacement = match second.get_particle_at(position) {
+            Some(_) => None,
+            None => Some(Particle::Common(Common::Stone)),
+        };
+        second.set_particle_at(position, replacement);
+        assert_ne!(first.world_hash(), second.world_hash());
+
+        // Liquid state counts as content too, even though liquids compare equal by kind.
+        let mut left = Map::empty(32, 32);
+        let mut right = Map::empty(32, 32);
+        left.set_particle_at(
+            UVec2::ZERO,
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+        );
+        right.set_particle_at(
+            UVec2::ZERO,
+            Some(Particle::Liquid(Liquid::Water(Direction::Right.into()))),
+        );
+        assert_ne!(left.world_hash(), right.world_hash());
+    }
+
+    /// Test that the current biome follows the generated biome of the player's column
+    #[test]
+    fn test_current_biome_matches_generation() {
+        use bevy::prelude::*;
+        use cavernborn::player::Player;
+        use cavernborn::world::ambience::{update_current_biome, CurrentBiome};
+        use cavernborn::world::biome::BiomeLayout;
+        use cavernborn::world::noise::PerlinNoise;
+
+        let seed = 2024;
+        let map = Map::generate(16, 2, seed);
+        let layout = BiomeLayout::generate(map.width, seed, &PerlinNoise::new(seed));
+        assert_eq!(
+            map.biomes, layout,
+            "The map should store the generated layout"
+        );
+        assert!(layout.regions().len() > 1);
+
+        let half_width = (map.width * 3 / 2) as f32;
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(map)
+            .init_resource::<CurrentBiome>()
+            .add_systems(Update, update_current_biome);
+        let player = app
+            .world_mut()
+            .spawn((Player, Transform::from_xyz(0.0, 0.0, 0.0)))
+            .id();
+
+        for region in layout.regions() {
+            for column in [region.start, region.start + 10] {
+                // Particles are 3 pixels wide and the map is centered on the origin.
+                let screen_x = (column as f32 + 0.5) * 3.0 - half_width;
+                app.world_mut()
+                    .get_mut::<Transform>(player)
+                    .unwrap()
+                    .translation
+                    .x = screen_x;
+                app.update();
+
+                assert_eq!(
+                    app.world().resource::<CurrentBiome>().0,
+                    layout.biome_at(column),
+                    "Wrong biome at column {}",
+                    column
+                );
+                assert_eq!(app.world().resource::<CurrentBiome>().0, region.biome);
+            }
+        }
+    }
+
+    /// Test that each column is generated from the ground of its biome, and that biomes restrict
+    /// and reweight what spawns in them
+    #[test]
+    fn test_biomes_shape_generation() {
+        use cavernborn::particle::{Gem, Ore, Special};
+        use cavernborn::world::biome::Biome;
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let map = Map::generate(16, 2, 2024);
+        assert!(map.biomes.regions().len() > 1);
+        for (x, &surface) in map.surface_profile().iter().enumerate() {
+            let x = x as u32;
+            // Just below the surface there are no caves or specials, only the top ground layer.
+            assert_eq!(
+                map.get_particle_at(UVec2::new(x, surface - 1)),
+                Some(map.biome_at(x).ground_at_depth(1, map.particles())),
+                "Wrong ground at column {}",
+                x
+            );
+        }
+        let particles = map.particles();
+        assert_ne!(
+            Biome::Desert.ground_at_depth(1, particles),
+            Biome::Plains.ground_at_depth(1, particles)
+        );
+        assert_eq!(
+            Biome::Plains.ground_at_depth(1, particles),
+            Particle::Common(Common::Grass)
+        );
+
+        let ruby = Special::Gem(Gem::Ruby);
+        let coal = Special::Ore(Ore::Coal);
+        assert_eq!(Biome::Plains.special_spawn_chance(ruby, particles), 0);
+        assert!(
+            Biome::Volcanic.special_spawn_chance(ruby, particles)
+                > particles.spawn_chance(Particle::Special(ruby))
+        );
+        assert_eq!(Biome::Desert.special_spawn_chance(coal, particles), 0);
+        assert_eq!(
+            Biome::Frozen.liquid_pocket_chance(Liquid::Lava(Direction::Left.into()), particles),
+            0
+        );
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let rolls: Vec<_> = (0..5000)
+            .filter_map(|_| Map::roll_special_particle(100, Biome::Plains, particles, &mut rng))
+            .collect();
+        assert!(rolls.is_empty(), "Rubies don't spawn in plains");
+        let rolls: Vec<_> = (0..5000)
+            .filter_map(|_| Map::roll_special_particle(100, Biome::Volcanic, particles, &mut rng))
+            .collect();
+        assert!(rolls.contains(&Particle::Special(ruby)));
+    }
+
+    /// Test that liquids draw over solids, and that particle definitions can change the order
+    #[test]
+    fn test_render_layer_draw_order() {
+        use cavernborn::particle::definition::ParticleDefinitions;
+        use cavernborn::particle::{RenderLayer, Solid};
+        use cavernborn::render::chunk_material::{decode_draw_order, decode_sprite};
+        use cavernborn::world::chunk::Chunk;
+
+        let stone = Particle::Common(Common::Stone);
+        let obsidian = Particle::Solid(Solid::Obsidian);
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+
+        assert_eq!(stone.render_layer(), RenderLayer::Solid);
+        assert_eq!(obsidian.render_layer(), RenderLayer::Solid);
+        assert_eq!(water.render_layer(), RenderLayer::Liquid);
+        assert_eq!(water.render_layer().draw_order(), 1);
+
+        // Water resting on stone reaches the shader with the higher draw order.
+        let particles = ParticleRegistry::default();
+        let mut chunk = Chunk::new(UVec2::ZERO);
+        chunk.set_particle(UVec2::new(0, 0), Some(stone));
+        chunk.set_particle(UVec2::new(0, 1), Some(water));
+        let cells = chunk.to_cell_indices(&particles);
+        let (stone_cell, water_cell) = (cells[0], cells[32]);
+        assert_eq!(decode_draw_order(stone_cell), 0);
+        assert_eq!(decode_draw_order(water_cell), 1);
+        assert!(decode_draw_order(water_cell) > decode_draw_order(stone_cell));
+        // The draw order doesn't change which sprite is drawn.
+        assert_eq!(decode_sprite(water_cell), particles.sprite(water));
+
+        // Definitions can lift a particle over others, up to the most the cells can hold.
+        let text = include_str!("../assets/particles.toml");
+        let mut definitions = ParticleDefinitions::from_toml(text).unwrap();
+        definitions.particles.get_mut("Stone").unwrap().draw_order = Some(4);
+        let particles = ParticleRegistry::from_definitions(&definitions).unwrap();
+        assert_eq!(particles.draw_order(stone), 4);
+        assert_eq!(particles.draw_order(obsidian), 0);
+        assert_eq!(decode_draw_order(chunk.to_cell_indices(&particles)[0]), 4);
+        definitions.particles.get_mut("Stone").unwrap().draw_order = Some(32);
+        assert!(ParticleRegistry::from_definitions(&definitions).is_err());
+    }
+
+    /// Test that structures are planned apart from each other and deep enough, and that the
+    /// generated map holds exactly their cells
+    #[test]
+    fn test_structures_are_stamped_apart() {
+        use cavernborn::world::generator::{plan_structures, StructureKind};
+
+        assert_eq!(Structure::from_template(&["#.", "#"]), None);
+        assert_eq!(Structure::from_template(&["#?"]), None);
+        let pocket = Structure::from_template(&["#R", ".W"]).unwrap();
+        assert_eq!(pocket.size(), UVec2::new(2, 2));
+        // The top row comes first.
+        assert_eq!(
+            pocket.get(UVec2::new(0, 1)),
+            Some(Particle::Common(Common::Stone))
+        );
+        assert_eq!(pocket.get(UVec2::new(0, 0)), None);
+
+        let map = Map::generate(16, 8, 12);
+        let plan = plan_structures(map.surface_profile(), map.gen_config().unwrap());
+        assert!(plan.len() > 1, "Some structures should be placed");
+        assert!(plan
+            .iter()
+            .any(|placed| placed.kind == StructureKind::Mineshaft));
+
+        for (i, placed) in plan.iter().enumerate() {
+            for other in &plan[i + 1..] {
+                let apart = placed.max().cmple(other.position).any()
+                    || other.max().cmple(placed.position).any();
+                assert!(apart, "{:?} overlaps {:?}", placed.kind, other.kind);
+            }
+            for (position, particle) in placed.cells() {
+                assert!(position.y < map.surface_profile()[position.x as usize]);
+                assert_eq!(map.get_particle_at(position), particle, "at {}", position);
+            }
+        }
+        assert_eq!(
+            plan,
+            plan_structures(map.surface_profile(), map.gen_config().unwrap())
+        );
+    }
+
+    /// Test that generated liquid pockets sit within their liquid's depth range, away from each
+    /// other, that the chunks holding them start out simulating, and that none are planned when
+    /// caves start below the bedrock
+    #[test]
+    fn test_liquid_pockets_are_generated() {
+        use cavernborn::world::biome::Biome;
+        use cavernborn::world::generator::plan_liquid_pockets;
+
+        let map = Map::generate(16, 20, 5);
+        let pockets = plan_liquid_pockets(
+            map.surface_profile(),
+            &map.biomes,
+            map.gen_config().unwrap(),
+        );
+        assert!(pockets.len() > 2, "Some pockets should form");
+        assert!(pockets
+            .iter()
+            .any(|pocket| matches!(pocket.liquid, Liquid::Water(_))));
+
+        for pocket in &pockets {
+            let depth = map.surface_profile()[pocket.center.x as usize] - pocket.center.y;
+            assert!(map
+                .particles()
+                .depths(pocket.liquid.into())
+                .contains(&depth));
+            assert!(
+                map.biome_at(pocket.center.x)
+                    .liquid_pocket_chance(pocket.liquid, map.particles())
+                    > 0
+            );
+            if map.biome_at(pocket.center.x) == Biome::Frozen {
+                assert!(!matches!(pocket.liquid, Liquid::Lava(_)));
+            }
+        }
+
+        let mut liquid_cells = 0;
+        for pocket in &pockets {
+            for (position, particle) in pocket.cells() {
+                // Structures are stamped over pockets, so only check untouched cells.
+                if map.get_particle_at(position) != particle {
+                    continue;
+                }
+                if particle.is_some() {
+                    liquid_cells += 1;
+                    let chunk = map.get_chunk_at(&(position / 32));
+                    assert!(
+                        chunk.should_simulate,
+                        "Chunk at {} is asleep",
+                        chunk.position
+                    );
+                }
+            }
+        }
+        assert!(liquid_cells > 0);
+
+        // Caves that only start below the bedrock leave no room for pockets.
+        let bedrock = map.particles().depths(Common::Bedrock.into()).start;
+        let mut config = map.gen_config().unwrap().clone();
+        config.terrain.cave_min_depth = bedrock + 10;
+        assert!(plan_liquid_pockets(map.surface_profile(), &map.biomes, &config).is_empty());
+    }
+
+    /// Test that a lazily generated chunk matches the same chunk from a full generation
+    #[test]
+    fn test_generate_chunk_matches_full_generation() {
+        let seed = 77;
+        let map = Map::generate(4, 4, seed);
+        assert_eq!(map.seed(), Some(seed));
+        assert_eq!(
+            Map::empty(64, 64).generate_chunk(UVec2::ZERO).map(|_| ()),
+            None
+        );
+
+        for chunk in map.chunks.iter().flatten() {
+            let lazy = map.generate_chunk(chunk.position).unwrap();
+            assert_eq!(lazy.position, chunk.position);
+            assert!(
+                lazy.cells() == chunk.cells(),
+                "Chunk {} differs from the full generation",
+                chunk.position
+            );
+        }
+        assert!(map.generate_chunk(UVec2::new(4, 0)).is_none());
+    }
+
+    /// Test that the chunk counts reflect the active set and each chunk's simulate flag
+    #[test]
+    fn test_active_and_simulating_chunk_counts() {
+        let mut map = Map::empty(128, 64);
+        assert_eq!(map.chunk_count(), 8);
+        assert_eq!(map.active_chunk_count(), 0);
+        assert_eq!(map.simulating_chunk_count(), 0);
+
+        map.set_active_chunks([UVec2::new(0, 0), UVec2::new(1, 0), UVec2::new(2, 1)].into());
+        assert_eq!(map.active_chunk_count(), 3);
+        assert_eq!(map.simulating_chunk_count(), 0);
+
+        map.chunks[1][0].should_simulate = true;
+        map.chunks[2][1].should_simulate = true;
+        // Inactive chunks don't count, even if they would simulate.
+        map.chunks[3][1].should_simulate = true;
+        assert_eq!(map.simulating_chunk_count(), 2);
+    }
+
+    /// Test that map_cells transforms cells and only dirties the chunks it changed
+    #[test]
+    fn test_map_cells_transforms_and_dirties_changed_chunks() {
+        let mut map = Map::empty(64, 64);
+        for x in 0..64 {
+            map.set_particle_at(UVec2::new(x, 5), Some(Particle::Common(Common::Dirt)));
+            map.set_particle_at(UVec2::new(x, 40), Some(Particle::Common(Common::Dirt)));
+        }
+        for chunk in map.chunks.iter_mut().flatten() {
+            chunk.dirty = false;
+        }
+        let versions: Vec<u64> = map.chunks.iter().flatten().map(|c| c.version).collect();
+
+        // Convert only the deep dirt (the bottom row of chunks) to stone.
+        map.map_cells(|pos, particle| match particle {
+            Some(Particle::Common(Common::Dirt)) if pos.y < 32 => {
+                Some(Particle::Common(Common::Stone))
+            }
+            other => other,
+        });
+
+        for x in 0..64 {
+            assert_eq!(
+                map.get_particle_at(UVec2::new(x, 5)),
+                Some(Particle::Common(Common::Stone))
+            );
+            assert_eq!(
+                map.get_particle_at(UVec2::new(x, 40)),
+                Some(Particle::Common(Common::Dirt))
+            );
+        }
+        for (chunk, version) in map.chunks.iter().flatten().zip(versions) {
+            let changed = chunk.position.y == 0;
+            assert_eq!(chunk.dirty, changed, "Chunk {}", chunk.position);
+            assert_eq!(
+                chunk.version != version,
+                changed,
+                "Chunk {}",
+                chunk.position
+            );
+        }
+
+        // Changing only a liquid's direction still counts as a change.
+        let water_pos = UVec2::new(3, 20);
+        map.set_particle_at(
+            water_pos,
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+        );
+        map.map_cells(|_, particle| match particle {
+            Some(Particle::Liquid(liquid)) => {
+                Some(Particle::Liquid(liquid.with_state(LiquidState {
+                    direction: Direction::Right,
+                    ..*liquid.get_state()
+                })))
+            }
+            other => other,
+        });
+        let Some(Particle::Liquid(water)) = map.get_particle_at(water_pos) else {
+            panic!("Water should stay in place");
+        };
+        assert_eq!(water.get_state().direction, Direction::Right);
+    }
+
+    /// Test that an out-of-range local coordinate panics with a descriptive message in debug builds
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "handle_particle_movement: local coordinate [32, 5] is outside the 32x32 chunk"
+    )]
+    fn test_cell_index_out_of_range_message() {
+        cavernborn::world::chunk::cell_index(UVec2::new(32, 5), "handle_particle_movement");
+    }
+
+    /// Test that seeded special rolls pick the same particle regardless of candidate order
+    #[test]
+    fn test_equal_chance_specials_roll_deterministically() {
+        use cavernborn::particle::{Gem, Ore, Special};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let gold = Special::Ore(Ore::Gold);
+        let ruby = Special::Gem(Gem::Ruby);
+        assert_ne!(gold.variant_id(), ruby.variant_id());
+
+        let roll_all = |candidates: Vec<(Special, i32)>| -> Vec<Option<Particle>> {
+            let mut rng = StdRng::seed_from_u64(42);
+            (0..500)
+                .map(|_| Map::roll_weighted_special(candidates.clone(), &mut rng))
+                .collect()
+        };
+
+        let forward = roll_all(vec![(gold, 300), (ruby, 300)]);
+        let reversed = roll_all(vec![(ruby, 300), (gold, 300)]);
+        assert_eq!(forward, reversed);
+        assert_eq!(forward, roll_all(vec![(gold, 300), (ruby, 300)]));
+        // Both particles actually get picked, so the order really matters.
+        assert!(forward.contains(&Some(Particle::Special(gold))));
+        assert!(forward.contains(&Some(Particle::Special(ruby))));
+    }
+
+    /// Test that every ore spawns only in its depth band, in veins shaped by its profile
+    #[test]
+    fn test_ore_veins_follow_profiles() {
+        use cavernborn::particle::{Ore, Special};
+        use cavernborn::world::biome::Biome;
+        use cavernborn::world::generator::{spawn_vein, MAX_VEIN_REACH};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let particles = ParticleRegistry::default();
+        let mut rng = StdRng::seed_from_u64(7);
+        for depth in [0, 10, 100, 300, 500] {
+            for _ in 0..2000 {
+                if let Some(Particle::Special(special)) =
+                    Map::roll_special_particle(depth, Biome::default(), &particles, &mut rng)
+                {
+                    assert!(particles
+                        .depths(Particle::Special(special))
+                        .contains(&depth));
+                }
+            }
+        }
+
+        // The average width, height and size of an ore's veins.
+        let measure = |ore: Ore| {
+            let mut rng = StdRng::seed_from_u64(11);
+            let start = UVec2::new(50, 50);
+            let (mut width, mut height, mut size) = (0, 0, 0);
+            for _ in 0..200 {
+                let vein = spawn_vein(start, ore, 100, 100, &mut rng);
+                let profile = ore.vein_profile();
+                assert!(vein.len() as u32 <= profile.max_size + 1);
+                assert!(vein
+                    .iter()
+                    .all(|&(_, particle)| particle == Particle::Special(Special::Ore(ore))));
+
+                let cells: Vec<UVec2> = vein.iter().map(|&(cell, _)| cell).collect();
+                for (i, cell) in cells.iter().enumerate() {
+                    assert!(cell.x.abs_diff(start.x) <= MAX_VEIN_REACH);
+                    // Every cell grew off one placed before it.
+                    assert!(
+                        i == 0
+                            || cells[..i].iter().any(|other| {
+                                other.x.abs_diff(cell.x) <= 1 && other.y.abs_diff(cell.y) <= 1
+                            })
+                    );
+                }
+                let span = |axis: fn(&UVec2) -> u32| {
+                    let values = cells.iter().map(axis);
+                    values.clone().max().unwrap() - values.min().unwrap() + 1
+                };
+                width += span(|cell| cell.x);
+                height += span(|cell| cell.y);
+                size += cells.len() as u32;
+            }
+            (
+                width as f32 / 200.0,
+                height as f32 / 200.0,
+                size as f32 / 200.0,
+            )
+        };
+
+        let (coal_width, coal_height, _) = measure(Ore::Coal);
+        assert!(
+            coal_width > coal_height * 1.5,
+            "Coal should form flat seams"
+        );
+        let (_, _, copper_size) = measure(Ore::Copper);
+        let (_, _, diamond_size) = measure(Ore::Diamond);
+        assert!(diamond_size > copper_size * 1.5);
+        assert!(
+            particles.spawn_chance(Particle::Special(Special::Ore(Ore::Diamond)))
+                < particles.spawn_chance(Particle::Special(Special::Ore(Ore::Copper)))
+        );
+    }
+
+    /// Builds a 3x2 structure with a distinct particle in every occupied cell:
+    ///
+    /// ```text
+    /// G . .
+    /// D C W
+    /// ```
+    fn lopsided_structure() -> Structure {
+        let mut structure = Structure::new(UVec2::new(3, 2));
+        structure.set(UVec2::new(0, 1), Some(Particle::Common(Common::Grass)));
+        structure.set(UVec2::new(0, 0), Some(Particle::Common(Common::Dirt)));
+        structure.set(UVec2::new(1, 0), Some(Particle::Common(Common::Clay)));
+        structure.set(
+            UVec2::new(2, 0),
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+        );
+        structure
+    }
+
+    /// Places the lopsided structure at (10, 10) and checks that exactly the `expected` cells,
+    /// relative to that corner, were written.
+    fn assert_placement(placement: Placement, expected: &[(u32, u32, Particle)]) {
+        let origin = UVec2::new(10, 10);
+        let mut map = Map::empty(32, 32);
+        map.place_structure(&lopsided_structure(), origin, &placement);
+
+        for x in 0..5 {
+            for y in 0..5 {
+                let want = expected
+                    .iter()
+                    .find(|(ex, ey, _)| (*ex, *ey) == (x, y))
+                    .map(|(_, _, particle)| *particle);
+                let got = map.get_particle_at(origin + UVec2::new(x, y));
+                // Compares liquid directions too, which `==` ignores.
+                let matches = match (got, want) {
+                    (Some(got), Some(want)) => got.is_identical(&want),
+                    (got, want) => got.is_none() && want.is_none(),
+                };
+                assert!(
+                    matches,
+                    "Cell ({}, {}) with {:?} is {:?}, expected {:?}",
+                    x, y, placement, got, want
+                );
+            }
+        }
+    }
+
+    /// Test that each clockwise rotation places the structure's cells where expected
+    #[test]
+    fn test_place_structure_rotations() {
+        let grass = Particle::Common(Common::Grass);
+        let dirt = Particle::Common(Common::Dirt);
+        let clay = Particle::Common(Common::Clay);
+        let water_left = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let water_right = Particle::Liquid(Liquid::Water(Direction::Right.into()));
+        let water_still = Particle::Liquid(Liquid::Water(Direction::Still.into()));
+
+        let cases = [
+            (
+                Rotation::None,
+                [
+                    (0, 1, grass),
+                    (0, 0, dirt),
+                    (1, 0, clay),
+                    (2, 0, water_left),
+                ],
+            ),
+            // Quarter turns point liquids up or down, so they come out still.
+            (
+                Rotation::Clockwise90,
+                [
+                    (1, 2, grass),
+                    (0, 2, dirt),
+                    (0, 1, clay),
+                    (0, 0, water_still),
+                ],
+            ),
+            // Turning around reverses which way liquids flow.
+            (
+                Rotation::Clockwise180,
+                [
+                    (2, 0, grass),
+                    (2, 1, dirt),
+                    (1, 1, clay),
+                    (0, 1, water_right),
+                ],
+            ),
+            (
+                Rotation::Clockwise270,
+                [
+                    (0, 0, grass),
+                    (1, 0, dirt),
+                    (1, 1, clay),
+                    (1, 2, water_still),
+                ],
+            ),
+        ];
+        for (rotation, expected) in cases {
+            assert_placement(
+                Placement {
+                    rotation,
+                    ..Default::default()
+                },
+                &expected,
+            );
+        }
+
+        let water_direction = |structure: &Structure, position| match structure.get(position) {
+            Some(Particle::Liquid(liquid)) => liquid.get_state().direction,
+            other => panic!("Expected water at {}, found {:?}", position, other),
+        };
+        let structure = lopsided_structure();
+        let water_pos = UVec2::new(2, 0);
+        assert_eq!(
+            water_direction(&structure.rotated(Rotation::Clockwise180), UVec2::new(0, 1)),
+            Direction::Right
+        );
+
+        // Two half turns bring the structure back to where it started, liquid directions
+        // included, while four quarter turns bring the cells back with the water still.
+        let turned = structure
+            .rotated(Rotation::Clockwise180)
+            .rotated(Rotation::Clockwise180);
+        assert_eq!(turned, structure);
+        assert_eq!(water_direction(&turned, water_pos), Direction::Left);
+        let turned = (0..4).fold(structure.clone(), |s, _| s.rotated(Rotation::Clockwise90));
+        assert_eq!(turned, structure);
+        assert_eq!(water_direction(&turned, water_pos), Direction::Still);
+    }
+
+    /// Test that mirroring flips the structure before rotating and that carving clears empty cells
+    #[test]
+    fn test_place_structure_mirror_and_carve() {
+        let grass = Particle::Common(Common::Grass);
+        let dirt = Particle::Common(Common::Dirt);
+        let clay = Particle::Common(Common::Clay);
+        let water_left = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let water_right = Particle::Liquid(Liquid::Water(Direction::Right.into()));
+
+        assert_placement(
+            Placement {
+                mirror: true,
+                ..Default::default()
+            },
+            &[
+                (2, 1, grass),
+                (2, 0, dirt),
+                (1, 0, clay),
+                (0, 0, water_right),
+            ],
+        );
+        // Mirroring and then turning around is the same as flipping upside down.
+        assert_placement(
+            Placement {
+                mirror: true,
+                rotation: Rotation::Clockwise180,
+                ..Default::default()
+            },
+            &[
+                (0, 0, grass),
+                (0, 1, dirt),
+                (1, 1, clay),
+                (2, 1, water_left),
+            ],
+        );
+
+        let mirrored = lopsided_structure().mirrored();
+        match mirrored.get(UVec2::new(0, 0)) {
+            Some(Particle::Liquid(water)) => {
+                assert_eq!(water.get_state().direction, Direction::Right)
+            }
+            other => panic!("Expected mirrored water, found {:?}", other),
+        }
+
+        // Without carving, the structure's empty cells keep whatever was there.
+        let stone = Some(Particle::Common(Common::Stone));
+        let mut map = Map::empty(32, 32);
+        map.set_particle_at(UVec2::new(12, 11), stone);
+        map.place_structure(
+            &lopsided_structure(),
+            UVec2::new(10, 10),
+            &Placement::default(),
+        );
+        assert_eq!(map.get_particle_at(UVec2::new(12, 11)), stone);
+
+        map.place_structure(
+            &lopsided_structure(),
+            UVec2::new(10, 10),
+            &Placement {
+                carve: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(map.get_particle_at(UVec2::new(12, 11)), None);
+
+        // Cells past the map edge are clipped instead of panicking.
+        map.place_structure(
+            &lopsided_structure(),
+            UVec2::new(31, 31),
+            &Placement::default(),
+        );
+        assert_eq!(map.get_particle_at(UVec2::new(31, 31)), Some(dirt));
+    }
+
+    /// Test that caves are carved according to the generator config and spare the surface and bedrock
+    #[test]
+    fn test_caves_follow_generator_config() {
+        use cavernborn::world::generator::GeneratorConfig;
+
+        // Count the empty cells below each column's surface, split into those within the cave
+        // free top layers, the Bedrock layer, and everything in between.
+        let count_gaps = |map: &Map, config: &GeneratorConfig| {
+            let (mut shallow, mut deep, mut bedrock) = (0, 0, 0);
+            for (x, &surface) in map.surface_profile().iter().enumerate() {
+                for y in 0..=surface {
+                    if map.get_particle_at(UVec2::new(x as u32, y)).is_some() {
+                        continue;
+                    }
+                    let depth = surface - y;
+                    if depth < config.cave_min_depth {
+                        shallow += 1;
+                    } else if depth >= map.particles().depths(Common::Bedrock.into()).start {
+                        bedrock += 1;
+                    } else {
+                        deep += 1;
+                    }
+                }
+            }
+            (shallow, deep, bedrock)
+        };
+
+        let solid = GeneratorConfig {
+            cave_density: 0.0,
+            tunnel_width: 0.0,
+            structure_frequency: 0.0,
+            pocket_frequency: 0.0,
+            ..Default::default()
+        };
+        let map = Map::generate_with_config(4, 20, 3, &solid);
+        assert_eq!(
+            count_gaps(&map, &solid),
+            (0, 0, 0),
+            "Nothing should be carved"
+        );
+
+        let default = GeneratorConfig::default();
+        let (shallow, sparse, bedrock) = count_gaps(&Map::generate(4, 20, 3), &default);
+        assert_eq!((shallow, bedrock), (0, 0));
+        assert!(sparse > 0, "The default config should carve some caves");
+
+        let dense = GeneratorConfig {
+            cave_density: 0.4,
+            ..default
+        };
+        let (_, many, _) = count_gaps(&Map::generate_with_config(4, 20, 3, &dense), &dense);
+        assert!(
+            many > sparse,
+            "Denser caves should carve more: {} vs {}",
+            many,
+            sparse
+        );
+
+        // Lazily generated chunks are carved the same way.
+        let map = Map::generate_with_config(4, 20, 3, &dense);
+        for chunk in map.chunks.iter().flatten() {
+            let lazy = map.generate_chunk(chunk.position).unwrap();
+            assert!(lazy.cells() == chunk.cells(), "Chunk {}", chunk.position);
+        }
+    }
+
+    /// Test that streamed chunks are generated in range exactly as the full generation made them
+    #[test]
+    fn test_streamed_chunks_match_full_generation() {
+        let seed = 31;
+        let full = Map::generate(6, 6, seed);
+        let mut streamed = Map::generate_streamed(6, 6, seed, 100);
+        assert!(streamed.is_streamed());
+        assert_eq!(streamed.loaded_chunk_count(), 0);
+        assert_eq!(streamed.surface_profile(), full.surface_profile());
+
+        streamed.stream_chunks(UVec2::new(2, 4), 1);
+
+        assert_eq!(streamed.loaded_chunk_count(), 9);
+        for chunk in full.chunks.iter().flatten() {
+            let pos = chunk.position;
+            let in_range = (1..=3).contains(&pos.x) && (3..=5).contains(&pos.y);
+            assert_eq!(streamed.is_chunk_loaded(pos), in_range);
+            if in_range {
+                assert!(
+                    streamed.get_chunk_at(&pos).cells() == chunk.cells(),
+                    "Chunk {} differs from the full generation",
+                    pos
+                );
+            } else {
+                assert!(streamed.get_chunk_at(&pos).is_compacted());
+            }
+        }
+        assert!(Map::empty(64, 64).is_chunk_loaded(UVec2::ZERO));
+    }
+
+    /// Test that the least recently used chunks are evicted first and changed chunks come back intact
+    #[test]
+    fn test_streaming_evicts_least_recently_used() {
+        let seed = 5;
+        let full = Map::generate(8, 2, seed);
+        let mut map = Map::generate_streamed(8, 2, seed, 6);
+
+        // Each call loads a 3x2 block of chunks around the center column.
+        map.stream_chunks(UVec2::new(1, 0), 1);
+        let changed = UVec2::new(40, 10);
+        map.set_particle_at(changed, Some(Particle::Common(Common::Bedrock)));
+        map.stream_chunks(UVec2::new(3, 0), 1);
+        assert_eq!(map.loaded_chunk_count(), 6, "Should be back within budget");
+        // Columns 0 and 1 were used longest ago, column 2 is still in range.
+        for y in 0..2 {
+            assert!(!map.is_chunk_loaded(UVec2::new(0, y)));
+            assert!(!map.is_chunk_loaded(UVec2::new(1, y)));
+            assert!(map.is_chunk_loaded(UVec2::new(2, y)));
+        }
+        assert_eq!(map.get_particle_at(changed), None);
+
+        // Coming back regenerates the untouched chunks and restores the changed one.
+        map.stream_chunks(UVec2::new(1, 0), 1);
+        assert_eq!(
+            map.get_particle_at(changed),
+            Some(Particle::Common(Common::Bedrock))
+        );
+        for y in 0..2 {
+            let pos = UVec2::new(0, y);
+            assert!(map.get_chunk_at(&pos).cells() == full.get_chunk_at(&pos).cells());
+        }
+
+        // The restored chunk keeps its change through another eviction, even untouched since.
+        map.stream_chunks(UVec2::new(3, 0), 1);
+        assert!(!map.is_chunk_loaded(UVec2::new(1, 0)));
+        map.stream_chunks(UVec2::new(1, 0), 1);
+        assert_eq!(
+            map.get_particle_at(changed),
+            Some(Particle::Common(Common::Bedrock))
+        );
+    }
+
+    /// Test that evicted chunks keep their heat, even when nothing else in them changed
+    #[test]
+    fn test_streaming_keeps_temperatures() {
+        use cavernborn::particle::AMBIENT_TEMPERATURE;
+
+        let mut map = Map::generate_streamed(2, 2, 9, 2);
+        let (below, above) = (UVec2::new(0, 0), UVec2::new(0, 1));
+        map.load_chunk(below);
+        map.load_chunk(above);
+
+        // Lava in the chunk above heats the top row of the one below through the border.
+        let warmed = (0..32)
+            .map(|x| UVec2::new(x, 31))
+            .find(|&pos| {
+                map.get_particle_at(pos)
+                    .is_some_and(|particle| !matches!(particle, Particle::Liquid(_)))
+            })
+            .expect("Some of the top row should be solid ground");
+        let lava = Particle::Liquid(Liquid::Lava(Direction::Still.into()));
+        map.set_particle_at(warmed + UVec2::Y, Some(lava));
+        map.set_active_chunks([below, above].into_iter().collect());
+        map.update_dirty_chunks();
+        for _ in 0..50 {
+            map.diffuse_heat();
+        }
+        let heated = map.get_temperature_at(warmed);
+        assert!(heated > AMBIENT_TEMPERATURE + 1.0);
+
+        // Loading a third chunk evicts the one below, used longest ago.
+        map.stream_chunks(UVec2::new(1, 1), 0);
+        assert!(!map.is_chunk_loaded(below));
+        map.load_chunk(below);
+        assert_eq!(map.get_temperature_at(warmed), heated);
+    }
+
+    /// Test that evicted chunks keep how worn their cells are
+    #[test]
+    fn test_streaming_keeps_wear() {
+        let mut map = Map::generate_streamed(2, 2, 9, 1);
+        map.load_chunk(UVec2::ZERO);
+        let worn = (0..32)
+            .flat_map(|x| (0..32).map(move |y| UVec2::new(x, y)))
+            .find(|&pos| {
+                map.get_particle_at(pos)
+                    .and_then(|particle| particle.get_hardness())
+                    .is_some_and(|hardness| hardness > 1)
+            })
+            .expect("The bottom chunk should hold hard ground");
+        let particle = map.get_particle_at(worn);
+        assert_eq!(map.wear_particle_at(worn, 1.0), None);
+
+        // Loading another chunk evicts this one, whose cells didn't change otherwise.
+        map.stream_chunks(UVec2::new(1, 1), 0);
+        assert!(!map.is_chunk_loaded(UVec2::ZERO));
+        map.load_chunk(UVec2::ZERO);
+        assert_eq!(map.get_particle_at(worn), particle);
+        assert_eq!(map.get_wear_at(worn), 1.0);
+    }
+
+    /// Test that writing into unloaded streamed chunks loads them, so loading them again later
+    /// doesn't generate over the write
+    #[test]
+    fn test_writing_unloaded_streamed_chunks() {
+        use cavernborn::particle::Solid;
+
+        let obsidian = Some(Particle::Solid(Solid::Obsidian));
+        let mut map = Map::generate_streamed(2, 2, 9, 1);
+        let written = [
+            UVec2::new(5, 5),
+            UVec2::new(40, 10),
+            UVec2::new(10, 40),
+            UVec2::new(50, 50),
+        ];
+        for position in written {
+            let chunk_pos = position / 32;
+            assert!(!map.is_chunk_loaded(chunk_pos));
+            map.set_particle_at(position, obsidian);
+            assert!(map.is_chunk_loaded(chunk_pos));
+        }
+        let wall = UVec2::new(60, 60);
+        map.set_background_at(wall, obsidian);
+
+        // Streaming evicts all but one chunk, then each is loaded again.
+        map.stream_chunks(UVec2::ZERO, 0);
+        for position in written {
+            map.stream_chunks(position / 32, 0);
+            assert_eq!(map.get_particle_at(position), obsidian);
+        }
+        assert_eq!(map.get_background_at(wall), obsidian);
+
+        // Wear reaches the real cell rather than the empty stand-in.
+        map.stream_chunks(UVec2::ZERO, 0);
+        let worn = (32..64)
+            .flat_map(|x| (0..32).map(move |y| UVec2::new(x, y)))
+            .find(|&pos| {
+                map.full_chunk(pos / 32)
+                    .get_particle(pos % 32)
+                    .and_then(|particle| particle.get_hardness())
+                    .is_some_and(|hardness| hardness > 1)
+            })
+            .expect("The bottom right chunk should hold hard ground");
+        assert!(!map.is_chunk_loaded(worn / 32));
+        assert_eq!(map.wear_particle_at(worn, 1.0), None);
+        assert!(map.is_chunk_loaded(worn / 32));
+        assert_eq!(map.get_wear_at(worn), 1.0);
+    }
+
+    /// Test that the editor reads and edits the real cells of unloaded streamed chunks
+    #[test]
+    fn test_editing_unloaded_streamed_chunks() {
+        use bevy::math::URect;
+        use cavernborn::editor::{copy_region, fill_rect};
+        use cavernborn::world::history::EditHistory;
+
+        let seed = 17;
+        let full = Map::generate(4, 2, seed);
+        let mut map = Map::generate_streamed(4, 2, seed, 1);
+        let everything = URect::new(0, 0, map.width, map.height);
+        assert_eq!(
+            copy_region(&map, everything),
+            copy_region(&full, everything),
+            "Unloaded chunks should copy as they were generated, not as air"
+        );
+
+        let far = UVec2::new(3, 1);
+        let bedrock = Some(Particle::Common(Common::Bedrock));
+        let mut history = EditHistory::default();
+        fill_rect(
+            &mut map,
+            &mut history,
+            URect::new(100, 40, 104, 44),
+            bedrock,
+        );
+        history.end_stroke();
+        assert!(map.is_chunk_loaded(far));
+        assert_eq!(map.get_particle_at(UVec2::new(101, 41)), bedrock);
+
+        // Streaming elsewhere evicts the edited chunk, which keeps the fill.
+        map.stream_chunks(UVec2::ZERO, 0);
+        assert!(!map.is_chunk_loaded(far));
+        let chunk = map.full_chunk(far);
+        assert_eq!(chunk.get_particle(UVec2::new(5, 9)), bedrock);
+        assert_eq!(
+            chunk.get_particle(UVec2::new(20, 20)),
+            full.get_particle_at(UVec2::new(116, 52))
+        );
+    }
+
+    /// Test that the world seed is read from the command line in either flag form
+    #[test]
+    fn test_world_seed_from_args() {
+        use cavernborn::world::generator::WorldSeed;
+
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(WorldSeed::from_args(args(&[])), Ok(None));
+        assert_eq!(WorldSeed::from_args(args(&["--verbose"])), Ok(None));
+        assert_eq!(
+            WorldSeed::from_args(args(&["--seed", "1234"])),
+            Ok(Some(WorldSeed(1234)))
+        );
+        assert_eq!(
+            WorldSeed::from_args(args(&["--fullscreen", "--seed=99"])),
+            Ok(Some(WorldSeed(99)))
+        );
+        assert!(WorldSeed::from_args(args(&["--seed"])).is_err());
+        assert!(WorldSeed::from_args(args(&["--seed", "cave"])).is_err());
+        assert!(WorldSeed::from_args(args(&["--seed=-1"])).is_err());
+        // Other flags that merely start the same way are left alone.
+        assert_eq!(WorldSeed::from_args(args(&["--seeds", "3"])), Ok(None));
+
+        // Generated maps simulate from their generation seed.
+        assert_eq!(Map::generate(2, 2, 77).simulation_seed(), 77);
+    }
+
+    /// Test that digging takes longer for harder particles and collects ore and gems
+    #[test]
+    fn test_digging_collects_ore_by_hardness() {
+        use cavernborn::particle::{Gem, Ore, Special};
+        use cavernborn::player::{dig_particle, Inventory};
+
+        let mut map = Map::empty(32, 32);
+        let dirt = UVec2::new(1, 1);
+        let stone = UVec2::new(2, 1);
+        let gold = UVec2::new(3, 1);
+        let ruby = UVec2::new(4, 1);
+        let bedrock = UVec2::new(5, 1);
+        map.set_particle_at(dirt, Some(Particle::Common(Common::Dirt)));
+        map.set_particle_at(stone, Some(Particle::Common(Common::Stone)));
+        map.set_particle_at(gold, Some(Particle::Special(Special::Ore(Ore::Gold))));
+        map.set_particle_at(ruby, Some(Particle::Special(Special::Gem(Gem::Ruby))));
+        map.set_particle_at(bedrock, Some(Particle::Common(Common::Bedrock)));
+
+        let mut inventory = Inventory::default();
+        let mut removed_after = |map: &mut Map, position: UVec2| {
+            (1..=100).find(|_| dig_particle(map, position, 0.05, &mut inventory))
+        };
+
+        let dirt_frames = removed_after(&mut map, dirt).unwrap();
+        let stone_frames = removed_after(&mut map, stone).unwrap();
+        let gold_frames = removed_after(&mut map, gold).unwrap();
+        assert!(dirt_frames < stone_frames && stone_frames < gold_frames);
+        assert!(removed_after(&mut map, ruby).is_some());
+        assert_eq!(
+            removed_after(&mut map, bedrock),
+            None,
+            "Bedrock can't be dug"
+        );
+
+        assert_eq!(map.get_particle_at(stone), None);
+        assert!(map.get_particle_at(bedrock).is_some());
+        // Only ore and gems are collected.
+        assert_eq!(inventory.count(Special::Ore(Ore::Gold)), 1);
+        assert_eq!(inventory.count(Special::Gem(Gem::Ruby)), 1);
+        assert_eq!(inventory.total(), 2);
+        assert_eq!(
+            inventory.entries(),
+            vec![(Special::Ore(Ore::Gold), 1), (Special::Gem(Gem::Ruby), 1)]
+        );
+
+        // Liquids are still cleared instantly.
+        let water = UVec2::new(6, 1);
+        map.set_particle_at(
+            water,
+            Some(Particle::Liquid(Liquid::Water(Direction::Left.into()))),
+        );
+        assert!(dig_particle(&mut map, water, 0.0, &mut inventory));
+
+        // Stopping halfway leaves the particle worn, until something else takes its place.
+        let clay = UVec2::new(7, 1);
+        map.set_particle_at(clay, Some(Particle::Common(Common::Clay)));
+        assert!(!dig_particle(&mut map, clay, 0.1, &mut inventory));
+        assert_eq!(map.get_wear_at(clay), 1.0);
+        assert!(dig_particle(&mut map, clay, 0.1, &mut inventory));
+        map.set_particle_at(clay, Some(Particle::Common(Common::Clay)));
+        assert_eq!(map.get_wear_at(clay), 0.0);
+    }
+
+    /// Test that the placement palette reaches every particle kind through its categories
+    #[test]
+    fn test_selected_particle_cycles_categories() {
+        use cavernborn::particle::{Ore, Solid, Special};
+        use cavernborn::player::SelectedParticle;
+
+        let categories = SelectedParticle::categories();
+        assert_eq!(categories.len(), 7);
+        assert_eq!(
+            categories.iter().map(Vec::len).sum::<usize>(),
+            Particle::all_variants().len()
+        );
+
+        let mut selected = SelectedParticle::default();
+        assert!(matches!(
+            selected.particle,
+            Particle::Liquid(Liquid::Water(_))
+        ));
+
+        // Switching category picks its first particle, pressing again cycles and wraps around.
+        assert!(selected.select_category(0));
+        assert_eq!(selected.particle, categories[0][0]);
+        assert!(selected.select_category(0));
+        assert_eq!(selected.particle, categories[0][1]);
+        for _ in 0..categories[0].len() {
+            selected.select_category(0);
+        }
+        assert_eq!(selected.particle, categories[0][1]);
+
+        assert!(selected.select_category(1));
+        assert_eq!(
+            selected.particle,
+            Particle::Special(Special::Ore(Ore::Gold))
+        );
+        assert!(selected.select_category(3));
+        assert_eq!(selected.particle, Particle::Solid(Solid::Obsidian));
+
+        assert!(!selected.select_category(7));
+        assert_eq!(selected.particle, Particle::Solid(Solid::Obsidian));
+    }
+
+    /// Test that each brush shape covers the cells it should, clipped to the map
+    #[test]
+    fn test_brush_shapes() {
+        use cavernborn::player::{Brush, BrushShape};
+
+        let cells = |brush: Brush, center: UVec2| {
+            let mut cells = Vec::new();
+            brush.for_each_cell(center, 32, 32, |cell| cells.push(cell));
+            cells
+        };
+        let center = UVec2::new(10, 10);
+        let brush = |shape: BrushShape, size: u32| Brush { shape, size };
+
+        assert_eq!(cells(brush(BrushShape::Square, 5), center).len(), 25);
+        assert_eq!(cells(brush(BrushShape::Circle, 5), center).len(), 13);
+        // Even sizes reach further left and down, and round circles off at the corners.
+        let square = cells(Brush::default(), center);
+        assert_eq!(square.len(), 4);
+        assert!(square.contains(&UVec2::new(9, 9)));
+        assert_eq!(cells(brush(BrushShape::Circle, 4), center).len(), 12);
+
+        // A size of 1 covers only the cell under the cursor, whatever the shape.
+        let mut shape = BrushShape::default();
+        for _ in 0..3 {
+            assert_eq!(cells(brush(shape, 1), center), vec![center]);
+            shape = shape.next();
+        }
+        assert_eq!(shape, BrushShape::default());
+
+        // Lines are as thick as the brush all the way from the press to the release.
+        let mut line = Vec::new();
+        brush(BrushShape::Line, 1)
+            .for_each_cell_on_line(center, UVec2::new(20, 10), 32, 32, |c| line.push(c));
+        assert_eq!(line.len(), 11);
+        assert!(line.iter().all(|cell| cell.y == center.y));
+        let mut thick = Vec::new();
+        brush(BrushShape::Line, 2)
+            .for_each_cell_on_line(center, UVec2::new(20, 10), 32, 32, |c| thick.push(c));
+        assert_eq!(thick.len(), 24, "Cells covered twice are only visited once");
+
+        // Cells off the map are skipped.
+        assert_eq!(cells(brush(BrushShape::Square, 3), UVec2::ZERO).len(), 4);
+        assert_eq!(
+            cells(brush(BrushShape::Circle, 3), UVec2::new(31, 31)).len(),
+            3
+        );
+    }
+
+    /// Test that undo restores every cell of a stroke at once, redo reapplies it, and the history
+    /// keeps only the newest strokes
+    #[test]
+    fn test_edit_history_undo_redo() {
+        use cavernborn::particle::{Gem, Special};
+        use cavernborn::player::{dig_particle, Inventory};
+        use cavernborn::world::history::{CellEdit, EditHistory, EditLayer};
+
+        let stone = Particle::Common(Common::Stone);
+        let dirt = Particle::Common(Common::Dirt);
+        let mut map = Map::empty(64, 64);
+        map.set_particle_at(UVec2::new(5, 5), Some(stone));
+        let mut history = EditHistory::new(2);
+        let mut inventory = Inventory::default();
+
+        // One drag stroke covering a cell twice, and a wall.
+        history.set_particle(&mut map, UVec2::new(5, 5), Some(dirt));
+        history.set_particle(&mut map, UVec2::new(6, 5), Some(dirt));
+        history.set_particle(&mut map, UVec2::new(5, 5), None);
+        history.set_wall(&mut map, UVec2::new(40, 40), Some(stone));
+        // Edits outside the map are ignored.
+        history.set_particle(&mut map, UVec2::new(64, 0), Some(dirt));
+        history.end_stroke();
+        assert_eq!(history.undo_len(), 1);
+
+        let version = map.get_chunk_at(&UVec2::new(1, 1)).version;
+        assert!(history.undo(&mut map, &mut inventory));
+        assert_eq!(map.get_particle_at(UVec2::new(5, 5)), Some(stone));
+        assert_eq!(map.get_particle_at(UVec2::new(6, 5)), None);
+        assert_eq!(map.get_background_at(UVec2::new(40, 40)), None);
+        assert!(map.get_chunk_at(&UVec2::new(1, 1)).dirty);
+        assert!(map.get_chunk_at(&UVec2::new(1, 1)).version > version);
+        assert!(!history.undo(&mut map, &mut inventory));
+
+        assert!(history.redo(&mut map, &mut inventory));
+        assert_eq!(map.get_particle_at(UVec2::new(5, 5)), None);
+        assert_eq!(map.get_particle_at(UVec2::new(6, 5)), Some(dirt));
+        assert_eq!(map.get_background_at(UVec2::new(40, 40)), Some(stone));
+        assert!(!history.redo(&mut map, &mut inventory));
+
+        // Edits made elsewhere, like digging, can be recorded after the fact.
+        map.set_particle_at(UVec2::new(6, 5), None);
+        history.record(CellEdit {
+            position: UVec2::new(6, 5),
+            layer: EditLayer::Particle,
+            before: Some(dirt),
+            after: None,
+        });
+        // A new stroke discards what could be redone, and only the newest two are kept.
+        assert!(history.undo(&mut map, &mut inventory));
+        assert_eq!(map.get_particle_at(UVec2::new(6, 5)), Some(dirt));
+        history.set_particle(&mut map, UVec2::new(7, 5), Some(stone));
+        history.end_stroke();
+        assert_eq!(history.redo_len(), 0);
+        history.set_particle(&mut map, UVec2::new(8, 5), Some(stone));
+        history.end_stroke();
+        assert_eq!(history.undo_len(), 2);
+
+        // Strokes that didn't change anything aren't kept.
+        history.set_particle(&mut map, UVec2::new(8, 5), Some(stone));
+        history.end_stroke();
+        assert_eq!(history.undo_len(), 2);
+
+        // Undoing a dig takes what it collected back out of the inventory, so digging the same
+        // ruby again doesn't collect it twice.
+        let ruby_pos = UVec2::new(20, 20);
+        let ruby = Special::Gem(Gem::Ruby);
+        map.set_particle_at(ruby_pos, Some(Particle::Special(ruby)));
+        let dig = |map: &mut Map, history: &mut EditHistory, inventory: &mut Inventory| {
+            assert!(dig_particle(map, ruby_pos, 100.0, inventory));
+            history.record(CellEdit {
+                position: ruby_pos,
+                layer: EditLayer::Particle,
+                before: Some(Particle::Special(ruby)),
+                after: None,
+            });
+            history.record_collected(ruby);
+            history.end_stroke();
+        };
+        dig(&mut map, &mut history, &mut inventory);
+        assert_eq!(inventory.count(ruby), 1);
+        assert!(history.undo(&mut map, &mut inventory));
+        assert_eq!(map.get_particle_at(ruby_pos), Some(Particle::Special(ruby)));
+        assert_eq!(inventory.count(ruby), 0);
+        assert!(history.redo(&mut map, &mut inventory));
+        assert_eq!(inventory.count(ruby), 1);
+        assert!(history.undo(&mut map, &mut inventory));
+        dig(&mut map, &mut history, &mut inventory);
+        assert_eq!(inventory.count(ruby), 1);
+    }
+
+    /// Test that the editor's rectangle fill, flood fill and copy/paste edit the right cells,
+    /// redraw their chunks, and can be undone
+    #[test]
+    fn test_editor_area_tools() {
+        use cavernborn::editor::{
+            copy_region, fill_rect, flood_fill, paste_region, selection_rect,
+        };
+        use cavernborn::player::Inventory;
+        use cavernborn::world::history::EditHistory;
+
+        let stone = Particle::Common(Common::Stone);
+        let dirt = Particle::Common(Common::Dirt);
+        let water = Particle::Liquid(Liquid::Water(Direction::Left.into()));
+        let mut map = Map::empty(64, 64);
+        let mut history = EditHistory::default();
+        let mut inventory = Inventory::default();
+        map.update_dirty_chunks();
+
+        // Corners can be given in any order, and the rectangle is clipped to the map.
+        let rect = selection_rect(UVec2::new(40, 12), UVec2::new(30, 10));
+        assert_eq!((rect.width(), rect.height()), (11, 3));
+        assert_eq!(fill_rect(&mut map, &mut history, rect, Some(stone)), 33);
+        assert_eq!(map.get_particle_at(UVec2::new(30, 10)), Some(stone));
+        assert_eq!(map.get_particle_at(UVec2::new(40, 12)), Some(stone));
+        assert_eq!(map.get_particle_at(UVec2::new(41, 12)), None);
+        assert!(map.get_chunk_at(&UVec2::ZERO).dirty);
+        assert!(map.get_chunk_at(&UVec2::new(1, 0)).dirty);
+        history.end_stroke();
+        let edge = selection_rect(UVec2::new(60, 60), UVec2::new(70, 70));
+        assert_eq!(fill_rect(&mut map, &mut history, edge, Some(dirt)), 16);
+        history.end_stroke();
+
+        // A ring of stone holds the flood fill of the air inside it.
+        map.update_dirty_chunks();
+        let ring = selection_rect(UVec2::new(2, 2), UVec2::new(8, 8));
+        fill_rect(&mut map, &mut history, ring, Some(stone));
+        let inside = selection_rect(UVec2::new(3, 3), UVec2::new(7, 7));
+        fill_rect(&mut map, &mut history, inside, None);
+        history.end_stroke();
+        assert_eq!(
+            flood_fill(&mut map, &mut history, UVec2::new(5, 5), Some(water)),
+            25
+        );
+        history.end_stroke();
+        assert_eq!(map.get_particle_at(UVec2::new(3, 7)), Some(water));
+        assert_eq!(map.get_particle_at(UVec2::new(1, 1)), None);
+        // Liquids match whatever way they're flowing, and filling with the same particle does nothing.
+        assert_eq!(
+            flood_fill(&mut map, &mut history, UVec2::new(5, 5), Some(water)),
+            0
+        );
+        assert_eq!(
+            flood_fill(&mut map, &mut history, UVec2::new(2, 2), Some(dirt)),
+            24
+        );
+        assert_eq!(map.get_particle_at(UVec2::new(30, 10)), Some(stone));
+        history.end_stroke();
+        assert!(history.undo(&mut map, &mut inventory));
+        assert_eq!(map.get_particle_at(UVec2::new(2, 2)), Some(stone));
+
+        // Pasting reproduces particles, walls and air, cut off at the edge of the map.
+        map.set_background_at(UVec2::new(4, 4), Some(dirt));
+        let clipboard = copy_region(&map, ring);
+        assert_eq!(clipboard.size, UVec2::new(7, 7));
+        fill_rect(
+            &mut map,
+            &mut history,
+            selection_rect(UVec2::new(20, 30), UVec2::new(26, 36)),
+            Some(dirt),
+        );
+        history.end_stroke();
+        assert_eq!(
+            paste_region(&mut map, &mut history, &clipboard, UVec2::new(20, 30)),
+            49
+        );
+        for x in 0..7 {
+            for y in 0..7 {
+                let (from, to) = (UVec2::new(2 + x, 2 + y), UVec2::new(20 + x, 30 + y));
+                assert_eq!(map.get_particle_at(to), map.get_particle_at(from));
+                assert_eq!(map.get_background_at(to), map.get_background_at(from));
+            }
+        }
+        assert_eq!(
+            paste_region(&mut map, &mut history, &clipboard, UVec2::new(60, 0)),
+            28
+        );
+        history.end_stroke();
+        assert!(history.undo(&mut map, &mut inventory));
+        assert_eq!(map.get_particle_at(UVec2::new(20, 30)), Some(dirt));
+        assert_eq!(map.get_particle_at(UVec2::new(60, 2)), None);
+    }
+
+    /// Test that flood filling a huge region stops at the cap, filling outwards from the clicked cell
+    #[test]
+    fn test_flood_fill_is_capped() {
+        use cavernborn::editor::{flood_fill, MAX_FLOOD_FILL};
+        use cavernborn::world::history::EditHistory;
+
+        let stone = Particle::Common(Common::Stone);
+        let mut map = Map::empty(256, 128);
+        let mut history = EditHistory::default();
+        let start = UVec2::new(128, 64);
+
+        assert_eq!(
+            flood_fill(&mut map, &mut history, start, Some(stone)),
+            MAX_FLOOD_FILL
+        );
+        assert_eq!(map.get_particle_at(start), Some(stone));
+        assert_eq!(map.get_particle_at(start + UVec2::new(10, 10)), Some(stone));
+        assert_eq!(map.get_particle_at(UVec2::ZERO), None);
+        history.end_stroke();
+        assert_eq!(history.undo_len(), 1);
+    }
 }
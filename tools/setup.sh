@@ -22,14 +22,22 @@ if [ -n "$STAGED_RS_FILES" ]; then
 else
     echo "No Rust files to format."
 fi
-# Run clippy
-echo -e "${BLUE}Running clippy...${NC}"
-cargo clippy -- -D warnings
-# If clippy fails, prevent the commit
-if [ $? -ne 0 ]; then
-    echo "clippy found issues. Please fix them before committing."
-    exit 1
-fi
+# Run clippy and the tests on the default build and the storage buffer build, so neither rots
+for FEATURES in "" "storage_buffers"; do
+    echo -e "${BLUE}Running clippy${FEATURES:+ with $FEATURES}...${NC}"
+    cargo clippy --all-targets --features "$FEATURES" -- -D warnings
+    # If clippy fails, prevent the commit
+    if [ $? -ne 0 ]; then
+        echo "clippy found issues. Please fix them before committing."
+        exit 1
+    fi
+    echo -e "${BLUE}Running tests${FEATURES:+ with $FEATURES}...${NC}"
+    cargo test --features "$FEATURES"
+    if [ $? -ne 0 ]; then
+        echo "Tests failed. Please fix them before committing."
+        exit 1
+    fi
+done
 # Run cargo-shear
 echo -e "${BLUE}Running cargo-shear...${NC}"
 cargo shear